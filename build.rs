@@ -0,0 +1,291 @@
+// Generates crabby.h, the C header of libcrabby, from src/capi.rs
+// Only what C can see is written: `#[repr(C)]` structs and enums, other structs as
+// opaque types, type aliases of function pointers, constants and `extern "C"`
+// functions. The comments above each of them are copied along.
+//
+// The header is written to OUT_DIR, since a build may not touch the source tree.
+// include/crabby.h is the copy C programmers use, and tests/c_api.rs fails when it
+// is not the same as the generated one.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::{Fields, FnArg, Item, ReturnType, Type};
+
+const SOURCE: &str = "src/capi.rs";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SOURCE);
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let source = fs::read_to_string(SOURCE).expect("src/capi.rs is readable");
+    let file = syn::parse_file(&source).expect("src/capi.rs parses");
+    let lines: Vec<&str> = source.lines().collect();
+
+    let mut header = String::from(
+        "// Generated from src/capi.rs by build.rs; edit that file instead\n\n\
+         #ifndef CRABBY_H\n#define CRABBY_H\n\n\
+         #include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n\n\
+         #ifdef __cplusplus\nextern \"C\" {\n#endif\n",
+    );
+    for item in &file.items {
+        if let Some(declaration) = declaration(item, &lines) {
+            header.push('\n');
+            header.push_str(&comment(&lines, first_line(item), ""));
+            header.push_str(&declaration);
+        }
+    }
+    header.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n#endif\n");
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").expect("cargo sets OUT_DIR"));
+    fs::write(out.join("crabby.h"), header).expect("OUT_DIR is writable");
+}
+
+fn declaration(item: &Item, lines: &[&str]) -> Option<String> {
+    match item {
+        Item::Struct(item) if is_public(&item.vis) => {
+            let name = item.ident.to_string();
+            if !is_repr_c(&item.attrs) {
+                return Some(format!("typedef struct {0} {0};\n", name));
+            }
+            let Fields::Named(fields) = &item.fields else {
+                return None;
+            };
+            let fields: String = fields
+                .named
+                .iter()
+                .map(|field| {
+                    let field_name = field.ident.as_ref().unwrap().unraw().to_string();
+                    format!("    {};\n", variable(&c_type(&field.ty), &field_name))
+                })
+                .collect();
+            Some(format!("typedef struct {0} {{\n{1}}} {0};\n", name, fields))
+        }
+        Item::Enum(item) if is_public(&item.vis) && is_repr_c(&item.attrs) => {
+            let name = item.ident.to_string();
+            let variants: String = item
+                .variants
+                .iter()
+                .map(|variant| {
+                    format!(
+                        "{}    {}_{},\n",
+                        comment(lines, variant.span().start().line, "    "),
+                        screaming(&name),
+                        screaming(&variant.ident.to_string())
+                    )
+                })
+                .collect();
+            Some(format!("typedef enum {0} {{\n{1}}} {0};\n", name, variants))
+        }
+        // Only `Option<unsafe extern "C" fn(...)>`, which is how a C function pointer
+        // that may be NULL is written
+        Item::Type(item) if is_public(&item.vis) => {
+            let Type::BareFn(function) = option_inner(&item.ty)? else {
+                return None;
+            };
+            let params: Vec<String> = function
+                .inputs
+                .iter()
+                .map(|arg| {
+                    let name = arg.name.as_ref().map(|(name, _)| name.to_string());
+                    variable(&c_type(&arg.ty), name.as_deref().unwrap_or(""))
+                })
+                .collect();
+            Some(format!(
+                "typedef {} (*{})({});\n",
+                return_type(&function.output),
+                item.ident,
+                parameters(params)
+            ))
+        }
+        Item::Const(item) if is_public(&item.vis) => {
+            let syn::Expr::Lit(value) = &*item.expr else {
+                return None;
+            };
+            let syn::Lit::Int(value) = &value.lit else {
+                return None;
+            };
+            Some(format!(
+                "#define {} {}\n",
+                item.ident,
+                value.base10_digits()
+            ))
+        }
+        Item::Fn(item)
+            if is_public(&item.vis)
+                && item
+                    .sig
+                    .abi
+                    .as_ref()
+                    .and_then(|abi| abi.name.as_ref())
+                    .is_some_and(|abi| abi.value() == "C") =>
+        {
+            let params: Vec<String> = item
+                .sig
+                .inputs
+                .iter()
+                .map(|arg| match arg {
+                    FnArg::Typed(arg) => {
+                        let name = match &*arg.pat {
+                            syn::Pat::Ident(pat) => pat.ident.to_string(),
+                            _ => String::new(),
+                        };
+                        variable(&c_type(&arg.ty), &name)
+                    }
+                    FnArg::Receiver(_) => unreachable!("extern functions have no self"),
+                })
+                .collect();
+            let returns = return_type(&item.sig.output);
+            Some(format!(
+                "{};\n",
+                variable(
+                    &returns,
+                    &format!("{}({})", item.sig.ident, parameters(params))
+                )
+            ))
+        }
+        _ => None,
+    }
+}
+
+// Where an item starts once its attributes are left out; `///` comments are
+// attributes too, but they are copied like `//` ones
+fn first_line(item: &Item) -> usize {
+    let visibility = match item {
+        Item::Struct(item) => &item.vis,
+        Item::Enum(item) => &item.vis,
+        Item::Type(item) => &item.vis,
+        Item::Const(item) => &item.vis,
+        Item::Fn(item) => &item.vis,
+        other => return other.span().start().line,
+    };
+    visibility.span().start().line
+}
+
+// The `//` comment block right above `line` (1-based), skipping attributes
+fn comment(lines: &[&str], line: usize, indent: &str) -> String {
+    let mut above = line.saturating_sub(1);
+    while above > 0 && lines[above - 1].trim_start().starts_with("#[") {
+        above -= 1;
+    }
+    let mut comment = Vec::new();
+    while above > 0 {
+        let text = lines[above - 1].trim_start();
+        if !text.starts_with("//") {
+            break;
+        }
+        // C has no doc comments, so `///` is written `//`
+        let text = text
+            .strip_prefix("///")
+            .map_or(text.to_string(), |doc| format!("//{}", doc));
+        comment.push(format!("{}{}\n", indent, text));
+        above -= 1;
+    }
+    comment.reverse();
+    comment.concat()
+}
+
+fn c_type(ty: &Type) -> String {
+    match ty {
+        Type::Ptr(pointer) => {
+            let inner = c_type(&pointer.elem);
+            match pointer.const_token {
+                Some(_) => format!("const {} *", inner),
+                None => format!("{} *", inner),
+            }
+        }
+        Type::Path(path) => {
+            let name = path.path.segments.last().unwrap().ident.to_string();
+            match name.as_str() {
+                "c_char" => "char",
+                "c_int" => "int",
+                "c_void" => "void",
+                "bool" => "bool",
+                "f64" => "double",
+                "f32" => "float",
+                "usize" => "size_t",
+                "isize" => "ptrdiff_t",
+                "i8" => "int8_t",
+                "i16" => "int16_t",
+                "i32" => "int32_t",
+                "i64" => "int64_t",
+                "u8" => "uint8_t",
+                "u16" => "uint16_t",
+                "u32" => "uint32_t",
+                "u64" => "uint64_t",
+                _ => return name,
+            }
+            .to_string()
+        }
+        other => panic!(
+            "no C type for the type at line {} of {}",
+            other.span().start().line,
+            SOURCE
+        ),
+    }
+}
+
+fn return_type(output: &ReturnType) -> String {
+    match output {
+        ReturnType::Default => "void".to_string(),
+        ReturnType::Type(_, ty) => c_type(ty),
+    }
+}
+
+// `char *` and `name` make `char *name`
+fn variable(ty: &str, name: &str) -> String {
+    match (ty.ends_with('*'), name.is_empty()) {
+        (_, true) => ty.trim_end().to_string(),
+        (true, false) => format!("{}{}", ty, name),
+        (false, false) => format!("{} {}", ty, name),
+    }
+}
+
+fn parameters(params: Vec<String>) -> String {
+    match params.is_empty() {
+        true => "void".to_string(),
+        false => params.join(", "),
+    }
+}
+
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match (segment.ident == "Option", arguments.args.first()?) {
+        (true, syn::GenericArgument::Type(inner)) => Some(inner),
+        _ => None,
+    }
+}
+
+fn is_public(vis: &syn::Visibility) -> bool {
+    matches!(vis, syn::Visibility::Public(_))
+}
+
+fn is_repr_c(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("repr")
+            && attr
+                .parse_args::<syn::Ident>()
+                .is_ok_and(|repr| repr == "C")
+    })
+}
+
+// `CrabbyErrorKind` is `CRABBY_ERROR_KIND`
+fn screaming(name: &str) -> String {
+    let mut screaming = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            screaming.push('_');
+        }
+        screaming.push(c.to_ascii_uppercase());
+    }
+    screaming
+}
@@ -0,0 +1 @@
+const SEARCH_INDEX = [{"kind":"module","module":"pr","name":"pr","summary":"","url":"pr.html"}];
@@ -0,0 +1,18 @@
+const input = document.getElementById("search");
+const results = document.getElementById("results");
+input.addEventListener("input", () => {
+    const query = input.value.trim().toLowerCase();
+    results.innerHTML = "";
+    if (!query) return;
+    for (const entry of SEARCH_INDEX) {
+        if (!entry.name.toLowerCase().includes(query)) continue;
+        const item = document.createElement("li");
+        const link = document.createElement("a");
+        link.href = entry.url;
+        link.textContent = entry.name + " (" + entry.kind + " in " + entry.module + ")";
+        item.appendChild(link);
+        if (entry.summary) item.append(" - " + entry.summary);
+        results.appendChild(item);
+        if (results.children.length >= 20) break;
+    }
+});
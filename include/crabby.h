@@ -0,0 +1,159 @@
+// Generated from src/capi.rs by build.rs; edit that file instead
+
+#ifndef CRABBY_H
+#define CRABBY_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// An interpreter, from crabby_new until crabby_free
+typedef struct CrabbyEngine CrabbyEngine;
+
+// What a CrabbyValue holds
+typedef enum CrabbyType {
+    CRABBY_TYPE_NULL,
+    CRABBY_TYPE_VOID,
+    CRABBY_TYPE_BOOLEAN,
+    CRABBY_TYPE_INTEGER,
+    CRABBY_TYPE_FLOAT,
+    CRABBY_TYPE_STRING,
+    // Arrays, dicts, functions, instances and integers too big for `integer`;
+    // `string` is how Crabby prints them
+    CRABBY_TYPE_OTHER,
+} CrabbyType;
+
+// A Crabby value; only the field for its `type` means anything. A value filled in by
+// libcrabby owns its string until crabby_value_free; a value filled in by the host
+// keeps ownership of it, and libcrabby copies what it needs.
+typedef struct CrabbyValue {
+    CrabbyType type;
+    bool boolean;
+    int64_t integer;
+    double number;
+    const char *string;
+} CrabbyValue;
+
+// What kind of error a call failed with
+typedef enum CrabbyErrorKind {
+    CRABBY_ERROR_KIND_SYNTAX,
+    CRABBY_ERROR_KIND_TYPE,
+    CRABBY_ERROR_KIND_RUNTIME,
+    CRABBY_ERROR_KIND_ASSERTION,
+    CRABBY_ERROR_KIND_LIMIT,
+    CRABBY_ERROR_KIND_IO,
+} CrabbyErrorKind;
+
+// The error a call failed with, from crabby_last_error. `line` and `column` are 0 when
+// the error has no location; `message` is the whole message, location included.
+typedef struct CrabbyError {
+    CrabbyErrorKind kind;
+    size_t line;
+    size_t column;
+    const char *message;
+} CrabbyError;
+
+// A C function called from Crabby with `count` arguments. It fills in `result` and
+// returns 0, or puts a message in `result` as a string and returns anything else to
+// stop the script with a runtime error.
+typedef int (*CrabbyCallback)(void *user_data, const CrabbyValue *args, size_t count, CrabbyValue *result);
+
+// What the functions returning `int` return
+#define CRABBY_OK 0
+
+#define CRABBY_ERROR 1
+
+// A new interpreter, with no access to files, the network and so on; what it prints
+// is kept for crabby_take_stdout and crabby_take_stderr
+CrabbyEngine *crabby_new(void);
+
+// Frees an interpreter and everything it made, apart from the values and strings it
+// handed out
+//
+// # Safety
+// `engine` is NULL or comes from crabby_new, and isn't used again
+void crabby_free(CrabbyEngine *engine);
+
+// Runs `source`; `result`, unless NULL, gets the value of its last expression
+//
+// # Safety
+// `engine` comes from crabby_new, `source` is a C string and `result` is NULL or
+// points to a CrabbyValue
+int crabby_eval(CrabbyEngine *engine, const char *source, CrabbyValue *result);
+
+// Runs the file at `path` like crabby_eval
+//
+// # Safety
+// `engine` comes from crabby_new, `path` is a C string and `result` is NULL or
+// points to a CrabbyValue
+int crabby_eval_file(CrabbyEngine *engine, const char *path, CrabbyValue *result);
+
+// Calls the function `name` with `count` arguments
+//
+// # Safety
+// `engine` comes from crabby_new, `name` is a C string, `args` points to `count`
+// values and `result` is NULL or points to a CrabbyValue
+int crabby_call(CrabbyEngine *engine, const char *name, const CrabbyValue *args, size_t count, CrabbyValue *result);
+
+// Sets the variable `name` for the scripts run afterwards
+//
+// # Safety
+// `engine` comes from crabby_new, `name` is a C string and a string `value` is a C
+// string
+int crabby_set_global(CrabbyEngine *engine, const char *name, CrabbyValue value);
+
+// A variable or function the scripts defined at the top level
+//
+// # Safety
+// `engine` comes from crabby_new, `name` is a C string and `result` is NULL or
+// points to a CrabbyValue
+int crabby_get_global(CrabbyEngine *engine, const char *name, CrabbyValue *result);
+
+// Makes `callback` callable from Crabby as `name`; `user_data` is passed back to it
+//
+// # Safety
+// `engine` comes from crabby_new, `name` is a C string, and `callback` can be called
+// with `user_data` for as long as the engine lives
+int crabby_register(CrabbyEngine *engine, const char *name, CrabbyCallback callback, void *user_data);
+
+// Why the last call returned CRABBY_ERROR, or NULL if it succeeded; valid until the
+// next call with the same `engine`
+//
+// # Safety
+// `engine` comes from crabby_new
+const CrabbyError *crabby_last_error(const CrabbyEngine *engine);
+
+// What the scripts printed since the last time; free it with crabby_string_free
+//
+// # Safety
+// `engine` comes from crabby_new
+char *crabby_take_stdout(CrabbyEngine *engine);
+
+// The warnings the scripts gave since the last time, such as for calls to
+// `@deprecated` functions; free it with crabby_string_free
+//
+// # Safety
+// `engine` comes from crabby_new
+char *crabby_take_stderr(CrabbyEngine *engine);
+
+// Frees a string from crabby_take_stdout or crabby_take_stderr
+//
+// # Safety
+// `string` is NULL or comes from one of them, and isn't used again
+void crabby_string_free(char *string);
+
+// Frees the string of a value filled in by libcrabby, and makes it null
+//
+// # Safety
+// `value` is NULL or points to a CrabbyValue filled in by libcrabby
+void crabby_value_free(CrabbyValue *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
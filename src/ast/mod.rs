@@ -1,3 +1,3 @@
-mod ast;
+mod nodes;
 
-pub use ast::*;
+pub use nodes::*;
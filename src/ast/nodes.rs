@@ -0,0 +1,488 @@
+use crate::format::FormatSpec;
+use crate::utils::Span;
+use num_bigint::BigInt;
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+    pub docstring: String, // a string opening the file documents the module
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self {
+            statements: Vec::new(),
+            docstring: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    FunctionDef {
+        name: String,
+        type_params: Vec<TypeParameter>,
+        params: Vec<Parameter>,
+        body: Box<Statement>,
+        return_type: Option<TypeExpr>,
+        docstring: String,
+        visibility: Visibility,
+        decorators: Vec<Decorator>,
+        span: Span,
+    },
+    FunctionFun {
+        name: String,
+        type_params: Vec<TypeParameter>,
+        params: Vec<Parameter>,
+        body: Box<Statement>,
+        return_type: Option<TypeExpr>,
+        docstring: String,
+        visibility: Visibility,
+        decorators: Vec<Decorator>,
+        span: Span,
+    },
+    // `extern "libm.so.6" def cos(x: float) -> float`; without a library, the function
+    // is looked up in the running program and the libraries it was linked with
+    Extern {
+        library: Option<String>,
+        name: String,
+        params: Vec<Parameter>,
+        return_type: Option<TypeExpr>,
+        span: Span, // the `extern` keyword
+    },
+    // `extern struct Point { x: f64, y: f64 }`, a struct laid out in memory as C would,
+    // so it can be passed to and returned from `extern` functions
+    ExternStruct {
+        name: String,
+        fields: Vec<ExternField>,
+        span: Span, // the `extern` keyword
+    },
+    Let {
+        name: String,
+        value: Box<Expression>,
+        type_annotation: Option<TypeExpr>,
+        mutable: bool, // `let mut`
+        span: Span,
+    },
+    Const {
+        name: String,
+        value: Box<Expression>,
+        type_annotation: Option<TypeExpr>,
+        span: Span,
+    },
+    Var {
+        name: String,
+        value: Box<Expression>,
+        type_annotation: Option<TypeExpr>,
+        span: Span,
+    },
+    Return(Box<Expression>, Span),
+    If {
+        condition: Box<Expression>,
+        then_branch: Box<Statement>,
+        else_branch: Option<Box<Statement>>,
+        span: Span,
+    },
+    AsyncFunction {
+        name: String,
+        params: Vec<Parameter>,
+        body: Box<Statement>,
+        return_type: Option<TypeExpr>,
+    },
+    And {
+        left: String,
+        right: String,
+    },
+    While {
+        condition: Box<Expression>,
+        body: Box<Statement>,
+        span: Span,
+    },
+    Enum {
+        name: String,
+        type_params: Vec<TypeParameter>,
+        variants: Vec<EnumVariant>,
+        where_clause: Option<Box<Expression>>,
+        docstring: String,
+        visibility: Visibility,
+        span: Span,
+    },
+    Struct {
+        name: String,
+        type_params: Vec<TypeParameter>,
+        fields: Vec<StructField>,
+        where_clause: Option<Box<Expression>>,
+        docstring: String,
+        visibility: Visibility,
+        span: Span,
+    },
+    Loop {
+        count: Box<Expression>,
+        body: Box<Statement>,
+        span: Span,
+    },
+    Match {
+        value: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    TypeDef {
+        name: String,
+        target: TypeExpr, // `typedef Vector = [Float]`
+        span: Span,
+    },
+    // `macro name!(params) { ... }`, removed from the program once calls are expanded
+    Macro {
+        name: String,
+        params: Vec<String>,
+        body: Box<Statement>,
+    },
+    // Mutable {
+    //    name: String,
+    // }, // the `mut` keyword
+    // Unless {
+    //    name: String,
+    //    body: Box<Statement>,
+    // },
+    ForIn {
+        variable: String,
+        iterator: Box<Expression>,
+        body: Box<Statement>,
+        span: Span,
+    },
+    Import {
+        name: String,
+        source: Option<String>,
+        span: Span,
+    },
+    // Static {
+    //    name: String,
+    //    value: Option<Box<Expression>>,
+    // },
+    Class {
+        name: String,
+        type_params: Vec<TypeParameter>,
+        parent: Option<String>,
+        methods: Vec<Statement>,
+        fields: Vec<ClassField>,
+        decorators: Vec<Decorator>,
+        docstring: String,
+        visibility: Visibility,
+        span: Span,
+    },
+    Extend {
+        class: String,
+        parent: String,
+        methods: Vec<Statement>,
+    },
+    Trait {
+        name: String,
+        methods: Vec<MethodDefinition>,
+        docstring: String,
+        visibility: Visibility,
+    },
+    // Maybe {
+    //    name: String,
+    // },
+    // Probably {
+    //    name: String,
+    // },
+    Impl {
+        target: String,
+        trait_name: Option<String>,
+        methods: Vec<MethodDefinition>,
+    },
+    ArrayAssign {
+        array: Expression,
+        index: Box<Expression>,
+        value: Box<Expression>,
+    },
+    // `assert condition, "message"`
+    Assert {
+        condition: Box<Expression>,
+        message: Option<Box<Expression>>,
+        span: Span,
+    },
+    // `test "name" { ... }`, only run by `crabby test`
+    Test {
+        name: String,
+        body: Box<Statement>,
+        span: Span,
+    },
+    Block(Vec<Statement>),
+    Expression(Expression),
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Integer(i64),
+    BigInteger(BigInt), // literals that do not fit in an `Integer`
+    Float(f64),
+    String(String),
+    Variable {
+        name: String,
+        span: Span,
+    },
+    Range(Box<Expression>),
+    Boolean(bool),
+    Maybe(Span),    // `maybe`, true half of the time
+    Probably(Span), // `probably`, true three times out of four
+    Null,
+    Array(Vec<Expression>),
+    Pattern(Box<PatternKind>),
+    Where {
+        expr: Box<Expression>,
+        condition: Box<Expression>,
+        body: Box<Statement>,
+    },
+    FString(Vec<FStringPart>),
+    Await {
+        expr: Box<Expression>,
+    },
+    Binary {
+        left: Box<Expression>,
+        operator: BinaryOp,
+        right: Box<Expression>,
+        span: Span, // the operator
+    },
+    Unary {
+        operator: UnaryOp,
+        operand: Box<Expression>,
+    },
+    // `&x` or `ref x`, and `&mut x` or `ref mut x`
+    Borrow {
+        expr: Box<Expression>,
+        mutable: bool,
+        span: Span, // the `&` or `ref`
+    },
+    // `move x` hands the value over, and `x` can't be used after
+    Move {
+        expr: Box<Expression>,
+        span: Span,
+    },
+    Call {
+        function: String,
+        arguments: Vec<Expression>,
+        keyword_arguments: Vec<KeywordArgument>,
+        span: Span,
+    },
+    Lambda {
+        params: Vec<Parameter>,
+        body: Box<Statement>,
+    },
+    Index {
+        array: Box<Expression>,
+        index: Box<Expression>,
+    },
+    Member {
+        object: Box<Expression>,
+        field: String,
+        optional: bool, // `?.`
+    },
+    MethodCall {
+        object: Box<Expression>,
+        method: String,
+        arguments: Vec<Expression>,
+        keyword_arguments: Vec<KeywordArgument>,
+        optional: bool, // `?.`
+    },
+    Try(Box<Expression>),    // `expr?`
+    TypeOf(Box<Expression>), // `typeof(expr)`
+    Is {
+        value: Box<Expression>,
+        type_expr: TypeExpr, // `value is Type`
+    },
+    MacroCall {
+        name: String,
+        arguments: Vec<Expression>,
+        span: Span, // call site, for expansion errors
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Visibility {
+    Public,
+    #[default]
+    Private,
+    Protect,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub default: Option<Expression>,
+    pub kind: ParameterKind,
+    pub type_annotation: Option<TypeExpr>,
+    pub span: Span, // the parameter's name
+}
+
+impl Parameter {
+    pub fn positional(name: String, span: Span) -> Self {
+        Self {
+            name,
+            default: None,
+            kind: ParameterKind::Positional,
+            type_annotation: None,
+            span,
+        }
+    }
+}
+
+// `var x = 5` in a class body; the default is evaluated for each new instance
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassField {
+    pub name: String,
+    pub default: Option<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternField {
+    pub name: String,
+    pub type_annotation: TypeExpr,
+}
+
+// `T` or `T: Num + Display` in `def largest<T: Num>(...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParameter {
+    pub name: String,
+    pub bounds: Vec<String>, // trait names
+}
+
+// A written type, as in `x: Int`, `-> [String]`, `Option<Int>`, `Int?` or `(Int) -> Int`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeExpr {
+    Named(String),
+    List(Box<TypeExpr>),
+    Generic {
+        name: String,
+        arguments: Vec<TypeExpr>,
+    },
+    Optional(Box<TypeExpr>),
+    Function {
+        params: Vec<TypeExpr>,
+        returns: Box<TypeExpr>,
+    },
+    // `u8[16]`, a C array of a fixed length
+    Array {
+        element: Box<TypeExpr>,
+        length: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ParameterKind {
+    #[default]
+    Positional,
+    Variadic,        // `*args`
+    KeywordVariadic, // `**kwargs`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordArgument {
+    pub name: String,
+    pub value: Expression,
+}
+
+// `@name` or `@name(args)` above a definition. Decorators are listed top to bottom
+// and applied bottom to top, so `@a @b def f` binds `f` to `a(b(f))`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decorator {
+    pub name: String,
+    pub arguments: Vec<Expression>,
+    pub keyword_arguments: Vec<KeywordArgument>,
+    pub called: bool, // `@name(...)` rather than `@name`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodDefinition {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub body: Box<Statement>,
+    pub docstring: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Expression,
+    pub body: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumVariant {
+    pub name: String,
+    pub fields: Option<Vec<Expression>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructField {
+    pub name: String,
+    pub type_expr: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    Literal(Box<Expression>),
+    Variable(String),
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Eq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Dot,
+    MatchOp,
+    NullCoalesce,
+}
+
+impl BinaryOp {
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq
+                | BinaryOp::NotEq
+                | BinaryOp::Less
+                | BinaryOp::LessEq
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEq
+        )
+    }
+}
+
+// `f"total: {sum:.2f}"` is the text `total: ` and a hole for `sum`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FStringPart {
+    Text(String),
+    Hole {
+        expression: Expression,
+        spec: Option<FormatSpec>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOp {
+    Neg,    // `-x`
+    BitNot, // `~x`
+    Not,    // `!x` and `not x`
+}
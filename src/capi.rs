@@ -0,0 +1,465 @@
+// The C interface of libcrabby, for hosting Crabby from C and C++
+// Everything here is wrapped around `Engine`. `build.rs` turns this file into
+// crabby.h (include/crabby.h is the copy in the repository), copying the comment
+// above each item, so what is written here is what C programmers read.
+//
+// A panic must not unwind into C, where it would abort the host, so every function
+// catches them: the ones returning `int` report CRABBY_ERROR, the others do nothing
+// or return NULL.
+
+use std::any::Any;
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::engine::Engine;
+use crate::utils::CrabbyError as Error;
+use crate::value::Value;
+
+// An interpreter, from crabby_new until crabby_free
+pub struct CrabbyEngine {
+    engine: Engine,
+    error: Option<CrabbyError>,
+    message: CString, // what `error.message` points to
+}
+
+// What a CrabbyValue holds
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrabbyType {
+    Null,
+    Void,
+    Boolean,
+    Integer,
+    Float,
+    String,
+    // Arrays, dicts, functions, instances and integers too big for `integer`;
+    // `string` is how Crabby prints them
+    Other,
+}
+
+// A Crabby value; only the field for its `type` means anything. A value filled in by
+// libcrabby owns its string until crabby_value_free; a value filled in by the host
+// keeps ownership of it, and libcrabby copies what it needs.
+#[repr(C)]
+pub struct CrabbyValue {
+    pub r#type: CrabbyType,
+    pub boolean: bool,
+    pub integer: i64,
+    pub number: f64,
+    pub string: *const c_char,
+}
+
+// What kind of error a call failed with
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrabbyErrorKind {
+    Syntax,
+    Type,
+    Runtime,
+    Assertion,
+    Limit,
+    Io,
+}
+
+// The error a call failed with, from crabby_last_error. `line` and `column` are 0 when
+// the error has no location; `message` is the whole message, location included.
+#[repr(C)]
+pub struct CrabbyError {
+    pub kind: CrabbyErrorKind,
+    pub line: usize,
+    pub column: usize,
+    pub message: *const c_char,
+}
+
+// A C function called from Crabby with `count` arguments. It fills in `result` and
+// returns 0, or puts a message in `result` as a string and returns anything else to
+// stop the script with a runtime error.
+pub type CrabbyCallback = Option<
+    unsafe extern "C" fn(
+        user_data: *mut c_void,
+        args: *const CrabbyValue,
+        count: usize,
+        result: *mut CrabbyValue,
+    ) -> c_int,
+>;
+
+// What the functions returning `int` return
+pub const CRABBY_OK: c_int = 0;
+pub const CRABBY_ERROR: c_int = 1;
+
+impl CrabbyValue {
+    fn null() -> Self {
+        Self {
+            r#type: CrabbyType::Null,
+            boolean: false,
+            integer: 0,
+            number: 0.0,
+            string: ptr::null(),
+        }
+    }
+
+    // The string is allocated here, for crabby_value_free
+    fn from_value(value: Value) -> Self {
+        let mut result = Self::null();
+        let text = |s: String| CString::new(s.replace('\0', "")).unwrap_or_default();
+        match value {
+            Value::Null => {}
+            Value::Void => result.r#type = CrabbyType::Void,
+            Value::Boolean(b) => {
+                result.r#type = CrabbyType::Boolean;
+                result.boolean = b;
+            }
+            Value::Integer(n) => {
+                result.r#type = CrabbyType::Integer;
+                result.integer = n;
+            }
+            Value::Float(f) => {
+                result.r#type = CrabbyType::Float;
+                result.number = f;
+            }
+            Value::String(s) => {
+                result.r#type = CrabbyType::String;
+                result.string = text(s).into_raw();
+            }
+            other => {
+                result.r#type = CrabbyType::Other;
+                result.string = text(other.to_string()).into_raw();
+            }
+        }
+        result
+    }
+
+    // Safety: `string` is null or a valid C string
+    unsafe fn to_value(&self) -> Result<Value, Error> {
+        Ok(match self.r#type {
+            CrabbyType::Null => Value::Null,
+            CrabbyType::Void => Value::Void,
+            CrabbyType::Boolean => Value::Boolean(self.boolean),
+            CrabbyType::Integer => Value::Integer(self.integer),
+            CrabbyType::Float => Value::Float(self.number),
+            CrabbyType::String => Value::String(unsafe { string(self.string) }?),
+            CrabbyType::Other => {
+                return Err(Error::TypeError(
+                    None,
+                    "CRABBY_TYPE_OTHER values can't be passed to Crabby".to_string(),
+                ));
+            }
+        })
+    }
+}
+
+impl CrabbyEngine {
+    // Keeps the error for crabby_last_error and returns CRABBY_ERROR
+    fn fail(&mut self, error: Error) -> c_int {
+        let (kind, line, column) = match &error {
+            Error::LexerError(at) | Error::ParserError(at) | Error::MissingCaseKeyword(at) => {
+                (CrabbyErrorKind::Syntax, at.line, at.column)
+            }
+            Error::ExpansionError(span, _) => (CrabbyErrorKind::Syntax, span.line, span.column),
+            Error::TypeError(span, _) => located(CrabbyErrorKind::Type, span),
+            Error::AssertionError(span, _) => located(CrabbyErrorKind::Assertion, span),
+            Error::LimitExceeded(span, _) => located(CrabbyErrorKind::Limit, span),
+            Error::IoError(_) => (CrabbyErrorKind::Io, 0, 0),
+            _ => (CrabbyErrorKind::Runtime, 0, 0),
+        };
+        self.message = CString::new(error.to_string().replace('\0', "")).unwrap_or_default();
+        self.error = Some(CrabbyError {
+            kind,
+            line,
+            column,
+            message: self.message.as_ptr(),
+        });
+        CRABBY_ERROR
+    }
+
+    // Safety: `result` is null or points to a CrabbyValue
+    unsafe fn finish(&mut self, outcome: Result<Value, Error>, result: *mut CrabbyValue) -> c_int {
+        self.error = None;
+        match outcome {
+            Ok(value) => {
+                if let Some(result) = unsafe { result.as_mut() } {
+                    *result = CrabbyValue::from_value(value);
+                }
+                CRABBY_OK
+            }
+            Err(error) => self.fail(error),
+        }
+    }
+}
+
+// Runs `call` on the engine and reports how it went; a panic becomes a runtime error.
+// Safety: `engine` comes from crabby_new and `result` is null or points to a CrabbyValue
+unsafe fn run(
+    engine: *mut CrabbyEngine,
+    result: *mut CrabbyValue,
+    call: impl FnOnce(&mut Engine) -> Result<Value, Error>,
+) -> c_int {
+    let engine = unsafe { &mut *engine };
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| call(&mut engine.engine)))
+        .unwrap_or_else(|panic| Err(panicked(panic)));
+    unsafe { engine.finish(outcome, result) }
+}
+
+// For the functions with nothing to report an error with
+fn or_else<T>(fallback: T, call: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or(fallback)
+}
+
+fn panicked(panic: Box<dyn Any + Send>) -> Error {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "no message".to_string());
+    Error::RuntimeError(format!("libcrabby panicked: {}", message))
+}
+
+fn located(
+    kind: CrabbyErrorKind,
+    span: &Option<crate::utils::Span>,
+) -> (CrabbyErrorKind, usize, usize) {
+    match span {
+        Some(span) => (kind, span.line, span.column),
+        None => (kind, 0, 0),
+    }
+}
+
+// Safety: `s` is null or a valid C string
+unsafe fn string(s: *const c_char) -> Result<String, Error> {
+    if s.is_null() {
+        return Err(Error::TypeError(
+            None,
+            "expected a string, got NULL".to_string(),
+        ));
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map(String::from)
+        .map_err(|_| Error::TypeError(None, "strings must be valid UTF-8".to_string()))
+}
+
+// A new interpreter, with no access to files, the network and so on; what it prints
+// is kept for crabby_take_stdout and crabby_take_stderr
+#[unsafe(no_mangle)]
+pub extern "C" fn crabby_new() -> *mut CrabbyEngine {
+    or_else(ptr::null_mut(), || {
+        Box::into_raw(Box::new(CrabbyEngine {
+            engine: Engine::new(),
+            error: None,
+            message: CString::default(),
+        }))
+    })
+}
+
+/// Frees an interpreter and everything it made, apart from the values and strings it
+/// handed out
+///
+/// # Safety
+/// `engine` is NULL or comes from crabby_new, and isn't used again
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_free(engine: *mut CrabbyEngine) {
+    if !engine.is_null() {
+        or_else((), || drop(unsafe { Box::from_raw(engine) }));
+    }
+}
+
+/// Runs `source`; `result`, unless NULL, gets the value of its last expression
+///
+/// # Safety
+/// `engine` comes from crabby_new, `source` is a C string and `result` is NULL or
+/// points to a CrabbyValue
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_eval(
+    engine: *mut CrabbyEngine,
+    source: *const c_char,
+    result: *mut CrabbyValue,
+) -> c_int {
+    let call = |engine: &mut Engine| unsafe { string(source) }.and_then(|s| engine.eval(&s));
+    unsafe { run(engine, result, call) }
+}
+
+/// Runs the file at `path` like crabby_eval
+///
+/// # Safety
+/// `engine` comes from crabby_new, `path` is a C string and `result` is NULL or
+/// points to a CrabbyValue
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_eval_file(
+    engine: *mut CrabbyEngine,
+    path: *const c_char,
+    result: *mut CrabbyValue,
+) -> c_int {
+    let call = |engine: &mut Engine| unsafe { string(path) }.and_then(|p| engine.eval_file(&p));
+    unsafe { run(engine, result, call) }
+}
+
+/// Calls the function `name` with `count` arguments
+///
+/// # Safety
+/// `engine` comes from crabby_new, `name` is a C string, `args` points to `count`
+/// values and `result` is NULL or points to a CrabbyValue
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_call(
+    engine: *mut CrabbyEngine,
+    name: *const c_char,
+    args: *const CrabbyValue,
+    count: usize,
+    result: *mut CrabbyValue,
+) -> c_int {
+    let call = |engine: &mut Engine| {
+        let name = unsafe { string(name) }?;
+        engine.call_function(&name, unsafe { values(args, count) }?)
+    };
+    unsafe { run(engine, result, call) }
+}
+
+/// Sets the variable `name` for the scripts run afterwards
+///
+/// # Safety
+/// `engine` comes from crabby_new, `name` is a C string and a string `value` is a C
+/// string
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_set_global(
+    engine: *mut CrabbyEngine,
+    name: *const c_char,
+    value: CrabbyValue,
+) -> c_int {
+    let call = |engine: &mut Engine| {
+        engine.set_global(&unsafe { string(name) }?, unsafe { value.to_value() }?);
+        Ok(Value::Void)
+    };
+    unsafe { run(engine, ptr::null_mut(), call) }
+}
+
+/// A variable or function the scripts defined at the top level
+///
+/// # Safety
+/// `engine` comes from crabby_new, `name` is a C string and `result` is NULL or
+/// points to a CrabbyValue
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_get_global(
+    engine: *mut CrabbyEngine,
+    name: *const c_char,
+    result: *mut CrabbyValue,
+) -> c_int {
+    let call = |engine: &mut Engine| engine.get_global(&unsafe { string(name) }?);
+    unsafe { run(engine, result, call) }
+}
+
+/// Makes `callback` callable from Crabby as `name`; `user_data` is passed back to it
+///
+/// # Safety
+/// `engine` comes from crabby_new, `name` is a C string, and `callback` can be called
+/// with `user_data` for as long as the engine lives
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_register(
+    engine: *mut CrabbyEngine,
+    name: *const c_char,
+    callback: CrabbyCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let call = |engine: &mut Engine| {
+        let name = unsafe { string(name) }?;
+        let callback = callback
+            .ok_or_else(|| Error::TypeError(None, format!("the callback for {} is NULL", name)))?;
+        engine.add_builtin(&name.clone(), move |args| {
+            let args: Vec<CrabbyValue> = args.into_iter().map(CrabbyValue::from_value).collect();
+            let mut result = CrabbyValue::null();
+            let status = unsafe { callback(user_data, args.as_ptr(), args.len(), &mut result) };
+            for mut arg in args {
+                unsafe { crabby_value_free(&mut arg) };
+            }
+            // The host owns `result`'s string
+            match status {
+                CRABBY_OK => unsafe { result.to_value() },
+                _ => Err(Error::RuntimeError(match result.r#type {
+                    CrabbyType::String => unsafe { string(result.string) }?,
+                    _ => format!("{} failed", name),
+                })),
+            }
+        });
+        Ok(Value::Void)
+    };
+    unsafe { run(engine, ptr::null_mut(), call) }
+}
+
+/// Why the last call returned CRABBY_ERROR, or NULL if it succeeded; valid until the
+/// next call with the same `engine`
+///
+/// # Safety
+/// `engine` comes from crabby_new
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_last_error(engine: *const CrabbyEngine) -> *const CrabbyError {
+    match unsafe { &(*engine).error } {
+        Some(error) => error,
+        None => ptr::null(),
+    }
+}
+
+/// What the scripts printed since the last time; free it with crabby_string_free
+///
+/// # Safety
+/// `engine` comes from crabby_new
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_take_stdout(engine: *mut CrabbyEngine) -> *mut c_char {
+    let engine = unsafe { &mut *engine };
+    or_else(ptr::null_mut(), || c_string(engine.engine.take_stdout()))
+}
+
+/// The warnings the scripts gave since the last time, such as for calls to
+/// `@deprecated` functions; free it with crabby_string_free
+///
+/// # Safety
+/// `engine` comes from crabby_new
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_take_stderr(engine: *mut CrabbyEngine) -> *mut c_char {
+    let engine = unsafe { &mut *engine };
+    or_else(ptr::null_mut(), || c_string(engine.engine.take_stderr()))
+}
+
+/// Frees a string from crabby_take_stdout or crabby_take_stderr
+///
+/// # Safety
+/// `string` is NULL or comes from one of them, and isn't used again
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        or_else((), || drop(unsafe { CString::from_raw(string) }));
+    }
+}
+
+/// Frees the string of a value filled in by libcrabby, and makes it null
+///
+/// # Safety
+/// `value` is NULL or points to a CrabbyValue filled in by libcrabby
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crabby_value_free(value: *mut CrabbyValue) {
+    let Some(value) = (unsafe { value.as_mut() }) else {
+        return;
+    };
+    if matches!(value.r#type, CrabbyType::String | CrabbyType::Other) && !value.string.is_null() {
+        or_else((), || {
+            drop(unsafe { CString::from_raw(value.string as *mut c_char) })
+        });
+    }
+    *value = CrabbyValue::null();
+}
+
+// Handed out to C, for crabby_string_free
+fn c_string(text: String) -> *mut c_char {
+    CString::new(text.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+// Safety: `args` points to `count` values, or `count` is 0
+unsafe fn values(args: *const CrabbyValue, count: usize) -> Result<Vec<Value>, Error> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    unsafe { std::slice::from_raw_parts(args, count) }
+        .iter()
+        .map(|arg| unsafe { arg.to_value() })
+        .collect()
+}
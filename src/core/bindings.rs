@@ -1 +1,356 @@
 // Bindings for Crabby, letting Crabby communicate through graphic APIs and more
+// `crabby bindgen header.h` writes the `extern` declarations of a C header as a Crabby
+// module. bindgen reads the header with libclang and writes Rust, which is translated
+// here: `extern "C"` functions become `extern def`, `#[repr(C)]` structs become
+// `extern struct`, and number constants become `let`. What Crabby can't declare, like
+// unions, bitfields and variadic functions, is left out with a comment saying so.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use syn::{Expr, Fields, FnArg, ForeignItem, Item, Lit, Pat, ReturnType, Type, UnOp};
+
+use crate::ast::{ExternField, Parameter, Program, Statement, TypeExpr};
+use crate::etc::printer::print_program;
+use crate::lexer::{Token, TokenStream};
+use crate::utils::{CrabbyError, Span};
+
+// Runs bindgen on `header`, and translates what it writes
+pub fn generate(header: &Path, library: Option<&str>) -> Result<String, CrabbyError> {
+    // bindgen panics when libclang is missing, so it is looked for first
+    clang_sys::load().map_err(|e| {
+        CrabbyError::RuntimeError(format!("`crabby bindgen` needs libclang: {}", e))
+    })?;
+    let path = header.to_string_lossy();
+    let bindings = bindgen::Builder::default()
+        .header(path.as_ref())
+        // What the header declares, not everything it includes
+        .allowlist_file(regex_escape(&path))
+        .layout_tests(false)
+        .generate_comments(false)
+        .generate()
+        .map_err(|e| {
+            CrabbyError::RuntimeError(format!("cannot read {}: {}", header.display(), e))
+        })?;
+    translate(&bindings.to_string(), &path, library)
+}
+
+// Translates the Rust bindgen wrote for `header` into Crabby
+pub fn translate(rust: &str, header: &str, library: Option<&str>) -> Result<String, CrabbyError> {
+    let file = syn::parse_file(rust)
+        .map_err(|e| CrabbyError::RuntimeError(format!("cannot translate the bindings: {}", e)))?;
+    let mut translator = Translator {
+        aliases: HashMap::new(),
+        structs: HashSet::new(),
+        library: library.map(String::from),
+    };
+    for item in &file.items {
+        if let Item::Type(alias) = item {
+            translator
+                .aliases
+                .insert(alias.ident.to_string(), (*alias.ty).clone());
+        }
+    }
+
+    let mut output = format!("// Generated from {} by `crabby bindgen`\n", header);
+    for item in &file.items {
+        let translated = match item {
+            Item::Const(constant) => translator.constant(constant),
+            Item::Struct(item) => translator.structure(item),
+            Item::Union(union) => Some(Err(format!(
+                "union `{}` left out: unions aren't supported",
+                union.ident
+            ))),
+            Item::ForeignMod(block) => {
+                for item in &block.items {
+                    if let ForeignItem::Fn(function) = item {
+                        output.push('\n');
+                        output.push_str(&render(translator.function(function)));
+                    }
+                }
+                None
+            }
+            // Type aliases are resolved where they are used, and the rest is Rust only
+            _ => None,
+        };
+        if let Some(translated) = translated {
+            output.push('\n');
+            output.push_str(&render(translated));
+        }
+    }
+    Ok(output)
+}
+
+// The source of a declaration, or the comment saying why it was left out
+fn render(translated: Result<String, String>) -> String {
+    match translated {
+        Ok(source) => source,
+        Err(reason) => format!("// {}\n", reason),
+    }
+}
+
+fn print(statement: Statement) -> String {
+    print_program(&Program {
+        statements: vec![statement],
+        docstring: String::new(),
+    })
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Crabby keywords can't name parameters and fields, so they get a `_`, as bindgen
+// does for Rust keywords
+fn identifier(name: &str) -> String {
+    let name = name.strip_prefix("r#").unwrap_or(name);
+    match is_identifier(name) {
+        true => name.to_string(),
+        false => format!("{}_", name),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    match TokenStream::tokenize(name.to_string()) {
+        Ok(tokens) => {
+            matches!(tokens.as_slice(), [only] if matches!(only.token, Token::Identifier(_)))
+        }
+        Err(_) => false,
+    }
+}
+
+struct Translator {
+    aliases: HashMap<String, Type>,
+    structs: HashSet<String>, // declared so far
+    library: Option<String>,
+}
+
+impl Translator {
+    fn constant(&self, constant: &syn::ItemConst) -> Option<Result<String, String>> {
+        let name = constant.ident.to_string();
+        let value = match &*constant.expr {
+            Expr::Lit(literal) => literal_value(&literal.lit),
+            Expr::Unary(unary) if matches!(unary.op, UnOp::Neg(_)) => match &*unary.expr {
+                Expr::Lit(literal) => {
+                    literal_value(&literal.lit).map(|value| format!("-{}", value))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        Some(match (value, is_identifier(&name)) {
+            (Some(value), true) => Ok(format!("let {} = {}\n", name, value)),
+            (None, _) => Err(format!(
+                "`{}` left out: only number constants are supported",
+                name
+            )),
+            (_, false) => Err(format!("`{}` left out: it is a Crabby keyword", name)),
+        })
+    }
+
+    fn structure(&mut self, item: &syn::ItemStruct) -> Option<Result<String, String>> {
+        let name = item.ident.to_string();
+        let Fields::Named(fields) = &item.fields else {
+            return None;
+        };
+        let fields: Vec<_> = fields.named.iter().collect();
+        // Structs the header only names, which are used through pointers
+        if fields
+            .iter()
+            .all(|field| field.ident.as_ref().unwrap() == "_unused")
+        {
+            return None;
+        }
+        if fields.iter().any(|field| {
+            field
+                .ident
+                .as_ref()
+                .unwrap()
+                .to_string()
+                .starts_with("_bitfield")
+        }) {
+            return Some(Err(format!(
+                "struct `{}` left out: bitfields aren't supported",
+                name
+            )));
+        }
+        let fields = fields
+            .iter()
+            .map(|field| {
+                let field_name = field.ident.as_ref().unwrap().to_string();
+                let type_annotation = self.type_expr(&field.ty).map_err(|reason| {
+                    format!(
+                        "struct `{}` left out: field `{}` {}",
+                        name, field_name, reason
+                    )
+                })?;
+                Ok(ExternField {
+                    name: identifier(&field_name),
+                    type_annotation,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>();
+        let fields = match fields {
+            Ok(fields) => fields,
+            Err(reason) => return Some(Err(reason)),
+        };
+        self.structs.insert(name.clone());
+        Some(Ok(print(Statement::ExternStruct {
+            name,
+            fields,
+            span: Span::default(),
+        })))
+    }
+
+    fn function(&self, function: &syn::ForeignItemFn) -> Result<String, String> {
+        let name = function.sig.ident.to_string();
+        if function.sig.variadic.is_some() {
+            return Err(format!(
+                "`{}` left out: variadic functions aren't supported",
+                name
+            ));
+        }
+        if !is_identifier(&name) {
+            return Err(format!("`{}` left out: it is a Crabby keyword", name));
+        }
+        let mut params = Vec::new();
+        for (i, input) in function.sig.inputs.iter().enumerate() {
+            let FnArg::Typed(param) = input else {
+                continue;
+            };
+            let param_name = match &*param.pat {
+                Pat::Ident(pat) => identifier(&pat.ident.to_string()),
+                _ => format!("arg{}", i + 1),
+            };
+            let annotation = self.type_expr(&param.ty).map_err(|reason| {
+                format!("`{}` left out: parameter `{}` {}", name, param_name, reason)
+            })?;
+            let mut parameter = Parameter::positional(param_name, Span::default());
+            parameter.type_annotation = Some(annotation);
+            params.push(parameter);
+        }
+        let return_type = match &function.sig.output {
+            ReturnType::Default => None,
+            ReturnType::Type(_, ty) => Some(
+                self.type_expr(ty)
+                    .map_err(|reason| format!("`{}` left out: its result {}", name, reason))?,
+            ),
+        };
+        Ok(print(Statement::Extern {
+            library: self.library.clone(),
+            name,
+            params,
+            return_type,
+            span: Span::default(),
+        }))
+    }
+
+    fn type_expr(&self, ty: &Type) -> Result<TypeExpr, String> {
+        let named = |name: &str| Ok(TypeExpr::Named(name.to_string()));
+        match ty {
+            Type::Path(path) => {
+                let segment = path.path.segments.last().unwrap();
+                let name = segment.ident.to_string();
+                // `Option<unsafe extern "C" fn(..)>`, a function pointer that may be NULL
+                if let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments
+                    && let (true, Some(syn::GenericArgument::Type(inner))) =
+                        (name == "Option", arguments.args.first())
+                {
+                    return self.type_expr(inner);
+                }
+                match name.as_str() {
+                    "c_char" | "c_schar" | "i8" => named("i8"),
+                    "c_uchar" | "u8" => named("u8"),
+                    "c_short" | "i16" => named("i16"),
+                    "c_ushort" | "u16" => named("u16"),
+                    "c_int" | "i32" => named("i32"),
+                    "c_uint" | "u32" => named("u32"),
+                    "c_long" | "c_longlong" | "i64" | "isize" => named("i64"),
+                    "c_ulong" | "c_ulonglong" | "u64" | "usize" => named("u64"),
+                    "c_float" | "f32" => named("f32"),
+                    "c_double" | "f64" => named("f64"),
+                    "bool" => named("bool"),
+                    "c_void" => named("void"),
+                    name if self.structs.contains(name) => named(name),
+                    name => match self.aliases.get(name) {
+                        Some(target) => self.type_expr(target),
+                        None => Err(format!("has the type `{}`, which Crabby can't pass", name)),
+                    },
+                }
+            }
+            Type::Ptr(pointer) => {
+                let pointee = self.type_expr(&pointer.elem);
+                match pointee {
+                    // A C string is only copied when the function won't write to it
+                    Ok(TypeExpr::Named(name)) if name == "i8" && pointer.const_token.is_some() => {
+                        named("string")
+                    }
+                    // Pointers to what Crabby can't lay out are only addresses
+                    Ok(TypeExpr::Named(name)) if name == "void" => named("ptr"),
+                    Err(_) => named("ptr"),
+                    Ok(pointee) => Ok(TypeExpr::Generic {
+                        name: "ptr".to_string(),
+                        arguments: vec![pointee],
+                    }),
+                }
+            }
+            Type::Array(array) => {
+                let length = match &array.len {
+                    Expr::Lit(syn::ExprLit {
+                        lit: Lit::Int(length),
+                        ..
+                    }) => length.base10_parse::<usize>().ok(),
+                    _ => None,
+                };
+                match length {
+                    Some(length) => Ok(TypeExpr::Array {
+                        element: Box::new(self.type_expr(&array.elem)?),
+                        length,
+                    }),
+                    None => Err("is an array without a number for its length".to_string()),
+                }
+            }
+            Type::BareFn(function) => {
+                if function.variadic.is_some() {
+                    return Err("is a variadic function pointer".to_string());
+                }
+                let params = function
+                    .inputs
+                    .iter()
+                    .map(|input| self.type_expr(&input.ty))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let returns = match &function.output {
+                    ReturnType::Default => TypeExpr::Named("void".to_string()),
+                    ReturnType::Type(_, ty) => self.type_expr(ty)?,
+                };
+                Ok(TypeExpr::Function {
+                    params,
+                    returns: Box::new(returns),
+                })
+            }
+            Type::Tuple(tuple) if tuple.elems.is_empty() => named("void"),
+            _ => Err("has a type Crabby can't pass".to_string()),
+        }
+    }
+}
+
+fn literal_value(literal: &Lit) -> Option<String> {
+    match literal {
+        Lit::Int(n) => Some(n.base10_digits().to_string()),
+        Lit::Float(f) => {
+            let digits = f.base10_digits();
+            match digits.contains(['.', 'e', 'E']) {
+                true => Some(digits.to_string()),
+                false => Some(format!("{}.0", digits)),
+            }
+        }
+        _ => None,
+    }
+}
@@ -1,234 +1,1251 @@
 // C/C++ Interaction for Crabby
-// Used for loading shared libraries like DLLs and .so files
+// Used for loading shared libraries like DLLs and .so files, and calling their
+// functions:
+//
+//     extern "libm.so.6" def cos(x: float) -> float
+//     print(cos(0.0))
+//
+// Without a library name, the function is looked up in the running program and the
+// libraries it was linked with, like libc. Declaring one needs `--allow-ffi`.
+//
+// `extern struct` lays a struct out as C would. Structs are passed by value when they
+// fit in registers, and arrays, structs and `&x` are copied into memory for a pointer
+// parameter. `&mut x` puts back into `x` what the function left in the copy:
+//
+//     extern struct Point { x: f64, y: f64 }
+//     extern "./libshapes.so" def move_by(p: *Point, dx: f64)
+//     let mut p = Point(1.0, 2.0)
+//     move_by(&mut p, 0.5)
+//
+// Crabby functions can be passed for C function pointers, and C can call them until
+// the call they were passed to returns.
+//
+// A call puts every integer, string and pointer argument in the integer registers, in
+// order, and every float in the floating-point registers. The C calling conventions of
+// x86-64 Linux and macOS and of ARM64 hand those registers out separately, so one
+// function pointer type with all of them covers every signature. Callbacks are the
+// same in reverse: a trampoline taking all the registers picks out the ones C used.
+// Variadic functions, and functions with more arguments than registers, aren't
+// supported.
 
-use std::ffi::{CStr, CString};
-use std::os::raw::{c_char, c_double, c_int, c_void};
-use libloading::{Library, Symbol};
-use crate::value::Value;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::fmt;
+use std::rc::Rc;
+
+use libloading::Library;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::ast::{ExternField, Parameter, TypeExpr};
 use crate::utils::CrabbyError;
-use crate::interpreter;
+use crate::value::{Instance, Value};
 
-#[derive(Debug, Clone)]
+#[cfg(target_arch = "aarch64")]
+const INT_REGISTERS: usize = 8;
+#[cfg(not(target_arch = "aarch64"))]
+const INT_REGISTERS: usize = 6;
+const FLOAT_REGISTERS: usize = 8;
+// How many Crabby functions C can hold at once
+const CALLBACK_SLOTS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum FFIType {
-    Int,
-    Float,
-    String,
+    I8,
+    I16,
+    I32, // also `int`
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,    // also `float` and `double`
+    Bool,   // C `bool`
+    String, // `const char*`, copied in and out
     Void,
     Pointer(Box<FFIType>),
+    Array(Box<FFIType>, usize),
+    Struct(Rc<StructLayout>),
+    // A function pointer, for which a Crabby function is passed
+    Callback {
+        params: Vec<FFIType>,
+        returns: Box<FFIType>,
+    },
 }
 
-pub struct FFIFunction<'a> {
-    lib: std::sync::Arc<Library>,
-    func: Symbol<'a, unsafe extern "C" fn()>,
-    arg_types: Vec<FFIType>,
-    return_type: FFIType,
+// A struct declared with `extern struct`, and where C puts its fields
+#[derive(Debug, PartialEq)]
+pub struct StructLayout {
+    pub name: String,
+    fields: Vec<(String, FFIType, usize)>, // with their offsets
+    size: usize,
+    align: usize,
 }
 
-#[derive(Debug, Clone)]
-pub enum FFIValue {
-    Int(c_int),
-    Float(c_double),
-    String(CString),
-    Void,
-    Pointer(*mut c_void),
+// The `extern` functions and structs a program declared
+#[derive(Clone, Default)]
+pub struct Declarations {
+    pub functions: HashMap<String, Rc<ForeignFunction>>,
+    pub structs: HashMap<String, Rc<StructLayout>>,
+}
+
+// An argument of an `extern` function; `&x` and `&mut x` are `Borrowed`
+pub enum Argument {
+    Value(Value),
+    Borrowed(Value),
+}
+
+// Runs the Crabby functions C calls back
+pub type Caller<'a> = dyn FnMut(&Value, Vec<Value>) -> Result<Value, CrabbyError> + 'a;
+
+// A C function declared with `extern`
+pub struct ForeignFunction {
+    name: String,
+    params: Vec<(String, FFIType)>,
+    returns: FFIType,
+    address: *const c_void,
+    _library: Rc<Library>, // keeps `address` valid
+}
+
+// How a struct travels by value: in registers, each holding `size` bytes from `offset`
+// in the struct, or as a pointer to a copy
+enum Passing {
+    Registers(Vec<Part>),
+    #[cfg_attr(not(target_arch = "aarch64"), allow(dead_code))] // only on ARM64
+    Reference,
+}
+
+struct Part {
+    float: bool,
+    offset: usize,
+    size: usize,
+}
+
+// The argument registers of a call, in the order they are filled
+#[derive(Default)]
+struct Registers {
+    ints: [i64; INT_REGISTERS],
+    floats: [f64; FLOAT_REGISTERS],
+    int_count: usize,
+    float_count: usize,
 }
 
-pub struct FFIManager {
-    loaded_libs: HashMap<String, Library>,
-    functions: HashMap<String, FFIFunction<'static>>,
+// Zeroed memory, aligned for anything C is given
+struct Buffer(Vec<u64>);
+
+// What a call keeps alive until it returns: C strings, the copies its pointers point
+// to, and the trampolines lent out for its callbacks
+#[derive(Default)]
+struct Call {
+    strings: Vec<CString>,
+    copies: Vec<(FFIType, Buffer)>,
+    slots: Vec<usize>,
+}
+
+// A Crabby function lent to C, and the C signature it is called with
+struct Slot {
+    function: Value,
+    params: Vec<FFIType>,
+    returns: FFIType,
+}
+
+thread_local! {
+    // Libraries stay open for as long as the program runs, and each one is opened once
+    static LIBRARIES: RefCell<HashMap<Option<String>, Rc<Library>>> = RefCell::new(HashMap::new());
+    static SLOTS: RefCell<Vec<Option<Rc<Slot>>>> = RefCell::new(vec![None; CALLBACK_SLOTS]);
+    // Set while an `extern` function runs, for the trampolines
+    static CALLER: Cell<Option<*mut Caller<'static>>> = const { Cell::new(None) };
+    // The first error of a callback, reported once the C function returns
+    static CALLBACK_ERROR: RefCell<Option<CrabbyError>> = const { RefCell::new(None) };
 }
 
-impl FFIManager {
-    pub fn new() -> Self {
-        Self {
-            loaded_libs: HashMap::new(),
-            functions: HashMap::new(),
+// Opens `path`, or the running program when there is none
+pub fn open(path: Option<&str>) -> Result<Rc<Library>, String> {
+    let key = path.map(String::from);
+    if let Some(library) = LIBRARIES.with(|libraries| libraries.borrow().get(&key).cloned()) {
+        return Ok(library);
+    }
+    // Safety: loading a library runs its initializers, which is what `--allow-ffi` trusts
+    let library = match path {
+        Some(path) => {
+            unsafe { Library::new(path) }.map_err(|e| format!("cannot load {:?}: {}", path, e))?
+        }
+        None => this_program()?,
+    };
+    let library = Rc::new(library);
+    LIBRARIES.with(|libraries| libraries.borrow_mut().insert(key, library.clone()));
+    Ok(library)
+}
+
+#[cfg(unix)]
+fn this_program() -> Result<Library, String> {
+    Ok(libloading::os::unix::Library::this().into())
+}
+
+#[cfg(windows)]
+fn this_program() -> Result<Library, String> {
+    libloading::os::windows::Library::this()
+        .map(Library::from)
+        .map_err(|e| format!("cannot look up the running program: {}", e))
+}
+
+impl FFIType {
+    // `i8` to `i64`, `u8` to `u64`, `f32`, `f64`, `bool`, `string`, `void`, the names of
+    // `extern struct`s, `*T` or `ptr<T>` for pointers, `T[N]` for arrays, and
+    // `(T, ...) -> R` for function pointers
+    pub fn from_annotation(
+        annotation: &TypeExpr,
+        structs: &HashMap<String, Rc<StructLayout>>,
+    ) -> Result<Self, CrabbyError> {
+        match annotation {
+            TypeExpr::Named(name) => match name.as_str() {
+                "i8" => Ok(FFIType::I8),
+                "i16" => Ok(FFIType::I16),
+                "i32" | "int" | "Int" => Ok(FFIType::I32),
+                "i64" | "isize" => Ok(FFIType::I64),
+                "u8" => Ok(FFIType::U8),
+                "u16" => Ok(FFIType::U16),
+                "u32" => Ok(FFIType::U32),
+                "u64" | "usize" => Ok(FFIType::U64),
+                "f32" => Ok(FFIType::F32),
+                "f64" | "float" | "Float" | "double" => Ok(FFIType::F64),
+                "string" | "String" | "str" => Ok(FFIType::String),
+                "bool" | "Boolean" => Ok(FFIType::Bool),
+                "void" | "Void" => Ok(FFIType::Void),
+                "ptr" => Ok(FFIType::Pointer(Box::new(FFIType::Void))),
+                name => match structs.get(name) {
+                    Some(layout) => Ok(FFIType::Struct(layout.clone())),
+                    None => Err(unsupported_type(annotation)),
+                },
+            },
+            TypeExpr::Generic { name, arguments } if name == "ptr" && arguments.len() == 1 => {
+                // A pointer to something C only names, like a struct declared later,
+                // is just an address
+                let pointee =
+                    FFIType::from_annotation(&arguments[0], structs).unwrap_or(FFIType::Void);
+                Ok(FFIType::Pointer(Box::new(pointee)))
+            }
+            TypeExpr::Array { element, length } => {
+                let element = FFIType::from_annotation(element, structs)?.sized(annotation)?;
+                Ok(FFIType::Array(Box::new(element), *length))
+            }
+            TypeExpr::Function { params, returns } => {
+                let params = params
+                    .iter()
+                    .map(|param| FFIType::from_annotation(param, structs))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let returns = FFIType::from_annotation(returns, structs)?;
+                // Callbacks take and return what fits in one register
+                let scalar = |ty: &FFIType| {
+                    !matches!(
+                        ty,
+                        FFIType::Array(..) | FFIType::Struct(_) | FFIType::Callback { .. }
+                    )
+                };
+                let ints = params.iter().filter(|ty| !ty.is_float()).count();
+                if !params.iter().chain([&returns]).all(scalar)
+                    || params.contains(&FFIType::Void)
+                    || returns == FFIType::String
+                    || ints > INT_REGISTERS
+                    || params.len() - ints > FLOAT_REGISTERS
+                {
+                    return Err(CrabbyError::TypeError(
+                        None,
+                        format!(
+                            "`{}` can't be a callback: callbacks take numbers, booleans, strings and pointers, and return numbers, booleans or pointers",
+                            annotation
+                        ),
+                    ));
+                }
+                Ok(FFIType::Callback {
+                    params,
+                    returns: Box::new(returns),
+                })
+            }
+            _ => Err(unsupported_type(annotation)),
         }
     }
 
-    pub fn load_library(&mut self, path: &str) -> Result<(), CrabbyError> {
-        unsafe {
-            let lib = Library::new(path).map_err(|e| {
-                CrabbyError::InterpreterError(format!("Failed to load library {}: {}", path, e))
-            })?;
-            self.loaded_libs.insert(path.to_string(), lib);
-            Ok(())
+    fn sized(self, annotation: &TypeExpr) -> Result<Self, CrabbyError> {
+        match self {
+            FFIType::Void => Err(CrabbyError::TypeError(
+                None,
+                format!("`{}` has no size", annotation),
+            )),
+            ty => Ok(ty),
         }
     }
 
-    pub fn register_function(
-        &mut self,
-        lib_path: &str,
-        func_name: &str,
-        arg_types: Vec<FFIType>,
-        return_type: FFIType
-    ) -> Result<(), CrabbyError> {
-        let lib = self.loaded_libs.get(lib_path).ok_or_else(|| {
-            CrabbyError::InterpreterError(format!("Library {} not loaded", lib_path))
-        })?;
+    fn size(&self) -> usize {
+        self.size_align().0
+    }
 
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn()> = lib.get(func_name.as_bytes())
-                .map_err(|e| CrabbyError::InterpreterError(
-                    format!("Failed to load function {}: {}", func_name, e)
-                ))?;
-
-            self.functions.insert(
-                func_name.to_string(),
-                FFIFunction {
-                    lib,
-                    func,
-                    arg_types,
-                    return_type,
-                }
-            );
+    fn size_align(&self) -> (usize, usize) {
+        match self {
+            FFIType::I8 | FFIType::U8 | FFIType::Bool => (1, 1),
+            FFIType::I16 | FFIType::U16 => (2, 2),
+            FFIType::I32 | FFIType::U32 | FFIType::F32 => (4, 4),
+            FFIType::I64 | FFIType::U64 | FFIType::F64 => (8, 8),
+            FFIType::String | FFIType::Pointer(_) | FFIType::Callback { .. } => {
+                (size_of::<usize>(), align_of::<usize>())
+            }
+            FFIType::Void => (0, 1),
+            FFIType::Array(element, length) => {
+                let (size, align) = element.size_align();
+                (size * length, align)
+            }
+            FFIType::Struct(layout) => (layout.size, layout.align),
         }
+    }
 
-        Ok(())
+    fn is_float(&self) -> bool {
+        matches!(self, FFIType::F32 | FFIType::F64)
     }
 
-    pub fn call_function(&self, name: &str, args: Vec<FFIValue>) -> Result<FFIValue, CrabbyError> {
-        let func = self.functions.get(name).ok_or_else(|| {
-            CrabbyError::InterpreterError(format!("Function {} not registered", name))
-        })?;
+    // What "must be ..." says of a wrong argument
+    fn description(&self) -> String {
+        match self {
+            FFIType::F32 | FFIType::F64 => "a float".to_string(),
+            FFIType::Bool => "a boolean".to_string(),
+            FFIType::String => "a string".to_string(),
+            FFIType::Void => "nothing".to_string(),
+            FFIType::Pointer(_) => "a pointer".to_string(),
+            FFIType::Array(element, length) => {
+                format!("an array of at most {} {}", length, element)
+            }
+            FFIType::Struct(layout) => format!("a {}", layout.name),
+            FFIType::Callback { .. } => "a function".to_string(),
+            integer => format!("an integer that fits in {}", integer),
+        }
+    }
 
-        if args.len() != func.arg_types.len() {
-            return Err(CrabbyError::InterpreterError(format!(
-                "Function {} expects {} arguments, got {}",
-                name,
-                func.arg_types.len(),
-                args.len()
-            )));
+    fn integer(&self, value: &Value) -> Result<i64, String> {
+        let (min, max) = match self {
+            FFIType::I8 => (i8::MIN as i128, i8::MAX as i128),
+            FFIType::I16 => (i16::MIN as i128, i16::MAX as i128),
+            FFIType::I32 => (i32::MIN as i128, i32::MAX as i128),
+            FFIType::I64 => (i64::MIN as i128, i64::MAX as i128),
+            FFIType::U8 => (0, u8::MAX as i128),
+            FFIType::U16 => (0, u16::MAX as i128),
+            FFIType::U32 => (0, u32::MAX as i128),
+            _ => (0, u64::MAX as i128),
+        };
+        let n = match value {
+            Value::Integer(n) => Some(*n as i128),
+            Value::BigInt(n) => n.to_i128(),
+            _ => None,
+        };
+        match n {
+            // A `u64` over `i64::MAX` keeps its bits
+            Some(n) if (min..=max).contains(&n) => Ok(n as i64),
+            _ => Err(mismatch(self, value)),
         }
+    }
 
-        let c_args: Vec<_> = args.iter().zip(&func.arg_types)
-            .map(|(arg, ty)| self.convert_to_c_value(arg, ty))
-            .collect::<Result<_, _>>()?;
+    // The bits of a value that goes in an integer register; the strings, copies and
+    // callbacks it needs are kept in `call`
+    fn word(&self, value: &Value, call: &mut Call) -> Result<i64, String> {
+        match (self, value) {
+            (FFIType::Bool, Value::Boolean(b)) => Ok(*b as i64),
+            (FFIType::String | FFIType::Pointer(_) | FFIType::Callback { .. }, Value::Null) => {
+                Ok(0)
+            }
+            (FFIType::String, Value::String(s)) => call.string(s),
+            (FFIType::Pointer(pointee), Value::String(s))
+                if matches!(**pointee, FFIType::I8 | FFIType::U8 | FFIType::Void) =>
+            {
+                call.string(s)
+            }
+            (FFIType::Pointer(_), Value::Integer(address)) => Ok(*address),
+            (FFIType::Pointer(pointee), Value::Array(_) | Value::Instance(_) | Value::Dict(_)) => {
+                let copy = call.copy(pointed(pointee, value)?, value)?;
+                Ok(call.address(copy))
+            }
+            (FFIType::Callback { params, returns }, Value::Lambda(_)) => {
+                call.callback(value, params, returns)
+            }
+            (
+                FFIType::I8
+                | FFIType::I16
+                | FFIType::I32
+                | FFIType::I64
+                | FFIType::U8
+                | FFIType::U16
+                | FFIType::U32
+                | FFIType::U64,
+                _,
+            ) => self.integer(value),
+            _ => Err(mismatch(self, value)),
+        }
+    }
 
-        unsafe {
-            let result = match func.return_type {
-                FFIType::Int => {
-                    let f: Symbol<unsafe extern "C" fn() -> c_int> =
-                        std::mem::transmute(func.func.clone());
-                    FFIValue::Int(f())
-                },
-                FFIType::Float => {
-                    let f: Symbol<unsafe extern "C" fn() -> c_double> =
-                        std::mem::transmute(func.func.clone());
-                    FFIValue::Float(f())
-                },
-                FFIType::String => {
-                    let f: Symbol<unsafe extern "C" fn() -> *const c_char> =
-                        std::mem::transmute(func.func.clone());
-                    let ptr = f();
-                    let cstr = CStr::from_ptr(ptr);
-                    FFIValue::String(CString::from(cstr))
-                },
-                FFIType::Void => {
-                    let f: Symbol<unsafe extern "C" fn()> = func.func.clone();
-                    f();
-                    FFIValue::Void
-                },
-                FFIType::Pointer(_) => {
-                    let f: Symbol<unsafe extern "C" fn() -> *mut c_void> =
-                        std::mem::transmute(func.func.clone());
-                    FFIValue::Pointer(f())
+    // Writes `value` into `memory`, laid out as C would
+    fn write(&self, value: &Value, memory: &mut [u8], call: &mut Call) -> Result<(), String> {
+        match self {
+            FFIType::F32 => {
+                let f = number(self, value)? as f32;
+                memory[..4].copy_from_slice(&f.to_ne_bytes());
+            }
+            FFIType::F64 => memory[..8].copy_from_slice(&number(self, value)?.to_ne_bytes()),
+            FFIType::Array(element, length) => {
+                let items = match value {
+                    Value::Array(items) if items.len() <= *length => items,
+                    _ => return Err(mismatch(self, value)),
+                };
+                let size = element.size();
+                for (i, item) in items.iter().enumerate() {
+                    element
+                        .write(item, &mut memory[i * size..(i + 1) * size], call)
+                        .map_err(|e| format!("has an item that {}", e))?;
+                }
+            }
+            FFIType::Struct(layout) => {
+                let fields = match value {
+                    Value::Instance(instance) if instance.type_name == layout.name => {
+                        &instance.fields
+                    }
+                    Value::Dict(entries) => entries,
+                    _ => return Err(mismatch(self, value)),
+                };
+                for (name, ty, offset) in &layout.fields {
+                    match fields.iter().find(|(field, _)| field == name) {
+                        // Fields left out are zero
+                        None | Some((_, Value::Null)) => {}
+                        Some((_, value)) => ty
+                            .write(value, &mut memory[*offset..offset + ty.size()], call)
+                            .map_err(|e| format!("has a field `{}` that {}", name, e))?,
+                    }
                 }
-            };
+            }
+            ty => {
+                let word = ty.word(value, call)?;
+                match ty.size() {
+                    1 => memory[0] = word as u8,
+                    2 => memory[..2].copy_from_slice(&(word as u16).to_ne_bytes()),
+                    4 => memory[..4].copy_from_slice(&(word as u32).to_ne_bytes()),
+                    _ => memory[..8].copy_from_slice(&word.to_ne_bytes()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Reads a value laid out as C would from `memory`
+    fn read(&self, memory: &[u8]) -> Value {
+        match self {
+            FFIType::I8 => Value::Integer(memory[0] as i8 as i64),
+            FFIType::U8 => Value::Integer(memory[0] as i64),
+            FFIType::Bool => Value::Boolean(memory[0] != 0),
+            FFIType::I16 => Value::Integer(i16::from_ne_bytes(bytes(memory)) as i64),
+            FFIType::U16 => Value::Integer(u16::from_ne_bytes(bytes(memory)) as i64),
+            FFIType::I32 => Value::Integer(i32::from_ne_bytes(bytes(memory)) as i64),
+            FFIType::U32 => Value::Integer(u32::from_ne_bytes(bytes(memory)) as i64),
+            FFIType::I64 => Value::Integer(i64::from_ne_bytes(bytes(memory))),
+            FFIType::U64 => unsigned(u64::from_ne_bytes(bytes(memory))),
+            FFIType::F32 => Value::Float(f32::from_ne_bytes(bytes(memory)) as f64),
+            FFIType::F64 => Value::Float(f64::from_ne_bytes(bytes(memory))),
+            FFIType::Void => Value::Void,
+            FFIType::String | FFIType::Pointer(_) | FFIType::Callback { .. } => {
+                self.read_word(usize::from_ne_bytes(bytes(memory)) as i64)
+            }
+            FFIType::Array(element, length) => {
+                let size = element.size();
+                Value::Array(
+                    (0..*length)
+                        .map(|i| element.read(&memory[i * size..]))
+                        .collect(),
+                )
+            }
+            FFIType::Struct(layout) => Value::Instance(Instance {
+                type_name: layout.name.clone(),
+                variant: None,
+                fields: layout
+                    .fields
+                    .iter()
+                    .map(|(name, ty, offset)| (name.clone(), ty.read(&memory[*offset..])))
+                    .collect(),
+            }),
+        }
+    }
 
-            Ok(result)
+    // The value of an integer register holding this type; C leaves the bits above
+    // narrow types undefined
+    fn read_word(&self, word: i64) -> Value {
+        match self {
+            FFIType::I8 => Value::Integer(word as i8 as i64),
+            FFIType::I16 => Value::Integer(word as i16 as i64),
+            FFIType::I32 => Value::Integer(word as i32 as i64),
+            FFIType::U8 => Value::Integer(word as u8 as i64),
+            FFIType::U16 => Value::Integer(word as u16 as i64),
+            FFIType::U32 => Value::Integer(word as u32 as i64),
+            FFIType::U64 => unsigned(word as u64),
+            FFIType::Bool => Value::Boolean(word as u8 != 0),
+            FFIType::String | FFIType::Pointer(_) | FFIType::Callback { .. } if word == 0 => {
+                Value::Null
+            }
+            FFIType::String => {
+                // Safety: the function was declared to hand out a C string
+                let s = unsafe { CStr::from_ptr(word as *const c_char) };
+                Value::String(s.to_string_lossy().into_owned())
+            }
+            _ => Value::Integer(word),
         }
     }
 
-    fn convert_to_c_value(&self, value: &FFIValue, ty: &FFIType) -> Result<FFIValue, CrabbyError> {
-        match (value, ty) {
-            (FFIValue::Int(i), FFIType::Int) => Ok(FFIValue::Int(*i)),
-            (FFIValue::Float(f), FFIType::Float) => Ok(FFIValue::Float(*f)),
-            (FFIValue::String(s), FFIType::String) => Ok(FFIValue::String(s.clone())),
-            (FFIValue::Pointer(p), FFIType::Pointer(_)) => Ok(FFIValue::Pointer(*p)),
-            (FFIValue::Void, FFIType::Void) => Ok(FFIValue::Void),
-            _ => Err(CrabbyError::InterpreterError(
-                format!("Type mismatch in FFI conversion")
-            ))
+    // A callback's argument, from the registers C called it with. Callbacks get what a
+    // pointer points to, so a comparison function for `qsort` can just compare.
+    fn read_registers(&self, registers: &mut Registers) -> Value {
+        match self {
+            FFIType::F32 => {
+                let bits = registers.next_float().to_bits() as u32;
+                Value::Float(f32::from_bits(bits) as f64)
+            }
+            FFIType::F64 => Value::Float(registers.next_float()),
+            FFIType::Pointer(pointee) => match registers.next_int() {
+                0 => Value::Null,
+                address if pointee.size() > 0 => {
+                    // Safety: C promised a pointer to a `pointee`
+                    let memory =
+                        unsafe { std::slice::from_raw_parts(address as *const u8, pointee.size()) };
+                    pointee.read(memory)
+                }
+                address => Value::Integer(address),
+            },
+            ty => ty.read_word(registers.next_int()),
         }
     }
+
+    // What a callback returning `value` leaves in its return register
+    fn returned_word(&self, value: &Value) -> Result<u64, String> {
+        match (self, value) {
+            (FFIType::Void, _) => Ok(0),
+            (FFIType::F64, value) => Ok(number(self, value)?.to_bits()),
+            (FFIType::F32, value) => Ok((number(self, value)? as f32).to_bits() as u64),
+            (FFIType::Bool, Value::Boolean(b)) => Ok(*b as u64),
+            (FFIType::Pointer(_), Value::Integer(address)) => Ok(*address as u64),
+            (FFIType::Pointer(_), Value::Null) => Ok(0),
+            (FFIType::Bool | FFIType::Pointer(_), value) => Err(mismatch(self, value)),
+            (integer, value) => Ok(integer.integer(value)? as u64),
+        }
+    }
+
+    // Every number, boolean and pointer inside, with its offset
+    fn scalars(&self, offset: usize, into: &mut Vec<(usize, FFIType)>) {
+        match self {
+            FFIType::Struct(layout) => {
+                for (_, ty, field_offset) in &layout.fields {
+                    ty.scalars(offset + field_offset, into);
+                }
+            }
+            FFIType::Array(element, length) => {
+                for i in 0..*length {
+                    element.scalars(offset + i * element.size(), into);
+                }
+            }
+            scalar => into.push((offset, scalar.clone())),
+        }
+    }
+}
+
+impl fmt::Display for FFIType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FFIType::I8 => write!(f, "i8"),
+            FFIType::I16 => write!(f, "i16"),
+            FFIType::I32 => write!(f, "i32"),
+            FFIType::I64 => write!(f, "i64"),
+            FFIType::U8 => write!(f, "u8"),
+            FFIType::U16 => write!(f, "u16"),
+            FFIType::U32 => write!(f, "u32"),
+            FFIType::U64 => write!(f, "u64"),
+            FFIType::F32 => write!(f, "f32"),
+            FFIType::F64 => write!(f, "f64"),
+            FFIType::Bool => write!(f, "bool"),
+            FFIType::String => write!(f, "string"),
+            FFIType::Void => write!(f, "void"),
+            FFIType::Pointer(pointee) => write!(f, "*{}", pointee),
+            FFIType::Array(element, length) => write!(f, "{}[{}]", element, length),
+            FFIType::Struct(layout) => write!(f, "{}", layout.name),
+            FFIType::Callback { params, returns } => {
+                let params: Vec<String> = params.iter().map(|param| param.to_string()).collect();
+                write!(f, "({}) -> {}", params.join(", "), returns)
+            }
+        }
+    }
+}
+
+fn unsupported_type(annotation: &TypeExpr) -> CrabbyError {
+    CrabbyError::TypeError(
+        None,
+        format!("`{}` can't be passed to or returned from C", annotation),
+    )
+}
+
+fn mismatch(ty: &FFIType, value: &Value) -> String {
+    format!("must be {}, got {}", ty.description(), value.type_name())
+}
+
+fn number(ty: &FFIType, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Float(f) => Ok(*f),
+        Value::Integer(n) => Ok(*n as f64),
+        _ => Err(mismatch(ty, value)),
+    }
 }
 
-fn parse_ffi_type(type_str: &str) -> Result<FFIType, CrabbyError> {
-    match type_str {
-        "int" => Ok(FFIType::Int),
-        "float" => Ok(FFIType::Float),
-        "string" => Ok(FFIType::String),
-        "void" => Ok(FFIType::Void),
-        s if s.starts_with("ptr<") && s.ends_with(">") => {
-            let inner = &s[4..s.len()-1];
-            Ok(FFIType::Pointer(Box::new(parse_ffi_type(inner)?)))
+fn unsigned(n: u64) -> Value {
+    match i64::try_from(n) {
+        Ok(n) => Value::Integer(n),
+        Err(_) => Value::BigInt(BigInt::from(n)),
+    }
+}
+
+fn bytes<const N: usize>(memory: &[u8]) -> [u8; N] {
+    memory[..N]
+        .try_into()
+        .expect("memory holds the whole value")
+}
+
+// What a pointer to `pointee` given `value` points to; an array stands for its first
+// item, as in C
+fn pointed(pointee: &FFIType, value: &Value) -> Result<FFIType, String> {
+    match (pointee, value) {
+        (FFIType::Array(..), _) => Ok(pointee.clone()),
+        (FFIType::Void, _) => Err(format!(
+            "can't point to a copy of {} through *void",
+            value.type_name()
+        )),
+        (_, Value::Array(items)) => Ok(FFIType::Array(Box::new(pointee.clone()), items.len())),
+        _ => Ok(pointee.clone()),
+    }
+}
+
+impl StructLayout {
+    pub fn new(
+        name: &str,
+        fields: &[ExternField],
+        structs: &HashMap<String, Rc<StructLayout>>,
+    ) -> Result<Self, CrabbyError> {
+        let mut layout = Self {
+            name: name.to_string(),
+            fields: Vec::new(),
+            size: 0,
+            align: 1,
+        };
+        for field in fields {
+            let ty = FFIType::from_annotation(&field.type_annotation, structs)?
+                .sized(&field.type_annotation)?;
+            let (size, align) = ty.size_align();
+            let offset = layout.size.next_multiple_of(align);
+            layout.fields.push((field.name.clone(), ty, offset));
+            layout.size = offset + size;
+            layout.align = layout.align.max(align);
+        }
+        layout.size = layout.size.next_multiple_of(layout.align);
+        Ok(layout)
+    }
+
+    // Eight bytes to a register, a float register when they only hold floats; bigger
+    // structs go on the stack, which calls can't use
+    #[cfg(not(target_arch = "aarch64"))]
+    fn passing(self: &Rc<Self>) -> Option<Passing> {
+        if self.size > 16 {
+            return None;
+        }
+        let mut scalars = Vec::new();
+        FFIType::Struct(self.clone()).scalars(0, &mut scalars);
+        let parts = (0..self.size.div_ceil(8))
+            .map(|i| Part {
+                float: scalars
+                    .iter()
+                    .filter(|(offset, _)| offset / 8 == i)
+                    .all(|(_, ty)| ty.is_float()),
+                offset: i * 8,
+                size: (self.size - i * 8).min(8),
+            })
+            .collect();
+        Some(Passing::Registers(parts))
+    }
+
+    // Up to four floats of one type get a float register each, other structs of up to
+    // sixteen bytes go in integer registers eight bytes at a time, and bigger ones are
+    // copied and passed by pointer
+    #[cfg(target_arch = "aarch64")]
+    fn passing(self: &Rc<Self>) -> Option<Passing> {
+        let mut scalars = Vec::new();
+        FFIType::Struct(self.clone()).scalars(0, &mut scalars);
+        if let Some((_, first)) = scalars.first()
+            && first.is_float()
+            && scalars.len() <= 4
+            && scalars.iter().all(|(_, ty)| ty == first)
+        {
+            let parts = scalars
+                .iter()
+                .map(|(offset, ty)| Part {
+                    float: true,
+                    offset: *offset,
+                    size: ty.size(),
+                })
+                .collect();
+            return Some(Passing::Registers(parts));
         }
-        _ => Err(CrabbyError::InterpreterError(format!("Unknown FFI type: {}", type_str)))
+        if self.size > 16 {
+            return Some(Passing::Reference);
+        }
+        let parts = (0..self.size.div_ceil(8))
+            .map(|i| Part {
+                float: false,
+                offset: i * 8,
+                size: (self.size - i * 8).min(8),
+            })
+            .collect();
+        Some(Passing::Registers(parts))
+    }
+}
+
+impl Registers {
+    fn push_int(&mut self, word: i64) {
+        self.ints[self.int_count] = word;
+        self.int_count += 1;
+    }
+
+    fn push_float(&mut self, word: f64) {
+        self.floats[self.float_count] = word;
+        self.float_count += 1;
+    }
+
+    fn next_int(&mut self) -> i64 {
+        self.int_count += 1;
+        self.ints[self.int_count - 1]
+    }
+
+    fn next_float(&mut self) -> f64 {
+        self.float_count += 1;
+        self.floats[self.float_count - 1]
+    }
+}
+
+impl Buffer {
+    fn new(size: usize) -> Self {
+        Buffer(vec![0; size.div_ceil(8).max(1)])
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // Safety: the `u64`s are plain bytes
+        unsafe { std::slice::from_raw_parts(self.0.as_ptr() as *const u8, self.0.len() * 8) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        // Safety: as above
+        unsafe { std::slice::from_raw_parts_mut(self.0.as_mut_ptr() as *mut u8, self.0.len() * 8) }
+    }
+
+    // The `size` bytes at `offset`, as the low bytes of a register
+    fn word(&self, offset: usize, size: usize) -> u64 {
+        let mut word = [0; 8];
+        word[..size].copy_from_slice(&self.bytes()[offset..offset + size]);
+        u64::from_le_bytes(word)
+    }
+
+    fn set_word(&mut self, offset: usize, size: usize, word: u64) {
+        self.bytes_mut()[offset..offset + size].copy_from_slice(&word.to_le_bytes()[..size]);
     }
 }
 
-pub fn register_ffi_builtins(interpreter: &mut interpreter::Interpreter) {
-    // Create separate FFIManager instance for each function
-    let ffi_manager1 = std::sync::Arc::new(std::sync::Mutex::new(FFIManager::new()));
-    let ffi_manager2 = ffi_manager1.clone();
+impl Call {
+    fn string(&mut self, s: &str) -> Result<i64, String> {
+        let s = CString::new(s).map_err(|_| "contains a NUL byte".to_string())?;
+        let address = s.as_ptr() as i64;
+        self.strings.push(s);
+        Ok(address)
+    }
 
-    interpreter.add_builtin("loadlib", {
-        let ffi_manager = ffi_manager1;
-        move |args| {
-            if args.len() != 1 {
-                return Err(CrabbyError::InterpreterError("load_library expects a library path".into()));
+    // Copies `value` into memory laid out as `ty`, and returns which copy it is
+    fn copy(&mut self, ty: FFIType, value: &Value) -> Result<usize, String> {
+        let mut buffer = Buffer::new(ty.size());
+        ty.write(value, buffer.bytes_mut(), self)?;
+        self.copies.push((ty, buffer));
+        Ok(self.copies.len() - 1)
+    }
+
+    fn address(&mut self, copy: usize) -> i64 {
+        self.copies[copy].1.0.as_mut_ptr() as i64
+    }
+
+    fn read(&self, copy: usize) -> Value {
+        let (ty, buffer) = &self.copies[copy];
+        ty.read(buffer.bytes())
+    }
+
+    // Lends `function` a trampoline, and returns its address
+    fn callback(
+        &mut self,
+        function: &Value,
+        params: &[FFIType],
+        returns: &FFIType,
+    ) -> Result<i64, String> {
+        let slot = SLOTS.with(|slots| {
+            let mut slots = slots.borrow_mut();
+            let free = slots.iter().position(Option::is_none)?;
+            slots[free] = Some(Rc::new(Slot {
+                function: function.clone(),
+                params: params.to_vec(),
+                returns: returns.clone(),
+            }));
+            Some(free)
+        });
+        let slot = slot.ok_or_else(|| {
+            format!(
+                "can't be passed while C holds {} other callbacks",
+                CALLBACK_SLOTS
+            )
+        })?;
+        self.slots.push(slot);
+        Ok(trampoline(slot, returns.is_float()))
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        SLOTS.with(|slots| {
+            let mut slots = slots.borrow_mut();
+            for slot in &self.slots {
+                slots[*slot] = None;
             }
-            let lib_path = match &args[0] {
-                Value::String(s) => s.clone(),
-                _ => return Err(CrabbyError::InterpreterError("First argument must be library path".into())),
-            };
-            ffi_manager.lock().unwrap().load_library(&lib_path)?;
-            Ok(Value::Void)
+        });
+    }
+}
+
+impl ForeignFunction {
+    pub fn load(
+        library: Option<&str>,
+        name: &str,
+        params: &[Parameter],
+        return_type: &Option<TypeExpr>,
+        structs: &HashMap<String, Rc<StructLayout>>,
+    ) -> Result<Self, CrabbyError> {
+        if !SUPPORTED {
+            return Err(CrabbyError::RuntimeError(
+                "calling C functions isn't supported on this platform yet".to_string(),
+            ));
+        }
+        let unsupported = |message: String| CrabbyError::TypeError(None, message);
+
+        let params = params
+            .iter()
+            .map(|param| {
+                let annotation = param.type_annotation.as_ref().ok_or_else(|| {
+                    CrabbyError::TypeError(
+                        Some(param.span),
+                        format!("parameter `{}` of `{}` needs a C type", param.name, name),
+                    )
+                })?;
+                let ty = match FFIType::from_annotation(annotation, structs)? {
+                    // C passes arrays as a pointer to their first item
+                    array @ FFIType::Array(..) => FFIType::Pointer(Box::new(array)),
+                    ty => ty.sized(annotation)?,
+                };
+                Ok((param.name.clone(), ty))
+            })
+            .collect::<Result<Vec<_>, CrabbyError>>()?;
+        let returns = match return_type {
+            Some(return_type) => FFIType::from_annotation(return_type, structs)?,
+            None => FFIType::Void,
+        };
+
+        let (mut ints, mut floats) = (0, 0);
+        for (param, ty) in &params {
+            match ty {
+                FFIType::F32 | FFIType::F64 => floats += 1,
+                FFIType::Struct(layout) => match layout.passing() {
+                    Some(Passing::Registers(parts)) => {
+                        let float_parts = parts.iter().filter(|part| part.float).count();
+                        floats += float_parts;
+                        ints += parts.len() - float_parts;
+                    }
+                    Some(Passing::Reference) => ints += 1,
+                    None => {
+                        return Err(unsupported(format!(
+                            "`{}` is too big to pass by value; make `{}` of `{}` a pointer",
+                            layout.name, param, name
+                        )));
+                    }
+                },
+                _ => ints += 1,
+            }
+        }
+        if ints > INT_REGISTERS || floats > FLOAT_REGISTERS {
+            return Err(unsupported(format!(
+                "`{}` takes more than {} integer or {} float arguments, which isn't supported",
+                name, INT_REGISTERS, FLOAT_REGISTERS
+            )));
+        }
+        match &returns {
+            FFIType::Array(..) => {
+                return Err(unsupported(format!(
+                    "C functions can't return arrays; make `{}` return a pointer",
+                    name
+                )));
+            }
+            FFIType::Struct(layout) if !matches!(layout.passing(), Some(Passing::Registers(_))) => {
+                return Err(unsupported(format!(
+                    "`{}` is too big to return by value from `{}`",
+                    layout.name, name
+                )));
+            }
+            _ => {}
+        }
+
+        let library = open(library).map_err(CrabbyError::RuntimeError)?;
+        // Safety: the symbol is only called through the signature it was declared with
+        let address = unsafe { library.get::<*const c_void>(name.as_bytes()) }
+            .map(|symbol| *symbol)
+            .map_err(|e| CrabbyError::RuntimeError(format!("cannot find `{}`: {}", name, e)))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            params,
+            returns,
+            address,
+            _library: library,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Returns what the function returned, and for each borrowed argument, what the
+    // function left in its copy
+    pub fn call(
+        &self,
+        args: Vec<Argument>,
+        caller: &mut Caller<'_>,
+    ) -> Result<(Value, Vec<Option<Value>>), CrabbyError> {
+        if args.len() != self.params.len() {
+            return Err(CrabbyError::TypeError(
+                None,
+                format!(
+                    "`{}` takes {} argument{}, got {}",
+                    self.name,
+                    self.params.len(),
+                    if self.params.len() == 1 { "" } else { "s" },
+                    args.len()
+                ),
+            ));
+        }
+
+        let mut call = Call::default();
+        let mut registers = Registers::default();
+        let mut copies = Vec::new();
+        for (arg, (param, ty)) in args.iter().zip(&self.params) {
+            let copy = self.pass(arg, ty, &mut registers, &mut call).map_err(|e| {
+                CrabbyError::TypeError(
+                    None,
+                    format!("argument `{}` of `{}` {}", param, self.name, e),
+                )
+            })?;
+            copies.push(copy);
         }
-    });
 
-    interpreter.add_builtin("extern", {
-        let ffi_manager = ffi_manager2;
-        move |args| {
-            if args.len() != 3 {
-                return Err(CrabbyError::InterpreterError("extern_function expects library path, function name, and type signature".into()));
-            }
-
-            let lib_path = match &args[0] {
-                Value::String(s) => s.clone(),
-                _ => return Err(CrabbyError::InterpreterError("First argument must be library path".into())),
-            };
-
-            let func_name = match &args[1] {
-                Value::String(s) => s.clone(),
-                _ => return Err(CrabbyError::InterpreterError("Second argument must be function name".into())),
-            };
-
-            let type_sig = match &args[2] {
-                Value::Array(types) => {
-                    let mut arg_types = Vec::new();
-                    let mut return_type = FFIType::Void;
-
-                    for (i, t) in types.iter().enumerate() {
-                        if let Value::String(type_str) = t {
-                            if i == types.len() - 1 {
-                                return_type = parse_ffi_type(type_str)?;
-                            } else {
-                                arg_types.push(parse_ffi_type(type_str)?);
+        let caller: *mut Caller<'_> = caller;
+        // Safety: the trampolines only use `caller` while this call runs, and a callback
+        // calling C again puts back what it replaced
+        let caller =
+            unsafe { std::mem::transmute::<*mut Caller<'_>, *mut Caller<'static>>(caller) };
+        let previous = CALLER.with(|current| current.replace(Some(caller)));
+        // Safety: `--allow-ffi` trusts the declaration to match the C function
+        let result = unsafe { self.returned(&registers) };
+        CALLER.with(|current| current.set(previous));
+        if let Some(error) = CALLBACK_ERROR.with(|error| error.borrow_mut().take()) {
+            return Err(error);
+        }
+
+        let copies = copies
+            .into_iter()
+            .map(|copy| copy.map(|copy| call.read(copy)))
+            .collect();
+        Ok((result, copies))
+    }
+
+    // Puts `arg` in the registers, and returns its copy if it was borrowed
+    fn pass(
+        &self,
+        arg: &Argument,
+        ty: &FFIType,
+        registers: &mut Registers,
+        call: &mut Call,
+    ) -> Result<Option<usize>, String> {
+        let (value, borrowed) = match arg {
+            Argument::Value(value) => (value, false),
+            Argument::Borrowed(value) => (value, true),
+        };
+        match ty {
+            FFIType::F64 => registers.push_float(number(ty, value)?),
+            FFIType::F32 => {
+                // In the low bits of the register
+                let bits = (number(ty, value)? as f32).to_bits() as u64;
+                registers.push_float(f64::from_bits(bits));
+            }
+            FFIType::Struct(layout) => {
+                let copy = call.copy(ty.clone(), value)?;
+                match layout.passing() {
+                    Some(Passing::Registers(parts)) => {
+                        let buffer = &call.copies[copy].1;
+                        for part in parts {
+                            let word = buffer.word(part.offset, part.size);
+                            match part.float {
+                                true => registers.push_float(f64::from_bits(word)),
+                                false => registers.push_int(word as i64),
                             }
-                        } else {
-                            return Err(CrabbyError::InterpreterError("Type signature must be strings".into()));
                         }
                     }
-                    (arg_types, return_type)
+                    _ => registers.push_int(call.address(copy)),
                 }
-                _ => return Err(CrabbyError::InterpreterError("Third argument must be array of type signatures".into())),
-            };
+            }
+            // `&x` points to a copy of `x`, whatever it holds
+            FFIType::Pointer(pointee) if borrowed => {
+                let copy = call.copy(pointed(pointee, value)?, value)?;
+                registers.push_int(call.address(copy));
+                return Ok(Some(copy));
+            }
+            ty => registers.push_int(ty.word(value, call)?),
+        }
+        Ok(None)
+    }
 
-            ffi_manager.lock().unwrap().register_function(&lib_path, &func_name, type_sig.0, type_sig.1)?;
-            Ok(Value::Void)
+    // Safety: as for `invoke`
+    unsafe fn returned(&self, registers: &Registers) -> Value {
+        let address = self.address;
+        unsafe {
+            match &self.returns {
+                FFIType::F64 => Value::Float(invoke::<f64>(address, registers)),
+                FFIType::F32 => Value::Float(invoke::<f32>(address, registers) as f64),
+                FFIType::Void => {
+                    invoke::<()>(address, registers);
+                    Value::Void
+                }
+                FFIType::Struct(layout) => {
+                    let Some(Passing::Registers(parts)) = layout.passing() else {
+                        unreachable!("`load` only allows structs returned in registers");
+                    };
+                    let words = invoke_parts(address, registers, &parts);
+                    let mut buffer = Buffer::new(layout.size);
+                    for (part, word) in parts.iter().zip(words) {
+                        buffer.set_word(part.offset, part.size, word);
+                    }
+                    self.returns.read(buffer.bytes())
+                }
+                ty => ty.read_word(invoke::<i64>(address, registers)),
+            }
         }
+    }
+}
+
+// Runs the Crabby function lent `slot` with the registers C called it with, and returns
+// what goes in the return register
+#[cfg_attr(
+    not(any(all(target_arch = "x86_64", not(windows)), target_arch = "aarch64")),
+    allow(dead_code)
+)]
+fn run_callback(slot: usize, ints: [i64; INT_REGISTERS], floats: [f64; FLOAT_REGISTERS]) -> u64 {
+    // Once a callback failed, the others do nothing until C returns
+    if CALLBACK_ERROR.with(|error| error.borrow().is_some()) {
+        return 0;
+    }
+    let Some(slot) = SLOTS.with(|slots| slots.borrow()[slot].clone()) else {
+        return 0;
+    };
+    let Some(caller) = CALLER.with(Cell::get) else {
+        return 0;
+    };
+    let mut registers = Registers {
+        ints,
+        floats,
+        ..Registers::default()
+    };
+    let args = slot
+        .params
+        .iter()
+        .map(|ty| ty.read_registers(&mut registers))
+        .collect();
+    // Safety: `CALLER` is only set while the call that lent the slot runs
+    let result = unsafe { (*caller)(&slot.function, args) }.and_then(|value| {
+        slot.returns
+            .returned_word(&value)
+            .map_err(|e| CrabbyError::TypeError(None, format!("a callback's result {}", e)))
     });
+    match result {
+        Ok(word) => word,
+        Err(e) => {
+            CALLBACK_ERROR.with(|error| *error.borrow_mut() = Some(e));
+            0
+        }
+    }
+}
+
+// A struct returned in two registers; only C builds one
+#[allow(dead_code)]
+#[repr(C)]
+struct Pair<T, U>(T, U);
+
+// Up to four floats of one type, returned in float registers
+#[cfg(target_arch = "aarch64")]
+#[allow(dead_code)]
+#[repr(C)]
+struct Floats<T, const N: usize>([T; N]);
+
+// Generates `invoke`, which calls a C function with every argument register, and the
+// trampolines, which C calls with every argument register
+macro_rules! calling_convention {
+    ($($int:ident),*; $($float:ident),*) => {
+        const SUPPORTED: bool = true;
+
+        type Function<R> = unsafe extern "C" fn($($int: i64,)* $($float: f64),*) -> R;
+
+        // Safety: `address` is a C function whose integer and float parameters are
+        // those of `registers`, in order, and which returns an `R`
+        unsafe fn invoke<R>(address: *const c_void, registers: &Registers) -> R {
+            let function = unsafe { std::mem::transmute::<*const c_void, Function<R>>(address) };
+            let [$($int),*] = registers.ints;
+            let [$($float),*] = registers.floats;
+            unsafe { function($($int,)* $($float),*) }
+        }
+
+        unsafe extern "C" fn int_trampoline<const SLOT: usize>(
+            $($int: i64,)* $($float: f64),*
+        ) -> i64 {
+            run_callback(SLOT, [$($int),*], [$($float),*]) as i64
+        }
+
+        unsafe extern "C" fn float_trampoline<const SLOT: usize>(
+            $($int: i64,)* $($float: f64),*
+        ) -> f64 {
+            f64::from_bits(run_callback(SLOT, [$($int),*], [$($float),*]))
+        }
+
+        // The address of the trampoline for `slot`
+        fn trampoline(slot: usize, float: bool) -> i64 {
+            const INT: [Function<i64>; CALLBACK_SLOTS] = [
+                int_trampoline::<0>,
+                int_trampoline::<1>,
+                int_trampoline::<2>,
+                int_trampoline::<3>,
+                int_trampoline::<4>,
+                int_trampoline::<5>,
+                int_trampoline::<6>,
+                int_trampoline::<7>,
+            ];
+            const FLOAT: [Function<f64>; CALLBACK_SLOTS] = [
+                float_trampoline::<0>,
+                float_trampoline::<1>,
+                float_trampoline::<2>,
+                float_trampoline::<3>,
+                float_trampoline::<4>,
+                float_trampoline::<5>,
+                float_trampoline::<6>,
+                float_trampoline::<7>,
+            ];
+            match float {
+                true => FLOAT[slot] as usize as i64,
+                false => INT[slot] as usize as i64,
+            }
+        }
+    };
+}
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+calling_convention!(i0, i1, i2, i3, i4, i5; f0, f1, f2, f3, f4, f5, f6, f7);
+#[cfg(target_arch = "aarch64")]
+calling_convention!(i0, i1, i2, i3, i4, i5, i6, i7; f0, f1, f2, f3, f4, f5, f6, f7);
+
+// The registers a struct comes back in, one for each of its parts
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+unsafe fn invoke_parts(address: *const c_void, registers: &Registers, parts: &[Part]) -> Vec<u64> {
+    let floats: Vec<bool> = parts.iter().map(|part| part.float).collect();
+    unsafe {
+        match floats.as_slice() {
+            [false] => vec![invoke::<i64>(address, registers) as u64],
+            [true] => vec![invoke::<f64>(address, registers).to_bits()],
+            [false, false] => {
+                let Pair(a, b) = invoke::<Pair<i64, i64>>(address, registers);
+                vec![a as u64, b as u64]
+            }
+            [false, true] => {
+                let Pair(a, b) = invoke::<Pair<i64, f64>>(address, registers);
+                vec![a as u64, b.to_bits()]
+            }
+            [true, false] => {
+                let Pair(a, b) = invoke::<Pair<f64, i64>>(address, registers);
+                vec![a.to_bits(), b as u64]
+            }
+            [true, true] => {
+                let Pair(a, b) = invoke::<Pair<f64, f64>>(address, registers);
+                vec![a.to_bits(), b.to_bits()]
+            }
+            _ => {
+                invoke::<()>(address, registers);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn invoke_parts(address: *const c_void, registers: &Registers, parts: &[Part]) -> Vec<u64> {
+    macro_rules! floats {
+        ($type:ty, $count:literal) => {{
+            let Floats(values) = invoke::<Floats<$type, $count>>(address, registers);
+            values.iter().map(|value| value.to_bits() as u64).collect()
+        }};
+    }
+    unsafe {
+        match (
+            parts.first().map(|part| (part.float, part.size)),
+            parts.len(),
+        ) {
+            (Some((true, 4)), 1) => floats!(f32, 1),
+            (Some((true, 4)), 2) => floats!(f32, 2),
+            (Some((true, 4)), 3) => floats!(f32, 3),
+            (Some((true, 4)), 4) => floats!(f32, 4),
+            (Some((true, _)), 1) => floats!(f64, 1),
+            (Some((true, _)), 2) => floats!(f64, 2),
+            (Some((true, _)), 3) => floats!(f64, 3),
+            (Some((true, _)), 4) => floats!(f64, 4),
+            (Some((false, _)), 1) => vec![invoke::<i64>(address, registers) as u64],
+            (Some((false, _)), 2) => {
+                let Pair(a, b) = invoke::<Pair<i64, i64>>(address, registers);
+                vec![a as u64, b as u64]
+            }
+            _ => {
+                invoke::<()>(address, registers);
+                Vec::new()
+            }
+        }
+    }
+}
+
+// Elsewhere `ForeignFunction::load` refuses every function, so nothing is called
+#[cfg(not(any(all(target_arch = "x86_64", not(windows)), target_arch = "aarch64")))]
+const SUPPORTED: bool = false;
+
+#[cfg(not(any(all(target_arch = "x86_64", not(windows)), target_arch = "aarch64")))]
+unsafe fn invoke<R>(_address: *const c_void, _registers: &Registers) -> R {
+    unreachable!("no C function is loaded on this platform")
+}
+
+#[cfg(not(any(all(target_arch = "x86_64", not(windows)), target_arch = "aarch64")))]
+unsafe fn invoke_parts(
+    _address: *const c_void,
+    _registers: &Registers,
+    _parts: &[Part],
+) -> Vec<u64> {
+    unreachable!("no C function is loaded on this platform")
+}
+
+#[cfg(not(any(all(target_arch = "x86_64", not(windows)), target_arch = "aarch64")))]
+fn trampoline(_slot: usize, _float: bool) -> i64 {
+    unreachable!("no C function is loaded on this platform")
 }
@@ -1,156 +1,149 @@
 // Crabby's Memory Management and Safety
 // By using lifetimes, Ownership and Borrowings. It makes Crabby memory safety as possible.
 // However, Memory safeties aren't always perfect, and Crabby is still in early development.
-
-use crate::ast::{Expression, Program, Statement};
-use crate::utils::CrabbyError;
+//
+// The checker runs before a program does. Every variable owns its value:
+//
+//     let b = move a     // `a` can't be used anymore
+//     let r = &a         // shared borrows, as many as needed, or `ref a`
+//     let mut m = 1
+//     let w = &mut m     // one mutable borrow, and `m` is off limits while it lasts
+//
+// A borrow held by a variable lasts until that variable's last use; any other
+// borrow lasts until the end of its statement. Variables are scoped like the
+// interpreter does it, per function. Numbers, booleans and shared borrows are
+// copied rather than moved. In strict mode, `let b = a` moves `a` like `move a`
+// does, and only `let mut` and `var` variables can have their elements assigned.
+
+use crate::ast::{Expression, FStringPart, Parameter, Program, Statement};
+use crate::utils::{Span, render_diagnostics};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Lifetime {
-    Static,
-    Local {
-        scope_depth: u32,
-    },
-    Borrowed {
-        from: String,
-        scope_depth: u32,
-        is_mutable: bool,
-    },
+    Static, // top-level variables, alive until the program ends
+    Local { scope_depth: usize },
 }
 
 #[derive(Debug, Clone)]
 struct OwnershipInfo {
+    name: String,
     lifetime: Lifetime,
-    borrowed_count: u32,
-    mut_borrowed: bool,
-    initialized: bool,
+    mutable: bool,
+    copy: bool, // numbers and the like are copied instead of moved
+    declared: Span,
+    moved: Option<Span>,
+}
+
+// A borrow of a variable, and the variables holding it
+#[derive(Debug, Clone)]
+struct Loan {
+    binding: usize,
+    mutable: bool,
+    span: Span,
+    statement: usize,
+    holders: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Access {
+    binding: usize,
+    kind: AccessKind,
+    span: Span,
+    statement: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AccessKind {
+    Read,
+    Assign,
+    Move,
+    Borrow { mutable: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnershipError {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub label: String,
+    pub related: Vec<(Span, String)>, // the borrow, move or declaration it conflicts with
 }
 
 pub struct MemoryChecker {
-    ownership_map: HashMap<String, OwnershipInfo>,
-    current_scope: u32,
-    moved_variables: Vec<String>,
+    strict: bool,
+    bindings: Vec<OwnershipInfo>,
+    scopes: Vec<HashMap<String, usize>>, // one per function, as in the interpreter
+    loans: Vec<Loan>,
+    accesses: Vec<Access>,
+    statement: usize, // numbers statements, to tell what happens in the same one
+    errors: Vec<OwnershipError>,
 }
 
 impl MemoryChecker {
-    pub fn new() -> Self {
+    pub fn new(strict: bool) -> Self {
         Self {
-            ownership_map: HashMap::new(),
-            current_scope: 0,
-            moved_variables: Vec::new(),
+            strict,
+            bindings: Vec::new(),
+            scopes: Vec::new(),
+            loans: Vec::new(),
+            accesses: Vec::new(),
+            statement: 0,
+            errors: Vec::new(),
         }
     }
 
-    pub fn check_program(&mut self, program: &Program) -> Result<(), CrabbyError> {
+    pub fn check_program(&mut self, program: &Program) -> Vec<OwnershipError> {
+        self.scopes.push(HashMap::new());
         for statement in &program.statements {
-            self.check_statement(statement)?;
+            self.check_statement(statement);
         }
-        Ok(())
+        self.scopes.pop();
+        self.check_loans();
+
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|error| error.span.start);
+        errors
     }
 
-    fn check_statement(&mut self, stmt: &Statement) -> Result<(), CrabbyError> {
+    fn check_statement(&mut self, stmt: &Statement) {
+        self.statement += 1;
         match stmt {
-            Statement::Let { name, value } => {
-                self.check_expression(value)?;
-
-                self.ownership_map.insert(
-                    name.clone(),
-                    OwnershipInfo {
-                        lifetime: Lifetime::Local {
-                            scope_depth: self.current_scope,
-                        },
-                        borrowed_count: 0,
-                        mut_borrowed: false,
-                        initialized: true,
-                    },
-                );
-            }
-
-            Statement::Var { name, value } => {
-                self.check_expression(value)?;
-                self.ownership_map.insert(
-                    name.clone(),
-                    OwnershipInfo {
-                        lifetime: Lifetime::Local {
-                            scope_depth: self.current_scope,
-                        },
-                        borrowed_count: 0,
-                        mut_borrowed: false,
-                        initialized: true,
-                    },
-                );
-            }
+            Statement::Let {
+                name,
+                value,
+                mutable,
+                span,
+                ..
+            } => self.check_binding(name, value, *mutable, *span),
+            Statement::Var {
+                name, value, span, ..
+            } => self.check_binding(name, value, true, *span),
 
             Statement::Block(statements) => {
-                self.current_scope += 1;
                 for stmt in statements {
-                    self.check_statement(stmt)?;
+                    self.check_statement(stmt);
                 }
-                self.cleanup_scope(self.current_scope);
-                self.current_scope -= 1;
             }
 
-            Statement::FunctionDef {
-                name: _,
-                params,
-                body,
-                return_type: _,
-                docstring: _,
-                visibility: _,
-            } => {
-                self.current_scope += 1;
-
-                for param in params {
-                    self.ownership_map.insert(
-                        param.clone(),
-                        OwnershipInfo {
-                            lifetime: Lifetime::Local {
-                                scope_depth: self.current_scope,
-                            },
-                            borrowed_count: 0,
-                            mut_borrowed: false,
-                            initialized: true,
-                        },
-                    );
+            Statement::FunctionDef { params, body, .. }
+            | Statement::FunctionFun { params, body, .. }
+            | Statement::AsyncFunction { params, body, .. } => self.check_function(params, body),
+            Statement::Class { methods, .. } | Statement::Extend { methods, .. } => {
+                for method in methods {
+                    self.check_statement(method);
                 }
-
-                self.check_statement(body)?;
-                self.cleanup_scope(self.current_scope);
-                self.current_scope -= 1;
             }
-
-            Statement::FunctionFun {
-                name: _,
-                params,
-                body,
-                return_type: _,
-                docstring: _,
-                visibility: _,
-            } => {
-                self.current_scope += 1;
-
-                for param in params {
-                    self.ownership_map.insert(
-                        param.clone(),
-                        OwnershipInfo {
-                            lifetime: Lifetime::Local {
-                                scope_depth: self.current_scope,
-                            },
-                            borrowed_count: 0,
-                            mut_borrowed: false,
-                            initialized: true,
-                        },
-                    );
+            Statement::Trait { methods, .. } | Statement::Impl { methods, .. } => {
+                for method in methods {
+                    self.check_function(&method.params, &method.body);
                 }
-
-                self.check_statement(body)?;
-                self.cleanup_scope(self.current_scope);
-                self.current_scope -= 1;
             }
+            Statement::Test { body, .. } => self.check_function(&[], body),
 
             Statement::Expression(expr) => {
-                self.check_expression(expr)?;
+                self.check_expression(expr);
             }
 
             Statement::ArrayAssign {
@@ -158,157 +151,537 @@ impl MemoryChecker {
                 index,
                 value,
             } => {
-                if let Expression::Variable(name) = array {
-                    self.check_mutable_access(name)?;
+                self.check_expression(index);
+                self.check_expression(value);
+                match self.variable(array) {
+                    Some((id, span)) => {
+                        self.check_not_moved(id, span);
+                        if self.strict && !self.bindings[id].mutable {
+                            self.error_immutable(id, span, "assign to an element of `{}`");
+                        }
+                        self.access(id, AccessKind::Assign, span);
+                    }
+                    None => {
+                        self.check_expression(array);
+                    }
                 }
-                self.check_expression(index)?;
-                self.check_expression(value)?;
             }
 
-            Statement::While { condition, body } => {
-                self.check_expression(condition)?;
-                self.current_scope += 1;
-                self.check_statement(body)?;
-                self.cleanup_scope(self.current_scope);
-                self.current_scope -= 1;
+            Statement::While {
+                condition: value,
+                body,
+                ..
+            }
+            | Statement::Loop {
+                count: value, body, ..
+            } => {
+                self.check_expression(value);
+                let before = self.moved();
+                self.check_statement(body);
+                self.check_loop(&before);
+            }
+            Statement::ForIn {
+                variable,
+                iterator,
+                body,
+                span,
+            } => {
+                self.check_expression(iterator);
+                self.declare(variable, false, false, *span);
+                let before = self.moved();
+                self.check_statement(body);
+                self.check_loop(&before);
             }
 
             Statement::If {
                 condition,
                 then_branch,
                 else_branch,
+                span: _,
             } => {
-                self.check_expression(condition)?;
-
-                self.current_scope += 1;
-                self.check_statement(then_branch)?;
-                self.cleanup_scope(self.current_scope);
-                self.current_scope -= 1;
-
+                self.check_expression(condition);
+                let before = self.moved();
+                let mut moved = before.clone();
+                self.check_statement(then_branch);
+                self.end_branch(&before, &mut moved);
                 if let Some(else_stmt) = else_branch {
-                    self.current_scope += 1;
-                    self.check_statement(else_stmt)?;
-                    self.cleanup_scope(self.current_scope);
-                    self.current_scope -= 1;
+                    self.check_statement(else_stmt);
+                    self.end_branch(&before, &mut moved);
+                }
+                self.set_moved(moved);
+            }
+            Statement::Match { value, arms } => {
+                self.check_expression(value);
+                let before = self.moved();
+                let mut moved = before.clone();
+                for arm in arms {
+                    self.check_expression(&arm.body);
+                    self.end_branch(&before, &mut moved);
                 }
+                self.set_moved(moved);
             }
 
-            Statement::Return(expr) => {
-                self.check_expression(expr)?;
+            Statement::Return(expr, _) => {
+                let loans = self.check_expression(expr);
+                self.check_escape(expr, &loans);
+            }
+            Statement::Assert {
+                condition, message, ..
+            } => {
+                self.check_expression(condition);
+                if let Some(message) = message {
+                    self.check_expression(message);
+                }
             }
 
             _ => {}
         }
-        Ok(())
     }
 
-    fn check_expression(&mut self, expr: &Expression) -> Result<(), CrabbyError> {
+    // `let` and `var`; the new variable holds any borrow its value carries
+    fn check_binding(&mut self, name: &str, value: &Expression, mutable: bool, span: Span) {
+        let copy = self.is_copy(value);
+        let loans = match self.variable(value) {
+            // In strict mode, assigning a variable moves its value
+            Some((id, span)) if self.strict && !copy => self.move_binding(id, span),
+            _ => self.check_expression(value),
+        };
+        let id = self.declare(name.trim_start_matches("pub "), mutable, copy, span);
+        for loan in loans {
+            self.loans[loan].holders.push(id);
+        }
+    }
+
+    fn check_function(&mut self, params: &[Parameter], body: &Statement) {
+        for param in params {
+            if let Some(default) = &param.default {
+                self.check_expression(default);
+            }
+        }
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.declare(&param.name, false, false, param.span);
+        }
+        self.check_statement(body);
+        self.scopes.pop();
+    }
+
+    // Checks the expression and returns the borrows its value carries
+    fn check_expression(&mut self, expr: &Expression) -> Vec<usize> {
         match expr {
-            Expression::Variable(name) => {
-                if self.moved_variables.contains(name) {
-                    return Err(CrabbyError::InterpreterError(format!(
-                        "Use of moved variable '{}'",
-                        name
-                    )));
-                }
+            Expression::Variable { name, span } => {
+                let Some(id) = self.lookup(name) else {
+                    return Vec::new();
+                };
+                self.check_not_moved(id, *span);
+                self.access(id, AccessKind::Read, *span);
+                self.held_by(id)
+            }
 
-                if let Some(info) = self.ownership_map.get(name) {
-                    if !info.initialized {
-                        return Err(CrabbyError::InterpreterError(format!(
-                            "Use of uninitialized variable '{}'",
-                            name
-                        )));
-                    }
-                } else {
-                    return Err(CrabbyError::InterpreterError(format!(
-                        "Use of undefined variable '{}'",
-                        name
-                    )));
+            Expression::Borrow {
+                expr,
+                mutable,
+                span,
+            } => {
+                let Some((id, name_span)) = self.variable(expr) else {
+                    return self.check_expression(expr);
+                };
+                // From the `&` to the end of the name
+                let span = &Span {
+                    end: name_span.end,
+                    ..*span
+                };
+                self.check_not_moved(id, *span);
+                if *mutable && !self.bindings[id].mutable {
+                    self.error_immutable(id, *span, "borrow `{}` as mutable");
                 }
+                self.access(id, AccessKind::Borrow { mutable: *mutable }, *span);
+                self.loans.push(Loan {
+                    binding: id,
+                    mutable: *mutable,
+                    span: *span,
+                    statement: self.statement,
+                    holders: Vec::new(),
+                });
+                vec![self.loans.len() - 1]
             }
 
-            Expression::Binary {
-                left,
-                operator: _,
-                right,
-            } => {
-                self.check_expression(left)?;
-                self.check_expression(right)?;
+            Expression::Move { expr, span } => match self.variable(expr) {
+                Some((id, name_span)) => self.move_binding(
+                    id,
+                    Span {
+                        end: name_span.end,
+                        ..*span
+                    },
+                ),
+                None => self.check_expression(expr),
+            },
+
+            Expression::Array(elements) => elements
+                .iter()
+                .flat_map(|element| self.check_expression(element))
+                .collect(),
+
+            Expression::Binary { left, right, .. } => {
+                self.check_expression(left);
+                self.check_expression(right);
+                Vec::new()
+            }
+
+            Expression::Index { array, index } => {
+                self.check_expression(array);
+                self.check_expression(index);
+                Vec::new()
             }
 
             Expression::Call {
-                function: _,
                 arguments,
+                keyword_arguments,
+                ..
             } => {
                 for arg in arguments {
-                    self.check_expression(arg)?;
+                    self.check_expression(arg);
                 }
+                for arg in keyword_arguments {
+                    self.check_expression(&arg.value);
+                }
+                Vec::new()
             }
-
-            Expression::Index { array, index } => {
-                self.check_expression(array)?;
-                self.check_expression(index)?;
+            Expression::MethodCall {
+                object,
+                arguments,
+                keyword_arguments,
+                ..
+            } => {
+                self.check_expression(object);
+                for arg in arguments {
+                    self.check_expression(arg);
+                }
+                for arg in keyword_arguments {
+                    self.check_expression(&arg.value);
+                }
+                Vec::new()
+            }
+            Expression::MacroCall { arguments, .. } => {
+                for arg in arguments {
+                    self.check_expression(arg);
+                }
+                Vec::new()
             }
 
-            Expression::Array(elements) => {
-                for elem in elements {
-                    self.check_expression(elem)?;
+            Expression::Lambda { params, body } => {
+                self.check_function(params, body);
+                Vec::new()
+            }
+            Expression::Where {
+                expr,
+                condition,
+                body,
+            } => {
+                self.check_expression(expr);
+                self.check_expression(condition);
+                self.check_statement(body);
+                Vec::new()
+            }
+            Expression::FString(parts) => {
+                for part in parts {
+                    if let FStringPart::Hole { expression, .. } = part {
+                        self.check_expression(expression);
+                    }
                 }
+                Vec::new()
             }
 
-            _ => {}
+            Expression::Unary { operand: value, .. }
+            | Expression::Member { object: value, .. }
+            | Expression::Range(value)
+            | Expression::Try(value)
+            | Expression::TypeOf(value)
+            | Expression::Await { expr: value }
+            | Expression::Is { value, .. } => {
+                self.check_expression(value);
+                Vec::new()
+            }
+
+            _ => Vec::new(),
         }
-        Ok(())
     }
 
-    fn check_mutable_access(&self, var_name: &str) -> Result<(), CrabbyError> {
-        if let Some(info) = self.ownership_map.get(var_name) {
-            if info.borrowed_count > 0 {
-                return Err(CrabbyError::InterpreterError(format!(
-                    "Cannot mutably access '{}' while borrowed",
-                    var_name
-                )));
-            }
-            if info.mut_borrowed {
-                return Err(CrabbyError::InterpreterError(format!(
-                    "Cannot access '{}' while mutably borrowed",
-                    var_name
-                )));
+    fn declare(&mut self, name: &str, mutable: bool, copy: bool, span: Span) -> usize {
+        let lifetime = match self.scopes.len() {
+            0 | 1 => Lifetime::Static,
+            depth => Lifetime::Local { scope_depth: depth },
+        };
+        self.bindings.push(OwnershipInfo {
+            name: name.to_string(),
+            lifetime,
+            mutable,
+            copy,
+            declared: span,
+            moved: None,
+        });
+        let id = self.bindings.len() - 1;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), id);
+        }
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    // The variable `expr` names, when it is one the checker knows
+    fn variable(&self, expr: &Expression) -> Option<(usize, Span)> {
+        match expr {
+            Expression::Variable { name, span } => Some((self.lookup(name)?, *span)),
+            _ => None,
+        }
+    }
+
+    fn access(&mut self, binding: usize, kind: AccessKind, span: Span) {
+        self.accesses.push(Access {
+            binding,
+            kind,
+            span,
+            statement: self.statement,
+        });
+    }
+
+    // The borrows held by a variable, which pass on to wherever its value goes
+    fn held_by(&self, binding: usize) -> Vec<usize> {
+        (0..self.loans.len())
+            .filter(|&loan| self.loans[loan].holders.contains(&binding))
+            .collect()
+    }
+
+    // Values that are copied rather than moved: numbers, booleans and shared borrows
+    fn is_copy(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Integer(_)
+            | Expression::BigInteger(_)
+            | Expression::Float(_)
+            | Expression::Boolean(_)
+            | Expression::Maybe(_)
+            | Expression::Probably(_)
+            | Expression::Null
+            | Expression::Unary { .. }
+            | Expression::Borrow { mutable: false, .. } => true,
+            Expression::Binary { left, right, .. } => self.is_copy(left) && self.is_copy(right),
+            Expression::Variable { name, .. } => {
+                self.lookup(name).is_some_and(|id| self.bindings[id].copy)
             }
+            _ => false,
+        }
+    }
+
+    fn move_binding(&mut self, id: usize, span: Span) -> Vec<usize> {
+        self.check_not_moved(id, span);
+        let loans = self.held_by(id);
+        if self.bindings[id].copy {
+            self.access(id, AccessKind::Read, span);
+        } else {
+            self.access(id, AccessKind::Move, span);
+            self.bindings[id].moved = Some(span);
+        }
+        loans
+    }
+
+    fn check_not_moved(&mut self, id: usize, span: Span) {
+        if let Some(moved) = self.bindings[id].moved {
+            self.errors.push(OwnershipError {
+                code: "moved-value",
+                message: format!("use of moved value `{}`", self.bindings[id].name),
+                span,
+                label: "value used here after move".to_string(),
+                related: vec![(moved, "value moved here".to_string())],
+            });
+            // One error per move is enough
+            self.bindings[id].moved = None;
         }
-        Ok(())
     }
 
-    fn cleanup_scope(&mut self, scope: u32) {
-        self.ownership_map.retain(|_, info| match info.lifetime {
-            Lifetime::Local { scope_depth } => scope_depth < scope,
-            Lifetime::Borrowed { scope_depth, .. } => scope_depth < scope,
-            Lifetime::Static => true,
+    // `action` has a `{}` for the variable's name
+    fn error_immutable(&mut self, id: usize, span: Span, action: &str) {
+        let info = &self.bindings[id];
+        let action = action.replace("{}", &info.name);
+        self.errors.push(OwnershipError {
+            code: "immutable-variable",
+            message: format!("cannot {}, as it is not declared as mutable", action),
+            span,
+            label: format!("cannot {}", action),
+            related: vec![(
+                info.declared,
+                format!(
+                    "declare it with `let mut {}` or `var {}`",
+                    info.name, info.name
+                ),
+            )],
         });
+    }
+
+    fn moved(&self) -> Vec<Option<Span>> {
+        self.bindings.iter().map(|info| info.moved).collect()
+    }
 
-        self.moved_variables.clear();
+    fn set_moved(&mut self, moved: Vec<Option<Span>>) {
+        for (info, moved) in self.bindings.iter_mut().zip(moved) {
+            info.moved = moved;
+        }
     }
 
-    pub fn mark_moved(&mut self, var_name: &str) {
-        self.moved_variables.push(var_name.to_string());
+    // A move in one branch doesn't affect the others, but is in effect after all of
+    // them; `moved` collects the moves of each branch
+    fn end_branch(&mut self, before: &[Option<Span>], moved: &mut [Option<Span>]) {
+        for (i, state) in moved.iter_mut().enumerate() {
+            *state = state.or(self.bindings[i].moved);
+            self.bindings[i].moved = before[i];
+        }
     }
 
-    pub fn check_borrowable(&self, var_name: &str, mutable: bool) -> Result<(), CrabbyError> {
-        if let Some(info) = self.ownership_map.get(var_name) {
-            if mutable {
-                if info.borrowed_count > 0 || info.mut_borrowed {
-                    return Err(CrabbyError::InterpreterError(format!(
-                        "Cannot mutably borrow '{}' while already borrowed",
-                        var_name
-                    )));
+    // A variable from outside a loop that the loop moves is gone by the next iteration
+    fn check_loop(&mut self, before: &[Option<Span>]) {
+        for (id, before) in before.iter().enumerate() {
+            if let (None, Some(moved)) = (before, self.bindings[id].moved) {
+                self.errors.push(OwnershipError {
+                    code: "moved-value",
+                    message: format!(
+                        "`{}` is moved in a loop, so it is gone by the next iteration",
+                        self.bindings[id].name
+                    ),
+                    span: moved,
+                    label: "value moved here, in the previous iteration of the loop".to_string(),
+                    related: vec![(
+                        self.bindings[id].declared,
+                        "declared outside the loop here".to_string(),
+                    )],
+                });
+            }
+        }
+    }
+
+    // A function can't return a borrow of its own variables, which are dropped on return
+    fn check_escape(&mut self, returned: &Expression, loans: &[usize]) {
+        let depth = self.scopes.len();
+        for &loan in loans {
+            let info = &self.bindings[self.loans[loan].binding];
+            if info.lifetime != (Lifetime::Local { scope_depth: depth }) {
+                continue;
+            }
+            let mut related = vec![(
+                info.declared,
+                format!("`{}` is dropped when the function returns", info.name),
+            )];
+            if let Expression::Variable { name, span } = returned {
+                related.push((*span, format!("`{}` is returned here", name)));
+            }
+            self.errors.push(OwnershipError {
+                code: "escaping-borrow",
+                message: format!(
+                    "cannot return a reference to the local variable `{}`",
+                    info.name
+                ),
+                span: self.loans[loan].span,
+                label: format!("`{}` is borrowed here", info.name),
+                related,
+            });
+        }
+    }
+
+    // Once the whole program is seen, each borrow is checked against what else
+    // happens to its variable while it lasts
+    fn check_loans(&mut self) {
+        for loan in &self.loans {
+            let last_use = self
+                .accesses
+                .iter()
+                .filter(|access| loan.holders.contains(&access.binding))
+                .map(|access| access.span)
+                .max_by_key(|span| span.start);
+            let end = last_use.map_or(loan.span.start, |span| span.start);
+            let name = &self.bindings[loan.binding].name;
+            let first = if loan.mutable { "mutable" } else { "immutable" };
+            let conflicts = self.accesses.iter().filter(|access| {
+                let during = access.statement == loan.statement || access.span.start <= end;
+                access.binding == loan.binding
+                    && access.span.start > loan.span.start
+                    && during
+                    && match access.kind {
+                        AccessKind::Read => loan.mutable,
+                        AccessKind::Borrow { mutable } => loan.mutable || mutable,
+                        AccessKind::Assign | AccessKind::Move => true,
+                    }
+            });
+            for conflict in conflicts {
+                let (message, label) = match conflict.kind {
+                    AccessKind::Read => (
+                        format!("cannot use `{}` while it is mutably borrowed", name),
+                        "use of the borrowed value".to_string(),
+                    ),
+                    AccessKind::Borrow { mutable: true } if loan.mutable => (
+                        format!(
+                            "cannot borrow `{}` as mutable more than once at a time",
+                            name
+                        ),
+                        "second mutable borrow occurs here".to_string(),
+                    ),
+                    AccessKind::Borrow { mutable } => {
+                        let second = if mutable { "mutable" } else { "immutable" };
+                        (
+                            format!(
+                                "cannot borrow `{}` as {} because it is also borrowed as {}",
+                                name, second, first
+                            ),
+                            format!("{} borrow occurs here", second),
+                        )
+                    }
+                    AccessKind::Assign => (
+                        format!("cannot assign to `{}` because it is borrowed", name),
+                        "assignment to the borrowed value".to_string(),
+                    ),
+                    AccessKind::Move => (
+                        format!("cannot move out of `{}` because it is borrowed", name),
+                        "move out of the borrowed value".to_string(),
+                    ),
+                };
+                let mut related = vec![(loan.span, format!("{} borrow occurs here", first))];
+                if let Some(last_use) = last_use.filter(|span| span.start > conflict.span.start) {
+                    related.push((last_use, "borrow later used here".to_string()));
                 }
-            } else if info.mut_borrowed {
-                return Err(CrabbyError::InterpreterError(format!(
-                    "Cannot borrow '{}' while mutably borrowed",
-                    var_name
-                )));
+                self.errors.push(OwnershipError {
+                    code: "borrow-conflict",
+                    message,
+                    span: conflict.span,
+                    label,
+                    related,
+                });
             }
         }
-        Ok(())
     }
 }
+
+impl OwnershipError {
+    fn to_diagnostic(&self) -> Diagnostic<()> {
+        let mut labels =
+            vec![Label::primary((), self.span.start..self.span.end).with_message(&self.label)];
+        for (span, message) in &self.related {
+            labels.push(Label::secondary((), span.start..span.end).with_message(message));
+        }
+        Diagnostic::error()
+            .with_code(self.code)
+            .with_message(&self.message)
+            .with_labels(labels)
+    }
+}
+
+// Renders ownership errors against the source they were found in
+pub fn render(errors: &[OwnershipError], file_name: &str, source: &str) -> String {
+    render_diagnostics(
+        errors.iter().map(OwnershipError::to_diagnostic),
+        file_name,
+        source,
+    )
+}
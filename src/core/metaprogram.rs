@@ -1,2 +1,528 @@
 // Handler for metaprogramming in Crabby
 // Introduces: Macros & Decorators (Python)
+//
+// Decorators are applied at runtime by the interpreter. Macros are expanded here,
+// before the program runs: every `name!(args)` call is replaced by a copy of the
+// macro's body with the arguments substituted for its parameters.
+
+use crate::ast::{Expression, FStringPart, Program, Statement};
+use crate::utils::{CrabbyError, Span};
+use std::collections::{HashMap, HashSet};
+
+// Guards against macros that (directly or not) expand into themselves
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+struct MacroDefinition {
+    params: Vec<String>,
+    body: Statement,
+}
+
+#[derive(Default)]
+pub struct MacroExpander {
+    macros: HashMap<String, MacroDefinition>,
+    depth: usize,
+    fresh_names: usize,
+    // Every name the program uses, which renamed bindings must not collide with
+    taken: HashSet<String>,
+}
+
+impl MacroExpander {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Macros can be used anywhere after they are defined, including inside other macros
+    pub fn expand(&mut self, mut program: Program) -> Result<Program, CrabbyError> {
+        for statement in &mut program.statements {
+            collect_names(Child::Statement(statement), &mut self.taken);
+        }
+
+        let mut expanded = Program::new();
+        expanded.docstring = program.docstring;
+        for statement in program.statements {
+            if let Some(statement) = self.expand_statement(statement)? {
+                expanded.statements.push(statement);
+            }
+        }
+        Ok(expanded)
+    }
+
+    // Returns `None` for macro definitions, which have no runtime meaning
+    fn expand_statement(&mut self, statement: Statement) -> Result<Option<Statement>, CrabbyError> {
+        match statement {
+            Statement::Macro { name, params, body } => {
+                self.macros.insert(
+                    name,
+                    MacroDefinition {
+                        params,
+                        body: *body,
+                    },
+                );
+                Ok(None)
+            }
+            // A macro used as a statement may expand to any number of statements
+            Statement::Expression(Expression::MacroCall {
+                name,
+                arguments,
+                span,
+            }) => self.expand_call(&name, arguments, span).map(Some),
+            mut statement => {
+                let mut result = Ok(());
+                walk_statement(&mut statement, &mut |child| {
+                    if result.is_ok() {
+                        result = self.expand_child(child);
+                    }
+                });
+                result.map(|_| Some(statement))
+            }
+        }
+    }
+
+    fn expand_child(&mut self, child: Child) -> Result<(), CrabbyError> {
+        match child {
+            Child::Expression(expr) => self.expand_expression(expr),
+            Child::Statement(statement) => {
+                let taken = std::mem::replace(statement, Statement::Block(Vec::new()));
+                // A nested macro definition leaves an empty block behind
+                if let Some(expanded) = self.expand_statement(taken)? {
+                    *statement = expanded;
+                }
+                Ok(())
+            }
+            Child::Binding(_) => Ok(()),
+        }
+    }
+
+    // Expands macro calls in expression position; the macro must produce a single expression
+    fn expand_expression(&mut self, expr: &mut Expression) -> Result<(), CrabbyError> {
+        if let Expression::MacroCall {
+            name,
+            arguments,
+            span,
+        } = expr
+        {
+            let (name, span) = (name.clone(), *span);
+            let body = self.expand_call(&name, std::mem::take(arguments), span)?;
+            *expr = match body {
+                Statement::Block(mut statements) if statements.len() == 1 => {
+                    match statements.remove(0) {
+                        Statement::Expression(value) => value,
+                        Statement::Return(value, _) => *value,
+                        _ => return Err(not_an_expression(&name, span)),
+                    }
+                }
+                Statement::Expression(value) => value,
+                _ => return Err(not_an_expression(&name, span)),
+            };
+            return Ok(());
+        }
+
+        let mut result = Ok(());
+        walk_expression(expr, &mut |child| {
+            if result.is_ok() {
+                result = self.expand_child(child);
+            }
+        });
+        result
+    }
+
+    fn expand_call(
+        &mut self,
+        name: &str,
+        mut arguments: Vec<Expression>,
+        span: Span,
+    ) -> Result<Statement, CrabbyError> {
+        let definition = self.macros.get(name).cloned().ok_or_else(|| {
+            CrabbyError::ExpansionError(span, format!("Undefined macro: {}!", name))
+        })?;
+
+        if arguments.len() != definition.params.len() {
+            return Err(CrabbyError::ExpansionError(
+                span,
+                format!(
+                    "Macro {}! expects {} arguments, got {}",
+                    name,
+                    definition.params.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+
+        if self.depth >= MAX_EXPANSION_DEPTH {
+            return Err(CrabbyError::ExpansionError(
+                span,
+                format!(
+                    "Macro {}! exceeded the expansion limit of {}; does it expand into itself?",
+                    name, MAX_EXPANSION_DEPTH
+                ),
+            ));
+        }
+
+        // Arguments belong to the call site, so they are expanded there
+        for argument in &mut arguments {
+            self.expand_expression(argument)?;
+        }
+
+        let mut body = definition.body;
+        self.rename_bindings(&mut body);
+
+        let substitutions: HashMap<String, Expression> =
+            definition.params.into_iter().zip(arguments).collect();
+        substitute(Child::Statement(&mut body), &substitutions);
+
+        self.depth += 1;
+        let expanded = self.expand_statement(body);
+        self.depth -= 1;
+
+        match expanded {
+            Ok(body) => Ok(body.unwrap_or(Statement::Block(Vec::new()))),
+            // Errors from nested expansions are reported at the outermost call site
+            Err(CrabbyError::ExpansionError(_, message)) if self.depth == 0 => Err(
+                CrabbyError::ExpansionError(span, format!("in {}!: {}", name, message)),
+            ),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Hygiene: variables the macro body declares get fresh names, so they can neither
+    // capture nor clobber variables of the same name at the call site
+    fn rename_bindings(&mut self, body: &mut Statement) {
+        let mut bound = Vec::new();
+        collect_bindings(Child::Statement(body), &mut bound);
+
+        let renames: HashMap<String, Expression> = bound
+            .into_iter()
+            .map(|name| {
+                let fresh = loop {
+                    self.fresh_names += 1;
+                    let fresh = format!("__{}_{}", name, self.fresh_names);
+                    if self.taken.insert(fresh.clone()) {
+                        break fresh;
+                    }
+                };
+                (
+                    name,
+                    Expression::Variable {
+                        name: fresh,
+                        span: Span::default(),
+                    },
+                )
+            })
+            .collect();
+
+        substitute(Child::Statement(body), &renames);
+    }
+}
+
+pub fn expand_macros(program: Program) -> Result<Program, CrabbyError> {
+    MacroExpander::new().expand(program)
+}
+
+fn not_an_expression(name: &str, span: Span) -> CrabbyError {
+    CrabbyError::ExpansionError(
+        span,
+        format!(
+            "Macro {}! expands to statements and cannot be used as a value",
+            name
+        ),
+    )
+}
+
+fn collect_bindings(child: Child, bound: &mut Vec<String>) {
+    match child {
+        Child::Binding(name) => {
+            if !bound.contains(name) {
+                bound.push(name.clone());
+            }
+        }
+        Child::Expression(expr) => {
+            walk_expression(expr, &mut |child| collect_bindings(child, bound))
+        }
+        Child::Statement(statement) => {
+            walk_statement(statement, &mut |child| collect_bindings(child, bound))
+        }
+    }
+}
+
+// Variables, their declarations and the functions that are defined or called
+fn collect_names(child: Child, names: &mut HashSet<String>) {
+    match child {
+        Child::Binding(name) => {
+            names.insert(name.clone());
+        }
+        Child::Expression(expr) => {
+            match expr {
+                Expression::Variable { name, .. } | Expression::Call { function: name, .. } => {
+                    names.insert(name.clone());
+                }
+                _ => {}
+            }
+            walk_expression(expr, &mut |child| collect_names(child, names))
+        }
+        Child::Statement(statement) => {
+            if let Statement::FunctionDef { name, .. } | Statement::FunctionFun { name, .. } =
+                statement
+            {
+                names.insert(name.clone());
+            }
+            walk_statement(statement, &mut |child| collect_names(child, names))
+        }
+    }
+}
+
+// Replaces variables (and declarations of them, for renames) by the given expressions
+fn substitute(child: Child, substitutions: &HashMap<String, Expression>) {
+    match child {
+        Child::Binding(name) => {
+            if let Some(Expression::Variable { name: fresh, .. }) = substitutions.get(name.as_str())
+            {
+                *name = fresh.clone();
+            }
+        }
+        Child::Expression(expr) => {
+            if let Expression::Variable { name, .. } = expr {
+                match substitutions.get(name.as_str()) {
+                    // A renamed variable keeps its place in the source
+                    Some(Expression::Variable { name: fresh, .. }) => *name = fresh.clone(),
+                    Some(replacement) => *expr = replacement.clone(),
+                    None => {}
+                }
+            } else {
+                walk_expression(expr, &mut |child| substitute(child, substitutions));
+            }
+        }
+        Child::Statement(statement) => {
+            walk_statement(statement, &mut |child| substitute(child, substitutions))
+        }
+    }
+}
+
+enum Child<'a> {
+    Expression(&'a mut Expression),
+    Statement(&'a mut Statement),
+    Binding(&'a mut String), // a name the parent declares
+}
+
+// Calls `visit` on each expression, statement and declared name directly inside
+// `statement`. Callers recurse from `visit`.
+fn walk_statement(statement: &mut Statement, visit: &mut dyn FnMut(Child)) {
+    match statement {
+        Statement::FunctionDef {
+            params,
+            body,
+            decorators,
+            ..
+        }
+        | Statement::FunctionFun {
+            params,
+            body,
+            decorators,
+            ..
+        } => {
+            for decorator in decorators {
+                for argument in &mut decorator.arguments {
+                    visit(Child::Expression(argument));
+                }
+                for argument in &mut decorator.keyword_arguments {
+                    visit(Child::Expression(&mut argument.value));
+                }
+            }
+            for param in params {
+                if let Some(default) = &mut param.default {
+                    visit(Child::Expression(default));
+                }
+            }
+            visit(Child::Statement(body));
+        }
+        Statement::Extern { params, .. } => {
+            for param in params {
+                if let Some(default) = &mut param.default {
+                    visit(Child::Expression(default));
+                }
+            }
+        }
+        Statement::AsyncFunction { params, body, .. } => {
+            for param in params {
+                if let Some(default) = &mut param.default {
+                    visit(Child::Expression(default));
+                }
+            }
+            visit(Child::Statement(body));
+        }
+        Statement::Let { name, value, .. }
+        | Statement::Var { name, value, .. }
+        | Statement::Const { name, value, .. } => {
+            visit(Child::Expression(value));
+            visit(Child::Binding(name));
+        }
+        Statement::Return(value, _) => visit(Child::Expression(value)),
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+            span: _,
+        } => {
+            visit(Child::Expression(condition));
+            visit(Child::Statement(then_branch));
+            if let Some(else_branch) = else_branch {
+                visit(Child::Statement(else_branch));
+            }
+        }
+        Statement::While {
+            condition, body, ..
+        } => {
+            visit(Child::Expression(condition));
+            visit(Child::Statement(body));
+        }
+        Statement::Loop { count, body, .. } => {
+            visit(Child::Expression(count));
+            visit(Child::Statement(body));
+        }
+        Statement::ForIn {
+            variable,
+            iterator,
+            body,
+            span: _,
+        } => {
+            visit(Child::Expression(iterator));
+            visit(Child::Binding(variable));
+            visit(Child::Statement(body));
+        }
+        Statement::Match { value, arms } => {
+            visit(Child::Expression(value));
+            for arm in arms {
+                visit(Child::Expression(&mut arm.pattern));
+                visit(Child::Expression(&mut arm.body));
+            }
+        }
+        Statement::Enum { where_clause, .. } | Statement::Struct { where_clause, .. } => {
+            if let Some(clause) = where_clause {
+                visit(Child::Expression(clause));
+            }
+        }
+        Statement::Class { methods, .. } | Statement::Extend { methods, .. } => {
+            for method in methods {
+                visit(Child::Statement(method));
+            }
+        }
+        Statement::Trait { methods, .. } | Statement::Impl { methods, .. } => {
+            for method in methods {
+                visit(Child::Statement(&mut method.body));
+            }
+        }
+        Statement::ArrayAssign {
+            array,
+            index,
+            value,
+        } => {
+            visit(Child::Expression(array));
+            visit(Child::Expression(index));
+            visit(Child::Expression(value));
+        }
+        Statement::Assert {
+            condition, message, ..
+        } => {
+            visit(Child::Expression(condition));
+            if let Some(message) = message {
+                visit(Child::Expression(message));
+            }
+        }
+        Statement::Test { body, .. } => visit(Child::Statement(body)),
+        Statement::Block(statements) => {
+            for statement in statements {
+                visit(Child::Statement(statement));
+            }
+        }
+        Statement::Expression(value) => visit(Child::Expression(value)),
+        Statement::Macro { .. }
+        | Statement::And { .. }
+        | Statement::Import { .. }
+        | Statement::ExternStruct { .. }
+        | Statement::TypeDef { .. } => {}
+    }
+}
+
+// Like `walk_statement`, for what is directly inside `expression`
+fn walk_expression(expression: &mut Expression, visit: &mut dyn FnMut(Child)) {
+    match expression {
+        Expression::Range(value) | Expression::Try(value) | Expression::TypeOf(value) => {
+            visit(Child::Expression(value))
+        }
+        Expression::Is { value, .. } => visit(Child::Expression(value)),
+        Expression::Unary { operand, .. }
+        | Expression::Borrow { expr: operand, .. }
+        | Expression::Move { expr: operand, .. } => visit(Child::Expression(operand)),
+        Expression::Await { expr } => visit(Child::Expression(expr)),
+        Expression::FString(parts) => {
+            for part in parts {
+                if let FStringPart::Hole { expression, .. } = part {
+                    visit(Child::Expression(expression));
+                }
+            }
+        }
+        Expression::Array(elements)
+        | Expression::MacroCall {
+            arguments: elements,
+            ..
+        } => {
+            for element in elements {
+                visit(Child::Expression(element));
+            }
+        }
+        Expression::Where {
+            expr,
+            condition,
+            body,
+        } => {
+            visit(Child::Expression(expr));
+            visit(Child::Expression(condition));
+            visit(Child::Statement(body));
+        }
+        Expression::Binary { left, right, .. } => {
+            visit(Child::Expression(left));
+            visit(Child::Expression(right));
+        }
+        Expression::Call {
+            arguments,
+            keyword_arguments,
+            ..
+        } => {
+            for argument in arguments {
+                visit(Child::Expression(argument));
+            }
+            for argument in keyword_arguments {
+                visit(Child::Expression(&mut argument.value));
+            }
+        }
+        Expression::MethodCall {
+            object,
+            arguments,
+            keyword_arguments,
+            ..
+        } => {
+            visit(Child::Expression(object));
+            for argument in arguments {
+                visit(Child::Expression(argument));
+            }
+            for argument in keyword_arguments {
+                visit(Child::Expression(&mut argument.value));
+            }
+        }
+        Expression::Lambda { params, body } => {
+            for param in params {
+                if let Some(default) = &mut param.default {
+                    visit(Child::Expression(default));
+                }
+                visit(Child::Binding(&mut param.name));
+            }
+            visit(Child::Statement(body));
+        }
+        Expression::Index { array, index } => {
+            visit(Child::Expression(array));
+            visit(Child::Expression(index));
+        }
+        Expression::Member { object, .. } => visit(Child::Expression(object)),
+        _ => {}
+    }
+}
@@ -1,7 +1,8 @@
 pub mod asm;
-pub mod memory;
-// pub mod ffi;
 pub mod bindings;
 pub mod embedded;
+pub mod ffi;
+pub mod memory;
 pub mod metaprogram;
+pub mod sandbox;
 pub mod r#unsafe;
@@ -0,0 +1,322 @@
+// Capabilities for running Crabby code that isn't trusted
+// The native functions below reach outside of the interpreter: files, the network,
+// environment variables, other processes and shared libraries. Each one asks the
+// program's `Capabilities` first, and nothing is granted unless asked for:
+//
+//     crabby run --allow-read=./data --allow-net=localhost plugin.crab
+//
+// A denied call returns `Err("permission denied: ...")` naming the capability, so a
+// program can handle it with `match` or pass it on with `?`.
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::core::ffi;
+use crate::utils::CrabbyError;
+use crate::value::Value;
+
+pub const NATIVE_FUNCTIONS: [&str; 6] =
+    ["read_file", "write_file", "env", "exec", "fetch", "loadlib"];
+
+// How long `fetch` waits on a server when the program has no `--timeout`
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Everything, or only what is listed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Grant<T> {
+    #[default]
+    Nothing,
+    Everything,
+    Only(Vec<T>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    pub read: Grant<PathBuf>,  // files and directories under these paths
+    pub write: Grant<PathBuf>, // same
+    pub net: Grant<String>,    // `host` for any port, or `host:port`
+    pub env: bool,
+    pub run: bool,
+    pub ffi: bool,
+}
+
+impl<T> Grant<T> {
+    fn allows(&self, wanted: impl Fn(&T) -> bool) -> bool {
+        match self {
+            Grant::Nothing => false,
+            Grant::Everything => true,
+            Grant::Only(items) => items.iter().any(wanted),
+        }
+    }
+}
+
+impl Capabilities {
+    // What the REPL and trusted embedders use
+    pub fn all() -> Self {
+        Self {
+            read: Grant::Everything,
+            write: Grant::Everything,
+            net: Grant::Everything,
+            env: true,
+            run: true,
+            ffi: true,
+        }
+    }
+
+    pub fn check_read(&self, path: &Path) -> Result<(), String> {
+        match self.read.allows(|root| within(path, root)) {
+            true => Ok(()),
+            false => Err(denied(
+                &format!("read access to {:?}", path),
+                "--allow-read",
+            )),
+        }
+    }
+
+    pub fn check_write(&self, path: &Path) -> Result<(), String> {
+        match self.write.allows(|root| within(path, root)) {
+            true => Ok(()),
+            false => Err(denied(
+                &format!("write access to {:?}", path),
+                "--allow-write",
+            )),
+        }
+    }
+
+    pub fn check_net(&self, host: &str, port: u16) -> Result<(), String> {
+        let allowed = self.net.allows(|granted| match granted.rsplit_once(':') {
+            Some((granted_host, granted_port)) => {
+                granted_host == host && granted_port == port.to_string()
+            }
+            None => granted == host,
+        });
+        match allowed {
+            true => Ok(()),
+            false => Err(denied(
+                &format!("network access to {}:{}", host, port),
+                "--allow-net",
+            )),
+        }
+    }
+
+    pub fn check_env(&self, name: &str) -> Result<(), String> {
+        match self.env {
+            true => Ok(()),
+            false => Err(denied(
+                &format!("access to the environment variable {:?}", name),
+                "--allow-env",
+            )),
+        }
+    }
+
+    pub fn check_run(&self, command: &str) -> Result<(), String> {
+        match self.run {
+            true => Ok(()),
+            false => Err(denied(&format!("running {:?}", command), "--allow-run")),
+        }
+    }
+
+    pub fn check_ffi(&self, library: &str) -> Result<(), String> {
+        match self.ffi {
+            true => Ok(()),
+            false => Err(denied(&format!("loading {:?}", library), "--allow-ffi")),
+        }
+    }
+}
+
+fn denied(what: &str, flag: &str) -> String {
+    format!("permission denied: {} (run with {})", what, flag)
+}
+
+// Compares real paths, so `data/../secret` and symlinks can't get out of `root`.
+// A file that doesn't exist yet is where its directory is.
+fn within(path: &Path, root: &Path) -> bool {
+    let resolve = |path: &Path| {
+        path.canonicalize().ok().or_else(|| {
+            let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+            let parent = parent.unwrap_or(Path::new(".")).canonicalize().ok()?;
+            Some(parent.join(path.file_name()?))
+        })
+    };
+    match (resolve(path), root.canonicalize()) {
+        (Some(path), Ok(root)) => path.starts_with(root),
+        _ => false,
+    }
+}
+
+// `None` when `name` isn't a native function. Permission and I/O failures are `Err`
+// values for the program; calling a function the wrong way is a `CrabbyError`.
+// Nothing waits on the network past `deadline`, the end of the program's `--timeout`.
+pub fn call_native(
+    name: &str,
+    args: &[Value],
+    capabilities: &Capabilities,
+    deadline: Option<Instant>,
+) -> Option<Result<Value, CrabbyError>> {
+    let result = match (name, args) {
+        ("read_file", [Value::String(path)]) => read_file(path, capabilities),
+        ("write_file", [Value::String(path), Value::String(text)]) => {
+            write_file(path, text, capabilities)
+        }
+        ("env", [Value::String(name)]) => env_var(name, capabilities),
+        ("exec", [Value::String(command)]) => exec(command, &[], capabilities),
+        ("exec", [Value::String(command), Value::Array(arguments)]) => {
+            exec(command, arguments, capabilities)
+        }
+        ("fetch", [Value::String(url)]) => fetch(
+            url,
+            capabilities,
+            deadline.unwrap_or_else(|| Instant::now() + FETCH_TIMEOUT),
+        ),
+        ("loadlib", [Value::String(path)]) => loadlib(path, capabilities),
+        (name, _) if NATIVE_FUNCTIONS.contains(&name) => {
+            return Some(Err(CrabbyError::TypeError(None, usage(name, args))));
+        }
+        _ => return None,
+    };
+    Some(Ok(match result {
+        Ok(value) => Value::Ok(Box::new(value)),
+        Err(message) => Value::Err(Box::new(Value::String(message))),
+    }))
+}
+
+fn usage(name: &str, args: &[Value]) -> String {
+    let signature = match name {
+        "read_file" => "read_file(path)",
+        "write_file" => "write_file(path, text)",
+        "env" => "env(name)",
+        "exec" => "exec(command) or exec(command, [arguments])",
+        "fetch" => "fetch(url)",
+        _ => "loadlib(path)",
+    };
+    let given: Vec<&str> = args.iter().map(|arg| arg.type_name()).collect();
+    format!(
+        "{} is called as {}, not with ({})",
+        name,
+        signature,
+        given.join(", ")
+    )
+}
+
+fn read_file(path: &str, capabilities: &Capabilities) -> Result<Value, String> {
+    capabilities.check_read(Path::new(path))?;
+    fs::read_to_string(path)
+        .map(Value::String)
+        .map_err(|e| format!("cannot read {:?}: {}", path, e))
+}
+
+fn write_file(path: &str, text: &str, capabilities: &Capabilities) -> Result<Value, String> {
+    capabilities.check_write(Path::new(path))?;
+    fs::write(path, text)
+        .map(|_| Value::Void)
+        .map_err(|e| format!("cannot write {:?}: {}", path, e))
+}
+
+fn env_var(name: &str, capabilities: &Capabilities) -> Result<Value, String> {
+    capabilities.check_env(name)?;
+    env::var(name)
+        .map(Value::String)
+        .map_err(|_| format!("the environment variable {:?} is not set", name))
+}
+
+// The command's output, when it succeeds
+fn exec(command: &str, arguments: &[Value], capabilities: &Capabilities) -> Result<Value, String> {
+    capabilities.check_run(command)?;
+    let arguments: Vec<String> = arguments
+        .iter()
+        .map(|arg| match arg {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+        .collect();
+    let output = Command::new(command)
+        .args(&arguments)
+        .output()
+        .map_err(|e| format!("cannot run {:?}: {}", command, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{:?} failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(Value::String(
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    ))
+}
+
+// The body of a plain `http://` GET
+fn fetch(url: &str, capabilities: &Capabilities, deadline: Instant) -> Result<Value, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("cannot fetch {:?}: only http:// URLs are supported", url))?;
+    let (address, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| format!("cannot fetch {:?}: invalid port", url))?,
+        ),
+        None => (address, 80),
+    };
+    capabilities.check_net(host, port)?;
+
+    let failed = |e: std::io::Error| match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+            format!("cannot fetch {:?}: timed out", url)
+        }
+        _ => format!("cannot fetch {:?}: {}", url, e),
+    };
+    // Each wait gets whatever time is left, so a slow server can't stretch it
+    let time_left = || match deadline.checked_duration_since(Instant::now()) {
+        Some(left) if !left.is_zero() => Ok(left),
+        _ => Err(format!("cannot fetch {:?}: timed out", url)),
+    };
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(failed)?
+        .next()
+        .ok_or_else(|| format!("cannot fetch {:?}: {} has no address", url, host))?;
+    let mut stream = TcpStream::connect_timeout(&address, time_left()?).map_err(failed)?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream
+        .set_write_timeout(Some(time_left()?))
+        .map_err(failed)?;
+    stream.write_all(request.as_bytes()).map_err(failed)?;
+    let mut response = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        stream
+            .set_read_timeout(Some(time_left()?))
+            .map_err(failed)?;
+        match stream.read(&mut chunk).map_err(failed)? {
+            0 => break,
+            read => response.extend_from_slice(&chunk[..read]),
+        }
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(Value::String(body.to_string())),
+        _ => Err(format!("cannot fetch {:?}: {}", url, status)),
+    }
+}
+
+fn loadlib(path: &str, capabilities: &Capabilities) -> Result<Value, String> {
+    capabilities.check_ffi(path)?;
+    ffi::open(Some(path)).map(|_| Value::Void)
+}
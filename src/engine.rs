@@ -0,0 +1,418 @@
+// Hosting Crabby scripts from Rust
+// An `Engine` keeps one interpreter around between calls, so a script can be loaded
+// once and its functions called many times:
+//
+//     let mut engine = Engine::new();
+//     engine.register_function("shout", |s: String| s.to_uppercase());
+//     engine.eval("def greet(name) { return shout(name) }")?;
+//     let greeting = engine.call_function("greet", vec!["ferris".into_value()])?;
+//
+// Whatever the script prints is kept for `take_stdout` and `take_stderr` instead of
+// being written out, and it can't reach files, the network or the like unless
+// `set_capabilities` says so.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::rc::Rc;
+
+use crate::ast::Statement;
+use crate::core::metaprogram::MacroExpander;
+use crate::core::sandbox::Capabilities;
+use crate::interpreter::Interpreter;
+use crate::lexer::TokenStream;
+use crate::parser::parse;
+use crate::runtime::Limits;
+use crate::utils::CrabbyError;
+use crate::value::Value;
+
+pub struct Engine {
+    interpreter: Interpreter,
+    // Macros declared by one `eval` can be used by the next
+    macros: MacroExpander,
+    stdout: Rc<RefCell<String>>,
+    stderr: Rc<RefCell<String>>,
+}
+
+// Turns a Rust value into a Crabby one; `None` is `null` and `Result` is `Ok`/`Err`
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+// Turns a Crabby value into a Rust one, failing with a `TypeError` if it is of another type
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, CrabbyError>;
+}
+
+// A Rust function or closure taking up to four arguments that are `FromValue`, and
+// returning something `IntoValue`; see `Engine::register_function`
+pub trait NativeFunction<Args> {
+    fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, CrabbyError>;
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        let stdout = Rc::new(RefCell::new(String::new()));
+        let stderr = Rc::new(RefCell::new(String::new()));
+        let mut interpreter = Interpreter::new(None);
+        interpreter.output = Some(stdout.clone());
+        interpreter.errors = Some(stderr.clone());
+        Self {
+            interpreter,
+            macros: MacroExpander::new(),
+            stdout,
+            stderr,
+        }
+    }
+
+    // What native functions like `read_file` may reach; nothing by default
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.interpreter.set_capabilities(capabilities);
+    }
+
+    // The limits count from here, for every call made afterwards
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.interpreter.set_limits(limits);
+    }
+
+    pub fn set_strict_numeric(&mut self, strict: bool) {
+        self.interpreter.strict_numeric = strict;
+    }
+
+    // Runs `source` after what was run before, and returns the value of its last
+    // statement when that is an expression
+    pub fn eval(&mut self, source: &str) -> Result<Value, CrabbyError> {
+        let program = match TokenStream::tokenize(source.to_string()) {
+            Ok(tokens) => self.macros.expand(parse(tokens)?)?,
+            Err(CrabbyError::LexerError(location)) if location.message == "Empty source file" => {
+                return Ok(Value::Void);
+            }
+            Err(e) => return Err(e),
+        };
+        let mut last = Value::Void;
+        for statement in &program.statements {
+            last = match self.interpreter.interpret_statement(statement)? {
+                Some(value) if matches!(statement, Statement::Expression(_)) => value,
+                _ => Value::Void,
+            };
+        }
+        Ok(last)
+    }
+
+    // Runs the file at `path` like `eval`
+    pub fn eval_file(&mut self, path: &str) -> Result<Value, CrabbyError> {
+        let source = fs::read_to_string(path)?;
+        self.eval(&source)
+    }
+
+    // Calls a function the script defined, or one registered from Rust
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, CrabbyError> {
+        self.interpreter.call(name, args)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl IntoValue) {
+        self.interpreter
+            .env
+            .insert(name.to_string(), value.into_value());
+    }
+
+    pub fn get_global<T: FromValue>(&self, name: &str) -> Result<T, CrabbyError> {
+        match self.interpreter.global(name) {
+            Some(value) => T::from_value(value),
+            None => Err(CrabbyError::InterpreterError(format!(
+                "Undefined variable: {}",
+                name
+            ))),
+        }
+    }
+
+    // A function taking and returning `Value`s as they are; an `Err` stops the script
+    pub fn add_builtin<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(Vec<Value>) -> Result<Value, CrabbyError> + 'static,
+    {
+        self.interpreter.add_builtin(name, function);
+    }
+
+    // A function or closure with typed arguments, such as `|a: i64, b: i64| a + b`.
+    // Arguments of the wrong type or count stop the script with a `TypeError`.
+    pub fn register_function<Args, F>(&mut self, name: &str, function: F)
+    where
+        F: NativeFunction<Args> + 'static,
+    {
+        let owned = name.to_string();
+        self.interpreter
+            .add_builtin(name, move |args| function.call(&owned, args));
+    }
+
+    // What the script printed since the last call
+    pub fn take_stdout(&mut self) -> String {
+        self.stdout.take()
+    }
+
+    // Warnings, such as calls to `@deprecated` functions
+    pub fn take_stderr(&mut self) -> String {
+        self.stderr.take()
+    }
+}
+
+macro_rules! native_function {
+    ($count:expr; $($arg:ident: $type:ident),*) => {
+        impl<F, R, $($type),*> NativeFunction<($($type,)*)> for F
+        where
+            F: Fn($($type),*) -> R,
+            R: IntoValue,
+            $($type: FromValue,)*
+        {
+            fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, CrabbyError> {
+                if args.len() != $count {
+                    return Err(CrabbyError::TypeError(
+                        None,
+                        format!(
+                            "{} takes {} argument{}, got {}",
+                            name,
+                            $count,
+                            if $count == 1 { "" } else { "s" },
+                            args.len()
+                        ),
+                    ));
+                }
+                #[allow(unused_mut, unused_variables)]
+                let mut args = args.into_iter();
+                $(let $arg = $type::from_value(args.next().expect("counted"))?;)*
+                Ok(self($($arg),*).into_value())
+            }
+        }
+    };
+}
+
+native_function!(0;);
+native_function!(1; a: A);
+native_function!(2; a: A, b: B);
+native_function!(3; a: A, b: B, c: C);
+native_function!(4; a: A, b: B, c: C, d: D);
+
+fn mismatch(expected: &str, value: &Value) -> CrabbyError {
+    CrabbyError::TypeError(
+        None,
+        format!("expected {}, got {}", expected, value.type_name()),
+    )
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Void
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        Value::Integer(self as i64)
+    }
+}
+
+impl IntoValue for usize {
+    fn into_value(self) -> Value {
+        match i64::try_from(self) {
+            Ok(n) => Value::Integer(n),
+            Err(_) => Value::BigInt(self.into()),
+        }
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::Array(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Null, IntoValue::into_value)
+    }
+}
+
+impl<T: IntoValue, E: IntoValue> IntoValue for Result<T, E> {
+    fn into_value(self) -> Value {
+        match self {
+            Ok(value) => Value::Ok(Box::new(value.into_value())),
+            Err(error) => Value::Err(Box::new(error.into_value())),
+        }
+    }
+}
+
+// Sorted by key, so the script sees the same order every time
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        self.into_iter().collect::<BTreeMap<_, _>>().into_value()
+    }
+}
+
+impl<T: IntoValue> IntoValue for BTreeMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::Dict(
+            self.into_iter()
+                .map(|(key, value)| (key, value.into_value()))
+                .collect(),
+        )
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        Ok(value)
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Void | Value::Null => Ok(()),
+            other => Err(mismatch("nothing", &other)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            other => Err(mismatch("a boolean", &other)),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Integer(n) => Ok(n),
+            other => Err(mismatch("an integer that fits in 64 bits", &other)),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Integer(n) if i32::try_from(n).is_ok() => Ok(n as i32),
+            other => Err(mismatch("an integer that fits in 32 bits", &other)),
+        }
+    }
+}
+
+impl FromValue for usize {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Integer(n) if n >= 0 => Ok(n as usize),
+            other => Err(mismatch("a non-negative integer", &other)),
+        }
+    }
+}
+
+// Integers are accepted as well
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Float(f) => Ok(f),
+            Value::Integer(n) => Ok(n as f64),
+            other => Err(mismatch("a float", &other)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::String(s) => Ok(s),
+            other => Err(mismatch("a string", &other)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Array(items) => items.into_iter().map(T::from_value).collect(),
+            other => Err(mismatch("an array", &other)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Null => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+impl<T: FromValue, E: FromValue> FromValue for Result<T, E> {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Ok(value) => T::from_value(*value).map(Ok),
+            Value::Err(error) => E::from_value(*error).map(Err),
+            other => Err(mismatch("a result", &other)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        match value {
+            Value::Dict(entries) => entries
+                .into_iter()
+                .map(|(key, value)| Ok((key, T::from_value(value)?)))
+                .collect(),
+            other => Err(mismatch("a dict", &other)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for BTreeMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, CrabbyError> {
+        HashMap::<String, T>::from_value(value).map(|map| map.into_iter().collect())
+    }
+}
@@ -0,0 +1,342 @@
+// Compiles Crabby to the crabvm bytecode format (.cby) and disassembles it
+// Used by `crabby build` and `crabby disasm`. The VM only knows constants, variables,
+// arithmetic and print, so only that subset of the language compiles for now.
+
+use std::fmt;
+
+use num_traits::ToPrimitive;
+
+use crate::ast::{BinaryOp, Expression, Program, Statement};
+use crate::etc::deadcode::statement_span;
+use crate::utils::CrabbyError;
+
+// Magic number, then a version byte, then the constants and the instructions,
+// each prefixed by a little-endian u32 count
+const MAGIC: &[u8] = b"CRAB1Y";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(f64),
+    String(String),
+    Boolean(bool),
+    Nil,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    LoadConstant(u32),
+    LoadVariable(String),
+    StoreVariable(String),
+    Print,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Pop,
+    Return,
+}
+
+#[derive(Debug, Default)]
+pub struct Bytecode {
+    pub constants: Vec<Constant>,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Instruction {
+    fn opcode(&self) -> u8 {
+        match self {
+            Instruction::LoadConstant(_) => 0x01,
+            Instruction::LoadVariable(_) => 0x02,
+            Instruction::StoreVariable(_) => 0x03,
+            Instruction::Add => 0x10,
+            Instruction::Subtract => 0x11,
+            Instruction::Multiply => 0x12,
+            Instruction::Divide => 0x13,
+            Instruction::Print => 0x20,
+            Instruction::Pop => 0x30,
+            Instruction::Return => 0x31,
+        }
+    }
+
+    // The mnemonics crabvm prints
+    fn name(&self) -> &'static str {
+        match self {
+            Instruction::LoadConstant(_) => "ldc",
+            Instruction::LoadVariable(_) => "aload",
+            Instruction::StoreVariable(_) => "astore",
+            Instruction::Add => "iadd",
+            Instruction::Subtract => "isub",
+            Instruction::Multiply => "imul",
+            Instruction::Divide => "idiv",
+            Instruction::Print => "print",
+            Instruction::Pop => "pop",
+            Instruction::Return => "return",
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constant::Number(n) => write!(f, "{}", n),
+            Constant::String(s) => write!(f, "\"{}\"", s),
+            Constant::Boolean(b) => write!(f, "{}", b),
+            Constant::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+impl Bytecode {
+    pub fn compile(program: &Program) -> Result<Self, CrabbyError> {
+        let mut bytecode = Bytecode::default();
+        for statement in &program.statements {
+            bytecode.statement(statement).map_err(|error| {
+                match (error, statement_span(statement)) {
+                    (CrabbyError::CompileError(message), Some(span)) => {
+                        CrabbyError::CompileError(format!("Line {}: {}", span.line, message))
+                    }
+                    (error, _) => error,
+                }
+            })?;
+        }
+        Ok(bytecode)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CrabbyError> {
+        match statement {
+            Statement::Let { name, value, .. }
+            | Statement::Var { name, value, .. }
+            | Statement::Const { name, value, .. } => {
+                self.expression(value)?;
+                let name = name.trim_start_matches("pub ").to_string();
+                self.instructions.push(Instruction::StoreVariable(name));
+            }
+            Statement::Expression(Expression::Call {
+                function,
+                arguments,
+                ..
+            }) if function == "print" && arguments.len() == 1 => {
+                self.expression(&arguments[0])?;
+                self.instructions.push(Instruction::Print);
+            }
+            Statement::Expression(value) => {
+                self.expression(value)?;
+                self.instructions.push(Instruction::Pop);
+            }
+            Statement::Return(value, _) => {
+                self.expression(value)?;
+                self.instructions.push(Instruction::Return);
+            }
+            other => {
+                // `FunctionDef { .. }` is reported as `FunctionDef statements`
+                let debug = format!("{:?}", other);
+                let kind = debug.split(|c: char| !c.is_alphanumeric()).next();
+                return Err(unsupported(&format!("{} statements", kind.unwrap_or(""))));
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<(), CrabbyError> {
+        let instruction = match expression {
+            Expression::Integer(n) => self.constant(Constant::Number(*n as f64)),
+            Expression::BigInteger(n) => {
+                self.constant(Constant::Number(n.to_f64().unwrap_or(f64::NAN)))
+            }
+            Expression::Float(f) => self.constant(Constant::Number(*f)),
+            Expression::String(s) => self.constant(Constant::String(s.clone())),
+            Expression::Boolean(b) => self.constant(Constant::Boolean(*b)),
+            Expression::Null => self.constant(Constant::Nil),
+            Expression::Variable { name, .. } => Instruction::LoadVariable(name.clone()),
+            Expression::Binary {
+                left,
+                operator,
+                right,
+                span: _,
+            } => {
+                let instruction = match operator {
+                    BinaryOp::Add => Instruction::Add,
+                    BinaryOp::Sub => Instruction::Subtract,
+                    BinaryOp::Mul => Instruction::Multiply,
+                    BinaryOp::Div => Instruction::Divide,
+                    _ => return Err(unsupported(&format!("the '{}' operator", operator))),
+                };
+                self.expression(left)?;
+                self.expression(right)?;
+                instruction
+            }
+            Expression::Borrow { expr, .. } | Expression::Move { expr, .. } => {
+                return self.expression(expr);
+            }
+            other => return Err(unsupported(&format!("`{}`", other))),
+        };
+        self.instructions.push(instruction);
+        Ok(())
+    }
+
+    // Equal constants share a slot in the pool
+    fn constant(&mut self, constant: Constant) -> Instruction {
+        let index = match self.constants.iter().position(|c| *c == constant) {
+            Some(index) => index,
+            None => {
+                self.constants.push(constant);
+                self.constants.len() - 1
+            }
+        };
+        Instruction::LoadConstant(index as u32)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        bytes.extend((self.constants.len() as u32).to_le_bytes());
+        for constant in &self.constants {
+            match constant {
+                Constant::Number(n) => {
+                    bytes.push(0x01);
+                    bytes.extend(n.to_le_bytes());
+                }
+                Constant::String(s) => {
+                    bytes.push(0x02);
+                    write_string(&mut bytes, s);
+                }
+                Constant::Boolean(b) => bytes.extend([0x03, *b as u8]),
+                Constant::Nil => bytes.push(0x04),
+            }
+        }
+
+        bytes.extend((self.instructions.len() as u32).to_le_bytes());
+        for instruction in &self.instructions {
+            bytes.push(instruction.opcode());
+            match instruction {
+                Instruction::LoadConstant(index) => bytes.extend(index.to_le_bytes()),
+                Instruction::LoadVariable(name) | Instruction::StoreVariable(name) => {
+                    write_string(&mut bytes, name)
+                }
+                _ => {}
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CrabbyError> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("wrong magic number"));
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported version {}", version)));
+        }
+
+        let mut bytecode = Bytecode::default();
+        for _ in 0..reader.u32()? {
+            let constant = match reader.byte()? {
+                0x01 => Constant::Number(f64::from_le_bytes(reader.array()?)),
+                0x02 => Constant::String(reader.string()?),
+                0x03 => Constant::Boolean(reader.byte()? != 0),
+                0x04 => Constant::Nil,
+                tag => return Err(invalid(&format!("unknown constant tag {:#04x}", tag))),
+            };
+            bytecode.constants.push(constant);
+        }
+        for _ in 0..reader.u32()? {
+            let instruction = match reader.byte()? {
+                0x01 => Instruction::LoadConstant(reader.u32()?),
+                0x02 => Instruction::LoadVariable(reader.string()?),
+                0x03 => Instruction::StoreVariable(reader.string()?),
+                0x10 => Instruction::Add,
+                0x11 => Instruction::Subtract,
+                0x12 => Instruction::Multiply,
+                0x13 => Instruction::Divide,
+                0x20 => Instruction::Print,
+                0x30 => Instruction::Pop,
+                0x31 => Instruction::Return,
+                opcode => return Err(invalid(&format!("unknown opcode {:#04x}", opcode))),
+            };
+            bytecode.instructions.push(instruction);
+        }
+        Ok(bytecode)
+    }
+
+    pub fn disassemble(&self) -> String {
+        let mut output = String::from("Code:\n");
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            let line = match instruction {
+                Instruction::LoadConstant(index) => {
+                    let constant = self
+                        .constants
+                        .get(*index as usize)
+                        .map(|c| c.to_string())
+                        .unwrap_or_else(|| "?".to_string());
+                    format!("{} #{:<8} // {}", instruction.name(), index, constant)
+                }
+                Instruction::LoadVariable(name) | Instruction::StoreVariable(name) => {
+                    format!("{} {}", instruction.name(), name)
+                }
+                _ => instruction.name().to_string(),
+            };
+            output.push_str(&format!("{:>6}: {}\n", i, line));
+        }
+        if !self.constants.is_empty() {
+            output.push_str("Constant pool:\n");
+            for (i, constant) in self.constants.iter().enumerate() {
+                output.push_str(&format!("{:>6} = {}\n", format!("#{}", i), constant));
+            }
+        }
+        output
+    }
+}
+
+fn write_string(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend((s.len() as u32).to_le_bytes());
+    bytes.extend(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], CrabbyError> {
+        let slice = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        self.position += count;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CrabbyError> {
+        Ok(self.take(N)?.try_into().expect("slice has N bytes"))
+    }
+
+    fn byte(&mut self) -> Result<u8, CrabbyError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, CrabbyError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, CrabbyError> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| invalid("string is not valid UTF-8"))
+    }
+}
+
+fn unsupported(what: &str) -> CrabbyError {
+    CrabbyError::CompileError(format!(
+        "Bytecode does not support {} yet; it only has variables, numbers, strings, \
+         booleans, null, + - * / and print, so run this program with `crabby run`",
+        what
+    ))
+}
+
+fn invalid(reason: &str) -> CrabbyError {
+    CrabbyError::CompileError(format!("Invalid bytecode file: {}", reason))
+}
@@ -0,0 +1,182 @@
+// Project settings from `crabby.toml`, found by walking up from a source file
+// Only the bits of TOML a settings file needs: `[sections]`, `# comments`, and
+// `key = value` where a value is an integer, a boolean, a string or a list of strings.
+//
+//     [fmt]
+//     indent_width = 4
+//     max_line_length = 100
+//
+//     [lint]
+//     naming = "deny"
+//     max_nesting_depth = 5
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::CrabbyError;
+
+pub const CONFIG_FILE: &str = "crabby.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    List(Vec<String>),
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    // Section name, then key; keys before any `[section]` are in ""
+    sections: HashMap<String, HashMap<String, ConfigValue>>,
+    pub path: Option<PathBuf>,
+}
+
+impl Config {
+    // The nearest `crabby.toml` in `start` or one of its parents; the defaults if there is none
+    pub fn find(start: &Path) -> Result<Self, CrabbyError> {
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+        let directory = if start.is_file() {
+            start.parent().map(Path::to_path_buf)
+        } else {
+            Some(start)
+        };
+        for directory in directory.iter().flat_map(|d| d.ancestors()) {
+            let path = directory.join(CONFIG_FILE);
+            if path.is_file() {
+                return Self::load(&path);
+            }
+        }
+        Ok(Self::default())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CrabbyError> {
+        let mut config = Self::parse(&fs::read_to_string(path)?)
+            .map_err(|e| CrabbyError::IoError(format!("{}: {}", path.display(), e)))?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut section = String::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+
+            if let Some(name) = line.strip_prefix('[') {
+                section = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("expected ']' after the section name"))?
+                    .trim()
+                    .to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`"))?;
+            let value = parse_value(value.trim()).map_err(|e| error(&e))?;
+            config
+                .sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_string(), value);
+        }
+        Ok(config)
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&ConfigValue> {
+        self.sections.get(section)?.get(key)
+    }
+
+    pub fn integer(&self, section: &str, key: &str) -> Result<Option<i64>, CrabbyError> {
+        match self.get(section, key) {
+            None => Ok(None),
+            Some(ConfigValue::Integer(n)) => Ok(Some(*n)),
+            Some(_) => Err(self.invalid(section, key, "an integer")),
+        }
+    }
+
+    pub fn string(&self, section: &str, key: &str) -> Result<Option<&str>, CrabbyError> {
+        match self.get(section, key) {
+            None => Ok(None),
+            Some(ConfigValue::String(s)) => Ok(Some(s)),
+            Some(_) => Err(self.invalid(section, key, "a string")),
+        }
+    }
+
+    // The keys set in `section`, sorted
+    pub fn keys(&self, section: &str) -> Vec<&str> {
+        let mut keys: Vec<&str> = self
+            .sections
+            .get(section)
+            .map(|keys| keys.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+        keys.sort();
+        keys
+    }
+
+    pub fn invalid(&self, section: &str, key: &str, expected: &str) -> CrabbyError {
+        let file = self
+            .path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| CONFIG_FILE.to_string());
+        CrabbyError::IoError(format!(
+            "{}: `{}.{}` must be {}",
+            file, section, key, expected
+        ))
+    }
+}
+
+// A `#` starts a comment unless it is inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<ConfigValue, String> {
+    match value {
+        "true" => return Ok(ConfigValue::Boolean(true)),
+        "false" => return Ok(ConfigValue::Boolean(false)),
+        _ => {}
+    }
+    if let Some(items) = value.strip_prefix('[') {
+        let items = items
+            .strip_suffix(']')
+            .ok_or("expected ']' at the end of the list")?;
+        return items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| parse_string(item).ok_or(format!("expected a string, found `{}`", item)))
+            .collect::<Result<_, _>>()
+            .map(ConfigValue::List);
+    }
+    if let Some(string) = parse_string(value) {
+        return Ok(ConfigValue::String(string));
+    }
+    value
+        .replace('_', "")
+        .parse()
+        .map(ConfigValue::Integer)
+        .map_err(|_| format!("unknown value `{}`", value))
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    value
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .map(str::to_string)
+}
@@ -1,146 +1,265 @@
 // Crabby scans crab code then checks if it's a dead/unused code or not
+//
+// Names are resolved like the interpreter does: each function, lambda and test
+// body is a scope, while the blocks of `if`, `while` and friends share the scope
+// of the function around them. Top-level functions and types can be used before
+// their definition, so they are declared before anything else is looked at.
 
-use crate::ast::{Expression, Program, Statement};
-use crate::utils::CrabbyError;
-use std::collections::{HashMap, HashSet};
+use crate::ast::{
+    Decorator, Expression, FStringPart, Parameter, PatternKind, Program, Statement, TypeExpr,
+    Visibility,
+};
+use crate::utils::{CrabbyError, Span, render_diagnostics};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use std::collections::HashSet;
+use std::ops::Range;
 
 pub struct DeadCodeAnalyzer {
-    defined_symbols: HashMap<String, SymbolInfo>,
-    used_symbols: HashSet<String>,
-    pub_exports: HashSet<String>,
+    scopes: Vec<Scope>,
+    blocks: usize, // blocks entered so far, which also numbers them
+    current_block: usize,
+    // Names read in a function body before the top-level variable they refer to is defined
+    pending: HashSet<String>,
+    warnings: Vec<DeadCodeWarning>,
+}
+
+struct Scope {
+    bindings: Vec<SymbolInfo>,
+    span: Span,              // what unreachable code without a span of its own points at
+    report_parameters: bool, // methods keep their signature for callers, used or not
 }
 
 #[derive(Debug)]
 pub struct SymbolInfo {
+    name: String,
     kind: SymbolKind,
-    line: usize,
-    column: usize,
+    span: Span,     // the first declaration
+    assigned: Span, // the declaration that gave the current value
+    block: usize,   // the block holding the current value's declaration
+    used: bool,
+    read: bool, // whether the current value has been read
+    exported: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DeadCodeWarning {
-    pub symbol: String,
-    pub kind: String,
-    pub line: usize,
-    pub column: usize,
+    pub kind: WarningKind,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarningKind {
+    Unused { name: String, kind: SymbolKind },
+    Unreachable,
+    Shadowed { name: String, previous: Span },
+    NeverRead { name: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SymbolKind {
     Function,
     Variable,
+    Parameter,
+    Import,
     Struct,
     Enum,
-    Macro,
+    Class,
+}
+
+impl Default for DeadCodeAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DeadCodeAnalyzer {
     pub fn new() -> Self {
         Self {
-            defined_symbols: HashMap::new(),
-            used_symbols: HashSet::new(),
-            pub_exports: HashSet::new(),
+            scopes: Vec::new(),
+            blocks: 0,
+            current_block: 0,
+            pending: HashSet::new(),
+            warnings: Vec::new(),
         }
     }
 
     pub fn analyze(&mut self, program: &Program) -> Result<Vec<DeadCodeWarning>, CrabbyError> {
+        self.scopes.push(Scope {
+            bindings: Vec::new(),
+            span: Span::default(),
+            report_parameters: true,
+        });
         self.collect_definitions(program)?;
-        self.collect_usage(program)?;
-
-        // Generate warnings for unused symbols
-        let mut warnings = Vec::new();
-
-        for (name, info) in &self.defined_symbols {
-            // Skips if symbol is public or used
-            if self.pub_exports.contains(name) || self.used_symbols.contains(name) {
-                continue;
-            }
-
-            warnings.push(DeadCodeWarning {
-                symbol: name.clone(),
-                kind: info.kind.to_string(),
-                line: info.line,
-                column: info.column,
-            });
-        }
+        self.analyze_block(&program.statements)?;
+        self.pop_scope();
 
+        let mut warnings = std::mem::take(&mut self.warnings);
+        warnings.sort_by_key(|warning| warning.span.start);
         Ok(warnings)
     }
 
+    // Declares the top-level functions and types, which may be used above their definition
     fn collect_definitions(&mut self, program: &Program) -> Result<(), CrabbyError> {
         for stmt in &program.statements {
             match stmt {
                 Statement::FunctionDef {
                     name,
-                    params: _,
-                    body: _,
-                    return_type: _,
-                    docstring: _,
-                    visibility: _,
+                    visibility,
+                    decorators,
+                    span,
+                    ..
+                }
+                | Statement::FunctionFun {
+                    name,
+                    visibility,
+                    decorators,
+                    span,
+                    ..
                 } => {
-                    if name.starts_with("pub ") {
-                        let clean_name = name.trim_start_matches("pub ").to_string();
-                        self.pub_exports.insert(clean_name.clone());
-                        self.add_symbol(clean_name, SymbolKind::Function, 0, 0);
-                    } else {
-                        self.add_symbol(name.clone(), SymbolKind::Function, 0, 0);
-                    }
+                    let exported = *visibility == Visibility::Public || name.starts_with("pub ");
+                    let name = name.trim_start_matches("pub ").to_string();
+                    // Test functions are run by the test runner, not called
+                    let test = decorators.iter().any(|decorator| decorator.name == "test");
+                    self.declare(name, SymbolKind::Function, *span, exported || test);
                 }
-                Statement::Let { name, value: _ } => {
-                    if name.starts_with("pub ") {
-                        let clean_name = name.trim_start_matches("pub ").to_string();
-                        self.pub_exports.insert(clean_name.clone());
-                        self.add_symbol(clean_name, SymbolKind::Variable, 0, 0);
-                    } else {
-                        self.add_symbol(name.clone(), SymbolKind::Variable, 0, 0);
-                    }
+                Statement::Extern { name, span, .. } => {
+                    self.declare(name.clone(), SymbolKind::Function, *span, false);
                 }
                 Statement::Struct {
                     name,
-                    fields: _,
-                    where_clause: _,
+                    visibility,
+                    span,
+                    ..
                 } => {
-                    self.add_symbol(name.clone(), SymbolKind::Struct, 0, 0);
+                    let exported = *visibility == Visibility::Public;
+                    self.declare(name.clone(), SymbolKind::Struct, *span, exported);
+                }
+                Statement::ExternStruct { name, span, .. } => {
+                    self.declare(name.clone(), SymbolKind::Struct, *span, false);
                 }
                 Statement::Enum {
                     name,
-                    variants: _,
-                    where_clause: _,
+                    visibility,
+                    span,
+                    ..
+                } => {
+                    let exported = *visibility == Visibility::Public;
+                    self.declare(name.clone(), SymbolKind::Enum, *span, exported);
+                }
+                Statement::Class {
+                    name,
+                    visibility,
+                    span,
+                    ..
                 } => {
-                    self.add_symbol(name.clone(), SymbolKind::Enum, 0, 0);
-                }
-                // Statement::Macro {
-                //     name,
-                //     params: _,
-                //     body: _,
-                // } => {
-                //     self.add_symbol(name.clone(), SymbolKind::Macro, 0, 0);
-                // }
+                    let exported = *visibility == Visibility::Public;
+                    self.declare(name.clone(), SymbolKind::Class, *span, exported);
+                }
+                Statement::Import { name, span, .. } => {
+                    self.declare(name.clone(), SymbolKind::Import, *span, false);
+                }
                 _ => {}
             }
         }
         Ok(())
     }
 
-    fn collect_usage(&mut self, program: &Program) -> Result<(), CrabbyError> {
-        for stmt in &program.statements {
+    fn analyze_block(&mut self, statements: &[Statement]) -> Result<(), CrabbyError> {
+        let outer_block = self.current_block;
+        self.blocks += 1;
+        self.current_block = self.blocks;
+
+        let mut reported = false;
+        for (i, stmt) in statements.iter().enumerate() {
+            if !reported && i > 0 && terminates(&statements[i - 1]) {
+                let span = statement_span(stmt).unwrap_or(self.scope().span);
+                self.warn(WarningKind::Unreachable, span);
+                reported = true;
+            }
+            // Unreachable code is still looked at, so what it uses is not reported too
             self.analyze_statement(stmt)?;
         }
+
+        self.current_block = outer_block;
         Ok(())
     }
 
     fn analyze_statement(&mut self, stmt: &Statement) -> Result<(), CrabbyError> {
         match stmt {
-            Statement::Expression(expr) => self.analyze_expression(expr)?,
-            Statement::Block(statements) => {
-                for stmt in statements {
-                    self.analyze_statement(stmt)?;
+            Statement::FunctionDef {
+                name,
+                params,
+                body,
+                return_type,
+                decorators,
+                span,
+                ..
+            }
+            | Statement::FunctionFun {
+                name,
+                params,
+                body,
+                return_type,
+                decorators,
+                span,
+                ..
+            } => {
+                self.analyze_decorators(decorators)?;
+                // Top-level functions were declared up front
+                if self.scopes.len() > 1 {
+                    self.declare(name.clone(), SymbolKind::Function, *span, false);
+                }
+                if let Some(return_type) = return_type {
+                    self.use_type(return_type);
+                }
+                self.analyze_function(params, body, *span, true)?;
+            }
+            Statement::AsyncFunction {
+                params,
+                body,
+                return_type,
+                ..
+            } => {
+                if let Some(return_type) = return_type {
+                    self.use_type(return_type);
+                }
+                let span = self.scope().span;
+                self.analyze_function(params, body, span, true)?;
+            }
+            Statement::Let {
+                name,
+                value,
+                type_annotation,
+                mutable: _,
+                span,
+            }
+            | Statement::Var {
+                name,
+                value,
+                type_annotation,
+                span,
+            }
+            | Statement::Const {
+                name,
+                value,
+                type_annotation,
+                span,
+            } => {
+                self.analyze_expression(value)?;
+                if let Some(type_annotation) = type_annotation {
+                    self.use_type(type_annotation);
                 }
+                let exported = name.starts_with("pub ");
+                let name = name.trim_start_matches("pub ").to_string();
+                self.assign(name, *span, exported);
             }
+            Statement::Return(value, _) => self.analyze_expression(value)?,
+            Statement::Block(statements) => self.analyze_block(statements)?,
             Statement::If {
                 condition,
                 then_branch,
                 else_branch,
+                span: _,
             } => {
                 self.analyze_expression(condition)?;
                 self.analyze_statement(then_branch)?;
@@ -148,33 +267,260 @@ impl DeadCodeAnalyzer {
                     self.analyze_statement(else_branch)?;
                 }
             }
-            Statement::While { condition, body } => {
+            Statement::While {
+                condition, body, ..
+            } => {
                 self.analyze_expression(condition)?;
                 self.analyze_statement(body)?;
             }
+            Statement::Loop { count, body, .. } => {
+                self.analyze_expression(count)?;
+                self.analyze_statement(body)?;
+            }
             Statement::ForIn {
-                variable: _,
+                variable,
                 iterator,
                 body,
+                span,
             } => {
                 self.analyze_expression(iterator)?;
+                // Loop variables are often only there to count, so they are never reported
+                if !self.scope().bindings.iter().any(|s| &s.name == variable) {
+                    self.declare(variable.clone(), SymbolKind::Variable, *span, true);
+                }
                 self.analyze_statement(body)?;
             }
-            _ => {}
+            Statement::Match { value, arms } => {
+                self.analyze_expression(value)?;
+                for arm in arms {
+                    self.analyze_expression(&arm.pattern)?;
+                    self.analyze_expression(&arm.body)?;
+                }
+            }
+            Statement::Struct {
+                fields,
+                where_clause,
+                ..
+            } => {
+                for field in fields {
+                    self.analyze_expression(&field.type_expr)?;
+                }
+                if let Some(clause) = where_clause {
+                    self.analyze_expression(clause)?;
+                }
+            }
+            Statement::Enum {
+                variants,
+                where_clause,
+                ..
+            } => {
+                for variant in variants {
+                    for field in variant.fields.iter().flatten() {
+                        self.analyze_expression(field)?;
+                    }
+                }
+                if let Some(clause) = where_clause {
+                    self.analyze_expression(clause)?;
+                }
+            }
+            Statement::Class {
+                parent,
+                methods,
+                decorators,
+                ..
+            } => {
+                if let Some(parent) = parent {
+                    self.use_name(parent);
+                }
+                self.analyze_decorators(decorators)?;
+                self.analyze_methods(methods)?;
+            }
+            Statement::Extend {
+                class,
+                parent,
+                methods,
+            } => {
+                self.use_name(class);
+                self.use_name(parent);
+                self.analyze_methods(methods)?;
+            }
+            Statement::Trait { methods, .. } => {
+                for method in methods {
+                    let span = self.scope().span;
+                    self.analyze_function(&method.params, &method.body, span, false)?;
+                }
+            }
+            Statement::Impl {
+                target,
+                trait_name,
+                methods,
+            } => {
+                self.use_name(target);
+                if let Some(trait_name) = trait_name {
+                    self.use_name(trait_name);
+                }
+                for method in methods {
+                    let span = self.scope().span;
+                    self.analyze_function(&method.params, &method.body, span, false)?;
+                }
+            }
+            Statement::TypeDef { target, .. } => self.use_type(target),
+            Statement::ExternStruct { fields, .. } => {
+                for field in fields {
+                    self.use_type(&field.type_annotation);
+                }
+            }
+            Statement::ArrayAssign {
+                array,
+                index,
+                value,
+            } => {
+                self.analyze_expression(array)?;
+                self.analyze_expression(index)?;
+                self.analyze_expression(value)?;
+            }
+            Statement::Assert {
+                condition, message, ..
+            } => {
+                self.analyze_expression(condition)?;
+                if let Some(message) = message {
+                    self.analyze_expression(message)?;
+                }
+            }
+            Statement::Test { body, span, .. } => {
+                self.analyze_function(&[], body, *span, true)?;
+            }
+            Statement::Extern {
+                name,
+                params,
+                return_type,
+                span,
+                ..
+            } => {
+                if self.scopes.len() > 1 {
+                    self.declare(name.clone(), SymbolKind::Function, *span, false);
+                }
+                for type_annotation in params
+                    .iter()
+                    .filter_map(|param| param.type_annotation.as_ref())
+                    .chain(return_type)
+                {
+                    self.use_type(type_annotation);
+                }
+            }
+            Statement::And { left, right } => {
+                self.use_name(left);
+                self.use_name(right);
+            }
+            Statement::Expression(expr) => self.analyze_expression(expr)?,
+            // Macros are expanded before the analysis, and imports declared up front
+            Statement::Macro { .. } | Statement::Import { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn analyze_methods(&mut self, methods: &[Statement]) -> Result<(), CrabbyError> {
+        for method in methods {
+            if let Statement::FunctionDef {
+                params,
+                body,
+                decorators,
+                span,
+                ..
+            }
+            | Statement::FunctionFun {
+                params,
+                body,
+                decorators,
+                span,
+                ..
+            } = method
+            {
+                self.analyze_decorators(decorators)?;
+                self.analyze_function(params, body, *span, false)?;
+            }
         }
         Ok(())
     }
 
+    fn analyze_decorators(&mut self, decorators: &[Decorator]) -> Result<(), CrabbyError> {
+        for decorator in decorators {
+            self.use_name(&decorator.name);
+            for arg in &decorator.arguments {
+                self.analyze_expression(arg)?;
+            }
+            for arg in &decorator.keyword_arguments {
+                self.analyze_expression(&arg.value)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Parameters get a scope of their own, so the body may shadow them
+    fn analyze_function(
+        &mut self,
+        params: &[Parameter],
+        body: &Statement,
+        span: Span,
+        report_parameters: bool,
+    ) -> Result<(), CrabbyError> {
+        // Defaults are evaluated where the function is called, outside its scope
+        for param in params {
+            if let Some(default) = &param.default {
+                self.analyze_expression(default)?;
+            }
+            if let Some(type_annotation) = &param.type_annotation {
+                self.use_type(type_annotation);
+            }
+        }
+
+        self.scopes.push(Scope {
+            bindings: Vec::new(),
+            span,
+            report_parameters,
+        });
+        for param in params {
+            // `self` is passed to every method, whether it needs it or not
+            let exported = param.name == "self";
+            self.declare(
+                param.name.clone(),
+                SymbolKind::Parameter,
+                param.span,
+                exported,
+            );
+        }
+        self.scopes.push(Scope {
+            bindings: Vec::new(),
+            span,
+            report_parameters,
+        });
+        let result = match body {
+            Statement::Block(statements) => self.analyze_block(statements),
+            body => self.analyze_statement(body),
+        };
+        self.pop_scope();
+        self.pop_scope();
+        result
+    }
+
     fn analyze_expression(&mut self, expr: &Expression) -> Result<(), CrabbyError> {
         match expr {
-            Expression::Variable(name) => {
-                self.used_symbols.insert(name.clone());
-            }
+            Expression::Variable { name, .. } => self.use_name(name),
             Expression::Call {
                 function,
                 arguments,
+                keyword_arguments,
+                span: _,
             } => {
-                self.used_symbols.insert(function.clone());
+                self.use_name(function);
+                for arg in arguments {
+                    self.analyze_expression(arg)?;
+                }
+                for arg in keyword_arguments {
+                    self.analyze_expression(&arg.value)?;
+                }
+            }
+            Expression::MacroCall { arguments, .. } | Expression::Array(arguments) => {
                 for arg in arguments {
                     self.analyze_expression(arg)?;
                 }
@@ -183,10 +529,20 @@ impl DeadCodeAnalyzer {
                 left,
                 operator: _,
                 right,
+                span: _,
             } => {
                 self.analyze_expression(left)?;
                 self.analyze_expression(right)?;
             }
+            Expression::Unary {
+                operator: _,
+                operand,
+            } => {
+                self.analyze_expression(operand)?;
+            }
+            Expression::Borrow { expr, .. } | Expression::Move { expr, .. } => {
+                self.analyze_expression(expr)?;
+            }
             Expression::Where {
                 expr,
                 condition,
@@ -196,25 +552,340 @@ impl DeadCodeAnalyzer {
                 self.analyze_expression(condition)?;
                 self.analyze_statement(body)?;
             }
+            Expression::Member { object, .. } => self.analyze_expression(object)?,
+            Expression::MethodCall {
+                object,
+                arguments,
+                keyword_arguments,
+                ..
+            } => {
+                self.analyze_expression(object)?;
+                for arg in arguments {
+                    self.analyze_expression(arg)?;
+                }
+                for arg in keyword_arguments {
+                    self.analyze_expression(&arg.value)?;
+                }
+            }
+            Expression::Index { array, index } => {
+                self.analyze_expression(array)?;
+                self.analyze_expression(index)?;
+            }
+            Expression::Lambda { params, body } => {
+                let span = self.scope().span;
+                self.analyze_function(params, body, span, true)?;
+            }
+            Expression::FString(parts) => {
+                for part in parts {
+                    if let FStringPart::Hole { expression, .. } = part {
+                        self.analyze_expression(expression)?;
+                    }
+                }
+            }
+            Expression::Pattern(pattern) => {
+                if let PatternKind::Literal(expr) = &**pattern {
+                    self.analyze_expression(expr)?;
+                }
+            }
+            Expression::Is { value, type_expr } => {
+                self.analyze_expression(value)?;
+                self.use_type(type_expr);
+            }
+            Expression::Range(expr)
+            | Expression::Try(expr)
+            | Expression::TypeOf(expr)
+            | Expression::Await { expr } => self.analyze_expression(expr)?,
             _ => {}
         }
         Ok(())
     }
 
-    fn add_symbol(&mut self, name: String, kind: SymbolKind, line: usize, column: usize) {
-        self.defined_symbols
-            .insert(name, SymbolInfo { kind, line, column });
+    // Type annotations use the structs, enums and classes they name
+    fn use_type(&mut self, type_expr: &TypeExpr) {
+        match type_expr {
+            TypeExpr::Named(name) => self.use_name(name),
+            TypeExpr::List(inner)
+            | TypeExpr::Optional(inner)
+            | TypeExpr::Array { element: inner, .. } => self.use_type(inner),
+            TypeExpr::Generic { name, arguments } => {
+                self.use_name(name);
+                for argument in arguments {
+                    self.use_type(argument);
+                }
+            }
+            TypeExpr::Function { params, returns } => {
+                for param in params {
+                    self.use_type(param);
+                }
+                self.use_type(returns);
+            }
+        }
+    }
+
+    fn use_name(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(symbol) = scope.bindings.iter_mut().find(|s| s.name == name) {
+                symbol.used = true;
+                symbol.read = true;
+                return;
+            }
+        }
+        // Perhaps a top-level variable defined further down, or a builtin
+        if self.scopes.len() > 1 {
+            self.pending.insert(name.to_string());
+        }
+    }
+
+    // A `let`, `var` or `const`, which replaces the value of a variable in the same scope
+    fn assign(&mut self, name: String, span: Span, exported: bool) {
+        let block = self.current_block;
+        let existing = self
+            .scope()
+            .bindings
+            .iter()
+            .position(|symbol| symbol.name == name);
+        let Some(index) = existing else {
+            self.declare(name, SymbolKind::Variable, span, exported);
+            return;
+        };
+
+        let symbol = &self.scope().bindings[index];
+        // An earlier value in the same block can't be read on some other path
+        if symbol.block == block && !symbol.read && !symbol.name.starts_with('_') {
+            let assigned = symbol.assigned;
+            self.warn(WarningKind::NeverRead { name }, assigned);
+        }
+        let symbol = &mut self.scopes.last_mut().unwrap().bindings[index];
+        symbol.assigned = span;
+        symbol.block = block;
+        symbol.read = false;
+        symbol.exported |= exported;
+    }
+
+    fn declare(&mut self, name: String, kind: SymbolKind, span: Span, exported: bool) {
+        let variable = matches!(kind, SymbolKind::Variable | SymbolKind::Parameter);
+        if variable && !exported && !name.starts_with('_') {
+            let previous = self.scopes[..self.scopes.len() - 1]
+                .iter()
+                .rev()
+                .find_map(|scope| scope.bindings.iter().find(|s| s.name == name))
+                .filter(|s| matches!(s.kind, SymbolKind::Variable | SymbolKind::Parameter));
+            if let Some(previous) = previous {
+                let previous = previous.span;
+                self.warn(
+                    WarningKind::Shadowed {
+                        name: name.clone(),
+                        previous,
+                    },
+                    span,
+                );
+            }
+        }
+
+        // A function body read this top-level variable before it was defined
+        let used = self.scopes.len() == 1 && self.pending.remove(&name);
+        let block = self.current_block;
+        self.scopes.last_mut().unwrap().bindings.push(SymbolInfo {
+            name,
+            kind,
+            span,
+            assigned: span,
+            block,
+            used,
+            read: used,
+            exported,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for symbol in scope.bindings {
+            if symbol.used || symbol.exported || symbol.name.starts_with('_') {
+                continue;
+            }
+            if symbol.kind == SymbolKind::Parameter && !scope.report_parameters {
+                continue;
+            }
+            self.warn(
+                WarningKind::Unused {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                },
+                // A variable given a new value in its own scope was reported there
+                // as never read, so this points at the binding that was left
+                symbol.assigned,
+            );
+        }
+    }
+
+    fn scope(&self) -> &Scope {
+        self.scopes.last().unwrap()
+    }
+
+    fn warn(&mut self, kind: WarningKind, span: Span) {
+        self.warnings.push(DeadCodeWarning { kind, span });
+    }
+}
+
+// Whether control never gets past `stmt`
+pub fn terminates(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Return(..) => true,
+        Statement::Block(statements) => statements.iter().any(terminates),
+        Statement::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => terminates(then_branch) && terminates(else_branch),
+        _ => false,
     }
 }
 
-impl SymbolKind {
-    fn to_string(&self) -> String {
-        match self {
-            SymbolKind::Function => "function".to_string(),
-            SymbolKind::Variable => "variable".to_string(),
-            SymbolKind::Struct => "struct".to_string(),
-            SymbolKind::Enum => "enum".to_string(),
-            SymbolKind::Macro => "macro".to_string(),
+// Where `stmt` starts, as far as the spans in the tree tell
+pub fn statement_span(stmt: &Statement) -> Option<Span> {
+    match stmt {
+        Statement::FunctionDef { span, .. }
+        | Statement::FunctionFun { span, .. }
+        | Statement::Let { span, .. }
+        | Statement::Var { span, .. }
+        | Statement::Const { span, .. }
+        | Statement::TypeDef { span, .. }
+        | Statement::Struct { span, .. }
+        | Statement::Enum { span, .. }
+        | Statement::Class { span, .. }
+        | Statement::Import { span, .. }
+        | Statement::Extern { span, .. }
+        | Statement::ExternStruct { span, .. }
+        | Statement::Assert { span, .. }
+        | Statement::Test { span, .. }
+        | Statement::If { span, .. }
+        | Statement::While { span, .. }
+        | Statement::Loop { span, .. }
+        | Statement::ForIn { span, .. } => Some(*span),
+        Statement::Return(_, span) => Some(*span),
+        Statement::Expression(value) => expression_span(value),
+        Statement::Match { value, .. } => expression_span(value),
+        Statement::Block(statements) => statements.iter().find_map(statement_span),
+        _ => None,
+    }
+}
+
+fn expression_span(expr: &Expression) -> Option<Span> {
+    match expr {
+        Expression::Call { span, .. }
+        | Expression::MacroCall { span, .. }
+        | Expression::Maybe(span)
+        | Expression::Probably(span)
+        | Expression::Variable { span, .. }
+        | Expression::Borrow { span, .. }
+        | Expression::Move { span, .. } => Some(*span),
+        Expression::Binary { left, span, .. } => expression_span(left).or(Some(*span)),
+        Expression::Unary { operand, .. } => expression_span(operand),
+        Expression::Member { object, .. } | Expression::MethodCall { object, .. } => {
+            expression_span(object)
+        }
+        Expression::Try(expr) | Expression::Await { expr } => expression_span(expr),
+        _ => None,
+    }
+}
+
+impl DeadCodeWarning {
+    pub fn message(&self) -> String {
+        match &self.kind {
+            WarningKind::Unused { name, kind } => format!("unused {} `{}`", kind, name),
+            WarningKind::Unreachable => "unreachable statement".to_string(),
+            WarningKind::Shadowed { name, .. } => {
+                format!("`{}` shadows a variable of the same name", name)
+            }
+            WarningKind::NeverRead { name } => {
+                format!("value assigned to `{}` is never read", name)
+            }
+        }
+    }
+
+    // Names the check, as in `warning[unused_parameter]`; spelled like the lint rules
+    pub fn code(&self) -> &'static str {
+        match &self.kind {
+            WarningKind::Unused { kind, .. } => match kind {
+                SymbolKind::Variable => "unused_variable",
+                SymbolKind::Parameter => "unused_parameter",
+                SymbolKind::Import => "unused_import",
+                _ => "dead_code",
+            },
+            WarningKind::Unreachable => "unreachable_code",
+            WarningKind::Shadowed { .. } => "shadowed_variable",
+            WarningKind::NeverRead { .. } => "unused_assignment",
         }
     }
+
+    // The name the warning is about, if any
+    pub fn symbol(&self) -> Option<&str> {
+        match &self.kind {
+            WarningKind::Unused { name, .. }
+            | WarningKind::Shadowed { name, .. }
+            | WarningKind::NeverRead { name } => Some(name),
+            WarningKind::Unreachable => None,
+        }
+    }
+
+    // The name in the declaration the span starts at, or the span itself when the warning
+    // is not about a name
+    pub fn name_range(&self, source: &str) -> Range<usize> {
+        let span = self.span.start..self.span.end;
+        let (Some(name), Some(rest)) = (self.symbol(), source.get(self.span.start..)) else {
+            return span;
+        };
+        let line = rest.split('\n').next().unwrap_or_default();
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        line.match_indices(name)
+            .find(|(i, _)| {
+                !line[..*i].ends_with(is_word) && !line[i + name.len()..].starts_with(is_word)
+            })
+            .map_or(span, |(i, _)| {
+                self.span.start + i..self.span.start + i + name.len()
+            })
+    }
+
+    fn to_diagnostic(&self, source: &str, deny: bool) -> Diagnostic<()> {
+        let diagnostic = if deny {
+            Diagnostic::error()
+        } else {
+            Diagnostic::warning()
+        };
+        let primary = Label::primary((), self.name_range(source));
+        let diagnostic = diagnostic
+            .with_code(self.code())
+            .with_message(self.message());
+        match &self.kind {
+            WarningKind::Unused { kind, .. } => {
+                let diagnostic = diagnostic.with_labels(vec![primary]);
+                if matches!(kind, SymbolKind::Variable | SymbolKind::Parameter) {
+                    diagnostic.with_notes(vec![
+                        "prefix the name with `_` if it is unused on purpose".to_string(),
+                    ])
+                } else {
+                    diagnostic
+                }
+            }
+            WarningKind::Unreachable => diagnostic.with_labels(vec![
+                primary.with_message("this is never run, as the code above always returns"),
+            ]),
+            WarningKind::Shadowed { previous, .. } => diagnostic.with_labels(vec![
+                primary,
+                Label::secondary((), previous.start..previous.end)
+                    .with_message("previously declared here"),
+            ]),
+            WarningKind::NeverRead { .. } => {
+                diagnostic.with_labels(vec![primary.with_message("overwritten before it is read")])
+            }
+        }
+    }
+}
+
+// Renders warnings against the source they were found in, as errors under `--deny-warnings`
+pub fn render(warnings: &[DeadCodeWarning], file_name: &str, source: &str, deny: bool) -> String {
+    let diagnostics = warnings
+        .iter()
+        .map(|warning| warning.to_diagnostic(source, deny));
+    render_diagnostics(diagnostics, file_name, source)
 }
@@ -1,76 +1,739 @@
 // Documentation Generator in Crabby
-// for comments like """this""" in crab codes
-// still being developed and improved currently.
+// for comments like """this""" and `/// this` in crab codes. Every source file
+// is a module; `crabby doc` renders them as a static HTML site, JSON or markdown.
 
-use crate::ast::Statement;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
-pub struct Documentation {
+use serde_json::{Value as Json, json};
+
+use crate::ast::{Program, Statement, Visibility};
+use crate::etc::printer::{
+    expression, expressions, return_suffix, type_params_suffix, visibility_prefix,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemKind {
+    Function,
+    Struct,
+    Enum,
+    Class,
+    Trait,
+    Method,
+    Field,
+    Variant,
+}
+
+impl ItemKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ItemKind::Function => "function",
+            ItemKind::Struct => "struct",
+            ItemKind::Enum => "enum",
+            ItemKind::Class => "class",
+            ItemKind::Trait => "trait",
+            ItemKind::Method => "method",
+            ItemKind::Field => "field",
+            ItemKind::Variant => "variant",
+        }
+    }
+
+    // Types are what signatures link to
+    fn is_type(self) -> bool {
+        matches!(
+            self,
+            ItemKind::Struct | ItemKind::Enum | ItemKind::Class | ItemKind::Trait
+        )
+    }
+}
+
+pub struct Item {
+    pub kind: ItemKind,
     pub name: String,
-    pub description: String,
-    pub params: Vec<String>,
-    pub body: Box<Statement>,
-    pub return_type: String,
+    pub signature: String,
+    pub doc: String,
+    pub members: Vec<Item>,
+}
+
+pub struct Module {
+    pub name: String,
+    pub doc: String,
+    pub items: Vec<Item>,
+    pub imports: Vec<String>, // the modules it imports from
+}
+
+pub struct Documentation {
+    pub modules: Vec<Module>,
+    // Items are private unless declared `pub`
+    pub include_private: bool,
 }
 
 impl Documentation {
-    pub fn generate_docs(statement: &Vec<Statement>) -> Vec<Documentation> {
-        let mut docs = Vec::new();
+    pub fn new(include_private: bool) -> Self {
+        Self {
+            modules: Vec::new(),
+            include_private,
+        }
+    }
+
+    pub fn add_module(&mut self, name: &str, program: &Program) {
+        let mut items = Vec::new();
+        let mut imports = Vec::new();
+        for statement in &program.statements {
+            if let Statement::Import {
+                source: Some(source),
+                ..
+            } = statement
+            {
+                imports.push(module_name(Path::new(source)));
+            }
+            if let Some(item) = self.item(statement, false) {
+                items.push(item);
+            }
+        }
+
+        self.modules.push(Module {
+            name: name.to_string(),
+            doc: program.docstring.clone(),
+            items,
+            imports,
+        });
+    }
 
-        for node in statement {
-            match node {
-                Statement::FunctionDef {
+    fn item(&self, statement: &Statement, in_class: bool) -> Option<Item> {
+        if let Statement::Struct { visibility, .. }
+        | Statement::Enum { visibility, .. }
+        | Statement::Class { visibility, .. }
+        | Statement::Trait { visibility, .. } = statement
+            && *visibility != Visibility::Public
+            && !self.include_private
+        {
+            return None;
+        }
+
+        let item = match statement {
+            Statement::FunctionDef {
+                name,
+                type_params,
+                params,
+                return_type,
+                docstring,
+                visibility,
+                ..
+            }
+            | Statement::FunctionFun {
+                name,
+                type_params,
+                params,
+                return_type,
+                docstring,
+                visibility,
+                ..
+            } => {
+                // Class methods are reachable through their class whatever they say
+                if !in_class && *visibility != Visibility::Public && !self.include_private {
+                    return None;
+                }
+                let keyword = match statement {
+                    Statement::FunctionFun { .. } => "fun",
+                    _ => "def",
+                };
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                Item {
+                    kind: if in_class {
+                        ItemKind::Method
+                    } else {
+                        ItemKind::Function
+                    },
+                    name: name.clone(),
+                    signature: format!(
+                        "{}{} {}{}({}){}",
+                        visibility_prefix(visibility),
+                        keyword,
+                        name,
+                        type_params_suffix(type_params),
+                        params.join(", "),
+                        return_suffix(return_type)
+                    ),
+                    doc: docstring.clone(),
+                    members: Vec::new(),
+                }
+            }
+            Statement::Struct {
+                name,
+                type_params,
+                fields,
+                docstring,
+                visibility,
+                ..
+            } => Item {
+                kind: ItemKind::Struct,
+                name: name.clone(),
+                signature: format!(
+                    "{}struct {}{}",
+                    visibility_prefix(visibility),
                     name,
-                    params,
-                    body,
-                    return_type,
-                    docstring,
-                    visibility: _,
-                } => {
-                    docs.push(Documentation {
-                        name: name.clone(),
-                        body: body.clone(),
-                        description: docstring.clone(),
-                        params: params.clone(),
-                        return_type: return_type.clone(),
-                    });
+                    type_params_suffix(type_params)
+                ),
+                doc: docstring.clone(),
+                members: fields
+                    .iter()
+                    .map(|field| {
+                        member(
+                            ItemKind::Field,
+                            &field.name,
+                            format!("{}: {}", field.name, expression(&field.type_expr)),
+                        )
+                    })
+                    .collect(),
+            },
+            Statement::Enum {
+                name,
+                type_params,
+                variants,
+                docstring,
+                visibility,
+                ..
+            } => Item {
+                kind: ItemKind::Enum,
+                name: name.clone(),
+                signature: format!(
+                    "{}enum {}{}",
+                    visibility_prefix(visibility),
+                    name,
+                    type_params_suffix(type_params)
+                ),
+                doc: docstring.clone(),
+                members: variants
+                    .iter()
+                    .map(|variant| {
+                        let signature = match &variant.fields {
+                            Some(fields) => format!("{}({})", variant.name, expressions(fields)),
+                            None => variant.name.clone(),
+                        };
+                        member(ItemKind::Variant, &variant.name, signature)
+                    })
+                    .collect(),
+            },
+            Statement::Class {
+                name,
+                type_params,
+                parent,
+                methods,
+                fields,
+                docstring,
+                visibility,
+                ..
+            } => {
+                let mut members: Vec<Item> = fields
+                    .iter()
+                    .map(|field| {
+                        member(ItemKind::Field, &field.name, format!("var {}", field.name))
+                    })
+                    .collect();
+                members.extend(methods.iter().filter_map(|method| self.item(method, true)));
+                Item {
+                    kind: ItemKind::Class,
+                    name: name.clone(),
+                    signature: format!(
+                        "{}class {}{}{}",
+                        visibility_prefix(visibility),
+                        name,
+                        type_params_suffix(type_params),
+                        parent
+                            .as_ref()
+                            .map(|parent| format!(" extend {}", parent))
+                            .unwrap_or_default()
+                    ),
+                    doc: docstring.clone(),
+                    members,
+                }
+            }
+            Statement::Trait {
+                name,
+                methods,
+                docstring,
+                visibility,
+            } => Item {
+                kind: ItemKind::Trait,
+                name: name.clone(),
+                signature: format!("{}trait {}", visibility_prefix(visibility), name),
+                doc: docstring.clone(),
+                members: methods
+                    .iter()
+                    .map(|method| {
+                        let params: Vec<String> =
+                            method.params.iter().map(|p| p.to_string()).collect();
+                        Item {
+                            kind: ItemKind::Method,
+                            name: method.name.clone(),
+                            signature: format!("def {}({})", method.name, params.join(", ")),
+                            doc: method.docstring.clone(),
+                            members: Vec::new(),
+                        }
+                    })
+                    .collect(),
+            },
+            _ => return None,
+        };
+        Some(item)
+    }
+
+    pub fn to_json(&self) -> Json {
+        fn item_json(item: &Item) -> Json {
+            json!({
+                "kind": item.kind.name(),
+                "name": item.name,
+                "signature": item.signature,
+                "doc": item.doc,
+                "members": item.members.iter().map(item_json).collect::<Vec<_>>(),
+            })
+        }
+
+        json!({
+            "modules": self.modules.iter().map(|module| json!({
+                "name": module.name,
+                "doc": module.doc,
+                "imports": module.imports,
+                "items": module.items.iter().map(item_json).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::new();
+        for module in &self.modules {
+            output.push_str(&format!("# {}\n\n", module.name));
+            if !module.doc.is_empty() {
+                output.push_str(&format!("{}\n\n", module.doc));
+            }
+            for item in &module.items {
+                output.push_str(&format!(
+                    "## {}\n\n```crabby\n{}\n```\n\n",
+                    item.name, item.signature
+                ));
+                if !item.doc.is_empty() {
+                    output.push_str(&format!("{}\n\n", item.doc));
+                }
+                for member in &item.members {
+                    output.push_str(&format!("- `{}`", member.signature));
+                    if let Some(summary) = summary(&member.doc) {
+                        output.push_str(&format!(": {}", summary));
+                    }
+                    output.push('\n');
+                }
+                if !item.members.is_empty() {
+                    output.push('\n');
+                }
+                output.push_str("---\n\n");
+            }
+        }
+        output
+    }
+
+    pub fn to_double_quotes(&self) -> String {
+        let mut output = String::new();
+        for item in self.modules.iter().flat_map(|module| &module.items) {
+            output.push_str(&format!("\"{}\"\n\"{}\"\n", item.name, item.doc));
+            output.push_str(&format!("Signature: \"{}\"\n\n", item.signature));
+        }
+        output
+    }
+
+    // An index page, a page per module, the stylesheet and the search index
+    pub fn write_html(&self, dir: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let links = self.links();
+
+        let mut index = String::from("<h1>Modules</h1>\n<dl>\n");
+        for module in &self.modules {
+            index.push_str(&format!(
+                "<dt><a href=\"{}\">{}</a></dt><dd>{}</dd>\n",
+                module_page(&module.name),
+                escape(&module.name),
+                summary(&module.doc).map(|s| escape(&s)).unwrap_or_default()
+            ));
+        }
+        index.push_str("</dl>\n");
+        fs::write(dir.join("index.html"), page("Documentation", &index))?;
+
+        for module in &self.modules {
+            let html = self.module_html(module, &links);
+            fs::write(
+                dir.join(module_page(&module.name)),
+                page(&module.name, &html),
+            )?;
+        }
+
+        let mut entries = Vec::new();
+        for module in &self.modules {
+            entries.push(json!({
+                "name": module.name,
+                "kind": "module",
+                "module": module.name,
+                "url": module_page(&module.name),
+                "summary": summary(&module.doc).unwrap_or_default(),
+            }));
+            for item in &module.items {
+                let url = format!("{}#{}", module_page(&module.name), anchor(item));
+                entries.push(json!({
+                    "name": item.name,
+                    "kind": item.kind.name(),
+                    "module": module.name,
+                    "url": url,
+                    "summary": summary(&item.doc).unwrap_or_default(),
+                }));
+                for member in &item.members {
+                    entries.push(json!({
+                        "name": format!("{}.{}", item.name, member.name),
+                        "kind": member.kind.name(),
+                        "module": module.name,
+                        "url": url,
+                        "summary": summary(&member.doc).unwrap_or_default(),
+                    }));
                 }
-                _ => {}
             }
         }
+        fs::write(
+            dir.join("search-index.js"),
+            format!("const SEARCH_INDEX = {};\n", Json::Array(entries)),
+        )?;
+        fs::write(dir.join("search.js"), SEARCH_SCRIPT)?;
+        fs::write(dir.join("style.css"), STYLESHEET)
+    }
 
-        docs
+    // Where each documented item lives, by name, for cross-links
+    fn links(&self) -> HashMap<String, Vec<(String, ItemKind, String)>> {
+        let mut links: HashMap<String, Vec<(String, ItemKind, String)>> = HashMap::new();
+        for module in &self.modules {
+            for item in &module.items {
+                links.entry(item.name.clone()).or_default().push((
+                    module.name.clone(),
+                    item.kind,
+                    format!("{}#{}", module_page(&module.name), anchor(item)),
+                ));
+            }
+        }
+        links
     }
 
-    pub fn export_docs(docs: Vec<Documentation>, format: &str) {
-        match format {
-            "markdown" => Self::export_to_markdown(docs),
-            "doublequotes" => Self::export_to_double_quotes(docs),
-            _ => println!("Unsupported format"),
+    fn module_html(
+        &self,
+        module: &Module,
+        links: &HashMap<String, Vec<(String, ItemKind, String)>>,
+    ) -> String {
+        // Items of this module win over same-named ones elsewhere
+        let link = |name: &str, types_only: bool| -> Option<String> {
+            let targets = links.get(name)?;
+            let targets: Vec<_> = targets
+                .iter()
+                .filter(|(_, kind, _)| !types_only || kind.is_type())
+                .collect();
+            targets
+                .iter()
+                .find(|(owner, _, _)| *owner == module.name)
+                .or_else(|| targets.first())
+                .map(|(_, _, url)| url.clone())
+        };
+
+        let mut html = format!("<h1>Module <code>{}</code></h1>\n", escape(&module.name));
+        html.push_str(&render_doc(&module.doc, &link));
+        if !module.imports.is_empty() {
+            html.push_str("<h2>Imports</h2>\n<ul>\n");
+            for import in &module.imports {
+                match self.modules.iter().find(|m| m.name == *import) {
+                    Some(_) => html.push_str(&format!(
+                        "<li><a href=\"{}\">{}</a></li>\n",
+                        module_page(import),
+                        escape(import)
+                    )),
+                    None => html.push_str(&format!("<li>{}</li>\n", escape(import))),
+                }
+            }
+            html.push_str("</ul>\n");
         }
+
+        for kind in [
+            ItemKind::Trait,
+            ItemKind::Class,
+            ItemKind::Struct,
+            ItemKind::Enum,
+            ItemKind::Function,
+        ] {
+            let items: Vec<&Item> = module.items.iter().filter(|i| i.kind == kind).collect();
+            if items.is_empty() {
+                continue;
+            }
+            html.push_str(&format!("<h2>{}</h2>\n", heading(kind)));
+            for item in items {
+                html.push_str(&format!(
+                    "<section id=\"{}\">\n<h3><a href=\"#{}\">{}</a></h3>\n<pre class=\"signature\">{}</pre>\n",
+                    anchor(item),
+                    anchor(item),
+                    escape(&item.name),
+                    link_types(&item.signature, &item.name, &link)
+                ));
+                html.push_str(&render_doc(&item.doc, &link));
+                if !item.members.is_empty() {
+                    html.push_str("<ul class=\"members\">\n");
+                    for member in &item.members {
+                        html.push_str(&format!(
+                            "<li><pre class=\"signature\">{}</pre>{}</li>\n",
+                            link_types(&member.signature, &member.name, &link),
+                            render_doc(&member.doc, &link)
+                        ));
+                    }
+                    html.push_str("</ul>\n");
+                }
+                html.push_str("</section>\n");
+            }
+        }
+        html
     }
+}
+
+fn member(kind: ItemKind, name: &str, signature: String) -> Item {
+    Item {
+        kind,
+        name: name.to_string(),
+        signature,
+        doc: String::new(),
+        members: Vec::new(),
+    }
+}
 
-    fn export_to_markdown(docs: Vec<Documentation>) {
-        for doc in docs {
-            println!("# {}", doc.name);
-            println!("\n{}\n", doc.description);
-            println!("## Parameters");
-            for param in doc.params {
-                println!("- `{}`", param);
+// A code block in a docstring, which `crabby test --doc` runs
+pub struct Example {
+    pub code: String,
+}
+
+impl Example {
+    // What the example should print: the text after each `// => `, with the
+    // line of the code it is on, from 1
+    pub fn expected(&self) -> Vec<(usize, String)> {
+        self.code
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let (_, output) = line.split_once("// =>")?;
+                Some((
+                    i + 1,
+                    output.strip_prefix(' ').unwrap_or(output).to_string(),
+                ))
+            })
+            .collect()
+    }
+}
+
+// The ```, ```crab and ```crabby blocks of a docstring; blocks in other languages
+// are only shown
+pub fn examples(doc: &str) -> Vec<Example> {
+    let mut examples = Vec::new();
+    let mut block: Option<(bool, Vec<&str>)> = None;
+    for line in doc.lines() {
+        let Some(info) = line.trim().strip_prefix("```") else {
+            if let Some((_, code)) = &mut block {
+                code.push(line);
+            }
+            continue;
+        };
+        match block.take() {
+            Some((runnable, code)) => {
+                if runnable {
+                    examples.push(Example {
+                        code: code.join("\n"),
+                    });
+                }
+            }
+            None => {
+                let runnable = matches!(info.trim(), "" | "crab" | "crabby" | "cb");
+                block = Some((runnable, Vec::new()));
             }
-            println!("\n## Returns\n{}\n", doc.return_type);
-            println!("---\n");
         }
     }
+    examples
+}
+
+// `lib/math.crab` is the module `lib.math`
+pub fn module_name(path: &Path) -> String {
+    let path = path.strip_prefix(".").unwrap_or(path).with_extension("");
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn module_page(module: &str) -> String {
+    format!("{}.html", module)
+}
 
-    fn export_to_double_quotes(docs: Vec<Documentation>) {
-        for doc in docs {
-            println!("\"{}\"", doc.name);
-            println!("\"{}\"", doc.description);
-            println!("Parameters:");
-            for param in doc.params {
-                println!("\"{}\"", param);
+fn anchor(item: &Item) -> String {
+    format!("{}.{}", item.kind.name(), item.name)
+}
+
+fn heading(kind: ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Trait => "Traits",
+        ItemKind::Class => "Classes",
+        ItemKind::Struct => "Structs",
+        ItemKind::Enum => "Enums",
+        _ => "Functions",
+    }
+}
+
+// The first paragraph of a docstring, on one line
+fn summary(doc: &str) -> Option<String> {
+    let paragraph: Vec<&str> = doc
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.is_empty() && !line.starts_with("```"))
+        .collect();
+    (!paragraph.is_empty()).then(|| paragraph.join(" "))
+}
+
+// Links the type names in a signature, except the item's own name
+fn link_types(
+    signature: &str,
+    own_name: &str,
+    link: &dyn Fn(&str, bool) -> Option<String>,
+) -> String {
+    let mut html = String::new();
+    let mut rest = signature;
+    while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+        html.push_str(&escape(&rest[..start]));
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        match link(word, true) {
+            Some(url) if word != own_name => {
+                html.push_str(&format!("<a href=\"{}\">{}</a>", url, escape(word)))
+            }
+            _ => html.push_str(&escape(word)),
+        }
+        rest = &rest[end..];
+    }
+    html.push_str(&escape(rest));
+    html
+}
+
+// Paragraphs, ``` code blocks and `code`, where code naming an item links to it
+fn render_doc(doc: &str, link: &dyn Fn(&str, bool) -> Option<String>) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+    for line in doc.lines().chain(std::iter::once("")) {
+        if let Some(block) = &mut code {
+            if line.trim_start().starts_with("```") {
+                html.push_str(&format!(
+                    "<pre><code>{}</code></pre>\n",
+                    escape(&block.join("\n"))
+                ));
+                code = None;
+            } else {
+                block.push(line);
+            }
+            continue;
+        }
+        if line.trim().is_empty() || line.trim_start().starts_with("```") {
+            if !paragraph.is_empty() {
+                html.push_str(&format!("<p>{}</p>\n", inline(&paragraph.join("\n"), link)));
+                paragraph.clear();
             }
-            println!("Returns: \"{}\"\n", doc.return_type);
+            if line.trim_start().starts_with("```") {
+                code = Some(Vec::new());
+            }
+        } else {
+            paragraph.push(line);
+        }
+    }
+    if let Some(block) = code {
+        html.push_str(&format!(
+            "<pre><code>{}</code></pre>\n",
+            escape(&block.join("\n"))
+        ));
+    }
+    html
+}
+
+fn inline(text: &str, link: &dyn Fn(&str, bool) -> Option<String>) -> String {
+    let mut html = String::new();
+    for (i, part) in text.split('`').enumerate() {
+        // Odd parts are between backticks
+        if i % 2 == 0 {
+            html.push_str(&escape(part));
+            continue;
+        }
+        let name = part.trim_end_matches("()");
+        match link(name, false) {
+            Some(url) => html.push_str(&format!(
+                "<a href=\"{}\"><code>{}</code></a>",
+                url,
+                escape(part)
+            )),
+            None => html.push_str(&format!("<code>{}</code>", escape(part))),
         }
     }
+    html
 }
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+<link rel="stylesheet" href="style.css">
+<script src="search-index.js"></script>
+<script src="search.js" defer></script>
+</head>
+<body>
+<nav><a href="index.html">Index</a> <input id="search" type="search" placeholder="Search..."><ul id="results"></ul></nav>
+<main>
+{}</main>
+</body>
+</html>
+"#,
+        escape(title),
+        body
+    )
+}
+
+const SEARCH_SCRIPT: &str = r#"const input = document.getElementById("search");
+const results = document.getElementById("results");
+input.addEventListener("input", () => {
+    const query = input.value.trim().toLowerCase();
+    results.innerHTML = "";
+    if (!query) return;
+    for (const entry of SEARCH_INDEX) {
+        if (!entry.name.toLowerCase().includes(query)) continue;
+        const item = document.createElement("li");
+        const link = document.createElement("a");
+        link.href = entry.url;
+        link.textContent = entry.name + " (" + entry.kind + " in " + entry.module + ")";
+        item.appendChild(link);
+        if (entry.summary) item.append(" - " + entry.summary);
+        results.appendChild(item);
+        if (results.children.length >= 20) break;
+    }
+});
+"#;
+
+const STYLESHEET: &str = r#"body { font-family: sans-serif; max-width: 60rem; margin: 0 auto; padding: 1rem; }
+nav { border-bottom: 1px solid #ccc; padding-bottom: 0.5rem; }
+#results { list-style: none; padding: 0; }
+pre { background: #f5f5f5; padding: 0.5rem; overflow-x: auto; }
+.signature { font-weight: bold; }
+.members { list-style: none; padding-left: 1rem; }
+section { margin-bottom: 1.5rem; }
+"#;
@@ -0,0 +1,563 @@
+// Canonical layout for Crabby source: `crabby fmt`
+// Works on the token stream (comments included) rather than the AST, so nothing the
+// parser throws away is lost. Line breaks stay where they were written, since the parser
+// doesn't care about them; indentation, spacing within a line and blank lines are redone,
+// and lines longer than the limit are split at their brackets.
+
+use std::collections::VecDeque;
+
+use crate::etc::config::Config;
+use crate::lexer::{Token, TokenStream};
+use crate::parser::parse;
+use crate::utils::CrabbyError;
+
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    pub indent_width: usize,
+    pub max_line_length: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            max_line_length: 100,
+        }
+    }
+}
+
+impl FormatOptions {
+    // The `[fmt]` section of crabby.toml
+    pub fn from_config(config: &Config) -> Result<Self, CrabbyError> {
+        let mut options = Self::default();
+        if let Some(width) = config.integer("fmt", "indent_width")? {
+            options.indent_width = positive(width, "fmt.indent_width")?;
+        }
+        if let Some(length) = config.integer("fmt", "max_line_length")? {
+            options.max_line_length = positive(length, "fmt.max_line_length")?;
+        }
+        Ok(options)
+    }
+}
+
+fn positive(value: i64, key: &str) -> Result<usize, CrabbyError> {
+    match usize::try_from(value) {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(CrabbyError::IoError(format!(
+            "`{}` must be a positive integer, not {}",
+            key, value
+        ))),
+    }
+}
+
+pub fn format_source(source: &str, options: &FormatOptions) -> Result<String, CrabbyError> {
+    let tokens = lex(source)?;
+    // Code that doesn't parse is left alone; a file of nothing but comments is fine
+    if tokens.iter().any(|t| !matches!(t.token, Token::Comment(_))) {
+        parse(TokenStream::tokenize(source.to_string())?)?;
+    }
+    let formatted = Layout::new(&tokens, options).render();
+
+    // The same tokens and comments must come out, and formatting twice must change nothing
+    if slices(&tokens) != slices(&lex(&formatted)?) {
+        return Err(formatter_bug("changed the code's tokens"));
+    }
+    if Layout::new(&lex(&formatted)?, options).render() != formatted {
+        return Err(formatter_bug("gave a different result on its own output"));
+    }
+    Ok(formatted)
+}
+
+fn lex(source: &str) -> Result<Vec<TokenStream>, CrabbyError> {
+    match TokenStream::tokenize_with_comments(source.to_string()) {
+        Ok(tokens) => Ok(tokens),
+        Err(CrabbyError::LexerError(location)) if location.message == "Empty source file" => {
+            Ok(Vec::new())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn slices(tokens: &[TokenStream]) -> Vec<&str> {
+    tokens.iter().map(|t| t.slice.trim_end()).collect()
+}
+
+fn formatter_bug(what: &str) -> CrabbyError {
+    CrabbyError::CompileError(format!(
+        "The formatter {}; the file was left unformatted",
+        what
+    ))
+}
+
+// A line of the output: indices into the token list
+struct Line {
+    tokens: Vec<usize>,
+    blank_before: bool,
+}
+
+struct Layout<'a> {
+    tokens: &'a [TokenStream],
+    options: &'a FormatOptions,
+    output: String,
+    // The output line each unclosed bracket was opened on, and the bracket
+    open: Vec<(usize, Token)>,
+    line_number: usize,
+    // The last code token before this line, for continuations
+    previous: Option<usize>,
+    // No blank line right after a line ending in an open bracket
+    after_opener: bool,
+}
+
+impl<'a> Layout<'a> {
+    fn new(tokens: &'a [TokenStream], options: &'a FormatOptions) -> Self {
+        Self {
+            tokens,
+            options,
+            output: String::new(),
+            open: Vec::new(),
+            line_number: 0,
+            previous: None,
+            after_opener: false,
+        }
+    }
+
+    fn render(mut self) -> String {
+        let mut queue = self.lines();
+        while let Some(line) = queue.pop_front() {
+            let closers = line
+                .tokens
+                .iter()
+                .take_while(|&&i| is_closer(&self.tokens[i].token))
+                .count();
+            let mut depth = depth(&self.open[..self.open.len().saturating_sub(closers)]);
+            if self.continues(&line) {
+                depth += 1;
+            }
+            let indent = " ".repeat(depth * self.options.indent_width);
+            let text = self.text(&line);
+
+            if indent.len() + text.chars().count() > self.options.max_line_length
+                && let Some(pieces) = self.split(&line)
+            {
+                for piece in pieces.into_iter().rev() {
+                    queue.push_front(piece);
+                }
+                continue;
+            }
+
+            if line.blank_before && self.line_number > 0 && !self.after_opener && closers == 0 {
+                self.output.push('\n');
+            }
+            self.output.push_str(&indent);
+            self.output.push_str(&text);
+            self.output.push('\n');
+
+            for &i in &line.tokens {
+                let token = &self.tokens[i].token;
+                if is_opener(token) {
+                    self.open.push((self.line_number, token.clone()));
+                } else if is_closer(token) {
+                    self.open.pop();
+                }
+            }
+            let last = line
+                .tokens
+                .iter()
+                .rev()
+                .find(|&&i| !matches!(self.tokens[i].token, Token::Comment(_)));
+            self.after_opener = last.is_some_and(|&i| is_opener(&self.tokens[i].token));
+            if let Some(&last) = last {
+                self.previous = Some(last);
+            }
+            self.line_number += 1;
+        }
+        self.output
+    }
+
+    // Groups the tokens by the line they start on; a multi-line string keeps
+    // whatever follows it on its last line
+    fn lines(&self) -> VecDeque<Line> {
+        let mut lines: VecDeque<Line> = VecDeque::new();
+        let mut end_line = 0;
+        for (i, token) in self.tokens.iter().enumerate() {
+            let line = token.span.line;
+            if lines.is_empty() || line > end_line {
+                lines.push_back(Line {
+                    tokens: Vec::new(),
+                    blank_before: !lines.is_empty() && line > end_line + 1,
+                });
+            }
+            if let Some(last) = lines.back_mut() {
+                last.tokens.push(i);
+            }
+            end_line = line + token.slice.matches('\n').count();
+        }
+        lines
+    }
+
+    // An expression carried over from the line before gets one more level
+    fn continues(&self, line: &Line) -> bool {
+        let ends_with_operator = self
+            .previous
+            .is_some_and(|i| is_binary_operator(&self.tokens[i].token));
+        let starts_with_operator = line.tokens.first().is_some_and(|&i| {
+            matches!(
+                self.tokens[i].token,
+                Token::Dot
+                    | Token::SafeNavigation
+                    | Token::Pipe
+                    | Token::NullCoalesce
+                    | Token::Or
+                    | Token::DoubleAmpersand
+                    | Token::And
+                    | Token::OrKeyword
+            )
+        });
+        ends_with_operator || starts_with_operator
+    }
+
+    fn text(&self, line: &Line) -> String {
+        let mut text = String::new();
+        // Brackets opened earlier on this line, on top of the ones still open before it
+        let mut open: Vec<&Token> = self.open.iter().map(|(_, token)| token).collect();
+        for (n, &i) in line.tokens.iter().enumerate() {
+            let token = &self.tokens[i];
+            if n > 0 {
+                let before = (n > 1).then(|| &self.tokens[line.tokens[n - 2]].token);
+                let in_parens = open.last().is_some_and(|t| matches!(t, Token::LParen));
+                if spaced(&self.tokens[line.tokens[n - 1]], token, before, in_parens) {
+                    text.push(' ');
+                }
+            }
+            text.push_str(match token.token {
+                Token::Comment(_) => token.slice.trim_end(),
+                _ => &token.slice,
+            });
+            if is_opener(&token.token) {
+                open.push(&token.token);
+            } else if is_closer(&token.token) {
+                open.pop();
+            }
+        }
+        text
+    }
+
+    // Breaks a line inside a bracket pair that closes on the same line, putting each
+    // comma-separated item on a line of its own. A block's braces go first, then brackets
+    // holding a list, then any others.
+    fn split(&self, line: &Line) -> Option<Vec<Line>> {
+        if line
+            .tokens
+            .iter()
+            .any(|&i| self.tokens[i].slice.contains('\n'))
+        {
+            return None;
+        }
+        let tokens = &line.tokens;
+        let mut groups = Vec::new();
+        let mut stack: Vec<(usize, Vec<usize>)> = Vec::new();
+        for (n, &i) in tokens.iter().enumerate() {
+            let token = &self.tokens[i].token;
+            if is_opener(token) {
+                stack.push((n, Vec::new()));
+            } else if is_closer(token) {
+                if let Some((start, breaks)) = stack.pop()
+                    && n > start + 1
+                {
+                    groups.push((start, n, breaks));
+                }
+            } else if let (Token::Comma, Some((_, breaks))) = (token, stack.last_mut()) {
+                breaks.push(n + 1);
+            }
+        }
+        groups.sort_by_key(|(start, _, breaks)| {
+            let rank = match self.tokens[tokens[*start]].token {
+                Token::LBrace => 0,
+                _ if !breaks.is_empty() => 1,
+                _ => 2,
+            };
+            (rank, *start)
+        });
+        let (start, end, breaks) = groups.into_iter().next()?;
+
+        let mut pieces = vec![start + 1];
+        pieces.extend(breaks);
+        pieces.push(end);
+        let mut lines = vec![Line {
+            tokens: tokens[..=start].to_vec(),
+            blank_before: line.blank_before,
+        }];
+        for window in pieces.windows(2) {
+            lines.push(Line {
+                tokens: tokens[window[0]..window[1]].to_vec(),
+                blank_before: false,
+            });
+        }
+        lines.push(Line {
+            tokens: tokens[end..].to_vec(),
+            blank_before: false,
+        });
+        Some(lines)
+    }
+}
+
+// Brackets opened on the same line count as one level of indentation
+fn depth(open: &[(usize, Token)]) -> usize {
+    let mut lines: Vec<usize> = open.iter().map(|(line, _)| *line).collect();
+    lines.dedup();
+    lines.len()
+}
+
+fn is_opener(token: &Token) -> bool {
+    matches!(token, Token::LParen | Token::LBracket | Token::LBrace)
+}
+
+fn is_closer(token: &Token) -> bool {
+    matches!(token, Token::RParen | Token::RBracket | Token::RBrace)
+}
+
+// Tokens that end an operand, so a `-` or `*` after one is binary
+fn is_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Identifier(_)
+            | Token::Integer(_)
+            | Token::Float(_)
+            | Token::String(_)
+            | Token::FString(_)
+            | Token::True
+            | Token::False
+            | Token::Null
+            | Token::Nil
+            | Token::RParen
+            | Token::RBracket
+            | Token::QuestionMark
+    )
+}
+
+// `-x`, `*args`, `**kwargs`, `&value`
+fn is_prefix_or_binary(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Minus | Token::Plus | Token::Star | Token::DoubleStar | Token::Ampersand
+    )
+}
+
+fn is_binary_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Slash
+            | Token::Percentage
+            | Token::Caret
+            | Token::Equals
+            | Token::DoubleEquals
+            | Token::NotEquals
+            | Token::LessThanOrEqual
+            | Token::GreaterThanOrEqual
+            | Token::Pipe
+            | Token::Or
+            | Token::DoubleAmpersand
+            | Token::Arrow
+            | Token::CoolerArrow
+            | Token::NullCoalesce
+    ) || is_prefix_or_binary(token)
+}
+
+// Whether `next` is written with a space after `previous`; `before` is the token
+// ahead of `previous` on the same line. Where the token alone can't tell (`<` opens
+// generics or compares, `|` starts a lambda or is bitwise or), the original spacing stays.
+fn spaced(
+    previous: &TokenStream,
+    next: &TokenStream,
+    before: Option<&Token>,
+    in_parens: bool,
+) -> bool {
+    let had_space = next.span.start > previous.span.end;
+    let (previous, next) = (&previous.token, &next.token);
+    match (previous, next) {
+        (_, Token::Comment(_)) => true,
+        (
+            Token::LParen
+            | Token::LBracket
+            | Token::Dot
+            | Token::SafeNavigation
+            | Token::Decorator
+            | Token::Tilde
+            | Token::ExclamationMark,
+            _,
+        ) => false,
+        (
+            _,
+            Token::RParen
+            | Token::RBracket
+            | Token::Comma
+            | Token::Dot
+            | Token::SafeNavigation
+            | Token::QuestionMark
+            | Token::Colon,
+        ) => false,
+        (Token::Comma | Token::Colon, _) => true,
+        (Token::LBrace, Token::RBrace) => false,
+        (Token::LBrace | Token::RBrace, _) | (_, Token::RBrace | Token::LBrace) => true,
+        // `name!(args)`
+        (Token::Identifier(_), Token::ExclamationMark) => false,
+        (
+            Token::LessThan
+            | Token::GreaterThan
+            | Token::ShiftLeft
+            | Token::ShiftRight
+            | Token::Bar,
+            _,
+        )
+        | (
+            _,
+            Token::LessThan
+            | Token::GreaterThan
+            | Token::ShiftLeft
+            | Token::ShiftRight
+            | Token::Bar,
+        ) => had_space,
+        // Keyword arguments and defaults are up to the author
+        (Token::Equals, _) | (_, Token::Equals) if in_parens => had_space,
+        (operator, _) if is_prefix_or_binary(operator) => before.is_some_and(is_operand),
+        (_, operator) if is_prefix_or_binary(operator) => {
+            is_operand(previous) || is_binary_operator(previous) || had_space
+        }
+        // Calls and indexing
+        (_, Token::LParen | Token::LBracket) if is_operand(previous) => false,
+        (operator, _) | (_, operator) if is_binary_operator(operator) => true,
+        _ => had_space,
+    }
+}
+
+// A unified diff of two versions of a file, as `diff -u` prints it
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let edits = edits(&diff_lines(old), &diff_lines(new));
+
+    let mut output = format!("--- {}\n+++ {}\n", path, path);
+    let changed: Vec<usize> = (0..edits.len())
+        .filter(|&i| !matches!(edits[i], Edit::Same(..)))
+        .collect();
+    let mut n = 0;
+    while n < changed.len() {
+        // Changes closer than twice the context share a hunk
+        let start = changed[n].saturating_sub(CONTEXT_LINES);
+        let mut last = changed[n];
+        while n + 1 < changed.len() && changed[n + 1] - last <= 2 * CONTEXT_LINES {
+            n += 1;
+            last = changed[n];
+        }
+        let end = (last + CONTEXT_LINES + 1).min(edits.len());
+        n += 1;
+
+        let hunk = &edits[start..end];
+        let (old_start, new_start) = edits[..start].iter().fold((0, 0), |(o, n), edit| {
+            let (a, b) = edit.counts();
+            (o + a, n + b)
+        });
+        let (old_count, new_count) = hunk.iter().fold((0, 0), |(o, n), edit| {
+            let (a, b) = edit.counts();
+            (o + a, n + b)
+        });
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + 1,
+            old_count,
+            new_start + 1,
+            new_count
+        ));
+        for edit in hunk {
+            let (prefix, (line, newline)) = match edit {
+                Edit::Same(line) => (' ', line),
+                Edit::Remove(line) => ('-', line),
+                Edit::Add(line) => ('+', line),
+            };
+            output.push_str(&format!("{}{}\n", prefix, line));
+            if !newline {
+                output.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+    output
+}
+
+// Every line of both texts, marked `-` when only in `old` and `+` when only in `new`
+pub fn line_diff(old: &str, new: &str) -> String {
+    let mut output = String::new();
+    for edit in edits(&diff_lines(old), &diff_lines(new)) {
+        let (prefix, (line, _)) = match edit {
+            Edit::Same(line) => (' ', line),
+            Edit::Remove(line) => ('-', line),
+            Edit::Add(line) => ('+', line),
+        };
+        output.push_str(&format!("{}{}\n", prefix, line));
+    }
+    output
+}
+
+// A line, and whether a newline ends it; only the last line of a file can lack one
+type DiffLine<'a> = (&'a str, bool);
+
+fn diff_lines(text: &str) -> Vec<DiffLine<'_>> {
+    let mut lines: Vec<DiffLine<'_>> = text
+        .split_terminator('\n')
+        .map(|line| (line, true))
+        .collect();
+    if !text.ends_with('\n')
+        && let Some(last) = lines.last_mut()
+    {
+        last.1 = false;
+    }
+    lines
+}
+
+enum Edit<'a> {
+    Same(DiffLine<'a>),
+    Remove(DiffLine<'a>),
+    Add(DiffLine<'a>),
+}
+
+impl Edit<'_> {
+    // Lines of the old and the new file this edit covers
+    fn counts(&self) -> (usize, usize) {
+        match self {
+            Edit::Same(_) => (1, 1),
+            Edit::Remove(_) => (1, 0),
+            Edit::Add(_) => (0, 1),
+        }
+    }
+}
+
+// The shortest edit script, from the longest common subsequence of lines
+fn edits<'a>(old: &[DiffLine<'a>], new: &[DiffLine<'a>]) -> Vec<Edit<'a>> {
+    let (n, m) = (old.len(), new.len());
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            edits.push(Edit::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || common[i + 1][j] >= common[i][j + 1]) {
+            edits.push(Edit::Remove(old[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Add(new[j]));
+            j += 1;
+        }
+    }
+    edits
+}
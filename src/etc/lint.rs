@@ -0,0 +1,997 @@
+// `crabby lint`: rules for code that works but is likely a mistake or hard to read
+// Each rule looks at every statement and expression of the parsed program and may
+// suggest a fix as a set of edits to the source. How much a rule matters is set per
+// project in crabby.toml, and a comment can silence it for one line:
+//
+//     [lint]
+//     float_equality = "deny"   # or "warn", or "allow" to turn it off
+//     max_nesting_depth = 5
+//
+//     if x == 0.5 { // crabby: allow(float_equality)
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use crate::ast::{BinaryOp, Expression, Parameter, Statement, Visibility};
+use crate::etc::config::Config;
+use crate::etc::deadcode::terminates;
+use crate::lexer::{TemplatePiece, Token, TokenStream};
+use crate::parser::parse;
+use crate::utils::{CrabbyError, Span, render_diagnostics};
+
+const SECTION: &str = "lint";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub rule: &'static str,
+    pub level: Level,
+    pub span: Span,
+    pub message: String,
+    pub fix: Option<Fix>,
+}
+
+// Edits that make the lint go away without changing what the program does
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl Lint {
+    pub fn new(rule: &'static str, span: Span, message: String) -> Self {
+        Self {
+            rule,
+            level: Level::Warn,
+            span,
+            message,
+            fix: None,
+        }
+    }
+
+    pub fn with_fix(mut self, fix: Option<Fix>) -> Self {
+        self.fix = fix;
+        self
+    }
+}
+
+// A check; new ones are added with `Linter::add_rule`
+pub trait Rule {
+    // As written in crabby.toml and `// crabby: allow(...)`
+    fn name(&self) -> &'static str;
+
+    fn default_level(&self) -> Level {
+        Level::Warn
+    }
+
+    // Settings of the rule's own in the `[lint]` table, besides its level
+    fn options(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn configure(&mut self, _config: &Config) -> Result<(), CrabbyError> {
+        Ok(())
+    }
+
+    fn check_statement(&self, _statement: &Statement, _context: &Context, _lints: &mut Vec<Lint>) {}
+
+    fn check_expression(
+        &self,
+        _expression: &Expression,
+        _context: &Context,
+        _lints: &mut Vec<Lint>,
+    ) {
+    }
+}
+
+// What a rule knows about where it is looking
+pub struct Context<'a> {
+    pub source: &'a str,
+    tokens: &'a [TokenStream], // without comments
+    pub depth: usize,          // `if`, `while`, `loop` and `for` blocks around, in this function
+    pub in_test: bool,         // in a test file, a `test` block or a `@test` function
+}
+
+impl Context<'_> {
+    // The token starting at `offset`
+    pub fn token_at(&self, offset: usize) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|token| token.span.start == offset)
+    }
+
+    pub fn token(&self, index: usize) -> Option<&TokenStream> {
+        self.tokens.get(index)
+    }
+
+    // Where `name` is written in the declaration that starts at `span`
+    pub fn name_span(&self, span: Span, name: &str) -> Span {
+        let Some(first) = self.token_at(span.start) else {
+            return span;
+        };
+        self.tokens[first..]
+            .iter()
+            .take(4)
+            .find(|token| matches!(&token.token, Token::Identifier(n) if n == name))
+            .map_or(span, |token| token.span)
+    }
+
+    // The first `{` after token `index` outside brackets, and its `}`
+    pub fn block_after(&self, index: usize) -> Option<(usize, usize)> {
+        let mut depth = 0usize;
+        for (i, token) in self.tokens.iter().enumerate().skip(index + 1) {
+            match token.token {
+                Token::LParen | Token::LBracket => depth += 1,
+                Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+                Token::LBrace if depth == 0 => return Some((i, self.matching_brace(i)?)),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn matching_brace(&self, open: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(open) {
+            match token.token {
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // The whitespace the line holding `offset` starts with
+    pub fn indentation(&self, offset: usize) -> &str {
+        let line_start = self.source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = &self.source[line_start..];
+        &line[..line.len() - line.trim_start().len()]
+    }
+}
+
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>,
+    levels: HashMap<&'static str, Level>, // from crabby.toml
+}
+
+impl Default for Linter {
+    // The built-in rules
+    fn default() -> Self {
+        let mut linter = Self {
+            rules: Vec::new(),
+            levels: HashMap::new(),
+        };
+        linter.add_rule(Box::new(Naming));
+        linter.add_rule(Box::new(MaybeProbably));
+        linter.add_rule(Box::new(FloatEquality));
+        linter.add_rule(Box::new(EmptyBlock));
+        linter.add_rule(Box::new(DeepNesting { max_depth: 4 }));
+        linter.add_rule(Box::new(RedundantElse));
+        linter
+    }
+}
+
+impl Linter {
+    pub fn add_rule(&mut self, rule: Box<dyn Rule>) {
+        self.rules.push(rule);
+    }
+
+    // The `[lint]` section of crabby.toml
+    pub fn configure(&mut self, config: &Config) -> Result<(), CrabbyError> {
+        for key in config.keys(SECTION) {
+            if self.rules.iter().any(|rule| rule.options().contains(&key)) {
+                continue;
+            }
+            let Some(rule) = self.rules.iter().find(|rule| rule.name() == key) else {
+                return Err(config.invalid(SECTION, key, "the name of a lint rule"));
+            };
+            let level = match config.string(SECTION, key)? {
+                Some("allow") => Level::Allow,
+                Some("warn") => Level::Warn,
+                Some("deny") => Level::Deny,
+                _ => return Err(config.invalid(SECTION, key, "\"allow\", \"warn\" or \"deny\"")),
+            };
+            self.levels.insert(rule.name(), level);
+        }
+        for rule in &mut self.rules {
+            rule.configure(config)?;
+        }
+        Ok(())
+    }
+
+    // The lints for a file, in source order; `test_file` is for `*_test.crab` files
+    pub fn lint(&self, source: &str, test_file: bool) -> Result<Vec<Lint>, CrabbyError> {
+        let all_tokens = match TokenStream::tokenize_with_comments(source.to_string()) {
+            Ok(tokens) => tokens,
+            Err(CrabbyError::LexerError(location)) if location.message == "Empty source file" => {
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e),
+        };
+        let (comments, tokens): (Vec<_>, Vec<_>) = all_tokens
+            .into_iter()
+            .partition(|token| matches!(token.token, Token::Comment(_)));
+        let program = parse(TokenStream::tokenize(source.to_string())?)?;
+
+        let mut walker = Walker {
+            rules: &self.rules,
+            context: Context {
+                source,
+                tokens: &tokens,
+                depth: 0,
+                in_test: test_file,
+            },
+            lints: Vec::new(),
+        };
+        for statement in &program.statements {
+            walker.statement(statement);
+        }
+
+        let allowed = suppressions(source, &comments);
+        let mut lints = walker.lints;
+        for lint in &mut lints {
+            lint.level = self.level(lint.rule);
+        }
+        lints.retain(|lint| {
+            lint.level != Level::Allow
+                && !allowed.contains(&(lint.span.line, lint.rule.to_string()))
+        });
+        lints.sort_by_key(|lint| lint.span.start);
+        Ok(lints)
+    }
+
+    fn level(&self, rule: &str) -> Level {
+        match self.levels.get(rule) {
+            Some(level) => *level,
+            None => self
+                .rules
+                .iter()
+                .find(|r| r.name() == rule)
+                .map_or(Level::Warn, |r| r.default_level()),
+        }
+    }
+}
+
+// `// crabby: allow(rule, ...)` silences the rules on the line it ends, or on the
+// next line when it has a line to itself
+fn suppressions(source: &str, comments: &[TokenStream]) -> HashSet<(usize, String)> {
+    let mut allowed = HashSet::new();
+    for comment in comments {
+        let Token::Comment(text) = &comment.token else {
+            continue;
+        };
+        let rules = text
+            .trim_start_matches('/')
+            .trim()
+            .strip_prefix("crabby:")
+            .map(str::trim)
+            .and_then(|text| text.strip_prefix("allow("))
+            .and_then(|text| text.trim_end().strip_suffix(')'));
+        let line_start = source[..comment.span.start]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let own_line = source[line_start..comment.span.start].trim().is_empty();
+        let line = comment.span.line + usize::from(own_line);
+        for rule in rules.into_iter().flat_map(|rules| rules.split(',')) {
+            allowed.insert((line, rule.trim().to_string()));
+        }
+    }
+    allowed
+}
+
+// Visits everything in a program, keeping the context up to date for the rules
+struct Walker<'a> {
+    rules: &'a [Box<dyn Rule>],
+    context: Context<'a>,
+    lints: Vec<Lint>,
+}
+
+impl Walker<'_> {
+    fn statement(&mut self, statement: &Statement) {
+        for rule in self.rules {
+            rule.check_statement(statement, &self.context, &mut self.lints);
+        }
+        match statement {
+            Statement::FunctionDef {
+                params,
+                body,
+                decorators,
+                ..
+            }
+            | Statement::FunctionFun {
+                params,
+                body,
+                decorators,
+                ..
+            } => {
+                for decorator in decorators {
+                    for argument in &decorator.arguments {
+                        self.expression(argument);
+                    }
+                    for argument in &decorator.keyword_arguments {
+                        self.expression(&argument.value);
+                    }
+                }
+                let test = decorators.iter().any(|decorator| decorator.name == "test");
+                self.function(params, body, test);
+            }
+            Statement::AsyncFunction { params, body, .. } => self.function(params, body, false),
+            Statement::Let { value, .. }
+            | Statement::Var { value, .. }
+            | Statement::Const { value, .. } => self.expression(value),
+            Statement::Return(value, _) => self.expression(value),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition);
+                self.nested(then_branch);
+                match else_branch.as_deref() {
+                    // `else { if ... }` is how an `else if` is written
+                    Some(Statement::Block(statements))
+                        if matches!(statements.as_slice(), [Statement::If { .. }]) =>
+                    {
+                        self.statement(&statements[0])
+                    }
+                    Some(else_branch) => self.nested(else_branch),
+                    None => {}
+                }
+            }
+            Statement::While {
+                condition: value,
+                body,
+                ..
+            }
+            | Statement::Loop {
+                count: value, body, ..
+            }
+            | Statement::ForIn {
+                iterator: value,
+                body,
+                ..
+            } => {
+                self.expression(value);
+                self.nested(body);
+            }
+            Statement::Match { value, arms } => {
+                self.expression(value);
+                for arm in arms {
+                    self.expression(&arm.pattern);
+                    self.expression(&arm.body);
+                }
+            }
+            Statement::Class { methods, .. } | Statement::Extend { methods, .. } => {
+                for method in methods {
+                    self.statement(method);
+                }
+            }
+            Statement::Trait { methods, .. } | Statement::Impl { methods, .. } => {
+                for method in methods {
+                    self.function(&method.params, &method.body, false);
+                }
+            }
+            Statement::ArrayAssign {
+                array,
+                index,
+                value,
+            } => {
+                self.expression(array);
+                self.expression(index);
+                self.expression(value);
+            }
+            Statement::Assert {
+                condition, message, ..
+            } => {
+                self.expression(condition);
+                if let Some(message) = message {
+                    self.expression(message);
+                }
+            }
+            Statement::Test { body, .. } => self.function(&[], body, true),
+            Statement::Macro { body, .. } => self.statement(body),
+            Statement::Block(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            Statement::Expression(expression) => self.expression(expression),
+            Statement::Enum { .. }
+            | Statement::Struct { .. }
+            | Statement::TypeDef { .. }
+            | Statement::Import { .. }
+            | Statement::Extern { .. }
+            | Statement::ExternStruct { .. }
+            | Statement::And { .. } => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        for rule in self.rules {
+            rule.check_expression(expression, &self.context, &mut self.lints);
+        }
+        match expression {
+            Expression::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Unary { operand: value, .. }
+            | Expression::Borrow { expr: value, .. }
+            | Expression::Move { expr: value, .. }
+            | Expression::Range(value)
+            | Expression::Try(value)
+            | Expression::TypeOf(value)
+            | Expression::Await { expr: value }
+            | Expression::Member { object: value, .. }
+            | Expression::Is { value, .. } => self.expression(value),
+            Expression::Call {
+                arguments,
+                keyword_arguments,
+                ..
+            } => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                for argument in keyword_arguments {
+                    self.expression(&argument.value);
+                }
+            }
+            Expression::MethodCall {
+                object,
+                arguments,
+                keyword_arguments,
+                ..
+            } => {
+                self.expression(object);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                for argument in keyword_arguments {
+                    self.expression(&argument.value);
+                }
+            }
+            Expression::Array(elements)
+            | Expression::MacroCall {
+                arguments: elements,
+                ..
+            } => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expression::Index { array, index } => {
+                self.expression(array);
+                self.expression(index);
+            }
+            Expression::Lambda { params, body } => self.function(params, body, false),
+            Expression::Where {
+                expr,
+                condition,
+                body,
+            } => {
+                self.expression(expr);
+                self.expression(condition);
+                self.statement(body);
+            }
+            _ => {}
+        }
+    }
+
+    // Nesting starts over in each function
+    fn function(&mut self, params: &[Parameter], body: &Statement, test: bool) {
+        for param in params {
+            if let Some(default) = &param.default {
+                self.expression(default);
+            }
+        }
+        let (depth, in_test) = (self.context.depth, self.context.in_test);
+        self.context.depth = 0;
+        self.context.in_test |= test;
+        self.statement(body);
+        self.context.depth = depth;
+        self.context.in_test = in_test;
+    }
+
+    fn nested(&mut self, body: &Statement) {
+        self.context.depth += 1;
+        self.statement(body);
+        self.context.depth -= 1;
+    }
+}
+
+// Applies the fixes of `lints`; an edit that overlaps one before it is left out
+pub fn apply_fixes(source: &str, lints: &[Lint]) -> String {
+    let mut edits: Vec<&Edit> = lints
+        .iter()
+        .filter_map(|lint| lint.fix.as_ref())
+        .flat_map(|fix| &fix.edits)
+        .collect();
+    edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+    edits.dedup();
+
+    let mut fixed = String::with_capacity(source.len());
+    let mut end = 0;
+    for edit in edits {
+        if edit.range.start < end {
+            continue;
+        }
+        fixed.push_str(&source[end..edit.range.start]);
+        fixed.push_str(&edit.replacement);
+        end = edit.range.end;
+    }
+    fixed.push_str(&source[end..]);
+    fixed
+}
+
+// Renders lints against their source, as warnings or, when denied, errors
+pub fn render(lints: &[Lint], file_name: &str, source: &str) -> String {
+    render_diagnostics(lints.iter().map(Lint::to_diagnostic), file_name, source)
+}
+
+impl Lint {
+    fn to_diagnostic(&self) -> Diagnostic<()> {
+        let diagnostic = match self.level {
+            Level::Deny => Diagnostic::error(),
+            _ => Diagnostic::warning(),
+        };
+        let diagnostic = diagnostic
+            .with_code(self.rule)
+            .with_message(&self.message)
+            .with_labels(vec![Label::primary((), self.span.start..self.span.end)]);
+        match &self.fix {
+            Some(fix) => diagnostic.with_notes(vec![format!(
+                "fix: {} (`crabby lint --fix` applies it)",
+                fix.message
+            )]),
+            None => diagnostic,
+        }
+    }
+}
+
+// Functions and variables are snake_case; structs, enums, classes and type aliases
+// are PascalCase
+struct Naming;
+
+impl Naming {
+    fn check(
+        &self,
+        name: &str,
+        span: Span,
+        pascal: bool,
+        fixable: bool,
+        context: &Context,
+        lints: &mut Vec<Lint>,
+    ) {
+        let (expected, renamed) = if pascal {
+            ("PascalCase", to_pascal_case(name))
+        } else {
+            ("snake_case", to_snake_case(name))
+        };
+        if renamed == name || renamed.trim_start_matches('_').is_empty() {
+            return;
+        }
+        let span = context.name_span(span, name);
+        let fix = if fixable {
+            rename(context, name, &renamed)
+        } else {
+            None
+        };
+        lints.push(
+            Lint::new(
+                self.name(),
+                span,
+                format!("`{}` should be written in {}", name, expected),
+            )
+            .with_fix(fix),
+        );
+    }
+}
+
+impl Rule for Naming {
+    fn name(&self) -> &'static str {
+        "naming"
+    }
+
+    fn check_statement(&self, statement: &Statement, context: &Context, lints: &mut Vec<Lint>) {
+        match statement {
+            Statement::FunctionDef {
+                name,
+                params,
+                visibility,
+                span,
+                ..
+            }
+            | Statement::FunctionFun {
+                name,
+                params,
+                visibility,
+                span,
+                ..
+            } => {
+                // Other modules call public functions by name
+                let public = *visibility == Visibility::Public || name.starts_with("pub ");
+                let name = name.trim_start_matches("pub ");
+                self.check(name, *span, false, !public, context, lints);
+                for param in params {
+                    self.check(&param.name, param.span, false, true, context, lints);
+                }
+            }
+            Statement::Let { name, span, .. } | Statement::Var { name, span, .. } => {
+                let public = name.starts_with("pub ");
+                let name = name.trim_start_matches("pub ");
+                self.check(name, *span, false, !public, context, lints);
+            }
+            Statement::ForIn { variable, span, .. } => {
+                self.check(variable, *span, false, true, context, lints);
+            }
+            Statement::Struct { name, span, .. }
+            | Statement::ExternStruct { name, span, .. }
+            | Statement::Enum { name, span, .. }
+            | Statement::Class { name, span, .. }
+            | Statement::TypeDef { name, span, .. } => {
+                self.check(name, *span, true, true, context, lints);
+            }
+            _ => {}
+        }
+    }
+
+    fn check_expression(&self, expression: &Expression, context: &Context, lints: &mut Vec<Lint>) {
+        if let Expression::Lambda { params, .. } = expression {
+            for param in params {
+                self.check(&param.name, param.span, false, true, context, lints);
+            }
+        }
+    }
+}
+
+// Renames every use of `name` in the file, unless `renamed` is taken or `name`
+// is also used where tokens don't reach, in an f-string
+fn rename(context: &Context, name: &str, renamed: &str) -> Option<Fix> {
+    let is_identifier = TokenStream::tokenize(renamed.to_string()).is_ok_and(|tokens| {
+        matches!(tokens.as_slice(), [token] if token.token == Token::Identifier(renamed.to_string()))
+    });
+    if !is_identifier {
+        return None;
+    }
+    let mut edits = Vec::new();
+    for token in context.tokens {
+        match &token.token {
+            Token::Identifier(n) if n == renamed => return None,
+            Token::Identifier(n) if n == name => edits.push(Edit {
+                range: token.span.start..token.span.end,
+                replacement: renamed.to_string(),
+            }),
+            Token::FString(pieces) => {
+                let in_hole = pieces.iter().any(
+                    |piece| matches!(piece, TemplatePiece::Hole { code, .. } if code.contains(name)),
+                );
+                if in_hole {
+                    return None;
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(Fix {
+        message: format!("rename it to `{}`", renamed),
+        edits,
+    })
+}
+
+// `maybe` and `probably` make a program behave differently from one run to the next
+struct MaybeProbably;
+
+impl Rule for MaybeProbably {
+    fn name(&self) -> &'static str {
+        "maybe_probably"
+    }
+
+    fn check_expression(&self, expression: &Expression, context: &Context, lints: &mut Vec<Lint>) {
+        let (keyword, span) = match expression {
+            Expression::Maybe(span) => ("maybe", span),
+            Expression::Probably(span) => ("probably", span),
+            _ => return,
+        };
+        if !context.in_test {
+            lints.push(Lint::new(
+                self.name(),
+                *span,
+                format!(
+                    "`{}` outside of a test makes the program unpredictable",
+                    keyword
+                ),
+            ));
+        }
+    }
+}
+
+// `0.1 + 0.2 == 0.3` is false, so floats are best compared with a tolerance
+struct FloatEquality;
+
+impl Rule for FloatEquality {
+    fn name(&self) -> &'static str {
+        "float_equality"
+    }
+
+    fn check_expression(&self, expression: &Expression, _context: &Context, lints: &mut Vec<Lint>) {
+        let Expression::Binary {
+            left,
+            operator: operator @ (BinaryOp::Eq | BinaryOp::NotEq),
+            right,
+            span,
+        } = expression
+        else {
+            return;
+        };
+        if matches!(**left, Expression::Float(_)) || matches!(**right, Expression::Float(_)) {
+            lints.push(Lint::new(
+                self.name(),
+                *span,
+                format!(
+                    "comparing floats with `{}` is unreliable; check that they differ by less than a small tolerance",
+                    operator
+                ),
+            ));
+        }
+    }
+}
+
+struct EmptyBlock;
+
+impl Rule for EmptyBlock {
+    fn name(&self) -> &'static str {
+        "empty_block"
+    }
+
+    fn check_statement(&self, statement: &Statement, context: &Context, lints: &mut Vec<Lint>) {
+        let (keyword, body, span) = match statement {
+            Statement::If {
+                then_branch,
+                else_branch,
+                span,
+                ..
+            } => {
+                if else_branch.as_deref().is_some_and(is_empty) {
+                    let fix = else_block(context, *span).map(|(else_token, _, close)| {
+                        let then_close = else_token - 1;
+                        Fix {
+                            message: "remove the `else`".to_string(),
+                            edits: vec![Edit {
+                                range: context.tokens[then_close].span.end
+                                    ..context.tokens[close].span.end,
+                                replacement: String::new(),
+                            }],
+                        }
+                    });
+                    let else_span = else_block(context, *span)
+                        .map_or(*span, |(else_token, _, _)| context.tokens[else_token].span);
+                    lints.push(
+                        Lint::new(self.name(), else_span, "empty `else` block".to_string())
+                            .with_fix(fix),
+                    );
+                }
+                ("if", then_branch, span)
+            }
+            Statement::While { body, span, .. } => ("while", body, span),
+            Statement::Loop { body, span, .. } => ("loop", body, span),
+            Statement::ForIn { body, span, .. } => ("for", body, span),
+            _ => return,
+        };
+        if is_empty(body) {
+            lints.push(Lint::new(
+                self.name(),
+                *span,
+                format!("empty `{}` block", keyword),
+            ));
+        }
+    }
+}
+
+fn is_empty(block: &Statement) -> bool {
+    matches!(block, Statement::Block(statements) if statements.is_empty())
+}
+
+// The `else` token of the `if` at `span`, and the braces of its block
+fn else_block(context: &Context, span: Span) -> Option<(usize, usize, usize)> {
+    let (_, then_close) = context.block_after(context.token_at(span.start)?)?;
+    let else_token = then_close + 1;
+    if context.token(else_token)?.token != Token::Else {
+        return None;
+    }
+    let (open, close) = context.block_after(else_token)?;
+    Some((else_token, open, close))
+}
+
+// Flags the first `if`, `while`, `loop` or `for` nested deeper than the limit
+struct DeepNesting {
+    max_depth: usize,
+}
+
+impl Rule for DeepNesting {
+    fn name(&self) -> &'static str {
+        "deep_nesting"
+    }
+
+    fn options(&self) -> &'static [&'static str] {
+        &["max_nesting_depth"]
+    }
+
+    fn configure(&mut self, config: &Config) -> Result<(), CrabbyError> {
+        if let Some(depth) = config.integer(SECTION, "max_nesting_depth")? {
+            self.max_depth = usize::try_from(depth)
+                .map_err(|_| config.invalid(SECTION, "max_nesting_depth", "positive"))?;
+        }
+        Ok(())
+    }
+
+    fn check_statement(&self, statement: &Statement, context: &Context, lints: &mut Vec<Lint>) {
+        let (keyword, span) = match statement {
+            Statement::If { span, .. } => ("if", span),
+            Statement::While { span, .. } => ("while", span),
+            Statement::Loop { span, .. } => ("loop", span),
+            Statement::ForIn { span, .. } => ("for", span),
+            _ => return,
+        };
+        // Anything deeper is inside this one, and reported with it
+        if context.depth == self.max_depth {
+            lints.push(Lint::new(
+                self.name(),
+                *span,
+                format!(
+                    "`{}` nested {} levels deep, more than the limit of {}; consider moving it into a function",
+                    keyword,
+                    context.depth + 1,
+                    self.max_depth
+                ),
+            ));
+        }
+    }
+}
+
+// `if x { return 1 } else { return 2 }` reads better as `if x { return 1 }` then `return 2`
+struct RedundantElse;
+
+impl Rule for RedundantElse {
+    fn name(&self) -> &'static str {
+        "redundant_else"
+    }
+
+    fn check_statement(&self, statement: &Statement, context: &Context, lints: &mut Vec<Lint>) {
+        let Statement::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            span,
+            ..
+        } = statement
+        else {
+            return;
+        };
+        if !terminates(then_branch) || is_empty(else_branch) {
+            return;
+        }
+        let Some((else_token, open, close)) = else_block(context, *span) else {
+            return;
+        };
+
+        // The else block's lines move out to the `if`, keeping how they are
+        // indented relative to each other
+        let then_close = &context.tokens[else_token - 1].span;
+        let close = &context.tokens[close].span;
+        let inner = &context.source[context.tokens[open].span.end..close.start];
+        let indentation = context.indentation(span.start);
+        let lines: Vec<String> = if inner.contains('\n') {
+            let lines: Vec<&str> = inner
+                .lines()
+                .skip_while(|line| line.trim().is_empty())
+                .collect();
+            let last = lines.iter().rposition(|line| !line.trim().is_empty());
+            let lines = &lines[..last.map_or(0, |last| last + 1)];
+            let common = lines
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or(0);
+            lines
+                .iter()
+                .map(|line| {
+                    if line.trim().is_empty() {
+                        String::new()
+                    } else {
+                        format!("{}{}", indentation, &line[common..])
+                    }
+                })
+                .collect()
+        } else {
+            vec![format!("{}{}", indentation, inner.trim())]
+        };
+        let fix = Fix {
+            message: "remove the `else` and keep its body after the `if`".to_string(),
+            edits: vec![Edit {
+                range: then_close.end..close.end,
+                replacement: format!("\n{}", lines.join("\n")),
+            }],
+        };
+        lints.push(
+            Lint::new(
+                self.name(),
+                context.tokens[else_token].span,
+                "`else` after a block that always returns".to_string(),
+            )
+            .with_fix(Some(fix)),
+        );
+    }
+}
+
+// Splits `myHTTPServer2` into `my`, `HTTP` and `Server2`, and `snake_case` at its underscores
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let starts_word = c.is_uppercase()
+            && i > 0
+            && !current.is_empty()
+            && (!chars[i - 1].is_uppercase()
+                || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+        if starts_word {
+            words.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+// Leading underscores, which mark a name as unused on purpose, are kept
+fn to_snake_case(name: &str) -> String {
+    let rest = name.trim_start_matches('_');
+    let prefix = &name[..name.len() - rest.len()];
+    let words: Vec<String> = words(rest).iter().map(|w| w.to_lowercase()).collect();
+    format!("{}{}", prefix, words.join("_"))
+}
+
+fn to_pascal_case(name: &str) -> String {
+    let rest = name.trim_start_matches('_');
+    let prefix = &name[..name.len() - rest.len()];
+    let words: String = words(rest)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    format!("{}{}", prefix, words)
+}
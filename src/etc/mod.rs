@@ -1,3 +1,10 @@
+pub mod bytecode;
+pub mod config;
 pub mod deadcode;
 pub mod docgen;
+pub mod formatter;
+pub mod lint;
+pub mod printer;
+pub mod testing;
+pub mod typechecker;
 pub mod wasm;
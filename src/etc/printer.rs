@@ -0,0 +1,621 @@
+// Turns a Crabby AST back into source code
+// Used by `crabby expand` to show a program after its macros are expanded
+
+use crate::ast::{
+    Decorator, Expression, FStringPart, Program, Statement, TypeExpr, TypeParameter, Visibility,
+};
+use crate::utils::escape;
+
+const INDENT: &str = "    ";
+
+pub fn print_program(program: &Program) -> String {
+    let mut printer = Printer::default();
+    for statement in &program.statements {
+        printer.statement(statement);
+    }
+    printer.output
+}
+
+#[derive(Default)]
+struct Printer {
+    output: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.output.push_str(INDENT);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    // Prints `header {`, the block's statements indented, then `}`
+    fn block(&mut self, header: &str, body: &Statement) {
+        self.line(&format!("{} {{", header));
+        self.body(body);
+        self.line("}");
+    }
+
+    // Types keep their docstring as a string opening their body
+    fn docstring(&mut self, docstring: &str) {
+        if !docstring.is_empty() {
+            self.line(&format!("\"{}\"", escape(docstring)));
+        }
+    }
+
+    fn body(&mut self, body: &Statement) {
+        self.depth += 1;
+        match body {
+            Statement::Block(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            statement => self.statement(statement),
+        }
+        self.depth -= 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::FunctionDef {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                visibility,
+                decorators,
+                ..
+            }
+            | Statement::FunctionFun {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                visibility,
+                decorators,
+                ..
+            } => {
+                self.decorators(decorators);
+                let keyword = if matches!(statement, Statement::FunctionDef { .. }) {
+                    "def"
+                } else {
+                    "fun"
+                };
+                let params = params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.block(
+                    &format!(
+                        "{}{} {}{}({}){}",
+                        visibility_prefix(visibility),
+                        keyword,
+                        name,
+                        type_params_suffix(type_params),
+                        params,
+                        return_suffix(return_type)
+                    ),
+                    body,
+                );
+            }
+            Statement::AsyncFunction {
+                name,
+                params,
+                body,
+                return_type,
+            } => {
+                let params = params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.block(
+                    &format!(
+                        "async def {}({}){}",
+                        name,
+                        params,
+                        return_suffix(return_type)
+                    ),
+                    body,
+                );
+            }
+            Statement::Let {
+                name,
+                value,
+                type_annotation,
+                ..
+            }
+            | Statement::Var {
+                name,
+                value,
+                type_annotation,
+                ..
+            }
+            | Statement::Const {
+                name,
+                value,
+                type_annotation,
+                ..
+            } => {
+                let keyword = match statement {
+                    Statement::Let { .. } => "let",
+                    Statement::Var { .. } => "var",
+                    _ => "const",
+                };
+                let annotation = type_annotation
+                    .as_ref()
+                    .map(|annotation| format!(": {}", annotation))
+                    .unwrap_or_default();
+                self.line(&format!(
+                    "{} {}{} = {}",
+                    keyword,
+                    name,
+                    annotation,
+                    expression(value)
+                ));
+            }
+            Statement::Return(value, _) => self.line(&format!("return {}", expression(value))),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                span: _,
+            } => {
+                self.line(&format!("if {} {{", expression(condition)));
+                self.body(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.line("} else {");
+                    self.body(else_branch);
+                }
+                self.line("}");
+            }
+            Statement::And { left, right } => self.line(&format!("and {} and {}", left, right)),
+            Statement::While {
+                condition, body, ..
+            } => self.block(&format!("while {}:", expression(condition)), body),
+            Statement::Assert {
+                condition, message, ..
+            } => match message {
+                Some(message) => self.line(&format!(
+                    "assert {}, {}",
+                    expression(condition),
+                    expression(message)
+                )),
+                None => self.line(&format!("assert {}", expression(condition))),
+            },
+            Statement::Test { name, body, .. } => {
+                self.block(&format!("test \"{}\"", escape(name)), body)
+            }
+            Statement::Loop { count, body, .. } => {
+                self.block(&format!("loop {}", expression(count)), body)
+            }
+            Statement::ForIn {
+                variable,
+                iterator,
+                body,
+                span: _,
+            } => self.block(
+                &format!("for {} in {}", variable, expression(iterator)),
+                body,
+            ),
+            Statement::Match { value, arms } => {
+                self.line(&format!("match {} {{", expression(value)));
+                self.depth += 1;
+                for arm in arms {
+                    self.line(&format!(
+                        "case {} => {},",
+                        expression(&arm.pattern),
+                        expression(&arm.body)
+                    ));
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::Enum {
+                name,
+                type_params,
+                variants,
+                where_clause,
+                docstring,
+                visibility,
+                span: _,
+            } => {
+                self.line(&format!(
+                    "{}enum {}{}{} {{",
+                    visibility_prefix(visibility),
+                    name,
+                    type_params_suffix(type_params),
+                    where_suffix(where_clause)
+                ));
+                self.depth += 1;
+                self.docstring(docstring);
+                for variant in variants {
+                    match &variant.fields {
+                        Some(fields) => {
+                            self.line(&format!("{}({}),", variant.name, expressions(fields)))
+                        }
+                        None => self.line(&format!("{},", variant.name)),
+                    }
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::Struct {
+                name,
+                type_params,
+                fields,
+                where_clause,
+                docstring,
+                visibility,
+                span: _,
+            } => {
+                self.line(&format!(
+                    "{}struct {}{}{} {{",
+                    visibility_prefix(visibility),
+                    name,
+                    type_params_suffix(type_params),
+                    where_suffix(where_clause)
+                ));
+                self.depth += 1;
+                self.docstring(docstring);
+                for field in fields {
+                    self.line(&format!(
+                        "{}: {},",
+                        field.name,
+                        expression(&field.type_expr)
+                    ));
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::TypeDef { name, target, .. } => {
+                self.line(&format!("typedef {} = {}", name, target))
+            }
+            Statement::Macro { name, params, body } => {
+                self.block(&format!("macro {}!({})", name, params.join(", ")), body)
+            }
+            Statement::Extern {
+                library,
+                name,
+                params,
+                return_type,
+                ..
+            } => {
+                let library = library
+                    .as_ref()
+                    .map(|library| format!(" \"{}\"", escape(library)))
+                    .unwrap_or_default();
+                let params = params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.line(&format!(
+                    "extern{} def {}({}){}",
+                    library,
+                    name,
+                    params,
+                    return_suffix(return_type)
+                ));
+            }
+            Statement::ExternStruct { name, fields, .. } => {
+                self.line(&format!("extern struct {} {{", name));
+                self.depth += 1;
+                for field in fields {
+                    self.line(&format!("{}: {},", field.name, field.type_annotation));
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::Import { name, source, .. } => match source {
+                Some(source) => self.line(&format!("import {} from \"{}\"", name, source)),
+                None => self.line(&format!("import {}", name)),
+            },
+            Statement::Class {
+                name,
+                type_params,
+                parent,
+                methods,
+                fields,
+                decorators,
+                docstring,
+                visibility,
+                span: _,
+            } => {
+                self.decorators(decorators);
+                let parent = parent
+                    .as_ref()
+                    .map(|parent| format!(" extend {}", parent))
+                    .unwrap_or_default();
+                self.line(&format!(
+                    "{}class {}{}{} {{",
+                    visibility_prefix(visibility),
+                    name,
+                    type_params_suffix(type_params),
+                    parent
+                ));
+                self.depth += 1;
+                self.docstring(docstring);
+                for field in fields {
+                    match &field.default {
+                        Some(default) => {
+                            self.line(&format!("var {} = {}", field.name, expression(default)))
+                        }
+                        None => self.line(&format!("var {}", field.name)),
+                    }
+                }
+                for method in methods {
+                    self.statement(method);
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::Extend {
+                class,
+                parent,
+                methods,
+            } => {
+                self.line(&format!("extend {} {} {{", class, parent));
+                self.depth += 1;
+                for method in methods {
+                    self.statement(method);
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::Trait { name, methods, .. }
+            | Statement::Impl {
+                target: name,
+                methods,
+                ..
+            } => {
+                let header = match statement {
+                    Statement::Impl {
+                        trait_name: Some(trait_name),
+                        ..
+                    } => format!("impl {} for {}", trait_name, name),
+                    Statement::Impl { .. } => format!("impl {}", name),
+                    Statement::Trait { visibility, .. } => {
+                        format!("{}trait {}", visibility_prefix(visibility), name)
+                    }
+                    _ => unreachable!(),
+                };
+                self.line(&format!("{} {{", header));
+                self.depth += 1;
+                if let Statement::Trait { docstring, .. } = statement {
+                    self.docstring(docstring);
+                }
+                for method in methods {
+                    let params = method
+                        .params
+                        .iter()
+                        .map(|param| param.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    self.block(&format!("def {}({})", method.name, params), &method.body);
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::ArrayAssign {
+                array,
+                index,
+                value,
+            } => self.line(&format!(
+                "{}[{}] = {}",
+                expression(array),
+                expression(index),
+                expression(value)
+            )),
+            Statement::Block(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            Statement::Expression(value) => self.line(&expression(value)),
+        }
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) {
+        for decorator in decorators {
+            if decorator.called {
+                let arguments = decorator
+                    .arguments
+                    .iter()
+                    .map(expression)
+                    .chain(
+                        decorator
+                            .keyword_arguments
+                            .iter()
+                            .map(|arg| format!("{}: {}", arg.name, expression(&arg.value))),
+                    )
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.line(&format!("@{}({})", decorator.name, arguments));
+            } else {
+                self.line(&format!("@{}", decorator.name));
+            }
+        }
+    }
+}
+
+pub fn visibility_prefix(visibility: &Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public => "pub ",
+        Visibility::Protect => "protect ",
+        Visibility::Private => "",
+    }
+}
+
+pub fn type_params_suffix(type_params: &[TypeParameter]) -> String {
+    if type_params.is_empty() {
+        return String::new();
+    }
+    format!(
+        "<{}>",
+        type_params
+            .iter()
+            .map(|param| param.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+pub fn return_suffix(return_type: &Option<TypeExpr>) -> String {
+    return_type
+        .as_ref()
+        .map(|return_type| format!(" -> {}", return_type))
+        .unwrap_or_default()
+}
+
+fn where_suffix(where_clause: &Option<Box<Expression>>) -> String {
+    where_clause
+        .as_ref()
+        .map(|clause| format!(" where {}", expression(clause)))
+        .unwrap_or_default()
+}
+
+pub fn expressions(values: &[Expression]) -> String {
+    values.iter().map(expression).collect::<Vec<_>>().join(", ")
+}
+
+// Unlike `Display`, this quotes strings and nests blocks over several lines
+pub fn expression(value: &Expression) -> String {
+    match value {
+        Expression::String(s) => format!("\"{}\"", escape(s)),
+        Expression::FString(parts) => {
+            let parts: String = parts
+                .iter()
+                .map(|part| match part {
+                    FStringPart::Text(text) => escape(text).replace('{', "{{").replace('}', "}}"),
+                    FStringPart::Hole {
+                        expression: value,
+                        spec,
+                    } => match spec {
+                        Some(spec) => format!("{{{}:{}}}", expression(value), spec),
+                        None => format!("{{{}}}", expression(value)),
+                    },
+                })
+                .collect();
+            format!("f\"{}\"", parts)
+        }
+        Expression::Binary {
+            left,
+            operator,
+            right,
+            span: _,
+        } => format!("{} {} {}", operand(left), operator, operand(right)),
+        Expression::Unary {
+            operator,
+            operand: value,
+        } => format!("{}{}", operator, operand(value)),
+        Expression::Borrow {
+            expr,
+            mutable: true,
+            ..
+        } => format!("&mut {}", operand(expr)),
+        Expression::Borrow { expr, .. } => format!("&{}", operand(expr)),
+        Expression::Move { expr, .. } => format!("move {}", operand(expr)),
+        Expression::Call {
+            function,
+            arguments,
+            keyword_arguments,
+            ..
+        } => format!(
+            "{}({})",
+            function,
+            arguments
+                .iter()
+                .map(expression)
+                .chain(keyword_arguments.iter().map(|arg| format!(
+                    "{}: {}",
+                    arg.name,
+                    expression(&arg.value)
+                )))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Expression::MethodCall {
+            object,
+            method,
+            arguments,
+            keyword_arguments,
+            optional,
+        } => format!(
+            "{}{}{}({})",
+            operand(object),
+            if *optional { "?." } else { "." },
+            method,
+            arguments
+                .iter()
+                .map(expression)
+                .chain(keyword_arguments.iter().map(|arg| format!(
+                    "{}: {}",
+                    arg.name,
+                    expression(&arg.value)
+                )))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Expression::Member {
+            object,
+            field,
+            optional,
+        } => format!(
+            "{}{}{}",
+            operand(object),
+            if *optional { "?." } else { "." },
+            field
+        ),
+        Expression::Lambda { params, body } => {
+            let params = params
+                .iter()
+                .map(|param| param.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            match body.as_ref() {
+                Statement::Return(value, _) => format!("({}) => {}", params, expression(value)),
+                body => {
+                    let mut printer = Printer::default();
+                    printer.block(&format!("({}) =>", params), body);
+                    printer.output.trim_end().to_string()
+                }
+            }
+        }
+        Expression::Array(elements) => format!("[{}]", expressions(elements)),
+        Expression::Index { array, index } => {
+            format!("{}[{}]", operand(array), expression(index))
+        }
+        Expression::Range(count) => format!("range({})", expression(count)),
+        Expression::Await { expr } => format!("await {}", expression(expr)),
+        Expression::Try(expr) => format!("{}?", operand(expr)),
+        Expression::TypeOf(expr) => format!("typeof({})", expression(expr)),
+        Expression::Is { value, type_expr } => format!("{} is {}", operand(value), type_expr),
+        Expression::MacroCall {
+            name, arguments, ..
+        } => format!("{}!({})", name, expressions(arguments)),
+        other => other.to_string(),
+    }
+}
+
+// Parenthesizes compound operands so the printed code keeps its meaning
+fn operand(value: &Expression) -> String {
+    match value {
+        Expression::Binary { .. }
+        | Expression::Lambda { .. }
+        | Expression::Await { .. }
+        | Expression::Is { .. }
+        | Expression::Unary { .. }
+        | Expression::Borrow { .. }
+        | Expression::Move { .. } => {
+            format!("({})", expression(value))
+        }
+        _ => expression(value),
+    }
+}
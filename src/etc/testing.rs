@@ -0,0 +1,414 @@
+// What `crabby test` needs: finding the tests in a program and the examples
+// in its docstrings, describing how a failed expectation differs from what
+// was expected, and JUnit XML for CI.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::ast::{Expression, Program, Statement};
+use crate::core::metaprogram::expand_macros;
+use crate::etc::docgen::{Documentation, Example, examples};
+use crate::etc::formatter::line_diff;
+use crate::interpreter::{Interpreter, values_equal};
+use crate::lexer::TokenStream;
+use crate::parser::parse;
+use crate::utils::{CrabbyError, escape};
+use crate::value::Value;
+
+pub struct TestCase {
+    pub name: String,
+    // What runs the test once the rest of the file has run
+    pub body: Statement,
+}
+
+pub struct TestOutcome {
+    pub file: String,
+    pub name: String,
+    pub duration: Duration,
+    pub failure: Option<String>,
+}
+
+// `math_test.crab` holds tests; other files in a test directory are helpers
+pub fn is_test_file(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.ends_with("_test"))
+}
+
+// Splits a program into its tests, `test "name" { ... }` blocks and `@test`
+// functions, and the setup every test runs first: everything but the test blocks
+pub fn collect_tests(program: &Program) -> (Program, Vec<TestCase>) {
+    let mut setup = Program::new();
+    let mut tests = Vec::new();
+    for statement in &program.statements {
+        match statement {
+            Statement::Test { name, body, .. } => tests.push(TestCase {
+                name: name.clone(),
+                body: (**body).clone(),
+            }),
+            Statement::FunctionDef {
+                name,
+                decorators,
+                span,
+                ..
+            }
+            | Statement::FunctionFun {
+                name,
+                decorators,
+                span,
+                ..
+            } => {
+                if decorators.iter().any(|decorator| decorator.name == "test") {
+                    tests.push(TestCase {
+                        name: name.clone(),
+                        body: Statement::Expression(Expression::Call {
+                            function: name.clone(),
+                            arguments: Vec::new(),
+                            keyword_arguments: Vec::new(),
+                            span: *span,
+                        }),
+                    });
+                }
+                setup.statements.push(statement.clone());
+            }
+            statement => setup.statements.push(statement.clone()),
+        }
+    }
+    (setup, tests)
+}
+
+pub struct Doctest {
+    pub name: String,
+    // The line of the file the example's ``` is on
+    pub line: usize,
+    pub example: Example,
+}
+
+// The examples in the docstrings of a file and of its items, private ones
+// included, in the order they appear
+pub fn collect_doctests(program: &Program, source: &str) -> Vec<Doctest> {
+    let mut documentation = Documentation::new(true);
+    documentation.add_module("", program);
+    let module = &documentation.modules[0];
+    let mut documented = vec![("module".to_string(), &module.doc)];
+    for item in &module.items {
+        documented.push((item.name.clone(), &item.doc));
+        for member in &item.members {
+            documented.push((format!("{}.{}", item.name, member.name), &member.doc));
+        }
+    }
+
+    let lines: Vec<&str> = source.lines().map(doc_text).collect();
+    let mut from = 0;
+    let mut doctests = Vec::new();
+    for (name, doc) in documented {
+        for example in examples(doc) {
+            let line = locate(&lines, &example, from);
+            from = line;
+            doctests.push(Doctest {
+                name: format!("{} (line {})", name, line),
+                line,
+                example,
+            });
+        }
+    }
+    doctests
+}
+
+// A source line as it reads in a docstring
+fn doc_text(line: &str) -> &str {
+    let line = line.trim();
+    line.strip_prefix("///").map(str::trim).unwrap_or(line)
+}
+
+// The line, from 1, of the ``` opening `example` at or after line `from`. The
+// docstring copies its lines, so the block is found by its text; failing that
+// (escapes in a string read differently), it is the next ``` in the file.
+fn locate(lines: &[&str], example: &Example, from: usize) -> usize {
+    let code: Vec<&str> = example.code.lines().map(str::trim).collect();
+    let fences = (from..lines.len()).filter(|&i| lines[i].starts_with("```"));
+    let mut next_fence = None;
+    for i in fences {
+        next_fence.get_or_insert(i);
+        let block = lines[i + 1..].iter().take(code.len());
+        if block.len() == code.len() && block.zip(&code).all(|(line, code)| line == code) {
+            return i + 1;
+        }
+    }
+    next_fence.map_or(from, |i| i + 1)
+}
+
+// The declarations of a program, which its examples can use without running
+// the rest of it
+pub fn declarations(program: &Program) -> Program {
+    let mut declarations = Program::new();
+    declarations.statements = program
+        .statements
+        .iter()
+        .filter(|statement| {
+            matches!(
+                statement,
+                Statement::FunctionDef { .. }
+                    | Statement::FunctionFun { .. }
+                    | Statement::AsyncFunction { .. }
+                    | Statement::Let { .. }
+                    | Statement::Const { .. }
+                    | Statement::Var { .. }
+                    | Statement::Enum { .. }
+                    | Statement::Struct { .. }
+                    | Statement::TypeDef { .. }
+                    | Statement::Class { .. }
+                    | Statement::Extend { .. }
+                    | Statement::Trait { .. }
+                    | Statement::Impl { .. }
+            )
+        })
+        .cloned()
+        .collect();
+    declarations
+}
+
+// Runs an example after `setup` and compares what it prints with its
+// `// => ` lines, if it has any. A failure names the line of the file.
+pub fn run_doctest(
+    doctest: &Doctest,
+    setup: &Program,
+    mut interpreter: Interpreter,
+) -> Result<(), String> {
+    let at = |line: usize| format!("at line {}: ", doctest.line + line);
+    let failure = |error: CrabbyError| format!("{}{}", at(error_line(&error).unwrap_or(0)), error);
+
+    let mut example = TokenStream::tokenize(doctest.example.code.clone())
+        .and_then(parse)
+        .and_then(expand_macros)
+        .map_err(failure)?;
+    // The file's declarations are already there to use
+    example
+        .statements
+        .retain(|statement| !matches!(statement, Statement::Import { .. }));
+
+    let printed = Rc::new(RefCell::new(String::new()));
+    interpreter.output = Some(printed.clone());
+    for statement in setup.statements.iter().chain(&example.statements) {
+        interpreter
+            .interpret_statement(statement)
+            .map_err(failure)?;
+    }
+
+    let expected = doctest.example.expected();
+    if expected.is_empty() {
+        return Ok(());
+    }
+    let output = printed.take();
+    let mut printed = output.lines();
+    for (line, text) in &expected {
+        match printed.next() {
+            Some(actual) if actual == text => {}
+            Some(actual) => {
+                return Err(format!(
+                    "{}expected {}, printed {}",
+                    at(*line),
+                    quote(text),
+                    quote(actual)
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "{}expected {}, printed nothing",
+                    at(*line),
+                    quote(text)
+                ));
+            }
+        }
+    }
+    match printed.next() {
+        Some(extra) => Err(format!(
+            "{}printed {} after everything expected",
+            at(expected[expected.len() - 1].0),
+            quote(extra)
+        )),
+        None => Ok(()),
+    }
+}
+
+// The line of the example an error is on, when it knows
+fn error_line(error: &CrabbyError) -> Option<usize> {
+    match error {
+        CrabbyError::LexerError(location)
+        | CrabbyError::ParserError(location)
+        | CrabbyError::MissingCaseKeyword(location) => Some(location.line),
+        CrabbyError::TypeError(Some(span), _)
+        | CrabbyError::AssertionError(Some(span), _)
+        | CrabbyError::ExpansionError(span, _) => Some(span.line),
+        _ => None,
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", escape(text))
+}
+
+// How `actual` differs from `expected`, pointing into lists, dicts and
+// instances at the first place they disagree
+pub fn mismatch(expected: &Value, actual: &Value) -> String {
+    if let (Value::String(expected), Value::String(actual)) = (expected, actual)
+        && (expected.contains('\n') || actual.contains('\n'))
+    {
+        return format!(
+            "strings differ (-expected +got):\n{}",
+            line_diff(expected, actual).trim_end()
+        );
+    }
+
+    match difference(expected, actual, &mut String::new()) {
+        Some(difference) => format!(
+            "values differ\n    expected: {}\n         got: {}\n    {}",
+            show(expected),
+            show(actual),
+            difference
+        ),
+        None => format!("expected {}, got {}", show(expected), show(actual)),
+    }
+}
+
+fn difference(expected: &Value, actual: &Value, path: &mut String) -> Option<String> {
+    match (expected, actual) {
+        (Value::Array(expected), Value::Array(actual)) => {
+            for (i, (e, a)) in expected.iter().zip(actual).enumerate() {
+                if !values_equal(e, a) {
+                    return nested(path, &format!("[{}]", i), e, a);
+                }
+            }
+            (expected.len() != actual.len()).then(|| {
+                let count = format!("expected {} elements, got {}", expected.len(), actual.len());
+                if path.is_empty() {
+                    count
+                } else {
+                    format!("at {}: {}", path, count)
+                }
+            })
+        }
+        (Value::Dict(expected), Value::Dict(actual)) => {
+            for (key, e) in expected {
+                match actual.iter().find(|(name, _)| name == key) {
+                    Some((_, a)) if !values_equal(e, a) => {
+                        return nested(path, &format!(".{}", key), e, a);
+                    }
+                    Some(_) => {}
+                    None => return Some(format!("at {}.{}: missing", path, key)),
+                }
+            }
+            actual
+                .iter()
+                .find(|(key, _)| !expected.iter().any(|(name, _)| name == key))
+                .map(|(key, _)| format!("at {}.{}: unexpected", path, key))
+        }
+        (Value::Instance(expected), Value::Instance(actual))
+            if expected.type_name == actual.type_name && expected.variant == actual.variant =>
+        {
+            for ((key, e), (_, a)) in expected.fields.iter().zip(&actual.fields) {
+                if !values_equal(e, a) {
+                    return nested(path, &format!(".{}", key), e, a);
+                }
+            }
+            None
+        }
+        (Value::Ok(expected), Value::Ok(actual)) | (Value::Err(expected), Value::Err(actual)) => {
+            difference(expected, actual, path)
+        }
+        // The whole values are already shown
+        _ if path.is_empty() => None,
+        _ => Some(format!(
+            "at {}: expected {}, got {}",
+            path,
+            show(expected),
+            show(actual)
+        )),
+    }
+}
+
+fn nested(path: &mut String, step: &str, expected: &Value, actual: &Value) -> Option<String> {
+    path.push_str(step);
+    difference(expected, actual, path)
+}
+
+// A value as it would be written in code, so `"1"` and `1` look different
+pub fn show(value: &Value) -> String {
+    match value {
+        Value::String(text) => format!("\"{}\"", escape(text)),
+        Value::Array(elements) => {
+            let elements: Vec<String> = elements.iter().map(show).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Value::Dict(entries) => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, show(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Ok(value) => format!("Ok({})", show(value)),
+        Value::Err(value) => format!("Err({})", show(value)),
+        other => other.to_string(),
+    }
+}
+
+// One <testsuite> per file, in the format CI servers read
+pub fn junit_report(outcomes: &[TestOutcome]) -> String {
+    let failures = outcomes.iter().filter(|o| o.failure.is_some()).count();
+    let total: Duration = outcomes.iter().map(|o| o.duration).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"crabby\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        outcomes.len(),
+        failures,
+        total.as_secs_f64()
+    ));
+
+    let mut files: Vec<&str> = Vec::new();
+    for outcome in outcomes {
+        if !files.contains(&outcome.file.as_str()) {
+            files.push(&outcome.file);
+        }
+    }
+    for file in files {
+        let suite: Vec<&TestOutcome> = outcomes.iter().filter(|o| o.file == file).collect();
+        let time: Duration = suite.iter().map(|o| o.duration).sum();
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            xml_escape(file),
+            suite.len(),
+            suite.iter().filter(|o| o.failure.is_some()).count(),
+            time.as_secs_f64()
+        ));
+        for outcome in suite {
+            let attributes = format!(
+                "name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                xml_escape(&outcome.name),
+                xml_escape(file),
+                outcome.duration.as_secs_f64()
+            );
+            match &outcome.failure {
+                Some(failure) => xml.push_str(&format!(
+                    "    <testcase {}>\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                    attributes,
+                    xml_escape(failure.lines().next().unwrap_or("")),
+                    xml_escape(failure)
+                )),
+                None => xml.push_str(&format!("    <testcase {}/>\n", attributes)),
+            }
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
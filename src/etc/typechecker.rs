@@ -0,0 +1,1407 @@
+// Crabby's gradual type checker, run by `crabby check`
+// Annotated code (`x: Int`, `-> String`) is checked before it runs. Anything
+// without an annotation gets the `Any` type and stays dynamically typed.
+
+use crate::ast::{
+    BinaryOp, Expression, FStringPart, KeywordArgument, Parameter, ParameterKind, Program,
+    Statement, TypeExpr, TypeParameter, UnaryOp,
+};
+use crate::format::FormatSpec;
+use crate::utils::{CrabbyError, Span};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Bool,
+    Null,
+    Void,
+    List(Box<Type>),
+    Optional(Box<Type>),
+    Function {
+        params: Vec<Type>,
+        returns: Box<Type>,
+    },
+    Named(String), // structs, enums and classes
+    Generic {
+        name: String,
+        arguments: Vec<Type>,
+    },
+    Param(String), // a type parameter such as `T` in `def add<T: Num>`
+    Any,           // not known until runtime
+}
+
+// Traits every type parameter bound may name without declaring them
+const BUILTIN_TRAITS: &[&str] = &["Num", "Display", "Eq", "Ord"];
+
+#[derive(Debug, Clone)]
+struct Signature {
+    type_params: Vec<TypeParameter>,
+    params: Vec<(Parameter, Type)>,
+    returns: Type,
+}
+
+#[derive(Default)]
+struct Scope {
+    variables: HashMap<String, Type>,
+    functions: HashMap<String, Signature>,
+}
+
+pub struct TypeChecker {
+    scopes: Vec<Scope>,
+    types: HashSet<String>,
+    // Type parameters of generic structs, enums and classes
+    generics: HashMap<String, Vec<TypeParameter>>,
+    // Trait name to the names of the types that implement it
+    traits: HashMap<String, HashSet<String>>,
+    // `typedef` names and what they stand for
+    aliases: HashMap<String, TypeExpr>,
+    expanding: Vec<String>, // aliases being resolved, to catch cycles
+    // Type parameters in scope, innermost definition last
+    type_params: Vec<Vec<TypeParameter>>,
+    // Name, declared return type and span of each function being checked
+    functions: Vec<(String, Type, Span)>,
+    span: Span, // nearest enclosing code with a known position
+    errors: Vec<CrabbyError>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+            types: HashSet::new(),
+            generics: HashMap::new(),
+            traits: HashMap::new(),
+            aliases: HashMap::new(),
+            expanding: Vec::new(),
+            type_params: Vec::new(),
+            functions: Vec::new(),
+            span: Span::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn check(&mut self, program: &Program) -> Vec<CrabbyError> {
+        for statement in &program.statements {
+            match statement {
+                Statement::Struct {
+                    name, type_params, ..
+                }
+                | Statement::Enum {
+                    name, type_params, ..
+                }
+                | Statement::Class {
+                    name, type_params, ..
+                } => {
+                    self.types.insert(name.clone());
+                    if !type_params.is_empty() {
+                        self.generics.insert(name.clone(), type_params.clone());
+                    }
+                }
+                Statement::ExternStruct { name, .. } => {
+                    self.types.insert(name.clone());
+                }
+                Statement::TypeDef { name, target, .. } => {
+                    self.aliases.insert(name.clone(), target.clone());
+                }
+                Statement::Trait { name, .. } => {
+                    self.traits.entry(name.clone()).or_default();
+                }
+                Statement::Impl {
+                    target,
+                    trait_name: Some(trait_name),
+                    ..
+                } => {
+                    self.traits
+                        .entry(trait_name.clone())
+                        .or_default()
+                        .insert(target.clone());
+                }
+                _ => {}
+            }
+        }
+        for statement in &program.statements {
+            if let Statement::Struct { type_params, .. }
+            | Statement::Enum { type_params, .. }
+            | Statement::Class { type_params, .. } = statement
+            {
+                self.check_bounds_exist(type_params);
+            }
+        }
+
+        self.declare_functions(&program.statements);
+        for statement in &program.statements {
+            self.check_statement(statement);
+        }
+
+        std::mem::take(&mut self.errors)
+    }
+
+    // The type of an expression given everything checked so far, for the REPL's `:type`
+    pub fn type_of(&mut self, expression: &Expression) -> Type {
+        let ty = self.infer(expression);
+        self.errors.clear();
+        ty
+    }
+
+    // Functions can be called before (and from within) their own definition
+    fn declare_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            if let Statement::FunctionDef {
+                name,
+                type_params,
+                params,
+                return_type,
+                decorators,
+                span,
+                ..
+            }
+            | Statement::FunctionFun {
+                name,
+                type_params,
+                params,
+                return_type,
+                decorators,
+                span,
+                ..
+            } = statement
+            {
+                // A user-defined decorator may replace the function with anything
+                let builtin_decorators = decorators
+                    .iter()
+                    .all(|d| matches!(d.name.as_str(), "memoize" | "deprecated" | "test"));
+                if !builtin_decorators {
+                    self.declare_variable(name, Type::Any);
+                    continue;
+                }
+
+                self.span = *span;
+                self.check_bounds_exist(type_params);
+                self.type_params.push(type_params.clone());
+                let signature = Signature {
+                    type_params: type_params.clone(),
+                    params: params
+                        .iter()
+                        .map(|param| (param.clone(), self.resolve_optional(&param.type_annotation)))
+                        .collect(),
+                    returns: self.resolve_optional(return_type),
+                };
+                self.type_params.pop();
+                self.scope().functions.insert(name.clone(), signature);
+            }
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                name,
+                value,
+                type_annotation,
+                mutable: _,
+                span,
+            }
+            | Statement::Var {
+                name,
+                value,
+                type_annotation,
+                span,
+            }
+            | Statement::Const {
+                name,
+                value,
+                type_annotation,
+                span,
+            } => {
+                self.span = *span;
+                let name = name.trim_start_matches("pub ");
+                let actual = self.infer(value);
+
+                let declared = match type_annotation {
+                    Some(annotation) => {
+                        let expected = self.resolve(annotation);
+                        if !is_assignable(&expected, &actual) {
+                            let message = if actual == Type::Null {
+                                format!(
+                                    "Cannot assign null to '{}' of non-optional type {}; use '{}?' to allow null",
+                                    name, expected, expected
+                                )
+                            } else {
+                                format!(
+                                    "Cannot assign {} to '{}' of type {}",
+                                    actual, name, expected
+                                )
+                            };
+                            self.error(*span, message);
+                        }
+                        expected
+                    }
+                    None => actual,
+                };
+
+                // Calls to a lambda or `def` bound by name can check defaults and keywords too
+                let signature = match (value.as_ref(), &declared) {
+                    (Expression::Variable { name, .. }, Type::Function { .. }) => {
+                        self.signature(name)
+                    }
+                    (Expression::Lambda { params, .. }, Type::Function { returns, .. }) => {
+                        Some(Signature {
+                            type_params: Vec::new(),
+                            params: params
+                                .iter()
+                                .map(|param| {
+                                    (param.clone(), self.resolve_optional(&param.type_annotation))
+                                })
+                                .collect(),
+                            returns: *returns.clone(),
+                        })
+                    }
+                    _ => None,
+                };
+                self.declare_variable(name, declared);
+                if let Some(signature) = signature {
+                    self.scope().functions.insert(name.to_string(), signature);
+                }
+            }
+            Statement::FunctionDef {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                span,
+                ..
+            }
+            | Statement::FunctionFun {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                span,
+                ..
+            } => {
+                self.span = *span;
+                self.type_params.push(type_params.clone());
+                let returns = self.resolve_optional(return_type);
+                self.check_function(name, params, body, returns, *span);
+                self.type_params.pop();
+            }
+            Statement::AsyncFunction {
+                name,
+                params,
+                body,
+                return_type,
+            } => {
+                let returns = self.resolve_optional(return_type);
+                self.check_function(name, params, body, returns, self.span);
+            }
+            Statement::Return(value, span) => {
+                self.span = *span;
+                let actual = self.infer(value);
+                if let Some((name, expected, _)) = self.functions.last().cloned()
+                    && !is_assignable(&expected, &actual)
+                {
+                    self.error(
+                        *span,
+                        format!(
+                            "Function '{}' is declared to return {}, but returns {}",
+                            name, expected, actual
+                        ),
+                    );
+                }
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                span: _,
+            } => {
+                self.infer(condition);
+                self.check_scoped(then_branch, Vec::new());
+                if let Some(else_branch) = else_branch {
+                    self.check_scoped(else_branch, Vec::new());
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.infer(condition);
+                self.check_scoped(body, Vec::new());
+            }
+            Statement::Loop { count, body, .. } => {
+                let count_type = self.infer(count);
+                if !is_assignable(&Type::Int, &count_type) {
+                    self.error(
+                        self.span,
+                        format!("Loop count must be Int, got {}", count_type),
+                    );
+                }
+                self.check_scoped(body, Vec::new());
+            }
+            Statement::ForIn {
+                variable,
+                iterator,
+                body,
+                span: _,
+            } => {
+                let element = match self.infer(iterator) {
+                    Type::List(element) => *element,
+                    Type::Int => Type::Int,
+                    _ => Type::Any,
+                };
+                self.check_scoped(body, vec![(variable.clone(), element)]);
+            }
+            Statement::Match { value, arms } => {
+                self.infer(value);
+                for arm in arms {
+                    self.infer(&arm.body);
+                }
+            }
+            Statement::Class {
+                methods,
+                type_params,
+                ..
+            } => {
+                self.type_params.push(type_params.clone());
+                self.check_methods(methods);
+                self.type_params.pop();
+            }
+            Statement::Extend { methods, .. } => self.check_methods(methods),
+            Statement::ArrayAssign {
+                array,
+                index,
+                value,
+            } => {
+                let array_type = self.infer(array);
+                self.infer(index);
+                let value_type = self.infer(value);
+                if let Type::List(element) = array_type
+                    && !is_assignable(&element, &value_type)
+                {
+                    self.error(
+                        self.span,
+                        format!("Cannot store {} in a list of {}", value_type, element),
+                    );
+                }
+            }
+            Statement::Block(statements) => {
+                self.scopes.push(Scope::default());
+                self.declare_functions(statements);
+                for statement in statements {
+                    self.check_statement(statement);
+                }
+                self.scopes.pop();
+            }
+            Statement::Assert {
+                condition,
+                message,
+                span,
+            } => {
+                self.span = *span;
+                self.infer(condition);
+                if let Some(message) = message {
+                    self.infer(message);
+                }
+            }
+            Statement::Test { body, span, .. } => {
+                self.span = *span;
+                self.check_scoped(body, Vec::new());
+            }
+            Statement::TypeDef { name, target, span } => {
+                self.span = *span;
+                self.aliases.insert(name.clone(), target.clone());
+                self.resolve_alias(name);
+            }
+            Statement::Expression(expr) => {
+                self.infer(expr);
+            }
+            Statement::Import { span, .. } => {
+                self.error(*span, "import is not supported yet".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn check_methods(&mut self, methods: &[Statement]) {
+        self.scopes.push(Scope::default());
+        self.declare_functions(methods);
+        for method in methods {
+            self.check_statement(method);
+        }
+        self.scopes.pop();
+    }
+
+    fn check_function(
+        &mut self,
+        name: &str,
+        params: &[Parameter],
+        body: &Statement,
+        returns: Type,
+        span: Span,
+    ) {
+        let mut bindings = Vec::new();
+        for param in params {
+            let declared = self.resolve_optional(&param.type_annotation);
+            if let Some(default) = &param.default {
+                let actual = self.infer(default);
+                if !is_assignable(&declared, &actual) {
+                    self.error(
+                        span,
+                        format!(
+                            "Default value of parameter '{}' is {}, but the parameter is {}",
+                            param.name, actual, declared
+                        ),
+                    );
+                }
+            }
+
+            let bound = match param.kind {
+                ParameterKind::Positional => declared,
+                ParameterKind::Variadic => Type::List(Box::new(declared)),
+                ParameterKind::KeywordVariadic => Type::Any,
+            };
+            bindings.push((param.name.clone(), bound));
+        }
+
+        self.functions.push((name.to_string(), returns, span));
+        self.check_scoped(body, bindings);
+        self.functions.pop();
+    }
+
+    // Checks `body` in a new scope that starts with `bindings`
+    fn check_scoped(&mut self, body: &Statement, bindings: Vec<(String, Type)>) {
+        let mut scope = Scope::default();
+        scope.variables.extend(bindings);
+        self.scopes.push(scope);
+        match body {
+            Statement::Block(statements) => {
+                self.declare_functions(statements);
+                for statement in statements {
+                    self.check_statement(statement);
+                }
+            }
+            statement => self.check_statement(statement),
+        }
+        self.scopes.pop();
+    }
+
+    fn infer(&mut self, expr: &Expression) -> Type {
+        match expr {
+            Expression::Integer(_) | Expression::BigInteger(_) => Type::Int,
+            Expression::Float(_) => Type::Float,
+            Expression::String(_) => Type::String,
+            Expression::Boolean(_) | Expression::Maybe(_) | Expression::Probably(_) => Type::Bool,
+            Expression::Null => Type::Null,
+            Expression::FString(parts) => {
+                for part in parts {
+                    if let FStringPart::Hole { expression, spec } = part {
+                        let ty = self.infer(expression);
+                        if let Some(spec) = spec {
+                            self.check_format(&ty, spec);
+                        }
+                    }
+                }
+                Type::String
+            }
+            Expression::Variable { name, .. } => self.lookup(name),
+            Expression::Array(elements) => {
+                let types: Vec<Type> = elements.iter().map(|e| self.infer(e)).collect();
+                Type::List(Box::new(common_type(&types)))
+            }
+            Expression::Range(count) => {
+                self.infer(count);
+                Type::Any
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+                span: _,
+            } => {
+                let left = self.infer(left);
+                let right = self.infer(right);
+                self.binary_type(operator, left, right)
+            }
+            Expression::Unary { operator, operand } => {
+                let operand = self.infer(operand);
+                self.unary_type(operator, operand)
+            }
+            Expression::Borrow { expr, .. } | Expression::Move { expr, .. } => self.infer(expr),
+            Expression::Call {
+                function,
+                arguments,
+                keyword_arguments,
+                span,
+            } => {
+                let outer = std::mem::replace(&mut self.span, *span);
+                let result = self.call_type(function, arguments, keyword_arguments, *span);
+                self.span = outer;
+                result
+            }
+            Expression::Lambda { params, body } => {
+                let mut param_types = Vec::new();
+                let mut bindings = Vec::new();
+                for param in params {
+                    let declared = self.resolve_optional(&param.type_annotation);
+                    param_types.push(declared.clone());
+                    bindings.push((param.name.clone(), declared));
+                }
+
+                // `x => x + 1` returns the type of its expression
+                let returns = match body.as_ref() {
+                    Statement::Return(value, _) => {
+                        let mut scope = Scope::default();
+                        scope.variables.extend(bindings);
+                        self.scopes.push(scope);
+                        let returns = self.infer(value);
+                        self.scopes.pop();
+                        returns
+                    }
+                    body => {
+                        self.functions
+                            .push(("<lambda>".to_string(), Type::Any, self.span));
+                        self.check_scoped(body, bindings);
+                        self.functions.pop();
+                        Type::Any
+                    }
+                };
+
+                Type::Function {
+                    params: param_types,
+                    returns: Box::new(returns),
+                }
+            }
+            Expression::Index { array, index } => {
+                let array = self.infer(array);
+                self.infer(index);
+                match array {
+                    Type::List(element) => *element,
+                    Type::String => Type::String,
+                    _ => Type::Any,
+                }
+            }
+            Expression::Member {
+                object, optional, ..
+            } => {
+                let object_type = self.infer(object);
+                self.check_not_nullable(object, &object_type, *optional);
+                Type::Any
+            }
+            Expression::MethodCall {
+                object,
+                arguments,
+                keyword_arguments,
+                optional,
+                ..
+            } => {
+                let object_type = self.infer(object);
+                self.check_not_nullable(object, &object_type, *optional);
+                for argument in arguments {
+                    self.infer(argument);
+                }
+                for argument in keyword_arguments {
+                    self.infer(&argument.value);
+                }
+                Type::Any
+            }
+            Expression::Try(value) => match self.infer(value) {
+                Type::Optional(inner) => *inner,
+                Type::Generic { name, arguments } if name == "Result" && !arguments.is_empty() => {
+                    arguments[0].clone()
+                }
+                _ => Type::Any,
+            },
+            Expression::Await { expr } => {
+                self.infer(expr);
+                Type::Any
+            }
+            Expression::TypeOf(expr) => {
+                self.infer(expr);
+                Type::Named("Type".to_string())
+            }
+            Expression::Is { value, type_expr } => {
+                self.infer(value);
+                self.resolve(type_expr);
+                Type::Bool
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn binary_type(&mut self, operator: &BinaryOp, left: Type, right: Type) -> Type {
+        if *operator == BinaryOp::NullCoalesce {
+            let value = match left {
+                Type::Optional(inner) => *inner,
+                Type::Null => return right,
+                other => other,
+            };
+            return if is_assignable(&value, &right) {
+                value
+            } else {
+                Type::Any
+            };
+        }
+
+        if matches!(operator, BinaryOp::Eq | BinaryOp::NotEq) {
+            return Type::Bool;
+        }
+        if matches!(
+            operator,
+            BinaryOp::Dot | BinaryOp::MatchOp | BinaryOp::NullCoalesce
+        ) {
+            return Type::Any;
+        }
+
+        for operand in [&left, &right] {
+            if matches!(operand, Type::Null | Type::Optional(_)) {
+                self.error(
+                    self.span,
+                    format!(
+                        "Cannot apply '{}' to {}, which may be null; use '??' to provide a default",
+                        operator, operand
+                    ),
+                );
+                return Type::Any;
+            }
+        }
+
+        if operator.is_comparison() {
+            let comparable = match (&left, &right) {
+                (Type::Any, _) | (_, Type::Any) => true,
+                (left, right) if left == right => self.implements(left, "Ord"),
+                (left, right) => is_numeric(left) && is_numeric(right),
+            };
+            if !comparable {
+                self.error(
+                    self.span,
+                    format!("Cannot compare {} and {} with '{}'", left, right, operator),
+                );
+            }
+            return Type::Bool;
+        }
+
+        let integer_only = matches!(
+            operator,
+            BinaryOp::BitAnd
+                | BinaryOp::BitOr
+                | BinaryOp::BitXor
+                | BinaryOp::ShiftLeft
+                | BinaryOp::ShiftRight
+        );
+
+        match (&left, operator, &right) {
+            (Type::String, BinaryOp::Add, _) | (_, BinaryOp::Add, Type::String) => Type::String,
+            (Type::Any, _, _) | (_, _, Type::Any) => Type::Any,
+            (Type::Int, _, Type::Int) if integer_only => Type::Int,
+            // A negative exponent gives a Float
+            (Type::Int, BinaryOp::Pow, Type::Int) => Type::Any,
+            _ if integer_only => {
+                self.error(
+                    self.span,
+                    format!("Cannot apply '{}' to {} and {}", operator, left, right),
+                );
+                Type::Any
+            }
+            (Type::Param(name), _, Type::Param(other)) if name == other => {
+                if !self.implements(&left, "Num") {
+                    self.error(
+                        self.span,
+                        format!(
+                            "Cannot apply '{}' to {} and {}; add a Num bound to {}",
+                            operator, left, right, name
+                        ),
+                    );
+                    return Type::Any;
+                }
+                left
+            }
+            (Type::Int, _, Type::Int) => Type::Int,
+            (Type::Int | Type::Float, _, Type::Int | Type::Float) => Type::Float,
+            _ => {
+                self.error(
+                    self.span,
+                    format!("Cannot apply '{}' to {} and {}", operator, left, right),
+                );
+                Type::Any
+            }
+        }
+    }
+
+    // `{x:.2f}` needs a number and `{x:x}` an integer
+    fn check_format(&mut self, ty: &Type, spec: &FormatSpec) {
+        let fits = match ty {
+            Type::Any | Type::Param(_) => true,
+            Type::Int => spec.wants_number(),
+            Type::Float => spec.wants_number() && !spec.wants_integer(),
+            _ => false,
+        };
+        if spec.wants_number() && !fits {
+            self.error(
+                self.span,
+                format!("Format '{}' cannot be applied to {}", spec, ty),
+            );
+        }
+    }
+
+    fn unary_type(&mut self, operator: &UnaryOp, operand: Type) -> Type {
+        match (operator, operand) {
+            (UnaryOp::Not, _) => Type::Bool,
+            (_, Type::Any) => Type::Any,
+            (UnaryOp::Neg, operand @ (Type::Int | Type::Float)) => operand,
+            (UnaryOp::BitNot, Type::Int) => Type::Int,
+            (UnaryOp::Neg, operand @ Type::Param(_)) if self.implements(&operand, "Num") => operand,
+            (operator, operand) => {
+                self.error(
+                    self.span,
+                    format!("Cannot apply '{}' to {}", operator, operand),
+                );
+                Type::Any
+            }
+        }
+    }
+
+    fn call_type(
+        &mut self,
+        function: &str,
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+        span: Span,
+    ) -> Type {
+        let positional: Vec<Type> = arguments.iter().map(|arg| self.infer(arg)).collect();
+        let keywords: Vec<(String, Type)> = keyword_arguments
+            .iter()
+            .map(|arg| (arg.name.clone(), self.infer(&arg.value)))
+            .collect();
+
+        match function {
+            "print" => return Type::Void,
+            "Ok" => {
+                return Type::Generic {
+                    name: "Result".to_string(),
+                    arguments: vec![positional.first().cloned().unwrap_or(Type::Any), Type::Any],
+                };
+            }
+            "Err" => {
+                return Type::Generic {
+                    name: "Result".to_string(),
+                    arguments: vec![Type::Any, positional.first().cloned().unwrap_or(Type::Any)],
+                };
+            }
+            _ => {}
+        }
+
+        for scope in self.scopes.iter().rev() {
+            if let Some(signature) = scope.functions.get(function) {
+                let signature = signature.clone();
+                return self.check_call(function, &signature, &positional, &keywords, span);
+            }
+            if let Some(variable) = scope.variables.get(function) {
+                return match variable.clone() {
+                    Type::Function { params, returns } => {
+                        self.check_lambda_call(function, &params, &positional, span);
+                        *returns
+                    }
+                    Type::Any => Type::Any,
+                    other => {
+                        self.error(span, format!("'{}' is {}, not a function", function, other));
+                        Type::Any
+                    }
+                };
+            }
+        }
+
+        // Unknown functions are reported by the interpreter
+        Type::Any
+    }
+
+    fn check_call(
+        &mut self,
+        function: &str,
+        signature: &Signature,
+        positional: &[Type],
+        keywords: &[(String, Type)],
+        span: Span,
+    ) -> Type {
+        let mut bound = HashSet::new();
+        let mut arguments = positional.iter();
+        // Parameter name, declared type and argument type of every argument passed
+        let mut passed = Vec::new();
+
+        for (param, declared) in &signature.params {
+            match param.kind {
+                ParameterKind::Positional => {
+                    if let Some(actual) = arguments.next() {
+                        bound.insert(param.name.clone());
+                        passed.push((param.name.clone(), declared, actual));
+                    }
+                }
+                ParameterKind::Variadic => {
+                    for actual in arguments.by_ref() {
+                        passed.push((param.name.clone(), declared, actual));
+                    }
+                }
+                ParameterKind::KeywordVariadic => {}
+            }
+        }
+
+        let extra = arguments.count();
+        if extra > 0 {
+            let accepted = signature
+                .params
+                .iter()
+                .filter(|(param, _)| param.kind == ParameterKind::Positional)
+                .count();
+            self.error(
+                span,
+                format!(
+                    "Function {} expects {} arguments, got {}",
+                    function,
+                    accepted,
+                    accepted + extra
+                ),
+            );
+        }
+
+        let takes_keywords = signature
+            .params
+            .iter()
+            .any(|(param, _)| param.kind == ParameterKind::KeywordVariadic);
+        for (name, actual) in keywords {
+            match signature
+                .params
+                .iter()
+                .find(|(param, _)| param.name == *name && param.kind == ParameterKind::Positional)
+            {
+                Some((param, declared)) => {
+                    bound.insert(param.name.clone());
+                    passed.push((param.name.clone(), declared, actual));
+                }
+                None if takes_keywords => {}
+                None => self.error(
+                    span,
+                    format!(
+                        "Function {} got an unexpected keyword argument '{}'",
+                        function, name
+                    ),
+                ),
+            }
+        }
+
+        for (param, _) in &signature.params {
+            if param.kind == ParameterKind::Positional
+                && param.default.is_none()
+                && !bound.contains(&param.name)
+            {
+                self.error(
+                    span,
+                    format!(
+                        "Function {} missing required argument '{}'",
+                        function, param.name
+                    ),
+                );
+            }
+        }
+
+        let instance = self.instantiate(function, &signature.type_params, &passed, span);
+        for (param, declared, actual) in passed {
+            let declared = substitute(declared, &instance);
+            self.check_argument(function, &param, &declared, actual, span);
+        }
+        substitute(&signature.returns, &instance)
+    }
+
+    // Binds each type parameter of a generic call to the arguments' types, then
+    // checks that the bound types implement the parameter's traits
+    fn instantiate(
+        &mut self,
+        function: &str,
+        type_params: &[TypeParameter],
+        passed: &[(String, &Type, &Type)],
+        span: Span,
+    ) -> HashMap<String, (Type, String)> {
+        let mut instance = HashMap::new();
+        if type_params.is_empty() {
+            return instance;
+        }
+
+        for (param, declared, actual) in passed {
+            bind(declared, actual, param, &mut instance);
+        }
+
+        for type_param in type_params {
+            let Some((ty, argument)) = instance.get(&type_param.name).cloned() else {
+                // Nothing passed decides it, e.g. a defaulted parameter
+                instance.insert(type_param.name.clone(), (Type::Any, String::new()));
+                continue;
+            };
+            for bound in &type_param.bounds {
+                if !self.implements(&ty, bound) {
+                    self.error(
+                        span,
+                        format!(
+                            "Type {} does not implement trait {} required by {} in call to {} (argument '{}')",
+                            ty, bound, type_param.name, function, argument
+                        ),
+                    );
+                }
+            }
+        }
+        instance
+    }
+
+    // Whether `ty` satisfies the trait bound `name`
+    fn implements(&self, ty: &Type, name: &str) -> bool {
+        if let Type::Param(param) = ty {
+            return self
+                .type_params
+                .iter()
+                .rev()
+                .flatten()
+                .find(|type_param| type_param.name == *param)
+                .is_some_and(|type_param| type_param.bounds.iter().any(|bound| bound == name));
+        }
+
+        match (name, ty) {
+            (_, Type::Any) => true,
+            ("Num", ty) => matches!(ty, Type::Int | Type::Float),
+            ("Ord", ty) => matches!(ty, Type::Int | Type::Float | Type::String),
+            ("Display" | "Eq", ty) => !matches!(ty, Type::Function { .. } | Type::Void),
+            (name, Type::Named(target) | Type::Generic { name: target, .. }) => self
+                .traits
+                .get(name)
+                .is_some_and(|targets| targets.contains(target)),
+            _ => false,
+        }
+    }
+
+    fn check_bounds_exist(&mut self, type_params: &[TypeParameter]) {
+        for type_param in type_params {
+            for bound in &type_param.bounds {
+                if !BUILTIN_TRAITS.contains(&bound.as_str()) && !self.traits.contains_key(bound) {
+                    self.error(
+                        self.span,
+                        format!(
+                            "Unknown trait '{}' in bound of type parameter {}",
+                            bound, type_param.name
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_lambda_call(
+        &mut self,
+        function: &str,
+        params: &[Type],
+        positional: &[Type],
+        span: Span,
+    ) {
+        if params.len() != positional.len() {
+            self.error(
+                span,
+                format!(
+                    "Function {} expects {} arguments, got {}",
+                    function,
+                    params.len(),
+                    positional.len()
+                ),
+            );
+            return;
+        }
+        for (index, (declared, actual)) in params.iter().zip(positional).enumerate() {
+            self.check_argument(function, &format!("#{}", index + 1), declared, actual, span);
+        }
+    }
+
+    fn check_argument(
+        &mut self,
+        function: &str,
+        param: &str,
+        declared: &Type,
+        actual: &Type,
+        span: Span,
+    ) {
+        if !is_assignable(declared, actual) {
+            self.error(
+                span,
+                format!(
+                    "Argument '{}' of '{}' expects {}, got {}",
+                    param, function, declared, actual
+                ),
+            );
+        }
+    }
+
+    fn check_not_nullable(&mut self, object: &Expression, object_type: &Type, optional: bool) {
+        if !optional && matches!(object_type, Type::Null | Type::Optional(_)) {
+            self.error(
+                self.span,
+                format!(
+                    "`{}` is {}, which may be null; use '?.' to access it",
+                    object, object_type
+                ),
+            );
+        }
+    }
+
+    fn resolve_optional(&mut self, annotation: &Option<TypeExpr>) -> Type {
+        match annotation {
+            Some(annotation) => self.resolve(annotation),
+            None => Type::Any,
+        }
+    }
+
+    fn resolve(&mut self, annotation: &TypeExpr) -> Type {
+        match annotation {
+            TypeExpr::Named(name) if self.is_type_param(name) => Type::Param(name.clone()),
+            TypeExpr::Named(name) if self.aliases.contains_key(name) => self.resolve_alias(name),
+            TypeExpr::Named(name) => match name.as_str() {
+                "Int" | "int" | "Integer" => Type::Int,
+                "Float" | "float" => Type::Float,
+                "String" | "string" | "str" => Type::String,
+                "Bool" | "bool" | "Boolean" => Type::Bool,
+                "null" | "Null" => Type::Null,
+                "Void" | "void" => Type::Void,
+                "Any" | "any" => Type::Any,
+                "List" | "Array" => Type::List(Box::new(Type::Any)),
+                "Dict" | "Map" | "Result" | "Type" | "Function" => Type::Named(name.clone()),
+                name if self.types.contains(name) => Type::Named(name.to_string()),
+                name => {
+                    self.error(self.span, format!("Unknown type '{}'", name));
+                    Type::Any
+                }
+            },
+            TypeExpr::List(element) | TypeExpr::Array { element, .. } => {
+                Type::List(Box::new(self.resolve(element)))
+            }
+            TypeExpr::Optional(inner) => match self.resolve(inner) {
+                optional @ Type::Optional(_) => optional,
+                inner => Type::Optional(Box::new(inner)),
+            },
+            TypeExpr::Generic { name, arguments } => {
+                let mut arguments: Vec<Type> = arguments.iter().map(|a| self.resolve(a)).collect();
+                match name.as_str() {
+                    "Option" if arguments.len() == 1 => {
+                        Type::Optional(Box::new(arguments.remove(0)))
+                    }
+                    "List" | "Array" if arguments.len() == 1 => {
+                        Type::List(Box::new(arguments.remove(0)))
+                    }
+                    "Result" | "Dict" | "Map" => Type::Generic {
+                        name: name.clone(),
+                        arguments,
+                    },
+                    name if self.types.contains(name) => {
+                        self.check_type_arguments(name, &arguments);
+                        Type::Generic {
+                            name: name.to_string(),
+                            arguments,
+                        }
+                    }
+                    name => {
+                        self.error(self.span, format!("Unknown type '{}'", name));
+                        Type::Any
+                    }
+                }
+            }
+            TypeExpr::Function { params, returns } => Type::Function {
+                params: params.iter().map(|param| self.resolve(param)).collect(),
+                returns: Box::new(self.resolve(returns)),
+            },
+        }
+    }
+
+    fn resolve_alias(&mut self, name: &str) -> Type {
+        if self.expanding.iter().any(|alias| alias == name) {
+            self.error(self.span, format!("Type alias '{}' refers to itself", name));
+            return Type::Any;
+        }
+        let target = self.aliases[name].clone();
+        self.expanding.push(name.to_string());
+        let resolved = self.resolve(&target);
+        self.expanding.pop();
+        resolved
+    }
+
+    fn is_type_param(&self, name: &str) -> bool {
+        self.type_params
+            .iter()
+            .flatten()
+            .any(|type_param| type_param.name == name)
+    }
+
+    // `Pair<Int, Bool>` must match the arity and bounds of `struct Pair<A, B>`
+    fn check_type_arguments(&mut self, name: &str, arguments: &[Type]) {
+        let type_params = self.generics.get(name).cloned().unwrap_or_default();
+        if type_params.len() != arguments.len() {
+            self.error(
+                self.span,
+                format!(
+                    "Type {} expects {} type arguments, got {}",
+                    name,
+                    type_params.len(),
+                    arguments.len()
+                ),
+            );
+            return;
+        }
+
+        for (type_param, argument) in type_params.iter().zip(arguments) {
+            for bound in &type_param.bounds {
+                if !self.implements(argument, bound) {
+                    self.error(
+                        self.span,
+                        format!(
+                            "Type {} does not implement trait {} required by {} in {}",
+                            argument, bound, type_param.name, name
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        for scope in self.scopes.iter().rev() {
+            if let Some(signature) = scope.functions.get(name) {
+                return Type::Function {
+                    params: signature.params.iter().map(|(_, ty)| ty.clone()).collect(),
+                    returns: Box::new(signature.returns.clone()),
+                };
+            }
+            if let Some(variable) = scope.variables.get(name) {
+                return variable.clone();
+            }
+        }
+        Type::Any
+    }
+
+    fn signature(&self, name: &str) -> Option<Signature> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(signature) = scope.functions.get(name) {
+                return Some(signature.clone());
+            }
+            if scope.variables.contains_key(name) {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn declare_variable(&mut self, name: &str, ty: Type) {
+        let scope = self.scope();
+        scope.functions.remove(name);
+        scope.variables.insert(name.to_string(), ty);
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("the global scope is never popped")
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        // Annotations are resolved both when declaring and when checking a function
+        let error = CrabbyError::TypeError(Some(span), message);
+        if !self
+            .errors
+            .iter()
+            .any(|existing| existing.to_string() == error.to_string())
+        {
+            self.errors.push(error);
+        }
+    }
+}
+
+// Whether a value of type `actual` can be used where `expected` is declared
+fn is_assignable(expected: &Type, actual: &Type) -> bool {
+    match (expected, actual) {
+        (Type::Any, _) | (_, Type::Any) => true,
+        (expected, actual) if expected == actual => true,
+        (Type::Float, Type::Int) => true,
+        (Type::Optional(_), Type::Null) => true,
+        (Type::Named(name), Type::Function { .. }) => name == "Function",
+        (Type::Optional(expected), Type::Optional(actual)) => is_assignable(expected, actual),
+        (Type::Optional(expected), actual) => is_assignable(expected, actual),
+        (Type::List(expected), Type::List(actual)) => is_assignable(expected, actual),
+        (
+            Type::Function {
+                params: expected_params,
+                returns: expected_returns,
+            },
+            Type::Function {
+                params: actual_params,
+                returns: actual_returns,
+            },
+        ) => {
+            expected_params.len() == actual_params.len()
+                && expected_params
+                    .iter()
+                    .zip(actual_params)
+                    .all(|(expected, actual)| is_assignable(actual, expected))
+                && is_assignable(expected_returns, actual_returns)
+        }
+        (
+            Type::Generic {
+                name: expected_name,
+                arguments: expected_arguments,
+            },
+            Type::Generic {
+                name: actual_name,
+                arguments: actual_arguments,
+            },
+        ) => {
+            expected_name == actual_name
+                && expected_arguments.len() == actual_arguments.len()
+                && expected_arguments
+                    .iter()
+                    .zip(actual_arguments)
+                    .all(|(expected, actual)| is_assignable(expected, actual))
+        }
+        (Type::Named(expected_name), Type::Generic { name, .. })
+        | (Type::Generic { name, .. }, Type::Named(expected_name)) => expected_name == name,
+        _ => false,
+    }
+}
+
+// Unifies a declared parameter type with an argument's type, recording what each
+// type parameter stands for and the argument that decided it
+fn bind(
+    declared: &Type,
+    actual: &Type,
+    argument: &str,
+    instance: &mut HashMap<String, (Type, String)>,
+) {
+    match (declared, actual) {
+        (Type::Param(name), actual) => match instance.get_mut(name) {
+            None => {
+                instance.insert(name.clone(), (actual.clone(), argument.to_string()));
+            }
+            // `add(1, 2.5)` widens T from Int to Float
+            Some((bound @ Type::Int, _)) if *actual == Type::Float => *bound = Type::Float,
+            Some((bound @ Type::Any, _)) => *bound = actual.clone(),
+            Some(_) => {}
+        },
+        (Type::List(declared), Type::List(actual))
+        | (Type::Optional(declared), Type::Optional(actual)) => {
+            bind(declared, actual, argument, instance)
+        }
+        (Type::Optional(declared), actual) => bind(declared, actual, argument, instance),
+        (
+            Type::Function { params, returns },
+            Type::Function {
+                params: actual_params,
+                returns: actual_returns,
+            },
+        ) => {
+            for (declared, actual) in params.iter().zip(actual_params) {
+                bind(declared, actual, argument, instance);
+            }
+            bind(returns, actual_returns, argument, instance);
+        }
+        (
+            Type::Generic { arguments, .. },
+            Type::Generic {
+                arguments: actual_arguments,
+                ..
+            },
+        ) => {
+            for (declared, actual) in arguments.iter().zip(actual_arguments) {
+                bind(declared, actual, argument, instance);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Replaces type parameters with what a call bound them to
+fn substitute(ty: &Type, instance: &HashMap<String, (Type, String)>) -> Type {
+    match ty {
+        Type::Param(name) if instance.contains_key(name) => instance[name].0.clone(),
+        Type::List(element) => Type::List(Box::new(substitute(element, instance))),
+        Type::Optional(inner) => Type::Optional(Box::new(substitute(inner, instance))),
+        Type::Function { params, returns } => Type::Function {
+            params: params
+                .iter()
+                .map(|param| substitute(param, instance))
+                .collect(),
+            returns: Box::new(substitute(returns, instance)),
+        },
+        Type::Generic { name, arguments } => Type::Generic {
+            name: name.clone(),
+            arguments: arguments
+                .iter()
+                .map(|argument| substitute(argument, instance))
+                .collect(),
+        },
+        other => other.clone(),
+    }
+}
+
+fn is_numeric(ty: &Type) -> bool {
+    matches!(ty, Type::Int | Type::Float)
+}
+
+// The element type of a list literal
+fn common_type(types: &[Type]) -> Type {
+    let Some(first) = types.first() else {
+        return Type::Any;
+    };
+    if types.iter().all(|ty| ty == first) {
+        first.clone()
+    } else if types.iter().all(|ty| matches!(ty, Type::Int | Type::Float)) {
+        Type::Float
+    } else if types.iter().all(|ty| ty == first || *ty == Type::Null) {
+        Type::Optional(Box::new(first.clone()))
+    } else {
+        Type::Any
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::String => write!(f, "String"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Null => write!(f, "null"),
+            Type::Void => write!(f, "Void"),
+            Type::Any => write!(f, "Any"),
+            Type::List(element) => write!(f, "[{}]", element),
+            Type::Optional(inner) => write!(f, "{}?", inner),
+            Type::Function { params, returns } => write!(
+                f,
+                "({}) -> {}",
+                params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                returns
+            ),
+            Type::Named(name) | Type::Param(name) => write!(f, "{}", name),
+            Type::Generic { name, arguments } => write!(
+                f,
+                "{}<{}>",
+                name,
+                arguments
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
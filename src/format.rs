@@ -0,0 +1,256 @@
+// Format specs for f-string holes: `{x:.2f}`, `{name:>10}`, `{n:+08,d}`
+// The grammar is Python's: [[fill]align][sign][0][width][,][.precision][kind]
+
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive};
+
+use crate::utils::CrabbyError;
+use crate::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>,
+    pub sign: bool,
+    pub zero: bool,
+    pub width: Option<usize>,
+    pub grouping: bool,
+    pub precision: Option<usize>,
+    pub kind: Option<char>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+const KINDS: &str = "sdfe%xXob";
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+        let mut result = FormatSpec {
+            fill: ' ',
+            align: None,
+            sign: false,
+            zero: false,
+            width: None,
+            grouping: false,
+            precision: None,
+            kind: None,
+        };
+
+        if let Some(align) = chars.get(1).and_then(|c| align(*c)) {
+            result.fill = chars[0];
+            result.align = Some(align);
+            i = 2;
+        } else if let Some(align) = chars.first().and_then(|c| align(*c)) {
+            result.align = Some(align);
+            i = 1;
+        }
+        if chars.get(i) == Some(&'+') {
+            result.sign = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            result.zero = true;
+            i += 1;
+        }
+        result.width = digits(&chars, &mut i);
+        if chars.get(i) == Some(&',') {
+            result.grouping = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            result.precision = Some(
+                digits(&chars, &mut i)
+                    .ok_or_else(|| format!("Expected a precision after '.' in '{}'", spec))?,
+            );
+        }
+        if let Some(kind) = chars.get(i).filter(|c| KINDS.contains(**c)) {
+            result.kind = Some(*kind);
+            i += 1;
+        }
+
+        if i < chars.len() {
+            return Err(format!("Invalid format spec '{}'", spec));
+        }
+        Ok(result)
+    }
+
+    // Kinds that only make sense for integers
+    pub fn wants_integer(&self) -> bool {
+        matches!(self.kind, Some('d' | 'x' | 'X' | 'o' | 'b'))
+    }
+
+    pub fn wants_number(&self) -> bool {
+        self.wants_integer() || matches!(self.kind, Some('f' | 'e' | '%'))
+    }
+
+    pub fn apply(&self, value: &Value) -> Result<String, CrabbyError> {
+        let is_number = matches!(
+            value,
+            Value::Integer(_) | Value::BigInt(_) | Value::Float(_)
+        );
+        if (self.wants_number() && !is_number)
+            || (self.wants_integer() && matches!(value, Value::Float(_)))
+        {
+            return Err(CrabbyError::TypeError(
+                None,
+                format!(
+                    "Format '{}' cannot be applied to {}",
+                    self.kind.unwrap_or('s'),
+                    value.type_name()
+                ),
+            ));
+        }
+
+        if !is_number || self.kind == Some('s') {
+            let mut text = value.to_string();
+            if let Some(precision) = self.precision {
+                text = text.chars().take(precision).collect();
+            }
+            return Ok(self.pad(String::new(), text, Align::Left));
+        }
+
+        let (negative, digits) = self.number(value);
+        let sign = match (negative, self.sign) {
+            (true, _) => "-",
+            (false, true) => "+",
+            (false, false) => "",
+        };
+        Ok(self.pad(sign.to_string(), digits, Align::Right))
+    }
+
+    // The sign and the digits of a number, formatted as the spec's kind asks
+    fn number(&self, value: &Value) -> (bool, String) {
+        let integer = match value {
+            Value::Integer(n) => Some(BigInt::from(*n)),
+            Value::BigInt(n) => Some(n.clone()),
+            _ => None,
+        };
+        let float = match value {
+            Value::Integer(n) => *n as f64,
+            Value::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+            Value::Float(f) => *f,
+            _ => f64::NAN,
+        };
+
+        let digits = match (self.kind, &integer) {
+            (Some('x'), Some(n)) => n.abs().to_str_radix(16),
+            (Some('X'), Some(n)) => n.abs().to_str_radix(16).to_uppercase(),
+            (Some('o'), Some(n)) => n.abs().to_str_radix(8),
+            (Some('b'), Some(n)) => n.abs().to_str_radix(2),
+            (Some('e'), _) => format!("{:.*e}", self.precision.unwrap_or(6), float.abs()),
+            (Some('%'), _) => format!("{:.*}%", self.precision.unwrap_or(6), float.abs() * 100.0),
+            (Some('f'), _) => format!("{:.*}", self.precision.unwrap_or(6), float.abs()),
+            (None, None) if self.precision.is_some() => {
+                format!("{:.*}", self.precision.unwrap_or(6), float.abs())
+            }
+            (_, Some(n)) => n.abs().to_string(),
+            (_, None) => Value::Float(float.abs()).to_string(),
+        };
+        let negative = match &integer {
+            Some(n) => n.is_negative(),
+            None => float.is_sign_negative() && float != 0.0,
+        };
+
+        let digits = if self.grouping && !matches!(self.kind, Some('x' | 'X' | 'o' | 'b' | 'e')) {
+            group(&digits)
+        } else {
+            digits
+        };
+        (negative, digits)
+    }
+
+    // Pads to the spec's width; `0` pads numbers between the sign and the digits
+    fn pad(&self, sign: String, text: String, default: Align) -> String {
+        let width = self.width.unwrap_or(0);
+        let length = sign.chars().count() + text.chars().count();
+        if length >= width {
+            return sign + &text;
+        }
+        let padding = width - length;
+
+        if self.zero && self.align.is_none() {
+            return sign + &"0".repeat(padding) + &text;
+        }
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        match self.align.unwrap_or(default) {
+            Align::Left => sign + &text + &fill(padding),
+            Align::Right => fill(padding) + &sign + &text,
+            Align::Center => fill(padding / 2) + &sign + &text + &fill(padding - padding / 2),
+        }
+    }
+}
+
+impl fmt::Display for FormatSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(align) = self.align {
+            if self.fill != ' ' {
+                write!(f, "{}", self.fill)?;
+            }
+            let align = match align {
+                Align::Left => '<',
+                Align::Right => '>',
+                Align::Center => '^',
+            };
+            write!(f, "{}", align)?;
+        }
+        if self.sign {
+            write!(f, "+")?;
+        }
+        if self.zero {
+            write!(f, "0")?;
+        }
+        if let Some(width) = self.width {
+            write!(f, "{}", width)?;
+        }
+        if self.grouping {
+            write!(f, ",")?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        if let Some(kind) = self.kind {
+            write!(f, "{}", kind)?;
+        }
+        Ok(())
+    }
+}
+
+fn align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+fn digits(chars: &[char], i: &mut usize) -> Option<usize> {
+    let start = *i;
+    while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+        *i += 1;
+    }
+    chars[start..*i].iter().collect::<String>().parse().ok()
+}
+
+// `1234567.5` becomes `1,234,567.5`
+fn group(digits: &str) -> String {
+    let (whole, rest) = digits.split_at(digits.find(['.', '%']).unwrap_or(digits.len()));
+    let mut grouped = String::new();
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped + rest
+}
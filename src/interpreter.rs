@@ -1,37 +1,82 @@
-use crate::fs;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fs;
 use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
-
-use crate::ast::{BinaryOp, Expression, MatchArm, PatternKind, Program, Statement, Visibility};
+use std::rc::Rc;
+
+use crate::ast::{
+    BinaryOp, ClassField, Decorator, Expression, FStringPart, KeywordArgument, MatchArm, Parameter,
+    ParameterKind, PatternKind, Program, Statement, TypeExpr, UnaryOp, Visibility,
+};
+use crate::core::ffi::{Argument, Declarations, ForeignFunction, StructLayout};
+use crate::core::sandbox::{self, Capabilities};
+use crate::etc::deadcode::statement_span;
+use crate::etc::testing;
 use crate::lexer::*;
 use crate::modules::Module;
+use crate::numeric;
 use crate::parser::*;
-use crate::utils::CrabbyError;
-use crate::value::{Function, Value};
-
-// use crate::core::ffi::{FFIManager, FFIValue};
+use crate::runtime::{Limits, RuntimeCheck};
+use crate::utils::{CrabbyError, Span};
+use crate::value::{Class, Function, Instance, Value};
 
 // Used for limiting the recursion Crabby receives
 // to avoid stack overflow at runtime interpretation
 const MAX_RECURSION_DEPTH: usize = 1000;
 
+// The type of what `expect(value)` returns
+const EXPECTATION: &str = "Expectation";
+
 pub struct Environment {
     variables: HashMap<String, Value>,
     parent: Option<Box<Environment>>,
 }
 
+// What a struct or enum declaration leaves behind for building its values
+#[derive(Clone)]
+enum TypeDefinition {
+    Struct(Vec<String>), // field names
+    Enum(Vec<String>),   // variant names
+}
+
+pub type Builtin = Rc<dyn Fn(Vec<Value>) -> Result<Value, CrabbyError>>;
+
+// Positional and keyword argument values, in call order
+type Arguments = (Vec<Value>, Vec<(String, Value)>);
+
+// An `async def` waiting for the program to finish its top-level statements
+type PendingCall = Pin<Box<dyn Future<Output = Result<Value, CrabbyError>>>>;
+
 pub struct Interpreter {
     pub env: Environment,
-    awaiting: Vec<Pin<Box<dyn Future<Output = Value>>>>,
     function_definitions: HashMap<String, Function>,
+    type_definitions: HashMap<String, TypeDefinition>,
+    // Integer overflow is an error instead of promoting to a big integer
+    pub strict_numeric: bool,
     call_stack: Vec<String>,
     pub module: Module,
     current_file: Option<PathBuf>,
     recursion_depth: usize,
-    // pub ffi_manager: FFIManager,
-    // add_builtin: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, CrabbyError> + Send + Sync>>,
+    // When set, `print` appends here instead of writing to stdout, and warnings go to
+    // `errors` instead of stderr; both are shared with the scopes of the calls it makes
+    pub output: Option<Rc<RefCell<String>>>,
+    pub errors: Option<Rc<RefCell<String>>>,
+    // Limits on what the program may do, shared with the scopes of the calls it makes
+    pub runtime_check: Option<Rc<RefCell<RuntimeCheck>>>,
+    // What native functions like `read_file` may reach; nothing unless granted
+    capabilities: Rc<Capabilities>,
+    // Functions written in Rust by whoever embeds the interpreter
+    builtins: HashMap<String, Builtin>,
+    // The C functions and structs declared with `extern`
+    foreign: Declarations,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
@@ -59,6 +104,20 @@ impl Environment {
     pub fn insert(&mut self, name: String, value: Value) {
         self.variables.insert(name, value);
     }
+
+    // Every visible variable, with inner scopes shadowing outer ones
+    pub fn snapshot(&self) -> Vec<(String, Value)> {
+        let mut variables = self
+            .parent
+            .as_ref()
+            .map(|parent| parent.snapshot())
+            .unwrap_or_default();
+        for (name, value) in &self.variables {
+            variables.retain(|(existing, _)| existing != name);
+            variables.push((name.clone(), value.clone()));
+        }
+        variables
+    }
 }
 
 impl Interpreter {
@@ -67,9 +126,10 @@ impl Interpreter {
 
         let mut interpreter = Self {
             env,
-            awaiting: Vec::new(),
             call_stack: Vec::new(),
             function_definitions: HashMap::new(),
+            type_definitions: HashMap::new(),
+            strict_numeric: false,
             module: Module {
                 public_items: HashMap::new(),
                 private_items: HashMap::new(),
@@ -78,63 +138,102 @@ impl Interpreter {
             },
             current_file: file_path,
             recursion_depth: 0,
-            // ffi_manager: FFIManager::new(),
-            // add_builtin: HashMap::new(),
+            output: None,
+            errors: None,
+            runtime_check: None,
+            capabilities: Rc::new(Capabilities::default()),
+            builtins: HashMap::new(),
+            foreign: Declarations::default(),
         };
 
         interpreter.function_definitions.insert(
             "print".into(),
-            Function {
-                params: vec!["value".into()],
-                body: Box::new(Statement::Expression(Expression::Variable("value".into()))),
-            },
+            Function::new(
+                vec![Parameter::positional("value".into(), Span::default())],
+                Box::new(Statement::Expression(Expression::Variable {
+                    name: "value".into(),
+                    span: Span::default(),
+                })),
+            ),
         );
 
         interpreter
     }
 
-    fn new_module() -> Module {
-        Module {
-            public_items: HashMap::new(),
-            private_items: HashMap::new(),
-            variable: HashMap::new(),
-            exports: HashMap::new(),
-        }
-    }
-
-    // pub fn add_builtin<F>(&mut self, name: &str, func: F)
-    // where
-    //     F: Fn(Vec<Value>) -> Result<Value, CrabbyError> + Send + Sync + 'static,
-    // {
-    //     self.add_builtin.insert(name.to_string(), Box::new(func));
-    // }
-
-    // pub fn call_ffi_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, CrabbyError> {
-    //     let ffi_args = args.into_iter()
-    //         .map(|arg| Ok(match arg {
-    //             Value::Integer(i) => FFIValue::Int(i as i32),
-    //             Value::Float(f) => FFIValue::Float(f),
-    //             Value::String(s) => FFIValue::String(std::ffi::CString::new(s)
-    //                 .map_err(|e| CrabbyError::InterpreterError(format!("Invalid string for FFI: {}", e)))?),
-    //             _ => return Err(CrabbyError::InterpreterError("Unsupported FFI argument type".into())),
-    //         }))
-    //         .collect::<Result<Vec<_>, _>>()?;
-
-    //     let result = self.ffi_manager.call_function(name, ffi_args)?;
-
-    //     Ok(match result {
-    //         FFIValue::Int(i) => Value::Integer(i as i64),
-    //         FFIValue::Float(f) => Value::Float(f),
-    //         FFIValue::String(s) => Value::String(s.to_string_lossy().into_owned()),
-    //        FFIValue::Void => Value::Void,
-    //        FFIValue::Pointer(_) => Value::Void, // Handle pointers appropriately
-    //     })
-    // }
+    // Stops the program with an error once it goes over one of `limits`
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.runtime_check = Some(Rc::new(RefCell::new(RuntimeCheck::new(limits))));
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = Rc::new(capabilities);
+    }
+
+    // Every name a program can refer to right now: variables, functions and built-in types
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .env
+            .snapshot()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        names.extend(self.function_definitions.keys().cloned());
+        names.extend(["Ok", "Err", "expect"].map(String::from));
+        names.extend(sandbox::NATIVE_FUNCTIONS.map(String::from));
+        names.extend(self.builtins.keys().cloned());
+        names.extend(BUILTIN_TYPES.map(String::from));
+        names
+    }
+
+    // Functions the program defines with the same name take its place
+    pub fn add_builtin<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(Vec<Value>) -> Result<Value, CrabbyError> + 'static,
+    {
+        self.builtins.insert(name.to_string(), Rc::new(func));
+    }
+
+    // A variable or function the program defined at the top level
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.env.get(name).or_else(|| {
+            self.function_definitions
+                .get(name)
+                .map(|function| Value::Lambda(function.clone()))
+        })
+    }
+
+    // Calls a function the program defined, or a built-in, from Rust
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, CrabbyError> {
+        match self.global(name) {
+            Some(Value::Lambda(function)) => {
+                self.call_function_value(name, &function, args, Vec::new())
+            }
+            Some(other) => Err(CrabbyError::TypeError(
+                None,
+                format!("'{}' is {}, not a function", name, other.type_name()),
+            )),
+            None if self.foreign.functions.contains_key(name) => {
+                let function = self.foreign.functions[name].clone();
+                let args = args.into_iter().map(Argument::Value).collect();
+                self.call_foreign(&function, args).map(|(result, _)| result)
+            }
+            None => match self.builtins.get(name).cloned() {
+                Some(builtin) => builtin(args),
+                None => match self.call_native(name, &args) {
+                    Some(result) => result,
+                    None => Err(CrabbyError::InterpreterError(format!(
+                        "Undefined function: {}",
+                        name
+                    ))),
+                },
+            },
+        }
+    }
 
     pub async fn interpret_function_def(
         &mut self,
         name: &str,
-        params: &[String],
+        params: &[Parameter],
         body: &Statement,
     ) -> Result<(), CrabbyError> {
         let is_public = name.starts_with("pub ");
@@ -144,10 +243,7 @@ impl Interpreter {
             name.to_string()
         };
 
-        let function = Function {
-            params: params.to_vec(),
-            body: Box::new(body.clone()),
-        };
+        let function = Function::new(params.to_vec(), Box::new(body.clone()));
 
         if is_public {
             self.module
@@ -184,21 +280,20 @@ impl Interpreter {
         }
 
         // Wait for all async operations to complete
-        futures::future::join_all(futures);
+        for result in futures::executor::block_on(futures::future::join_all(futures)) {
+            result?;
+        }
         Ok(())
     }
 
     pub fn handle_async_function(
         &mut self,
         name: &str,
-        params: &[String],
+        params: &[Parameter],
         body: &Statement,
-        _return_type: Option<String>,
-    ) -> Result<Pin<Box<dyn Future<Output = Result<Value, CrabbyError>>>>, CrabbyError> {
-        let function = Function {
-            params: params.to_vec(),
-            body: Box::new(body.clone()),
-        };
+        _return_type: Option<TypeExpr>,
+    ) -> Result<PendingCall, CrabbyError> {
+        let function = Function::new(params.to_vec(), Box::new(body.clone()));
 
         self.function_definitions.insert(name.to_string(), function);
         Ok(Box::pin(async move { Ok(Value::Void) }))
@@ -208,6 +303,7 @@ impl Interpreter {
         &mut self,
         function: &str,
         arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
     ) -> Result<Value, CrabbyError> {
         // Checks recursion depth
         if self.recursion_depth >= MAX_RECURSION_DEPTH {
@@ -220,10 +316,12 @@ impl Interpreter {
         self.recursion_depth += 1;
         let lambda_opt = self.env.get(function);
         let result = match lambda_opt {
-            Some(Value::Lambda(lambda)) => self.handle_lambda_call(lambda.clone(), arguments),
+            Some(Value::Lambda(lambda)) => {
+                self.handle_lambda_call(function, lambda, arguments, keyword_arguments)
+            }
             _ => {
                 if let Some(func) = self.function_definitions.get(function).cloned() {
-                    self.handle_lambda_call(func, arguments)
+                    self.handle_lambda_call(function, func, arguments, keyword_arguments)
                 } else {
                     Err(CrabbyError::InterpreterError(format!(
                         "Undefined function: {}",
@@ -238,19 +336,342 @@ impl Interpreter {
 
     pub fn handle_lambda_call(
         &mut self,
+        name: &str,
         lambda: Function,
         arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
     ) -> Result<Value, CrabbyError> {
-        for (param, arg) in lambda.params.iter().zip(arguments) {
-            let arg_value = self.interpret_expression(arg)?;
-            self.env.insert(param.clone(), arg_value);
+        let (positional, keywords) = self.evaluate_arguments(name, arguments, keyword_arguments)?;
+        self.call_function_value(name, &lambda, positional, keywords)
+    }
+
+    // Runs a function in its own scope: the variables it captured when it was defined,
+    // every function defined so far, and its bound parameters
+    pub fn call_function_value(
+        &mut self,
+        name: &str,
+        function: &Function,
+        positional: Vec<Value>,
+        keywords: Vec<(String, Value)>,
+    ) -> Result<Value, CrabbyError> {
+        if let Some(message) = &function.deprecated {
+            let warning = format!("Warning: '{}' is deprecated: {}", name, message);
+            match &self.errors {
+                Some(errors) => {
+                    errors.borrow_mut().push_str(&warning);
+                    errors.borrow_mut().push('\n');
+                }
+                None => eprintln!("{}", warning),
+            }
         }
 
-        if let Some(value) = self.interpret_statement(&lambda.body)? {
-            Ok(value)
-        } else {
-            Ok(Value::Void)
+        let bindings = self.bind_values(name, &function.params, positional, keywords)?;
+
+        let memo_key: Vec<Value> = bindings.iter().map(|(_, value)| value.clone()).collect();
+        if let Some(memo) = &function.memo
+            && let Some((_, cached)) = memo.borrow().iter().find(|(args, _)| *args == memo_key)
+        {
+            return Ok(cached.clone());
+        }
+
+        let mut scope = Interpreter::new(self.current_file.clone());
+        // A function body can call the functions defined before it; `?` has nothing to
+        // propagate through otherwise
+        scope
+            .function_definitions
+            .extend(self.function_definitions.clone());
+        scope.type_definitions.extend(self.type_definitions.clone());
+        scope.strict_numeric = self.strict_numeric;
+        scope.runtime_check = self.runtime_check.clone();
+        scope.capabilities = self.capabilities.clone();
+        scope.builtins = self.builtins.clone();
+        scope.foreign = self.foreign.clone();
+        scope.output = self.output.clone();
+        scope.errors = self.errors.clone();
+        for (captured, value) in &function.captured {
+            scope.env.insert(captured.clone(), value.clone());
+        }
+        for (param, arg_value) in bindings {
+            scope.env.insert(param, arg_value);
         }
+
+        self.check_limits(|check| check.enter_call(name))?;
+        let result = scope.interpret_statement(&function.body);
+        if let Some(runtime_check) = &self.runtime_check {
+            runtime_check.borrow_mut().exit_call();
+        }
+        let result = match result {
+            Ok(Some(value)) => value,
+            Ok(None) => Value::Void,
+            Err(CrabbyError::Propagated(value)) => value,
+            Err(e) => return Err(e),
+        };
+
+        if let Some(memo) = &function.memo {
+            memo.borrow_mut().push((memo_key, result.clone()));
+        }
+
+        Ok(result)
+    }
+
+    fn evaluate_arguments(
+        &mut self,
+        function: &str,
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Result<Arguments, CrabbyError> {
+        let mut positional = Vec::new();
+        for arg in arguments {
+            positional.push(self.interpret_expression(arg)?);
+        }
+
+        let mut keywords: Vec<(String, Value)> = Vec::new();
+        for arg in keyword_arguments {
+            if keywords.iter().any(|(name, _)| *name == arg.name) {
+                return Err(CrabbyError::InterpreterError(format!(
+                    "Function {} got multiple values for keyword argument '{}'",
+                    function, arg.name
+                )));
+            }
+            let value = self.interpret_expression(&arg.value)?;
+            keywords.push((arg.name.clone(), value));
+        }
+
+        Ok((positional, keywords))
+    }
+
+    // Matches call-site arguments to parameters: positionals fill parameters in order,
+    // keywords fill them by name, `*args` collects leftover positionals and `**kwargs`
+    // collects leftover keywords. Missing parameters fall back to their defaults.
+    pub fn bind_values(
+        &mut self,
+        function: &str,
+        params: &[Parameter],
+        positional: Vec<Value>,
+        mut keywords: Vec<(String, Value)>,
+    ) -> Result<Vec<(String, Value)>, CrabbyError> {
+        // A misspelled keyword is reported as such, not as the argument it left missing
+        if !params
+            .iter()
+            .any(|param| param.kind == ParameterKind::KeywordVariadic)
+        {
+            let unknown = keywords.iter().find(|(name, _)| {
+                !params
+                    .iter()
+                    .any(|param| param.kind == ParameterKind::Positional && param.name == *name)
+            });
+            if let Some((name, _)) = unknown {
+                return Err(CrabbyError::InterpreterError(format!(
+                    "Function {} got an unexpected keyword argument '{}'",
+                    function, name
+                )));
+            }
+        }
+
+        let positional_count = positional.len();
+        let mut positional = positional.into_iter();
+        let mut bindings = Vec::new();
+        for param in params {
+            match param.kind {
+                ParameterKind::Positional => {
+                    let keyword_index = keywords.iter().position(|(name, _)| *name == param.name);
+                    let value = if let Some(value) = positional.next() {
+                        if keyword_index.is_some() {
+                            return Err(CrabbyError::InterpreterError(format!(
+                                "Function {} got multiple values for argument '{}'",
+                                function, param.name
+                            )));
+                        }
+                        value
+                    } else if let Some(index) = keyword_index {
+                        keywords.remove(index).1
+                    } else if let Some(default) = &param.default {
+                        self.interpret_expression(default)?
+                    } else {
+                        return Err(CrabbyError::InterpreterError(format!(
+                            "Function {} missing required argument '{}'",
+                            function, param.name
+                        )));
+                    };
+                    bindings.push((param.name.clone(), value));
+                }
+                ParameterKind::Variadic => {
+                    bindings.push((
+                        param.name.clone(),
+                        Value::Array(positional.by_ref().collect()),
+                    ));
+                }
+                ParameterKind::KeywordVariadic => {
+                    bindings.push((
+                        param.name.clone(),
+                        Value::Dict(std::mem::take(&mut keywords)),
+                    ));
+                }
+            }
+        }
+
+        if positional.len() > 0 {
+            let expected = params
+                .iter()
+                .filter(|param| param.kind == ParameterKind::Positional)
+                .count();
+            return Err(CrabbyError::InterpreterError(format!(
+                "Function {} expects {} arguments, got {}",
+                function, expected, positional_count
+            )));
+        }
+
+        if let Some((name, _)) = keywords.first() {
+            return Err(CrabbyError::InterpreterError(format!(
+                "Function {} got an unexpected keyword argument '{}'",
+                function, name
+            )));
+        }
+
+        Ok(bindings)
+    }
+
+    // Binds a `def`/`fun` after running it through its decorators. A decorator may
+    // replace the function with any value; only functions stay callable by name.
+    fn define_function(
+        &mut self,
+        name: &str,
+        mut function: Function,
+        visibility: &Visibility,
+        decorators: &[Decorator],
+    ) -> Result<(), CrabbyError> {
+        function.captured = self.env.snapshot();
+        // Defined first so the decorators (and the function itself) can refer to it
+        self.function_definitions
+            .insert(name.to_string(), function.clone());
+
+        let decorated = self.apply_decorators(name, Value::Lambda(function), decorators)?;
+        match &decorated {
+            Value::Lambda(function) => {
+                self.function_definitions
+                    .insert(name.to_string(), function.clone());
+            }
+            other => {
+                self.function_definitions.remove(name);
+                self.env.insert(name.to_string(), other.clone());
+            }
+        }
+
+        match visibility {
+            Visibility::Public => {
+                self.module.public_items.insert(name.to_string(), decorated);
+            }
+            Visibility::Private => {
+                self.module
+                    .private_items
+                    .insert(name.to_string(), decorated);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // `@a @b def f` is `a(b(f))`: the decorator closest to the definition runs first
+    fn apply_decorators(
+        &mut self,
+        target: &str,
+        mut value: Value,
+        decorators: &[Decorator],
+    ) -> Result<Value, CrabbyError> {
+        for decorator in decorators.iter().rev() {
+            let user_defined = match self.env.get(&decorator.name) {
+                Some(Value::Lambda(function)) => Some(function),
+                _ => self.function_definitions.get(&decorator.name).cloned(),
+            };
+
+            value = match user_defined {
+                Some(function) => {
+                    let function = if decorator.called {
+                        let (positional, keywords) = self.evaluate_arguments(
+                            &decorator.name,
+                            &decorator.arguments,
+                            &decorator.keyword_arguments,
+                        )?;
+                        match self.call_function_value(
+                            &decorator.name,
+                            &function,
+                            positional,
+                            keywords,
+                        )? {
+                            Value::Lambda(function) => function,
+                            other => {
+                                return Err(CrabbyError::TypeError(
+                                    None,
+                                    format!(
+                                        "Decorator factory '{}' must return a function, got {}",
+                                        decorator.name,
+                                        other.type_name()
+                                    ),
+                                ));
+                            }
+                        }
+                    } else {
+                        function
+                    };
+                    self.call_function_value(&decorator.name, &function, vec![value], Vec::new())?
+                }
+                None => self.apply_builtin_decorator(target, value, decorator)?,
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn apply_builtin_decorator(
+        &mut self,
+        target: &str,
+        value: Value,
+        decorator: &Decorator,
+    ) -> Result<Value, CrabbyError> {
+        let mut function = match value {
+            Value::Lambda(function)
+                if matches!(decorator.name.as_str(), "memoize" | "deprecated" | "test") =>
+            {
+                function
+            }
+            other if matches!(decorator.name.as_str(), "memoize" | "deprecated" | "test") => {
+                return Err(CrabbyError::TypeError(
+                    None,
+                    format!(
+                        "@{} can only decorate functions, but '{}' is a {}",
+                        decorator.name,
+                        target,
+                        other.type_name()
+                    ),
+                ));
+            }
+            _ => {
+                return Err(CrabbyError::InterpreterError(format!(
+                    "Undefined decorator: @{}",
+                    decorator.name
+                )));
+            }
+        };
+
+        match decorator.name.as_str() {
+            "memoize" => {
+                function.memo = Some(Rc::new(RefCell::new(Vec::new())));
+            }
+            "deprecated" => {
+                let message = match decorator.arguments.first() {
+                    Some(argument) => self.interpret_expression(argument)?.to_string(),
+                    None => String::from("it will be removed in a future version"),
+                };
+                function.deprecated = Some(message);
+            }
+            "test" => {
+                function.is_test = true;
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(Value::Lambda(function))
     }
 
     pub fn interpret_let_statement(
@@ -309,8 +730,14 @@ impl Interpreter {
         }
 
         let value = self.interpret_expression(&args[0])?;
-        println!("{}", value.to_string());
-        Ok(Value::Integer(0))
+        match &self.output {
+            Some(output) => {
+                output.borrow_mut().push_str(&value.to_string());
+                output.borrow_mut().push('\n');
+            }
+            None => println!("{}", value),
+        }
+        Ok(Value::Void)
     }
 
     pub fn interpret(mut self, program: &Program) -> Result<(), CrabbyError> {
@@ -385,13 +812,30 @@ impl Interpreter {
         let tokens = TokenStream::tokenize(source_code)?;
         let ast = parse(tokens)?;
         let mut module_interpreter = Interpreter::new(Some(resolved_path.clone()));
+        module_interpreter.capabilities = self.capabilities.clone();
         for statement in &ast.statements {
             module_interpreter.interpret_statement(statement)?;
         }
         Ok(module_interpreter.module.clone())
     }
 
+    // Runs `check` when the program runs with limits
+    fn check_limits(
+        &self,
+        check: impl FnOnce(&mut RuntimeCheck) -> Result<(), CrabbyError>,
+    ) -> Result<(), CrabbyError> {
+        match &self.runtime_check {
+            Some(runtime_check) => check(&mut runtime_check.borrow_mut()),
+            None => Ok(()),
+        }
+    }
+
     pub fn interpret_statement(&mut self, stmt: &Statement) -> Result<Option<Value>, CrabbyError> {
+        self.check_limits(|check| check.statement(statement_span(stmt)))?;
+        self.execute_statement(stmt)
+    }
+
+    fn execute_statement(&mut self, stmt: &Statement) -> Result<Option<Value>, CrabbyError> {
         match stmt {
             Statement::FunctionDef {
                 name,
@@ -400,6 +844,9 @@ impl Interpreter {
                 return_type: _,
                 docstring: _,
                 visibility,
+                decorators,
+                span: _,
+                type_params: _,
             } => {
                 // let is_public = name.starts_with("pub ");
                 // let func_name = if is_public {
@@ -408,34 +855,8 @@ impl Interpreter {
                 //    name.to_string()
                 // };
 
-                let function = Function {
-                    params: params.clone(),
-                    body: body.clone(),
-                };
-
-                self.function_definitions
-                    .insert(name.to_string(), function.clone());
-
-                match visibility {
-                    Visibility::Public => {
-                        self.module
-                            .public_items
-                            .insert(name.to_string(), Value::Lambda(function));
-                    }
-                    Visibility::Private => {
-                        self.module
-                            .private_items
-                            .insert(name.to_string(), Value::Lambda(function));
-                    }
-                    _ => {}
-                }
-                // if is_public {
-                //    self.module.public_items.insert(func_name.clone(), Value::Lambda(function.clone()));
-                // } else {
-                //    self.module.private_items.insert(func_name.clone(), Value::Lambda(function.clone()));
-                // }
-
-                // self.function_definitions.insert(func_name, function);
+                let function = Function::new(params.clone(), body.clone());
+                self.define_function(name, function, visibility, decorators)?;
 
                 Ok(None)
             }
@@ -446,46 +867,118 @@ impl Interpreter {
                 return_type: _,
                 docstring: _,
                 visibility,
+                decorators,
+                span: _,
+                type_params: _,
             } => {
-                // let is_public = name.starts_with("pub ");
-                // let func_name = if is_public {
-                //     name.trim_start_matches("pub ").to_string()
-                // } else {
-                //    name.to_string()
-                // };
+                let function = Function::new(params.clone(), body.clone());
+                self.define_function(name, function, visibility, decorators)?;
 
-                let function = Function {
-                    params: params.clone(),
-                    body: body.clone(),
-                };
-
-                self.function_definitions
-                    .insert(name.clone(), function.clone());
-
-                match visibility {
-                    Visibility::Public => {
-                        self.module
-                            .public_items
-                            .insert(name.to_string(), Value::Lambda(function));
+                Ok(None)
+            }
+            Statement::Class {
+                name,
+                parent,
+                methods,
+                fields,
+                decorators,
+                type_params: _,
+                docstring: _,
+                visibility: _,
+                span: _,
+            } => {
+                let mut class_methods = Vec::new();
+                for method in methods {
+                    if let Statement::FunctionDef {
+                        name: method_name,
+                        params,
+                        body,
+                        decorators,
+                        ..
                     }
-                    Visibility::Private => {
-                        self.module
-                            .private_items
-                            .insert(name.to_string(), Value::Lambda(function));
+                    | Statement::FunctionFun {
+                        name: method_name,
+                        params,
+                        body,
+                        decorators,
+                        ..
+                    } = method
+                    {
+                        let mut function = Function::new(params.clone(), body.clone());
+                        function.captured = self.env.snapshot();
+                        let target = format!("{}.{}", name, method_name);
+                        let function = match self.apply_decorators(
+                            &target,
+                            Value::Lambda(function),
+                            decorators,
+                        )? {
+                            Value::Lambda(function) => function,
+                            other => {
+                                return Err(CrabbyError::TypeError(
+                                    None,
+                                    format!(
+                                        "Decorators on method '{}' must return a function, got {}",
+                                        target,
+                                        other.type_name()
+                                    ),
+                                ));
+                            }
+                        };
+                        class_methods.push((method_name.clone(), function));
                     }
-                    _ => {}
                 }
-                // if is_public {
-                //    self.module.public_items.insert(func_name.clone(), Value::Lambda(function.clone()));
-                // } else {
-                //    self.module.private_items.insert(func_name.clone(), Value::Lambda(function.clone()));
-                // }
 
-                // self.function_definitions.insert(func_name, function);
+                let class = Value::Class(Class {
+                    name: name.clone(),
+                    parent: parent.clone(),
+                    fields: fields.clone(),
+                    methods: class_methods,
+                });
+                let class = self.apply_decorators(name, class, decorators)?;
+                self.env.insert(name.clone(), class);
 
                 Ok(None)
             }
-            Statement::Let { name, value } => {
+            Statement::Extern {
+                library,
+                name,
+                params,
+                return_type,
+                span,
+            } => {
+                let source = library.as_deref().unwrap_or("the program itself");
+                self.capabilities.check_ffi(source).map_err(|message| {
+                    CrabbyError::RuntimeError(format!("{} at line {}", message, span.line))
+                })?;
+                let function = ForeignFunction::load(
+                    library.as_deref(),
+                    name,
+                    params,
+                    return_type,
+                    &self.foreign.structs,
+                )?;
+                self.foreign
+                    .functions
+                    .insert(name.clone(), Rc::new(function));
+                Ok(None)
+            }
+            Statement::ExternStruct { name, fields, .. } => {
+                let layout = StructLayout::new(name, fields, &self.foreign.structs)?;
+                self.foreign.structs.insert(name.clone(), Rc::new(layout));
+                let fields = fields.iter().map(|f| f.name.clone()).collect();
+                self.type_definitions
+                    .insert(name.clone(), TypeDefinition::Struct(fields));
+                self.env
+                    .insert(name.clone(), Value::Type(TypeExpr::Named(name.clone())));
+                Ok(None)
+            }
+            Statement::Let {
+                name,
+                value,
+                type_annotation,
+                mutable: _,
+                span: _,
+            } => {
                 let is_public = name.starts_with("pub ");
                 let var_name = if is_public {
                     name.trim_start_matches("pub ").to_string()
@@ -494,6 +987,7 @@ impl Interpreter {
                 };
 
                 let interpreted_value = self.interpret_expression(value)?;
+                self.check_nullable(&var_name, type_annotation, &interpreted_value)?;
 
                 if is_public {
                     self.module
@@ -508,7 +1002,12 @@ impl Interpreter {
                 self.env.insert(var_name, interpreted_value);
                 Ok(None)
             }
-            Statement::Var { name, value } => {
+            Statement::Var {
+                name,
+                value,
+                type_annotation,
+                span: _,
+            } => {
                 let is_public = name.starts_with("pub ");
                 let var_name = if is_public {
                     name.trim_start_matches("pub ").to_string()
@@ -517,6 +1016,7 @@ impl Interpreter {
                 };
 
                 let interpreted_value = self.interpret_expression(value)?;
+                self.check_nullable(&var_name, type_annotation, &interpreted_value)?;
 
                 if is_public {
                     self.module
@@ -531,7 +1031,12 @@ impl Interpreter {
                 self.env.insert(var_name, interpreted_value);
                 Ok(None)
             }
-            Statement::Const { name, value } => {
+            Statement::Const {
+                name,
+                value,
+                type_annotation,
+                span: _,
+            } => {
                 let is_public = name.starts_with("pub ");
                 let var_name = if is_public {
                     name.trim_start_matches("pub ").to_string()
@@ -540,6 +1045,7 @@ impl Interpreter {
                 };
 
                 let interpreted_value = self.interpret_expression(value)?;
+                self.check_nullable(&var_name, type_annotation, &interpreted_value)?;
 
                 if is_public {
                     self.module
@@ -583,15 +1089,16 @@ impl Interpreter {
                     ))
                 }
             }
-            Statement::Match { value, arms } => self.interpret_match(&value, &arms),
-            Statement::Return(expr) => {
+            Statement::Match { value, arms } => self.interpret_match(value, arms),
+            Statement::Return(expr, _) => {
                 let value = self.interpret_expression(expr)?;
                 Ok(Some(value))
             }
-            Statement::Loop { count, body } => {
+            Statement::Loop { count, body, .. } => {
                 let count_value = self.interpret_expression(count)?;
                 if let Value::Integer(n) = count_value {
                     for _ in 0..n {
+                        self.check_limits(RuntimeCheck::loop_iteration)?;
                         self.interpret_statement(body)?;
                     }
                     Ok(None)
@@ -605,41 +1112,64 @@ impl Interpreter {
                 condition,
                 then_branch,
                 else_branch,
+                span: _,
             } => {
                 let cond_value = self.interpret_expression(condition)?;
-                match cond_value {
-                    Value::Boolean(true) => Ok(self.interpret_statement(then_branch)?),
-                    Value::Boolean(false) => {
-                        if let Some(else_branch) = else_branch {
-                            Ok(self.interpret_statement(else_branch)?)
-                        } else {
-                            Ok(None)
-                        }
-                    }
-                    _ => {
-                        return Err(CrabbyError::InterpreterError(
-                            "Condition must be boolean".into(),
-                        ));
-                    }
+                if cond_value.condition()? {
+                    Ok(self.interpret_statement(then_branch)?)
+                } else if let Some(else_branch) = else_branch {
+                    Ok(self.interpret_statement(else_branch)?)
+                } else {
+                    Ok(None)
                 }
             }
-            Statement::While { condition, body } => {
+            Statement::While {
+                condition, body, ..
+            } => {
                 loop {
-                    let condition_value = self.interpret_expression(condition)?;
-                    match condition_value {
-                        Value::Integer(0) => break,
-                        _ => {
-                            if let Some(Value::Integer(-1)) = self.interpret_statement(body)? {
-                                break;
-                            }
-                        }
+                    self.check_limits(RuntimeCheck::loop_iteration)?;
+                    // Loops have always stopped at an integer 0
+                    let running = match self.interpret_expression(condition)? {
+                        Value::Integer(n) => n != 0,
+                        value => value.condition()?,
+                    };
+                    if !running {
+                        break;
+                    }
+                    if let Some(Value::Integer(-1)) = self.interpret_statement(body)? {
+                        break;
                     }
                 }
                 Ok(None)
             }
+            Statement::Assert {
+                condition,
+                message,
+                span,
+            } => {
+                if self.interpret_expression(condition)?.is_truthy() {
+                    return Ok(None);
+                }
+                let message = match message {
+                    Some(message) => self.interpret_expression(message)?.to_string(),
+                    None => format!("`{}` is false", condition),
+                };
+                Err(CrabbyError::AssertionError(Some(*span), message))
+            }
+            // Test blocks only run under `crabby test`
+            Statement::Test { .. } => Ok(None),
             Statement::Block(statements) => {
                 for stmt in statements {
-                    self.interpret_statement(stmt)?;
+                    let value = self.interpret_statement(stmt)?;
+                    // A `return` unwinds every enclosing block up to the function body
+                    if value.is_some()
+                        && matches!(
+                            stmt,
+                            Statement::Return(..) | Statement::If { .. } | Statement::Block(_)
+                        )
+                    {
+                        return Ok(value);
+                    }
                 }
                 Ok(None)
             }
@@ -647,7 +1177,7 @@ impl Interpreter {
                 let value = self.interpret_expression(expr)?;
                 Ok(Some(value))
             }
-            Statement::Import { name: _, source: _ } => {
+            Statement::Import { span, .. } => {
                 // let module = self
                 //     .module_loader
                 //     .load(self.current_file.as_ref().unwrap(), &source.unwrap())?;
@@ -658,23 +1188,26 @@ impl Interpreter {
 
                 // self.env.define(name, value.clone());
                 // Ok(None)
-                unimplemented!(/* I just want it to compile... */)
-            }
-            // Statement::Macro { name, params, body } => {
-            //    self.env.insert(name.clone(), Value::Lambda(Function {
-            //        params: vec![params.clone()],
-            //        body: Box::new(Statement::Expression(*(*body).clone())),
-            //    }));
-            //    Ok(None)
-            // },
+                Err(CrabbyError::RuntimeError(format!(
+                    "import is not supported yet at line {}",
+                    span.line
+                )))
+            }
+            // Macros are expanded away before interpretation (see `core::metaprogram`)
+            Statement::Macro { name, .. } => Err(CrabbyError::InterpreterError(format!(
+                "Macro {}! must be expanded before the program runs",
+                name
+            ))),
             Statement::ForIn {
                 variable,
                 iterator,
                 body,
+                span: _,
             } => {
                 let iter_value = self.interpret_expression(iterator)?;
                 if let Value::Integer(n) = iter_value {
                     for i in 0..n {
+                        self.check_limits(RuntimeCheck::loop_iteration)?;
                         self.env.insert(variable.clone(), Value::Integer(i));
                         self.interpret_statement(body)?;
                     }
@@ -687,20 +1220,39 @@ impl Interpreter {
             }
             Statement::Enum {
                 name,
-                variants: _variants,
+                variants,
                 where_clause: _,
+                type_params: _,
+                docstring: _,
+                visibility: _,
+                span: _,
             } => {
-                let value = Value::String(format!("enum {}", name));
-                self.env.insert(name.clone(), value);
+                let variants = variants.iter().map(|v| v.name.clone()).collect();
+                self.type_definitions
+                    .insert(name.clone(), TypeDefinition::Enum(variants));
+                self.env
+                    .insert(name.clone(), Value::Type(TypeExpr::Named(name.clone())));
                 Ok(None)
             }
             Statement::Struct {
                 name,
-                fields: _fields,
+                fields,
                 where_clause: _where_clause,
+                type_params: _,
+                docstring: _,
+                visibility: _,
+                span: _,
             } => {
-                let value = Value::String(format!("struct {}", name));
-                self.env.insert(name.clone(), value);
+                let fields = fields.iter().map(|f| f.name.clone()).collect();
+                self.type_definitions
+                    .insert(name.clone(), TypeDefinition::Struct(fields));
+                self.env
+                    .insert(name.clone(), Value::Type(TypeExpr::Named(name.clone())));
+                Ok(None)
+            }
+            Statement::TypeDef { name, target, .. } => {
+                let target = self.expand_aliases(target);
+                self.env.insert(name.clone(), Value::Type(target));
                 Ok(None)
             }
             _ => Ok(None),
@@ -708,21 +1260,61 @@ impl Interpreter {
     }
 
     pub fn interpret_expression(&mut self, expr: &Expression) -> Result<Value, CrabbyError> {
+        let value = self.evaluate_expression(expr)?;
+        self.check_limits(|check| check.allocated(&value))?;
+        Ok(value)
+    }
+
+    fn evaluate_expression(&mut self, expr: &Expression) -> Result<Value, CrabbyError> {
         #[allow(unreachable_patterns)]
         match expr {
             Expression::Integer(n) => Ok(Value::Integer(*n)),
+            Expression::BigInteger(n) if self.strict_numeric => Err(CrabbyError::RuntimeError(
+                format!("Integer literal {} does not fit in 64 bits", n),
+            )),
+            Expression::BigInteger(n) => Ok(Value::BigInt(n.clone())),
+            Expression::Unary { operator, operand } => {
+                let value = self.interpret_expression(operand)?;
+                match operator {
+                    UnaryOp::Neg => numeric::negate(&value, self.strict_numeric),
+                    UnaryOp::BitNot => numeric::bit_not(&value),
+                    UnaryOp::Not => Ok(Value::Boolean(!value.is_truthy())),
+                }
+            }
+            // Nothing can change a value while it is borrowed, which the ownership
+            // checker makes sure of, so a borrow reads the same as a copy
+            Expression::Borrow { expr, .. } | Expression::Move { expr, .. } => {
+                self.interpret_expression(expr)
+            }
             Expression::Float(f) => Ok(Value::Float(*f)),
             Expression::String(s) => Ok(Value::String(s.clone())),
-            Expression::Boolean(value) => Ok(Value::Integer(if *value { 1 } else { 0 })),
-            Expression::Variable(name) => self.env.get(&name).ok_or_else(|| {
-                CrabbyError::InterpreterError(format!("Undefined variable: {}", name))
-            }),
+            // The same values `==` produces, so `true` and `false` pass as conditions
+            Expression::Boolean(value) => Ok(Value::Boolean(*value)),
+            Expression::Maybe(_) => Ok(Value::Boolean(rand::random_bool(0.5))),
+            Expression::Probably(_) => Ok(Value::Boolean(rand::random_bool(0.75))),
+            Expression::Null => Ok(Value::Null),
+            Expression::Variable { name, .. } => self
+                .env
+                .get(name)
+                // A `def` named without a call is a value, like a lambda
+                .or_else(|| {
+                    self.function_definitions
+                        .get(name)
+                        .cloned()
+                        .map(Value::Lambda)
+                })
+                .or_else(|| builtin_type(name))
+                .ok_or_else(|| {
+                    CrabbyError::InterpreterError(format!("Undefined variable: {}", name))
+                }),
             Expression::Await { expr } => self.interpret_expression(expr),
             Expression::Call {
                 function,
                 arguments,
+                keyword_arguments,
+                span: _,
             } => {
-                if self.call_stack.contains(&function) {
+                if self.call_stack.contains(function) {
                     return Err(CrabbyError::InterpreterError(format!(
                         "Recursion is not allowed: function '{}' calls itself",
                         function
@@ -732,47 +1324,73 @@ impl Interpreter {
 
                 if function == "print" {
                     self.call_stack.pop();
-                    return self.handle_print(&arguments);
+                    return self.handle_print(arguments);
                 }
 
-                let lambda_opt = self.env.get(&function);
-                if let Some(Value::Lambda(lambda)) = lambda_opt {
-                    let result = self.handle_lambda_call(lambda.clone(), &arguments);
+                if function == "Ok" || function == "Err" {
                     self.call_stack.pop();
-                    return result;
+                    return self.handle_result_constructor(function, arguments);
                 }
 
-                let func = self
-                    .function_definitions
-                    .get(function)
-                    .cloned()
-                    .ok_or_else(|| {
-                        CrabbyError::InterpreterError(format!("Undefined function: {}", function))
-                    })?;
+                if function == "expect" {
+                    self.call_stack.pop();
+                    return self.handle_expect(arguments);
+                }
 
-                if arguments.len() != func.params.len() {
+                let lambda_opt = self.env.get(function);
+                // Functions the program defines take the place of built-in ones
+                if lambda_opt.is_none()
+                    && !self.function_definitions.contains_key(function)
+                    && let Some(result) = self.call_builtin(function, arguments, keyword_arguments)
+                {
                     self.call_stack.pop();
-                    return Err(CrabbyError::InterpreterError(format!(
-                        "Function {} expects {} arguments, got {}",
-                        function,
-                        func.params.len(),
-                        arguments.len()
+                    return result;
+                }
+                if let Some(Value::Null) = lambda_opt {
+                    self.call_stack.pop();
+                    return Err(CrabbyError::RuntimeError(format!(
+                        "Cannot call '{}': `{}` is null",
+                        function, function
                     )));
                 }
 
-                let mut new_interpret = Interpreter::new(None);
-                for (param, arg) in func.params.iter().zip(arguments) {
-                    let arg_value = self.interpret_expression(arg)?;
-                    new_interpret.env.insert(param.clone(), arg_value);
+                // `Point(1, 2)` and `Person(name: "Ferris")` build instances
+                let fields = match (&lambda_opt, self.type_definitions.get(function)) {
+                    (Some(Value::Type(_)), Some(TypeDefinition::Struct(fields))) => Some(
+                        fields
+                            .iter()
+                            .map(|name| ClassField {
+                                name: name.clone(),
+                                default: None,
+                            })
+                            .collect(),
+                    ),
+                    (Some(Value::Class(class)), _) => Some(class.fields.clone()),
+                    _ => None,
+                };
+                if let Some(fields) = fields {
+                    let result =
+                        self.construct_instance(function, &fields, arguments, keyword_arguments);
+                    self.call_stack.pop();
+                    return result;
                 }
 
-                let result = match new_interpret.interpret_statement(&func.body) {
-                    Ok(Some(value)) => Ok(value),
-                    Ok(None) => Ok(Value::Integer(0)),
-                    Err(e) => Err(e),
-                    _ => Ok(Value::Void),
+                let func = match lambda_opt {
+                    Some(Value::Lambda(lambda)) => lambda,
+                    _ => match self.function_definitions.get(function).cloned() {
+                        Some(func) => func,
+                        None => {
+                            self.call_stack.pop();
+                            return Err(CrabbyError::InterpreterError(format!(
+                                "Undefined function: {}",
+                                function
+                            )));
+                        }
+                    },
                 };
 
+                let result = self.handle_lambda_call(function, func, arguments, keyword_arguments);
+
                 self.call_stack.pop();
                 result
             }
@@ -818,145 +1436,118 @@ impl Interpreter {
                     )),
                 }
             }
-            Expression::FString {
-                template,
-                expressions,
-            } => {
-                let result = template.clone();
-                let mut expr_values = Vec::new();
-
-                // Evaluate all expressions
-                for expr in expressions {
-                    let value = self.interpret_expression(expr)?;
-                    expr_values.push(value);
-                }
-
-                // Replace placeholders with values
-                let mut curr_pos = 0;
-                let mut final_string = String::new();
-
-                for (_i, value) in expr_values.iter().enumerate() {
-                    if let Some(start) = result[curr_pos..].find('{') {
-                        if let Some(end) = result[curr_pos + start..].find('}') {
-                            final_string.push_str(&result[curr_pos..curr_pos + start]);
-                            final_string.push_str(&value.to_string());
-                            curr_pos = curr_pos + start + end + 1;
+            Expression::FString(parts) => {
+                let mut result = String::new();
+                for part in parts {
+                    match part {
+                        FStringPart::Text(text) => result.push_str(text),
+                        FStringPart::Hole { expression, spec } => {
+                            let value = self.interpret_expression(expression)?;
+                            match spec {
+                                Some(spec) => result.push_str(&spec.apply(&value)?),
+                                None => result.push_str(&value.to_string()),
+                            }
                         }
                     }
                 }
-
-                final_string.push_str(&result[curr_pos..]);
-                Ok(Value::String(final_string))
+                Ok(Value::String(result))
             }
             Expression::Pattern(pattern_kind) => match &**pattern_kind {
-                PatternKind::Literal(expr) => self.interpret_expression(&expr),
+                PatternKind::Literal(expr) => self.interpret_expression(expr),
                 PatternKind::Variable(name) => Ok(Value::String(name.clone())),
                 PatternKind::Wildcard => Ok(Value::Void),
             },
-            Expression::Lambda { params, body } => Ok(Value::Lambda(Function {
-                params: params.clone(),
-                body: body.clone(),
-            })),
+            Expression::Lambda { params, body } => {
+                let mut function = Function::new(params.clone(), body.clone());
+                function.captured = self.env.snapshot();
+                Ok(Value::Lambda(function))
+            }
             Expression::Binary {
                 left,
                 operator,
                 right,
+                span: _,
             } => {
                 let left_clone = left.clone();
                 let right_clone = right.clone();
                 let left_val = self.interpret_expression(left)?;
-                let right_val = self.interpret_expression(right)?;
 
-                match (left_val, operator, right_val) {
-                    // Integer operations
-                    (Value::Integer(l), BinaryOp::Add, Value::Integer(r)) => {
-                        Ok(Value::Integer(l + r))
-                    }
-                    (Value::Integer(l), BinaryOp::Sub, Value::Integer(r)) => {
-                        Ok(Value::Integer(l - r))
-                    }
-                    (Value::Integer(l), BinaryOp::Mul, Value::Integer(r)) => {
-                        Ok(Value::Integer(l * r))
+                if *operator == BinaryOp::NullCoalesce {
+                    return match left_val {
+                        Value::Null => self.interpret_expression(right),
+                        value => Ok(value),
+                    };
+                }
+
+                let right_val = self.interpret_expression(right)?;
+                // `x == null` is fine; anything else on null is a mistake
+                match operator {
+                    BinaryOp::Eq => return Ok(Value::Boolean(values_equal(&left_val, &right_val))),
+                    BinaryOp::NotEq => {
+                        return Ok(Value::Boolean(!values_equal(&left_val, &right_val)));
                     }
-                    (Value::Integer(l), BinaryOp::Div, Value::Integer(r)) => {
-                        if r == 0 {
-                            return Err(CrabbyError::InterpreterError(
-                                "Division by zero".to_string(),
-                            ));
-                        }
-                        Ok(Value::Integer(l / r))
+                    _ => {}
+                }
+                for (value, expr) in [(&left_val, left), (&right_val, right)] {
+                    if matches!(value, Value::Null) {
+                        return Err(CrabbyError::RuntimeError(format!(
+                            "Cannot apply '{}' to null: `{}` is null",
+                            operator, expr
+                        )));
                     }
+                }
 
-                    // Float operations
-                    (Value::Float(l), BinaryOp::Add, Value::Float(r)) => Ok(Value::Float(l + r)),
-                    (Value::Float(l), BinaryOp::Sub, Value::Float(r)) => Ok(Value::Float(l - r)),
-                    (Value::Float(l), BinaryOp::Mul, Value::Float(r)) => Ok(Value::Float(l * r)),
-                    (Value::Float(l), BinaryOp::Div, Value::Float(r)) => {
-                        if r == 0.0 {
-                            return Err(CrabbyError::InterpreterError(
-                                "Division by zero".to_string(),
+                if operator.is_comparison() {
+                    let ordering = match (&left_val, &right_val) {
+                        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+                        (l, r) if numeric::is_number(l) && numeric::is_number(r) => {
+                            numeric::compare(l, r)
+                        }
+                        (l, r) => {
+                            return Err(CrabbyError::TypeError(
+                                None,
+                                format!(
+                                    "Cannot compare {} and {} with '{}'",
+                                    l.type_name(),
+                                    r.type_name(),
+                                    operator
+                                ),
                             ));
                         }
-                        Ok(Value::Float(l / r))
-                    }
+                    };
+                    // NaN is neither less, equal nor greater
+                    let result = ordering.is_some_and(|ordering| match operator {
+                        BinaryOp::Less => ordering.is_lt(),
+                        BinaryOp::LessEq => ordering.is_le(),
+                        BinaryOp::Greater => ordering.is_gt(),
+                        _ => ordering.is_ge(),
+                    });
+                    return Ok(Value::Boolean(result));
+                }
 
-                    // Mixed Integer and Float operations
-                    (Value::Integer(l), op, Value::Float(r)) => {
-                        let l = l as f64;
-                        match op {
-                            BinaryOp::Add => Ok(Value::Float(l + r)),
-                            BinaryOp::Sub => Ok(Value::Float(l - r)),
-                            BinaryOp::Mul => Ok(Value::Float(l * r)),
-                            BinaryOp::Div => {
-                                if r == 0.0 {
-                                    return Err(CrabbyError::InterpreterError(
-                                        "Division by zero".to_string(),
-                                    ));
-                                }
-                                Ok(Value::Float(l / r))
-                            }
-                            BinaryOp::Eq => Ok(Value::Integer(if (l - r).abs() < f64::EPSILON {
-                                1
-                            } else {
-                                0
-                            })),
-                            BinaryOp::MatchOp => {
-                                let left_expr = left_clone.as_ref();
-                                let right_expr = right_clone.as_ref();
-                                Ok(Value::Boolean(left_expr.matches(right_expr)))
-                            }
-                            BinaryOp::Dot => Err(CrabbyError::InterpreterError(
-                                "Cannot use dot operator with numbers".to_string(),
-                            )),
-                        }
+                match (left_val, operator, right_val) {
+                    (Value::Integer(_), BinaryOp::MatchOp, Value::Float(_)) => {
+                        let left_expr = left_clone.as_ref();
+                        let right_expr = right_clone.as_ref();
+                        Ok(Value::Boolean(left_expr.matches(right_expr)))
                     }
-
-                    (Value::Float(l), op, Value::Integer(r)) => {
-                        let r = r as f64;
-                        match op {
-                            BinaryOp::Add => Ok(Value::Float(l + r)),
-                            BinaryOp::Sub => Ok(Value::Float(l - r)),
-                            BinaryOp::Mul => Ok(Value::Float(l * r)),
-                            BinaryOp::Div => {
-                                if r == 0.0 {
-                                    return Err(CrabbyError::InterpreterError(
-                                        "Division by zero".to_string(),
-                                    ));
-                                }
-                                Ok(Value::Float(l / r))
-                            }
-                            BinaryOp::Eq => Ok(Value::Integer(if (l - r).abs() < f64::EPSILON {
-                                1
-                            } else {
-                                0
-                            })),
-                            BinaryOp::MatchOp => Err(CrabbyError::InterpreterError(
-                                "Cannot use match operator with numbers".to_string(),
-                            )),
-                            BinaryOp::Dot => Err(CrabbyError::InterpreterError(
-                                "Cannot use dot operator with numbers".to_string(),
-                            )),
+                    (l, BinaryOp::MatchOp, r)
+                        if numeric::is_number(&l) && numeric::is_number(&r) =>
+                    {
+                        Err(CrabbyError::InterpreterError(
+                            "Cannot use match operator with numbers".to_string(),
+                        ))
+                    }
+                    (l, BinaryOp::Dot, r) if numeric::is_number(&l) && numeric::is_number(&r) => {
+                        Err(CrabbyError::InterpreterError(
+                            "Cannot use dot operator with numbers".to_string(),
+                        ))
+                    }
+                    (l, op, r) if numeric::is_number(&l) && numeric::is_number(&r) => {
+                        if let Some(bits) = numeric::result_bits(op, &l, &r) {
+                            self.check_limits(|check| check.will_allocate_integer(bits))?;
                         }
+                        numeric::binary(op, &l, &r, self.strict_numeric)
                     }
 
                     // String operations
@@ -967,10 +1558,10 @@ impl Interpreter {
                         Ok(Value::String(format!("{}.{}", l, r)))
                     }
                     (Value::String(l), BinaryOp::Add, r) => {
-                        Ok(Value::String(format!("{}{}", l, r.to_string())))
+                        Ok(Value::String(format!("{}{}", l, r)))
                     }
                     (l, BinaryOp::Add, Value::String(r)) => {
-                        Ok(Value::String(format!("{}{}", l.to_string(), r)))
+                        Ok(Value::String(format!("{}{}", l, r)))
                     }
 
                     _ => Err(CrabbyError::InterpreterError(
@@ -978,7 +1569,677 @@ impl Interpreter {
                     )),
                 }
             }
+            Expression::Member {
+                object,
+                field,
+                optional,
+            } => {
+                let object_value = self.interpret_expression(object)?;
+                match object_value {
+                    Value::Null if *optional => Ok(Value::Null),
+                    Value::Null => Err(CrabbyError::RuntimeError(format!(
+                        "Cannot read field '{}': `{}` is null",
+                        field, object
+                    ))),
+                    value @ Value::Type(_) => self.enum_variant(&value, field, Vec::new()),
+                    value => value.get_field(field),
+                }
+            }
+            Expression::MethodCall {
+                object,
+                method,
+                arguments,
+                keyword_arguments,
+                optional,
+            } => {
+                let object_value = self.interpret_expression(object)?;
+                match object_value {
+                    Value::Null if *optional => Ok(Value::Null),
+                    Value::Null => Err(CrabbyError::RuntimeError(format!(
+                        "Cannot call method '{}': `{}` is null",
+                        method, object
+                    ))),
+                    value @ Value::Type(_) => {
+                        let (positional, keywords) =
+                            self.evaluate_arguments(method, arguments, keyword_arguments)?;
+                        if !keywords.is_empty() {
+                            return Err(CrabbyError::InterpreterError(format!(
+                                "Enum variant {} does not take keyword arguments",
+                                method
+                            )));
+                        }
+                        self.enum_variant(&value, method, positional)
+                    }
+                    Value::Instance(mut expectation) if expectation.type_name == EXPECTATION => {
+                        let (positional, keywords) =
+                            self.evaluate_arguments(method, arguments, keyword_arguments)?;
+                        if !keywords.is_empty() {
+                            return Err(CrabbyError::InterpreterError(format!(
+                                "{} does not take keyword arguments",
+                                method
+                            )));
+                        }
+                        let actual = expectation.fields.swap_remove(0).1;
+                        self.check_expectation(actual, method, positional)?;
+                        Ok(Value::Void)
+                    }
+                    Value::Instance(instance)
+                        if !instance.fields.iter().any(|(name, _)| name == method)
+                            && let Some(function) =
+                                self.class_method(&instance.type_name, method) =>
+                    {
+                        let (mut positional, keywords) =
+                            self.evaluate_arguments(method, arguments, keyword_arguments)?;
+                        // A method that takes `self` gets the instance it was called on
+                        if function
+                            .params
+                            .first()
+                            .is_some_and(|param| param.name == "self")
+                        {
+                            positional.insert(0, Value::Instance(instance));
+                        }
+                        self.call_function_value(method, &function, positional, keywords)
+                    }
+                    value => match value.get_field(method)? {
+                        Value::Lambda(lambda) => {
+                            self.handle_lambda_call(method, lambda, arguments, keyword_arguments)
+                        }
+                        other => Err(CrabbyError::TypeError(
+                            None,
+                            format!("'{}' is {}, not a method", method, other.type_name()),
+                        )),
+                    },
+                }
+            }
+            Expression::Try(expr) => match self.interpret_expression(expr)? {
+                Value::Ok(value) => Ok(*value),
+                value @ (Value::Err(_) | Value::Null) => Err(CrabbyError::Propagated(value)),
+                value => Ok(value),
+            },
+            Expression::TypeOf(expr) => {
+                let value = self.interpret_expression(expr)?;
+                Ok(Value::Type(type_of(&value)))
+            }
+            Expression::Is { value, type_expr } => {
+                let value = self.interpret_expression(value)?;
+                let type_expr = self.expand_aliases(type_expr);
+                Ok(Value::Boolean(self.is_instance(&value, &type_expr)?))
+            }
+            Expression::MacroCall { name, .. } => Err(CrabbyError::InterpreterError(format!(
+                "Macro {}! must be expanded before the program runs",
+                name
+            ))),
             _ => Ok(Value::Void),
         }
     }
+
+    // `None` when `function` is neither added with `add_builtin`, declared with
+    // `extern`, nor one of `sandbox::NATIVE_FUNCTIONS`
+    fn call_builtin(
+        &mut self,
+        function: &str,
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Option<Result<Value, CrabbyError>> {
+        if let Some(foreign) = self.foreign.functions.get(function).cloned() {
+            return Some(self.call_extern(&foreign, arguments, keyword_arguments));
+        }
+        let builtin = self.builtins.get(function).cloned();
+        if builtin.is_none() && !sandbox::NATIVE_FUNCTIONS.contains(&function) {
+            return None;
+        }
+        if let Some(keyword) = keyword_arguments.first() {
+            return Some(Err(CrabbyError::InterpreterError(format!(
+                "{} got an unexpected keyword argument '{}'",
+                function, keyword.name
+            ))));
+        }
+        let mut args = Vec::new();
+        for argument in arguments {
+            match self.interpret_expression(argument) {
+                Ok(value) => args.push(value),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        match builtin {
+            Some(builtin) => Some(builtin(args)),
+            None => self.call_native(function, &args),
+        }
+    }
+
+    // Natives that wait on the outside world give up when the program runs out of time
+    fn call_native(&self, name: &str, args: &[Value]) -> Option<Result<Value, CrabbyError>> {
+        let deadline = self
+            .runtime_check
+            .as_ref()
+            .and_then(|check| check.borrow().deadline());
+        sandbox::call_native(name, args, &self.capabilities, deadline)
+    }
+
+    // `&mut x` gives `x` what the C function left in its copy, and `def` functions are
+    // passed as callbacks by name
+    fn call_extern(
+        &mut self,
+        function: &ForeignFunction,
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Result<Value, CrabbyError> {
+        if let Some(keyword) = keyword_arguments.first() {
+            return Err(CrabbyError::InterpreterError(format!(
+                "{} got an unexpected keyword argument '{}'",
+                function.name(),
+                keyword.name
+            )));
+        }
+        let mut args = Vec::new();
+        for argument in arguments {
+            args.push(match argument {
+                Expression::Borrow { expr, .. } => {
+                    Argument::Borrowed(self.interpret_expression(expr)?)
+                }
+                Expression::Variable { name, .. } if self.env.get(name).is_none() => {
+                    match self.function_definitions.get(name) {
+                        Some(callback) => Argument::Value(Value::Lambda(callback.clone())),
+                        None => Argument::Value(self.interpret_expression(argument)?),
+                    }
+                }
+                argument => Argument::Value(self.interpret_expression(argument)?),
+            });
+        }
+
+        let (result, copies) = self.call_foreign(function, args)?;
+        for (argument, copy) in arguments.iter().zip(copies) {
+            if let (
+                Expression::Borrow {
+                    expr,
+                    mutable: true,
+                    ..
+                },
+                Some(copy),
+            ) = (argument, copy)
+                && let Expression::Variable { name, .. } = &**expr
+            {
+                self.env.insert(name.clone(), copy);
+            }
+        }
+        Ok(result)
+    }
+
+    // Runs the Crabby functions the C function calls back in this interpreter
+    fn call_foreign(
+        &mut self,
+        function: &ForeignFunction,
+        args: Vec<Argument>,
+    ) -> Result<(Value, Vec<Option<Value>>), CrabbyError> {
+        function.call(args, &mut |callback, args| match callback {
+            Value::Lambda(callback) => {
+                self.call_function_value("callback", callback, args, Vec::new())
+            }
+            other => Err(CrabbyError::TypeError(
+                None,
+                format!("a callback must be a function, got {}", other.type_name()),
+            )),
+        })
+    }
+
+    // `Ok(value)` and `Err(error)` build the result-like values that `?` unwraps
+    fn handle_result_constructor(
+        &mut self,
+        constructor: &str,
+        args: &[Expression],
+    ) -> Result<Value, CrabbyError> {
+        if args.len() != 1 {
+            return Err(CrabbyError::InterpreterError(format!(
+                "{} takes exactly one argument",
+                constructor
+            )));
+        }
+
+        let value = Box::new(self.interpret_expression(&args[0])?);
+        if constructor == "Ok" {
+            Ok(Value::Ok(value))
+        } else {
+            Ok(Value::Err(value))
+        }
+    }
+
+    // `expect(value)` wraps a value so that `.to_equal(...)` and friends can check it
+    fn handle_expect(&mut self, args: &[Expression]) -> Result<Value, CrabbyError> {
+        if args.len() != 1 {
+            return Err(CrabbyError::InterpreterError(
+                "expect takes exactly one argument".to_string(),
+            ));
+        }
+
+        let actual = self.interpret_expression(&args[0])?;
+        Ok(Value::Instance(Instance {
+            type_name: EXPECTATION.to_string(),
+            variant: None,
+            fields: vec![("actual".to_string(), actual)],
+        }))
+    }
+
+    fn check_expectation(
+        &mut self,
+        actual: Value,
+        matcher: &str,
+        args: Vec<Value>,
+    ) -> Result<(), CrabbyError> {
+        let arity = match matcher {
+            "to_be_true" | "to_be_false" | "to_be_null" | "to_be_ok" | "to_be_err" => 0,
+            "to_throw" => args.len().min(1),
+            "to_equal" | "to_not_equal" | "to_be_greater_than" | "to_be_less_than"
+            | "to_contain" | "to_have_length" => 1,
+            _ => {
+                return Err(CrabbyError::InterpreterError(format!(
+                    "Unknown expectation '{}'",
+                    matcher
+                )));
+            }
+        };
+        if args.len() != arity {
+            return Err(CrabbyError::InterpreterError(format!(
+                "{} takes {} argument{}, got {}",
+                matcher,
+                arity,
+                if arity == 1 { "" } else { "s" },
+                args.len()
+            )));
+        }
+
+        let failure = match matcher {
+            "to_equal" => {
+                (!values_equal(&actual, &args[0])).then(|| testing::mismatch(&args[0], &actual))
+            }
+            "to_not_equal" => (values_equal(&actual, &args[0]))
+                .then(|| format!("expected a value other than {}", testing::show(&actual))),
+            "to_be_true" | "to_be_false" | "to_be_null" => {
+                let expected = match matcher {
+                    "to_be_true" => Value::Boolean(true),
+                    "to_be_false" => Value::Boolean(false),
+                    _ => Value::Null,
+                };
+                (actual != expected).then(|| testing::mismatch(&expected, &actual))
+            }
+            "to_be_ok" => (!matches!(actual, Value::Ok(_)))
+                .then(|| format!("expected Ok(...), got {}", testing::show(&actual))),
+            "to_be_err" => (!matches!(actual, Value::Err(_)))
+                .then(|| format!("expected Err(...), got {}", testing::show(&actual))),
+            "to_be_greater_than" | "to_be_less_than" => {
+                let wanted = if matcher == "to_be_greater_than" {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Less
+                };
+                let relation = if matcher == "to_be_greater_than" {
+                    ">"
+                } else {
+                    "<"
+                };
+                (numeric::compare(&actual, &args[0]) != Some(wanted)).then(|| {
+                    format!(
+                        "expected {} {} {}",
+                        testing::show(&actual),
+                        relation,
+                        testing::show(&args[0])
+                    )
+                })
+            }
+            "to_contain" => {
+                let contains = match (&actual, &args[0]) {
+                    (Value::Array(elements), item) => {
+                        elements.iter().any(|element| values_equal(element, item))
+                    }
+                    (Value::String(text), Value::String(part)) => text.contains(part.as_str()),
+                    (Value::Dict(entries), Value::String(key)) => {
+                        entries.iter().any(|(name, _)| name == key)
+                    }
+                    (other, _) => {
+                        return Err(CrabbyError::TypeError(
+                            None,
+                            format!("to_contain cannot look inside {}", other.type_name()),
+                        ));
+                    }
+                };
+                (!contains).then(|| {
+                    format!(
+                        "expected {} to contain {}",
+                        testing::show(&actual),
+                        testing::show(&args[0])
+                    )
+                })
+            }
+            "to_have_length" => {
+                let length = match &actual {
+                    Value::Array(elements) => elements.len(),
+                    Value::Dict(entries) => entries.len(),
+                    Value::String(text) => text.chars().count(),
+                    other => {
+                        return Err(CrabbyError::TypeError(
+                            None,
+                            format!("{} has no length", other.type_name()),
+                        ));
+                    }
+                };
+                (!values_equal(&Value::Integer(length as i64), &args[0])).then(|| {
+                    format!(
+                        "expected length {}, got {} for {}",
+                        args[0],
+                        length,
+                        testing::show(&actual)
+                    )
+                })
+            }
+            // `expect(() => risky()).to_throw("message")`
+            "to_throw" => {
+                let Value::Lambda(function) = &actual else {
+                    return Err(CrabbyError::TypeError(
+                        None,
+                        format!("to_throw needs a function, got {}", actual.type_name()),
+                    ));
+                };
+                match self.call_function_value(
+                    "<expected to throw>",
+                    function,
+                    Vec::new(),
+                    Vec::new(),
+                ) {
+                    Ok(value) => Some(format!(
+                        "expected an error, but the function returned {}",
+                        testing::show(&value)
+                    )),
+                    Err(error) => match args.first() {
+                        Some(expected) if !error.to_string().contains(&expected.to_string()) => {
+                            Some(format!(
+                                "expected an error containing {}, got: {}",
+                                testing::show(expected),
+                                error
+                            ))
+                        }
+                        _ => None,
+                    },
+                }
+            }
+            _ => unreachable!(),
+        };
+
+        match failure {
+            Some(message) => Err(CrabbyError::AssertionError(None, message)),
+            None => Ok(()),
+        }
+    }
+
+    // Binds constructor arguments to fields by position or by name
+    fn construct_instance(
+        &mut self,
+        type_name: &str,
+        fields: &[ClassField],
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Result<Value, CrabbyError> {
+        let (positional, keywords) =
+            self.evaluate_arguments(type_name, arguments, keyword_arguments)?;
+        if positional.len() > fields.len() {
+            return Err(CrabbyError::InterpreterError(format!(
+                "{} has {} fields, got {} arguments",
+                type_name,
+                fields.len(),
+                positional.len()
+            )));
+        }
+
+        let mut values: Vec<(String, Value)> = fields
+            .iter()
+            .map(|field| field.name.clone())
+            .zip(positional)
+            .collect();
+        for (name, value) in keywords {
+            if !fields.iter().any(|field| field.name == name) {
+                return Err(CrabbyError::InterpreterError(format!(
+                    "{} has no field '{}'",
+                    type_name, name
+                )));
+            }
+            if values.iter().any(|(field, _)| *field == name) {
+                return Err(CrabbyError::InterpreterError(format!(
+                    "{} got multiple values for field '{}'",
+                    type_name, name
+                )));
+            }
+            values.push((name, value));
+        }
+
+        // Fields left out take their default, or start as null
+        let mut instance_fields = Vec::new();
+        for field in fields {
+            let value = match values.iter().find(|(name, _)| *name == field.name) {
+                Some((_, value)) => value.clone(),
+                None => match &field.default {
+                    Some(default) => self.interpret_expression(default)?,
+                    None => Value::Null,
+                },
+            };
+            instance_fields.push((field.name.clone(), value));
+        }
+
+        Ok(Value::Instance(Instance {
+            type_name: type_name.to_string(),
+            variant: None,
+            fields: instance_fields,
+        }))
+    }
+
+    // Looks a method up on a class and then on its parents
+    fn class_method(&self, class: &str, method: &str) -> Option<Function> {
+        let Some(Value::Class(class)) = self.env.get(class) else {
+            return None;
+        };
+        match class.methods.iter().find(|(name, _)| name == method) {
+            Some((_, function)) => Some(function.clone()),
+            None => self.class_method(class.parent.as_deref()?, method),
+        }
+    }
+
+    // `Color.Red` and `Shape.Circle(2.0)`
+    fn enum_variant(
+        &self,
+        enum_type: &Value,
+        variant: &str,
+        values: Vec<Value>,
+    ) -> Result<Value, CrabbyError> {
+        if let Value::Type(TypeExpr::Named(name)) = enum_type
+            && let Some(TypeDefinition::Enum(variants)) = self.type_definitions.get(name)
+        {
+            if !variants.iter().any(|v| v == variant) {
+                return Err(CrabbyError::InterpreterError(format!(
+                    "Enum {} has no variant '{}'",
+                    name, variant
+                )));
+            }
+            return Ok(Value::Instance(Instance {
+                type_name: name.clone(),
+                variant: Some(variant.to_string()),
+                fields: values
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, value)| (index.to_string(), value))
+                    .collect(),
+            }));
+        }
+        enum_type.get_field(variant)
+    }
+
+    // Replaces `typedef` names with the types they stand for
+    fn expand_aliases(&self, type_expr: &TypeExpr) -> TypeExpr {
+        match type_expr {
+            TypeExpr::Named(name) => match self.env.get(name) {
+                // Aliases are expanded when defined, so one lookup is enough
+                Some(Value::Type(target)) => target,
+                _ => type_expr.clone(),
+            },
+            TypeExpr::List(element) => TypeExpr::List(Box::new(self.expand_aliases(element))),
+            TypeExpr::Optional(inner) => TypeExpr::Optional(Box::new(self.expand_aliases(inner))),
+            TypeExpr::Generic { name, arguments } => TypeExpr::Generic {
+                name: name.clone(),
+                arguments: arguments
+                    .iter()
+                    .map(|argument| self.expand_aliases(argument))
+                    .collect(),
+            },
+            TypeExpr::Function { params, returns } => TypeExpr::Function {
+                params: params
+                    .iter()
+                    .map(|param| self.expand_aliases(param))
+                    .collect(),
+                returns: Box::new(self.expand_aliases(returns)),
+            },
+            TypeExpr::Array { element, length } => TypeExpr::Array {
+                element: Box::new(self.expand_aliases(element)),
+                length: *length,
+            },
+        }
+    }
+
+    // `value is Type`, with aliases already expanded
+    fn is_instance(&self, value: &Value, type_expr: &TypeExpr) -> Result<bool, CrabbyError> {
+        match type_expr {
+            TypeExpr::Optional(inner) => {
+                Ok(matches!(value, Value::Null) || self.is_instance(value, inner)?)
+            }
+            TypeExpr::List(element) => match value {
+                Value::Array(elements) => {
+                    for element_value in elements {
+                        if !self.is_instance(element_value, element)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            TypeExpr::Array { element, length } => match value {
+                Value::Array(elements) if elements.len() == *length => {
+                    self.is_instance(value, &TypeExpr::List(element.clone()))
+                }
+                _ => Ok(false),
+            },
+            TypeExpr::Function { .. } => Ok(matches!(value, Value::Lambda(_))),
+            TypeExpr::Generic { name, arguments } => match (name.as_str(), arguments.as_slice()) {
+                ("List" | "Array", [element]) => {
+                    self.is_instance(value, &TypeExpr::List(Box::new(element.clone())))
+                }
+                ("Option", [inner]) => {
+                    self.is_instance(value, &TypeExpr::Optional(Box::new(inner.clone())))
+                }
+                _ => self.is_instance(value, &TypeExpr::Named(name.clone())),
+            },
+            TypeExpr::Named(name) => Ok(match name.as_str() {
+                "Any" | "any" => true,
+                "Int" | "int" | "Integer" => matches!(value, Value::Integer(_) | Value::BigInt(_)),
+                "Float" | "float" => matches!(value, Value::Float(_)),
+                "String" | "string" | "str" => matches!(value, Value::String(_)),
+                "Bool" | "bool" | "Boolean" => matches!(value, Value::Boolean(_)),
+                "null" | "Null" => matches!(value, Value::Null),
+                "Void" | "void" => matches!(value, Value::Void),
+                "List" | "Array" => matches!(value, Value::Array(_)),
+                "Dict" | "Map" => matches!(value, Value::Dict(_)),
+                "Result" => matches!(value, Value::Ok(_) | Value::Err(_)),
+                "Function" => matches!(value, Value::Lambda(_)),
+                "Type" => matches!(value, Value::Type(_) | Value::Class(_)),
+                name => match self.env.get(name) {
+                    Some(Value::Type(_) | Value::Class(_)) => self.instance_of(value, name),
+                    _ => {
+                        return Err(CrabbyError::RuntimeError(format!(
+                            "Unknown type '{}'",
+                            name
+                        )));
+                    }
+                },
+            }),
+        }
+    }
+
+    // Whether `value` was built from `type_name` or from a class extending it
+    fn instance_of(&self, value: &Value, type_name: &str) -> bool {
+        let Value::Instance(instance) = value else {
+            return false;
+        };
+        let mut current = Some(instance.type_name.clone());
+        while let Some(name) = current {
+            if name == type_name {
+                return true;
+            }
+            current = match self.env.get(&name) {
+                Some(Value::Class(class)) => class.parent,
+                _ => None,
+            };
+        }
+        false
+    }
+
+    // Only optional annotations (`T?` or `Option<T>`) may hold null
+    fn check_nullable(
+        &self,
+        name: &str,
+        type_annotation: &Option<TypeExpr>,
+        value: &Value,
+    ) -> Result<(), CrabbyError> {
+        let Some(annotation) = type_annotation else {
+            return Ok(());
+        };
+        // `typedef MaybeInt = Int?` makes `MaybeInt` optional too
+        let expanded = self.expand_aliases(annotation);
+        if matches!(value, Value::Null)
+            && !matches!(expanded, TypeExpr::Optional(_))
+            && !matches!(&expanded, TypeExpr::Generic { name, .. } if name == "Option")
+        {
+            return Err(CrabbyError::TypeError(
+                None,
+                format!(
+                    "Cannot assign null to '{}' of non-optional type '{}'; use '{}?' to allow null",
+                    name, annotation, annotation
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// `==`: numbers compare by value, so `1 == 1.0`
+pub fn values_equal(left: &Value, right: &Value) -> bool {
+    if numeric::is_number(left) && numeric::is_number(right) {
+        numeric::compare(left, right) == Some(std::cmp::Ordering::Equal)
+    } else {
+        left == right
+    }
+}
+
+// The type `typeof` reports for a value
+fn type_of(value: &Value) -> TypeExpr {
+    let name = match value {
+        Value::Integer(_) | Value::BigInt(_) => "Int",
+        Value::Float(_) => "Float",
+        Value::String(_) => "String",
+        Value::Boolean(_) => "Bool",
+        Value::Lambda(_) => "Function",
+        Value::Class(_) | Value::Type(_) => "Type",
+        Value::Array(_) => "List",
+        Value::Dict(_) => "Dict",
+        Value::Ok(_) | Value::Err(_) => "Result",
+        Value::Instance(instance) => &instance.type_name,
+        Value::Null => "null",
+        Value::Void => "Void",
+    };
+    TypeExpr::Named(name.to_string())
+}
+
+const BUILTIN_TYPES: [&str; 11] = [
+    "Int", "Float", "String", "Bool", "Void", "Any", "List", "Dict", "Function", "Result", "Type",
+];
+
+// Built-in type names can be used as values, e.g. `typeof(x) == Int`
+fn builtin_type(name: &str) -> Option<Value> {
+    BUILTIN_TYPES
+        .contains(&name)
+        .then(|| Value::Type(TypeExpr::Named(name.to_string())))
 }
@@ -1,3 +1,3 @@
 mod tokenizer;
 
-pub use tokenizer::{Token, TokenStream};
+pub use tokenizer::{TemplatePiece, Token, TokenStream};
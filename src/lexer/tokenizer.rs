@@ -1,7 +1,9 @@
 use crate::utils::{CrabbyError, ErrorLocation, Span};
-use logos::Logos;
+use logos::{Lexer, Logos};
+use num_bigint::BigInt;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
+#[logos(error = LexError)]
 pub enum Token {
     // KEYWORDS
     #[token("def")]
@@ -227,18 +229,33 @@ pub enum Token {
     From,
 
     // LITERALS
-    #[regex(r"-?[0-9]+\.[0-9]+", |lex| lex.slice().parse::<f64>().ok())]
+    // `-` is a separate token, so `n-1` is `n - 1`
+    #[regex(r"[0-9][0-9_]*\.[0-9][0-9_]*", |lex| parse_float(lex.slice()))]
     Float(f64),
 
-    #[regex(r"-?[0-9]+", |lex| lex.slice().parse::<i64>().ok())]
-    Integer(i64),
+    // `1_000_000`, `0xFF`, `0o755` and `0b1010`; too big for 64 bits becomes a big integer
+    #[regex(r"[0-9][0-9_]*", |lex| parse_integer(lex.slice(), 10))]
+    #[regex(r"0[xX][0-9a-fA-F_]*", |lex| parse_integer(&lex.slice()[2..], 16))]
+    #[regex(r"0[oO][0-7_]*", |lex| parse_integer(&lex.slice()[2..], 8))]
+    #[regex(r"0[bB][01_]*", |lex| parse_integer(&lex.slice()[2..], 2))]
+    Integer(BigInt),
 
-    #[regex(r#""[^"]*""#, |lex| Some(lex.slice().trim_matches('"').to_string()))]
+    // `"..."` and `'...'` take escapes, `r"..."` doesn't, and `"""..."""` may span lines
+    #[token("\"", |lex| string(lex, "\"", false))]
+    #[token("'", |lex| string(lex, "'", false))]
+    #[token("\"\"\"", |lex| string(lex, "\"\"\"", false))]
+    #[token("'''", |lex| string(lex, "'''", false))]
+    #[token("r\"", |lex| string(lex, "\"", true))]
+    #[token("r'", |lex| string(lex, "'", true))]
+    #[token("r\"\"\"", |lex| string(lex, "\"\"\"", true))]
+    #[token("r'''", |lex| string(lex, "'''", true))]
     String(String),
 
-    #[regex(r#"f'[^']*'"#, |lex| Some(lex.slice()[2..lex.slice().len()-1].to_string()))]
-    #[regex(r#"f"[^"]*""#, |lex| Some(lex.slice()[2..lex.slice().len()-1].to_string()))]
-    FString(String),
+    #[token("f\"", |lex| template(lex, "\""))]
+    #[token("f'", |lex| template(lex, "'"))]
+    #[token("f\"\"\"", |lex| template(lex, "\"\"\""))]
+    #[token("f'''", |lex| template(lex, "'''"))]
+    FString(Vec<TemplatePiece>),
 
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| Some(lex.slice().to_string()))]
     Identifier(String),
@@ -261,6 +278,18 @@ pub enum Token {
     Minus,
     #[token("*")]
     Star,
+    #[token("**")]
+    DoubleStar,
+    #[token("~")]
+    Tilde,
+    #[token("^")]
+    Caret,
+    #[token("|")]
+    Bar,
+    #[token("<<")]
+    ShiftLeft,
+    #[token(">>")]
+    ShiftRight,
     #[token("/")]
     Slash,
     #[token("=")]
@@ -269,6 +298,10 @@ pub enum Token {
     DollarSign,
     #[token("?")]
     QuestionMark,
+    #[token("?.")] // Safe navigation: `a?.b` is null when `a` is null
+    SafeNavigation,
+    #[token("??")] // Null-coalescing: `a ?? b` is `b` when `a` is null
+    NullCoalesce,
     #[token("_", priority = 3)]
     Underscore,
     #[token("!=")]
@@ -310,7 +343,7 @@ pub enum Token {
      * In Crabby, the `@` symbol indicates a decorator call,
      * similar to Python's decorator feature:
      *
-     * ```
+     * ```crabby
      * def sprinkles() {
      *  print("Adding Sprinkles!❄️")
      * }
@@ -347,9 +380,269 @@ pub enum Token {
     #[token(".")]
     Dot,
 
+    // Dropped by `tokenize`, except `///` doc comments, which the parser attaches to
+    // the declaration after them; the formatter keeps every comment
+    #[regex(r"//[^\r\n]*", |lex| lex.slice().to_string())]
+    Comment(String),
+
     #[regex(r"[ \t\r\n]+", logos::skip)]
-    #[regex(r"//[^\r\n]*", logos::skip)]
     Whitespace,
+
+    // Never lexed: what the parser sees once it has read every token
+    Eof,
+}
+
+// `_` only goes between digits, so `1_` and `0x` are mistakes rather than `1` and `0`
+fn parse_integer(digits: &str, radix: u32) -> Result<BigInt, LexError> {
+    let kind = match radix {
+        16 => "hex",
+        8 => "octal",
+        2 => "binary",
+        _ => "decimal",
+    };
+    if digits.replace('_', "").is_empty() {
+        return Err(LexError(Some(format!(
+            "Invalid number; expected {} digits",
+            kind
+        ))));
+    }
+    if digits.ends_with('_') {
+        return Err(trailing_underscore());
+    }
+    BigInt::parse_bytes(digits.replace('_', "").as_bytes(), radix).ok_or_else(LexError::default)
+}
+
+fn parse_float(text: &str) -> Result<f64, LexError> {
+    if text.ends_with('_') || text.contains("_.") {
+        return Err(trailing_underscore());
+    }
+    text.replace('_', "")
+        .parse::<f64>()
+        .map_err(|_| LexError::default())
+}
+
+fn trailing_underscore() -> LexError {
+    LexError(Some(
+        "Invalid number; `_` goes between digits, not after them".to_string(),
+    ))
+}
+
+// `None` is a character the lexer doesn't know; `Some` says what is wrong with a token
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LexError(Option<String>);
+
+// A piece of an f-string: text with its escapes already applied, or the source
+// of a `{hole}` and its format spec, which the parser reads on its own
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplatePiece {
+    Text(String),
+    Hole { code: String, spec: Option<String> },
+}
+
+// The rest of a string literal after its opening quote
+fn string(lex: &mut Lexer<Token>, quote: &str, raw: bool) -> Result<String, LexError> {
+    let body = string_body(lex, quote, raw)?;
+    // A triple-quoted string starts on the line after its quotes
+    let body = match quote.len() {
+        3 => body
+            .strip_prefix("\r\n")
+            .or_else(|| body.strip_prefix('\n'))
+            .unwrap_or(body),
+        _ => body,
+    };
+    if raw {
+        Ok(body.to_string())
+    } else {
+        unescape(body)
+    }
+}
+
+fn string_body<'s>(
+    lex: &mut Lexer<'s, Token>,
+    quote: &str,
+    raw: bool,
+) -> Result<&'s str, LexError> {
+    let rest = lex.remainder();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if rest[i..].starts_with(quote) {
+            lex.bump(i + quote.len());
+            return Ok(&rest[..i]);
+        }
+        match c {
+            '\n' if quote.len() == 1 => break,
+            '\\' if !raw => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    Err(unterminated(quote))
+}
+
+fn template(lex: &mut Lexer<Token>, quote: &str) -> Result<Vec<TemplatePiece>, LexError> {
+    let rest = lex.remainder();
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = rest.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if rest[i..].starts_with(quote) {
+            if !text.is_empty() {
+                pieces.push(TemplatePiece::Text(unescape(&text)?));
+            }
+            lex.bump(i + quote.len());
+            return Ok(pieces);
+        }
+        match c {
+            '\n' if quote.len() == 1 => break,
+            '\\' => {
+                text.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    text.push(escaped);
+                }
+            }
+            '{' if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if matches!(chars.peek(), Some((_, '}'))) => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                if !text.is_empty() {
+                    pieces.push(TemplatePiece::Text(unescape(&text)?));
+                    text.clear();
+                }
+                let end = hole_end(&rest[i + 1..])
+                    .ok_or_else(|| LexError(Some("Unclosed '{' in f-string".to_string())))?;
+                pieces.push(hole(&rest[i + 1..i + 1 + end]));
+                while chars.peek().is_some_and(|(j, _)| *j <= i + 1 + end) {
+                    chars.next();
+                }
+            }
+            '}' => {
+                return Err(LexError(Some(
+                    "Single '}' in f-string; write '}}' for a literal brace".to_string(),
+                )));
+            }
+            _ => text.push(c),
+        }
+    }
+    Err(unterminated(quote))
+}
+
+// Where the `}` closing a hole is, skipping nested brackets and strings
+fn hole_end(code: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string: Option<char> = None;
+    let mut chars = code.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (in_string, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => in_string = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => in_string = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, '}') if depth == 0 => return Some(i),
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, '\n') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+// `{x:.2f}` splits at the first `:` outside brackets and strings
+fn hole(code: &str) -> TemplatePiece {
+    let mut depth = 0;
+    let mut in_string: Option<char> = None;
+    for (i, c) in code.char_indices() {
+        match (in_string, c) {
+            (Some(q), c) if c == q => in_string = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => in_string = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ':') if depth == 0 => {
+                return TemplatePiece::Hole {
+                    code: code[..i].to_string(),
+                    spec: Some(code[i + 1..].to_string()),
+                };
+            }
+            _ => {}
+        }
+    }
+    TemplatePiece::Hole {
+        code: code.to_string(),
+        spec: None,
+    }
+}
+
+fn unescape(text: &str) -> Result<String, LexError> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some(c @ ('\\' | '"' | '\'' | '{' | '}')) => result.push(c),
+            // A backslash at the end of a line joins it to the next
+            Some('\n') => {}
+            Some('u') => result.push(unicode_escape(&mut chars)?),
+            Some(c) => {
+                return Err(LexError(Some(format!("Unknown escape sequence '\\{}'", c))));
+            }
+            None => return Err(LexError(Some("Unfinished escape sequence".to_string()))),
+        }
+    }
+    Ok(result)
+}
+
+// `\u{1F980}`
+fn unicode_escape(chars: &mut std::str::Chars) -> Result<char, LexError> {
+    let invalid = || {
+        LexError(Some(
+            "Expected '\\u{XXXX}' with 1 to 6 hex digits".to_string(),
+        ))
+    };
+    if chars.next() != Some('{') {
+        return Err(invalid());
+    }
+    let mut digits = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => digits.push(c),
+            None => return Err(invalid()),
+        }
+    }
+    if digits.is_empty() || digits.len() > 6 {
+        return Err(invalid());
+    }
+    let code = u32::from_str_radix(&digits, 16).map_err(|_| invalid())?;
+    char::from_u32(code).ok_or_else(|| {
+        LexError(Some(format!(
+            "'\\u{{{}}}' is not a valid unicode character",
+            digits
+        )))
+    })
+}
+
+fn unterminated(quote: &str) -> LexError {
+    LexError(Some(format!(
+        "Unterminated string; expected a closing {}",
+        quote
+    )))
 }
 
 pub struct TokenStream {
@@ -360,9 +653,31 @@ pub struct TokenStream {
     pub source: String,
 }
 
+impl Token {
+    // The text of a `/// doc comment`; `////` starts a plain comment, as in Rust
+    pub fn doc_comment(&self) -> Option<&str> {
+        match self {
+            Token::Comment(text) if !text.starts_with("////") => {
+                let text = text.strip_prefix("///")?;
+                Some(text.strip_prefix(' ').unwrap_or(text))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl TokenStream {
     // yeah my bad for even making this async in the first place :p
     pub fn tokenize(source: String) -> Result<Vec<Self>, CrabbyError> {
+        Self::lex(source, false)
+    }
+
+    // Keeps `Comment` tokens, so the source can be printed back without losing them
+    pub fn tokenize_with_comments(source: String) -> Result<Vec<Self>, CrabbyError> {
+        Self::lex(source, true)
+    }
+
+    fn lex(source: String, keep_comments: bool) -> Result<Vec<Self>, CrabbyError> {
         let mut tokens = Vec::new();
         let mut lex = Token::lexer(&source);
         let mut line = 1;
@@ -390,8 +705,15 @@ impl TokenStream {
 
             match token_result {
                 Ok(token) => {
-                    // Skip the Whitespace token as it's handled above
-                    if matches!(token, Token::Whitespace) {
+                    // Skip the Whitespace token as it's handled above. Its text is
+                    // counted with the gap before the next token, so only the gap
+                    // before it is behind us
+                    if matches!(token, Token::Whitespace)
+                        || (matches!(token, Token::Comment(_))
+                            && token.doc_comment().is_none()
+                            && !keep_comments)
+                    {
+                        last_valid_pos = span_start;
                         continue;
                     }
 
@@ -405,11 +727,25 @@ impl TokenStream {
                         source: source.clone(),
                     });
 
-                    // Update column for the token
-                    column += lex.slice().len();
+                    // Update line and column for the token, which may be a multi-line string
+                    for ch in lex.slice().chars() {
+                        if ch == '\n' {
+                            line += 1;
+                            column = 1;
+                        } else {
+                            column += 1;
+                        }
+                    }
                     last_valid_pos = lex.span().end;
                 }
-                Err(_) => {
+                Err(LexError(Some(message))) => {
+                    return Err(CrabbyError::LexerError(ErrorLocation {
+                        line,
+                        column,
+                        message,
+                    }));
+                }
+                Err(LexError(None)) => {
                     if last_valid_pos < source.len() {
                         let problem_char = source[span_start..]
                             .chars()
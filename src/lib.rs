@@ -0,0 +1,24 @@
+// The Crabby interpreter as a library, for hosting Crabby scripts from Rust.
+// `crabby::Engine` is the way in; the modules below are what the `crabby` command uses.
+
+pub mod ast;
+pub mod capi;
+pub mod core;
+pub mod engine;
+pub mod etc;
+pub mod format;
+pub mod interpreter;
+pub mod lexer;
+pub mod lsp;
+pub mod modules;
+pub mod numeric;
+pub mod parser;
+pub mod repl;
+pub mod runtime;
+pub mod utils;
+pub mod value;
+
+pub use crate::core::sandbox::Capabilities;
+pub use crate::engine::{Engine, FromValue, IntoValue};
+pub use crate::utils::CrabbyError;
+pub use crate::value::Value;
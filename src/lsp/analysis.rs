@@ -0,0 +1,680 @@
+// What the server knows about one open document
+// Names are found in the token stream, where every identifier has a position; the
+// AST only has spans for a few nodes. A name belongs to the innermost `{ }` block it
+// is declared in, and function parameters to the function's body.
+
+use std::collections::HashMap;
+
+use serde_json::{Value as Json, json};
+
+use super::protocol::LineIndex;
+use crate::ast::{Program, Statement};
+use crate::core::memory::MemoryChecker;
+use crate::core::metaprogram::expand_macros;
+use crate::etc::deadcode::{DeadCodeAnalyzer, WarningKind};
+use crate::etc::typechecker::TypeChecker;
+use crate::lexer::{Token, TokenStream};
+use crate::parser::parse;
+use crate::utils::{CrabbyError, ErrorLocation};
+
+pub const KEYWORDS: [&str; 40] = [
+    "def", "fun", "return", "if", "elif", "else", "while", "loop", "for", "in", "let", "var",
+    "const", "mut", "match", "case", "class", "struct", "enum", "macro", "import", "from", "pub",
+    "try", "catch", "throw", "typedef", "typeof", "is", "lambda", "and", "or", "not", "break",
+    "continue", "assert", "async", "await", "true", "false",
+];
+
+// Severities and tags from the LSP spec
+const ERROR: u8 = 1;
+const WARNING: u8 = 2;
+const UNNECESSARY: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Variable,
+    Constant,
+    Parameter,
+    Class,
+    Struct,
+    Enum,
+    Macro,
+    TypeAlias,
+    Import,
+}
+
+impl SymbolKind {
+    // `SymbolKind` in the spec
+    pub fn symbol_number(self) -> u8 {
+        match self {
+            SymbolKind::Function | SymbolKind::Macro => 12,
+            SymbolKind::Method => 6,
+            SymbolKind::Variable | SymbolKind::Parameter => 13,
+            SymbolKind::Constant => 14,
+            SymbolKind::Class => 5,
+            SymbolKind::Struct => 23,
+            SymbolKind::Enum => 10,
+            SymbolKind::TypeAlias => 26,
+            SymbolKind::Import => 2,
+        }
+    }
+
+    // `CompletionItemKind` in the spec
+    pub fn completion_number(self) -> u8 {
+        match self {
+            SymbolKind::Function | SymbolKind::Macro => 3,
+            SymbolKind::Method => 2,
+            SymbolKind::Variable | SymbolKind::Parameter => 6,
+            SymbolKind::Constant => 21,
+            SymbolKind::Class => 7,
+            SymbolKind::Struct => 22,
+            SymbolKind::Enum => 13,
+            SymbolKind::TypeAlias => 25,
+            SymbolKind::Import => 9,
+        }
+    }
+
+    // Functions and types can be used before the line that declares them
+    fn is_hoisted(self) -> bool {
+        !matches!(
+            self,
+            SymbolKind::Variable | SymbolKind::Constant | SymbolKind::Import
+        )
+    }
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // The token holding the name
+    pub token: usize,
+    // The tokens the name can be used in
+    scope: (usize, usize),
+    // The last token of the whole definition, such as a function's closing brace
+    end: usize,
+    // A signature or a declaration, and a docstring
+    pub detail: String,
+    pub doc: String,
+}
+
+pub struct Document {
+    pub text: String,
+    pub lines: LineIndex,
+    tokens: Vec<TokenStream>,
+    // The innermost `{` around each token, and where each bracket's partner is
+    blocks: Vec<Option<usize>>,
+    matching: Vec<Option<usize>>,
+    pub symbols: Vec<Symbol>,
+    // The symbol each identifier token names, if it is one of ours
+    targets: HashMap<usize, usize>,
+    pub diagnostics: Vec<Json>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let mut document = Self {
+            lines: LineIndex::new(&text),
+            text,
+            tokens: Vec::new(),
+            blocks: Vec::new(),
+            matching: Vec::new(),
+            symbols: Vec::new(),
+            targets: HashMap::new(),
+            diagnostics: Vec::new(),
+        };
+        match TokenStream::tokenize(document.text.clone()) {
+            // Doc comments reach hovers through the parsed docstrings
+            Ok(tokens) => {
+                document.tokens = tokens
+                    .into_iter()
+                    .filter(|token| !matches!(token.token, Token::Comment(_)))
+                    .collect()
+            }
+            Err(CrabbyError::LexerError(location)) if location.message == "Empty source file" => {}
+            Err(e) => {
+                document.diagnose(&e);
+                return document;
+            }
+        }
+        document.find_blocks();
+        document.collect_symbols();
+        document.resolve();
+
+        if document.tokens.is_empty() {
+            return document;
+        }
+        let program = TokenStream::tokenize(document.text.clone())
+            .and_then(parse)
+            .and_then(expand_macros);
+        match program {
+            Ok(program) => document.check(&program),
+            Err(e) => document.diagnose(&e),
+        }
+        document
+    }
+
+    pub fn range(&self, start: usize, end: usize) -> Json {
+        self.lines.range(&self.text, start, end)
+    }
+
+    pub fn token_range(&self, token: usize) -> Json {
+        let span = &self.tokens[token].span;
+        self.range(span.start, span.end)
+    }
+
+    // The range of a whole definition, from its keyword to its end
+    pub fn symbol_range(&self, symbol: &Symbol) -> Json {
+        let start = self.definition_start(symbol.token);
+        self.range(
+            self.tokens[start].span.start,
+            self.tokens[symbol.end].span.end,
+        )
+    }
+
+    // The symbol named by the identifier at or just before `offset`
+    pub fn symbol_at(&self, offset: usize) -> Option<usize> {
+        let token = self.tokens.iter().position(|token| {
+            token.span.start <= offset
+                && offset <= token.span.end
+                && matches!(token.token, Token::Identifier(_))
+        })?;
+        self.targets.get(&token).copied()
+    }
+
+    pub fn references(&self, symbol: usize, include_declaration: bool) -> Vec<usize> {
+        let mut tokens: Vec<usize> = self
+            .targets
+            .iter()
+            .filter(|(token, target)| {
+                **target == symbol && (include_declaration || **token != self.symbols[symbol].token)
+            })
+            .map(|(token, _)| *token)
+            .collect();
+        tokens.sort();
+        tokens
+    }
+
+    // The symbols that can be used at `offset`, innermost first
+    pub fn visible(&self, offset: usize) -> Vec<&Symbol> {
+        if self.tokens.is_empty() {
+            return Vec::new();
+        }
+        let token = self
+            .tokens
+            .partition_point(|t| t.span.end < offset)
+            .min(self.tokens.len() - 1);
+        let mut visible: Vec<&Symbol> = self
+            .symbols
+            .iter()
+            .filter(|symbol| {
+                symbol.scope.0 <= token
+                    && token <= symbol.scope.1
+                    && (symbol.kind.is_hoisted()
+                        || symbol.token < token
+                        || self.scope_of(token) != symbol.scope)
+            })
+            .collect();
+        visible.sort_by_key(|symbol| std::cmp::Reverse(symbol.scope.0));
+        visible
+    }
+
+    // Symbols directly inside `parent` (or at the top level), for the outline
+    pub fn children(&self, parent: Option<usize>) -> Vec<usize> {
+        (0..self.symbols.len())
+            .filter(|&i| !matches!(self.symbols[i].kind, SymbolKind::Parameter))
+            .filter(|&i| self.parent(i) == parent)
+            .collect()
+    }
+
+    // The innermost definition whose body holds symbol `i`
+    fn parent(&self, i: usize) -> Option<usize> {
+        let token = self.symbols[i].token;
+        (0..self.symbols.len())
+            .filter(|&j| j != i && !matches!(self.symbols[j].kind, SymbolKind::Parameter))
+            .filter(|&j| self.symbols[j].token < token && token <= self.symbols[j].end)
+            .max_by_key(|&j| self.symbols[j].token)
+    }
+
+    fn definition_start(&self, token: usize) -> usize {
+        let mut start = token;
+        while start > 0
+            && matches!(
+                self.tokens[start - 1].token,
+                Token::Def
+                    | Token::Function
+                    | Token::Let
+                    | Token::Variable
+                    | Token::Constant
+                    | Token::Mutable
+                    | Token::Public
+                    | Token::Class
+                    | Token::Struct
+                    | Token::Enum
+                    | Token::Interface
+                    | Token::Trait
+                    | Token::Macro
+                    | Token::TypeDef
+                    | Token::Import
+            )
+        {
+            start -= 1;
+        }
+        start
+    }
+
+    fn find_blocks(&mut self) {
+        self.matching = vec![None; self.tokens.len()];
+        let mut brackets = Vec::new();
+        let mut braces: Vec<usize> = Vec::new();
+        for (i, token) in self.tokens.iter().enumerate() {
+            match token.token {
+                Token::LParen | Token::LBracket | Token::LBrace => brackets.push(i),
+                Token::RParen | Token::RBracket | Token::RBrace => {
+                    if let Some(start) = brackets.pop() {
+                        self.matching[start] = Some(i);
+                        self.matching[i] = Some(start);
+                    }
+                }
+                _ => {}
+            }
+            if matches!(token.token, Token::RBrace) {
+                braces.pop();
+            }
+            self.blocks.push(braces.last().copied());
+            if matches!(token.token, Token::LBrace) {
+                braces.push(i);
+            }
+        }
+    }
+
+    // The tokens of the block around token `i`
+    fn scope_of(&self, i: usize) -> (usize, usize) {
+        let last = self.tokens.len().saturating_sub(1);
+        match self.blocks[i] {
+            Some(brace) => (brace, self.matching[brace].unwrap_or(last)),
+            None => (0, last),
+        }
+    }
+
+    fn collect_symbols(&mut self) {
+        let docs = docstrings(&self.text);
+        let mut class_bodies = Vec::new();
+
+        for i in 0..self.tokens.len() {
+            let Some(name_token) = self.name_after(i) else {
+                continue;
+            };
+            let Token::Identifier(name) = &self.tokens[name_token].token else {
+                continue;
+            };
+            let name = name.clone();
+            let in_class = self.blocks[i].is_some_and(|brace| class_bodies.contains(&brace));
+            let (kind, body) = match self.tokens[i].token {
+                Token::Def | Token::Function if in_class => (SymbolKind::Method, true),
+                Token::Def | Token::Function => (SymbolKind::Function, true),
+                Token::Let | Token::Variable => (SymbolKind::Variable, false),
+                Token::Constant => (SymbolKind::Constant, false),
+                Token::Class | Token::Interface | Token::Trait => (SymbolKind::Class, true),
+                Token::Struct => (SymbolKind::Struct, true),
+                Token::Enum => (SymbolKind::Enum, true),
+                Token::Macro => (SymbolKind::Macro, true),
+                Token::TypeDef => (SymbolKind::TypeAlias, false),
+                Token::Import => (SymbolKind::Import, false),
+                Token::For => (SymbolKind::Variable, false),
+                _ => continue,
+            };
+
+            // The body is the next block at the same depth
+            let brace = (name_token..self.tokens.len()).find(|&j| {
+                matches!(self.tokens[j].token, Token::LBrace) && self.blocks[j] == self.blocks[i]
+            });
+            let body_end = brace.and_then(|brace| self.matching[brace]);
+            let end = match (body, body_end) {
+                (true, Some(end)) => end,
+                _ => self.statement_end(name_token),
+            };
+            if let (SymbolKind::Class | SymbolKind::Struct, Some(brace)) = (kind, brace) {
+                class_bodies.push(brace);
+            }
+
+            if matches!(self.tokens[i].token, Token::For) {
+                // `for x in items { ... }`: `x` only exists in the loop body
+                if let (Some(brace), Some(body_end)) = (brace, body_end) {
+                    self.symbols.push(Symbol {
+                        detail: format!("for {}", name),
+                        name,
+                        kind: SymbolKind::Variable,
+                        token: name_token,
+                        scope: (brace, body_end),
+                        end: name_token,
+                        doc: String::new(),
+                    });
+                }
+                continue;
+            }
+
+            let start = self.tokens[self.definition_start(name_token)].span.start;
+            let detail = match kind {
+                // The signature, up to the body
+                SymbolKind::Function | SymbolKind::Method | SymbolKind::Macro => {
+                    let end =
+                        brace.map_or(self.tokens[end].span.end, |b| self.tokens[b].span.start);
+                    one_line(&self.text[start..end])
+                }
+                SymbolKind::Variable | SymbolKind::Constant => {
+                    let end = (name_token..=end)
+                        .find(|&j| matches!(self.tokens[j].token, Token::Equals))
+                        .map_or(self.tokens[name_token].span.end, |j| {
+                            self.tokens[j].span.start
+                        });
+                    one_line(&self.text[start..end])
+                }
+                SymbolKind::Class | SymbolKind::Struct | SymbolKind::Enum => {
+                    let end = brace.map_or(self.tokens[name_token].span.end, |b| {
+                        self.tokens[b].span.start
+                    });
+                    one_line(&self.text[start..end])
+                }
+                _ => one_line(&self.text[start..self.tokens[end].span.end]),
+            };
+            let symbol = self.symbols.len();
+            self.symbols.push(Symbol {
+                name,
+                kind,
+                token: name_token,
+                scope: self.scope_of(i),
+                end,
+                detail,
+                doc: docs
+                    .get(&self.tokens[i].span.start)
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+
+            if matches!(kind, SymbolKind::Function | SymbolKind::Method)
+                && let (Some(brace), Some(body_end)) = (brace, body_end)
+            {
+                self.collect_parameters(name_token, brace, body_end, symbol);
+            }
+        }
+    }
+
+    // The name a declaring keyword at `i` introduces
+    fn name_after(&self, i: usize) -> Option<usize> {
+        let declares = matches!(
+            self.tokens[i].token,
+            Token::Def
+                | Token::Function
+                | Token::Let
+                | Token::Variable
+                | Token::Constant
+                | Token::Class
+                | Token::Interface
+                | Token::Trait
+                | Token::Struct
+                | Token::Enum
+                | Token::Macro
+                | Token::TypeDef
+                | Token::Import
+                | Token::For
+        );
+        if !declares {
+            return None;
+        }
+        let mut j = i + 1;
+        while matches!(
+            self.tokens.get(j).map(|t| &t.token),
+            Some(Token::Mutable | Token::Public)
+        ) {
+            j += 1;
+        }
+        matches!(self.tokens.get(j)?.token, Token::Identifier(_)).then_some(j)
+    }
+
+    // `def f(a, b: Int, *rest)`: the names right after `(`, `,`, `*` or `**`
+    fn collect_parameters(&mut self, name: usize, brace: usize, body_end: usize, function: usize) {
+        let Some(open) = (name..brace).find(|&j| matches!(self.tokens[j].token, Token::LParen))
+        else {
+            return;
+        };
+        let mut depth = 0;
+        for j in open..brace {
+            match &self.tokens[j].token {
+                Token::LParen | Token::LBracket | Token::LessThan => depth += 1,
+                Token::RParen | Token::RBracket | Token::GreaterThan => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Token::Identifier(parameter) if depth == 1 => {
+                    let after_separator = matches!(
+                        self.tokens[j - 1].token,
+                        Token::LParen | Token::Comma | Token::Star | Token::DoubleStar
+                    );
+                    if after_separator {
+                        let end = (j..brace)
+                            .find(|&k| {
+                                matches!(self.tokens[k + 1].token, Token::Comma | Token::RParen)
+                            })
+                            .unwrap_or(j);
+                        let detail = one_line(
+                            &self.text[self.tokens[j].span.start..self.tokens[end].span.end],
+                        );
+                        self.symbols.push(Symbol {
+                            name: parameter.clone(),
+                            kind: SymbolKind::Parameter,
+                            token: j,
+                            scope: (brace, body_end),
+                            end: j,
+                            detail: format!(
+                                "{} (parameter of {})",
+                                detail, self.symbols[function].name
+                            ),
+                            doc: String::new(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Where a statement without a body ends: before the next token on a later line
+    // that is not inside brackets opened by the statement
+    fn statement_end(&self, name: usize) -> usize {
+        let line = self.tokens[name].span.line;
+        let mut depth = 0i32;
+        let mut end = name;
+        for j in name + 1..self.tokens.len() {
+            let token = &self.tokens[j];
+            if depth == 0 && (token.span.line > line || self.blocks[j] != self.blocks[name]) {
+                break;
+            }
+            match token.token {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                _ => {}
+            }
+            end = j;
+        }
+        end
+    }
+
+    // Points every identifier at the declaration it uses
+    fn resolve(&mut self) {
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            self.targets.insert(symbol.token, i);
+        }
+        for j in 0..self.tokens.len() {
+            let Token::Identifier(name) = &self.tokens[j].token else {
+                continue;
+            };
+            if self.targets.contains_key(&j) {
+                continue;
+            }
+            // `object.name` is a field, and `f(name: value)` a keyword argument
+            let previous = j.checked_sub(1).map(|p| &self.tokens[p].token);
+            let next = self.tokens.get(j + 1).map(|t| &t.token);
+            if matches!(previous, Some(Token::Dot | Token::SafeNavigation))
+                || (matches!(next, Some(Token::Colon)) && self.in_call(j))
+            {
+                continue;
+            }
+
+            let target = self
+                .symbols
+                .iter()
+                .enumerate()
+                .filter(|(_, symbol)| {
+                    symbol.name == *name
+                        && symbol.scope.0 <= j
+                        && j <= symbol.scope.1
+                        && (symbol.kind.is_hoisted()
+                            || symbol.token < j
+                            || self.scope_of(j) != symbol.scope)
+                })
+                // The innermost scope, then the latest declaration in it
+                .max_by_key(|(_, symbol)| (symbol.scope.0, symbol.token))
+                .map(|(i, _)| i);
+            if let Some(target) = target {
+                self.targets.insert(j, target);
+            }
+        }
+    }
+
+    // Whether the innermost bracket around token `j` is a `(`
+    fn in_call(&self, j: usize) -> bool {
+        let mut depth = 0;
+        for token in self.tokens[..j].iter().rev() {
+            match token.token {
+                Token::RParen | Token::RBracket | Token::RBrace => depth += 1,
+                Token::LParen | Token::LBracket | Token::LBrace if depth > 0 => depth -= 1,
+                Token::LParen => return true,
+                Token::LBracket | Token::LBrace => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn check(&mut self, program: &Program) {
+        for error in TypeChecker::new().check(program) {
+            self.diagnose(&error);
+        }
+        for error in MemoryChecker::new(false).check_program(program) {
+            self.diagnostics.push(json!({
+                "range": self.range(error.span.start, error.span.end),
+                "severity": ERROR,
+                "source": "crabby",
+                "code": error.code,
+                "message": error.message,
+            }));
+        }
+
+        let Ok(warnings) = DeadCodeAnalyzer::new().analyze(program) else {
+            return;
+        };
+        for warning in warnings {
+            let range = warning.name_range(&self.text);
+            let mut diagnostic = json!({
+                "range": self.range(range.start, range.end),
+                "severity": WARNING,
+                "source": "crabby",
+                "code": warning.code(),
+                "message": warning.message(),
+            });
+            if !matches!(warning.kind, WarningKind::Shadowed { .. }) {
+                diagnostic["tags"] = json!([UNNECESSARY]);
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn diagnose(&mut self, error: &CrabbyError) {
+        let (range, message) = match error {
+            CrabbyError::LexerError(location)
+            | CrabbyError::ParserError(location)
+            | CrabbyError::MissingCaseKeyword(location) => {
+                (self.location_range(location), location.message.clone())
+            }
+            CrabbyError::TypeError(Some(span), message)
+            | CrabbyError::ExpansionError(span, message) => {
+                (self.range(span.start, span.end), message.clone())
+            }
+            error => (self.range(0, 0), error.to_string()),
+        };
+        self.diagnostics.push(json!({
+            "range": range,
+            "severity": ERROR,
+            "source": "crabby",
+            "message": message,
+        }));
+    }
+
+    // The token an error points at, or the character if it isn't at a token
+    fn location_range(&self, location: &ErrorLocation) -> Json {
+        let offset = self
+            .lines
+            .offset_of(&self.text, location.line, location.column);
+        match self.tokens.iter().find(|token| token.span.start == offset) {
+            Some(token) => self.range(token.span.start, token.span.end),
+            None => {
+                let end = self.text[offset..]
+                    .chars()
+                    .next()
+                    .map_or(offset, |c| offset + c.len_utf8());
+                self.range(offset, end)
+            }
+        }
+    }
+}
+
+// `def   add(a,\n b)` is shown as `def add(a, b)`
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Docstrings by the offset of their `def` or `fun`, from anywhere in the program
+fn docstrings(text: &str) -> HashMap<usize, String> {
+    let mut docs = HashMap::new();
+    if let Ok(program) = TokenStream::tokenize(text.to_string()).and_then(parse) {
+        for statement in &program.statements {
+            collect_docstrings(statement, &mut docs);
+        }
+    }
+    docs
+}
+
+fn collect_docstrings(statement: &Statement, docs: &mut HashMap<usize, String>) {
+    match statement {
+        Statement::FunctionDef {
+            docstring,
+            span,
+            body,
+            ..
+        }
+        | Statement::FunctionFun {
+            docstring,
+            span,
+            body,
+            ..
+        } => {
+            if !docstring.is_empty() {
+                docs.insert(span.start, docstring.clone());
+            }
+            collect_docstrings(body, docs);
+        }
+        Statement::Block(statements)
+        | Statement::Class {
+            methods: statements,
+            ..
+        } => {
+            for statement in statements {
+                collect_docstrings(statement, docs);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,337 @@
+// `crabby lsp`: a language server speaking JSON-RPC over stdin and stdout
+// Editors send whole documents on every change; each one is lexed, parsed and
+// checked again, and its diagnostics are published right away.
+
+mod analysis;
+mod protocol;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{Value as Json, json};
+
+use crate::etc::config::Config;
+use crate::etc::formatter::{FormatOptions, format_source};
+use crate::interpreter::Interpreter;
+use crate::lexer::{Token, TokenStream};
+use crate::utils::CrabbyError;
+use analysis::{Document, KEYWORDS};
+use protocol::{
+    INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR, Position, REQUEST_FAILED,
+    ResponseError, SERVER_NOT_INITIALIZED,
+};
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+    // Names every program can use without declaring them
+    builtins: Vec<String>,
+    initialized: bool,
+    shutdown: bool,
+}
+
+// Serves stdin until the client sends `exit`; returns the process's exit code
+pub fn run() -> Result<i32, CrabbyError> {
+    let stdin = io::stdin();
+    Server::new(io::stdout()).serve(&mut stdin.lock())
+}
+
+impl<W: Write> Server<W> {
+    fn new(output: W) -> Self {
+        let mut builtins = Interpreter::new(None).names();
+        builtins.sort();
+        Self {
+            output,
+            documents: HashMap::new(),
+            builtins,
+            initialized: false,
+            shutdown: false,
+        }
+    }
+
+    fn serve(&mut self, input: &mut impl BufRead) -> Result<i32, CrabbyError> {
+        while let Some(body) = protocol::read_message(input)? {
+            let message: Json = match serde_json::from_str(&body) {
+                Ok(message) => message,
+                Err(e) => {
+                    let error = ResponseError::new(PARSE_ERROR, e.to_string());
+                    self.send(protocol::response(&Json::Null, Err(error)))?;
+                    continue;
+                }
+            };
+            let method = message.get("method").and_then(Json::as_str);
+            let params = message.get("params").cloned().unwrap_or(Json::Null);
+            match (method, message.get("id")) {
+                (Some("exit"), _) => return Ok(if self.shutdown { 0 } else { 1 }),
+                (Some(method), Some(id)) => {
+                    let result = self.request(method, &params);
+                    self.send(protocol::response(id, result))?;
+                }
+                (Some(method), None) => self.notification(method, &params)?,
+                // A response to a request we never make
+                (None, Some(_)) => {}
+                (None, None) => {
+                    let error = ResponseError::new(INVALID_REQUEST, "Message has no method");
+                    self.send(protocol::response(&Json::Null, Err(error)))?;
+                }
+            }
+        }
+        // The client went away without `shutdown` and `exit`
+        Ok(1)
+    }
+
+    fn send(&mut self, message: Json) -> Result<(), CrabbyError> {
+        Ok(protocol::write_message(&mut self.output, &message)?)
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, ResponseError> {
+        if !self.initialized && method != "initialize" {
+            return Err(ResponseError::new(
+                SERVER_NOT_INITIALIZED,
+                "The server has not been initialized",
+            ));
+        }
+        match method {
+            "initialize" => {
+                self.initialized = true;
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": 1, // the whole document on every change
+                        "definitionProvider": true,
+                        "referencesProvider": true,
+                        "hoverProvider": true,
+                        "completionProvider": { "triggerCharacters": [] },
+                        "documentSymbolProvider": true,
+                        "renameProvider": true,
+                        "documentFormattingProvider": true,
+                    },
+                    "serverInfo": { "name": "crabby", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/rename" => self.rename(params),
+            "textDocument/formatting" => self.formatting(params),
+            _ => Err(ResponseError::new(
+                METHOD_NOT_FOUND,
+                format!("Unsupported method '{}'", method),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Result<(), CrabbyError> {
+        let Some(uri) = params["textDocument"]["uri"].as_str() else {
+            return Ok(());
+        };
+        let uri = uri.to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.update(uri, text.to_string())
+            }
+            // With full sync, the last change holds the whole text
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                match changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    Some(text) => self.update(uri, text.to_string()),
+                    None => Ok(()),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.send(protocol::notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: String, text: String) -> Result<(), CrabbyError> {
+        let document = Document::new(text);
+        let diagnostics = json!({ "uri": uri, "diagnostics": document.diagnostics });
+        self.documents.insert(uri, document);
+        self.send(protocol::notification(
+            "textDocument/publishDiagnostics",
+            diagnostics,
+        ))
+    }
+
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), ResponseError> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, "Missing textDocument.uri"))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, format!("'{}' is not open", uri)))?;
+        Ok((uri, document))
+    }
+
+    // The document and the byte offset of `params.position`
+    fn position<'a>(
+        &'a self,
+        params: &'a Json,
+    ) -> Result<(&'a str, &'a Document, usize), ResponseError> {
+        let (uri, document) = self.document(params)?;
+        let position = Position::from_json(&params["position"])
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, "Missing position"))?;
+        Ok((
+            uri,
+            document,
+            document.lines.offset(&document.text, position),
+        ))
+    }
+
+    fn definition(&self, params: &Json) -> Result<Json, ResponseError> {
+        let (uri, document, offset) = self.position(params)?;
+        Ok(match document.symbol_at(offset) {
+            Some(symbol) => json!({
+                "uri": uri,
+                "range": document.token_range(document.symbols[symbol].token),
+            }),
+            None => Json::Null,
+        })
+    }
+
+    fn references(&self, params: &Json) -> Result<Json, ResponseError> {
+        let (uri, document, offset) = self.position(params)?;
+        let include_declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+        let Some(symbol) = document.symbol_at(offset) else {
+            return Ok(Json::Null);
+        };
+        let locations: Vec<Json> = document
+            .references(symbol, include_declaration)
+            .into_iter()
+            .map(|token| json!({ "uri": uri, "range": document.token_range(token) }))
+            .collect();
+        Ok(json!(locations))
+    }
+
+    fn hover(&self, params: &Json) -> Result<Json, ResponseError> {
+        let (_, document, offset) = self.position(params)?;
+        let Some(symbol) = document.symbol_at(offset) else {
+            return Ok(Json::Null);
+        };
+        let symbol = &document.symbols[symbol];
+        let mut value = format!("```crabby\n{}\n```", symbol.detail);
+        if !symbol.doc.is_empty() {
+            value.push_str("\n\n");
+            value.push_str(&symbol.doc);
+        }
+        Ok(json!({ "contents": { "kind": "markdown", "value": value } }))
+    }
+
+    fn completion(&self, params: &Json) -> Result<Json, ResponseError> {
+        let (_, document, offset) = self.position(params)?;
+        let mut seen = std::collections::HashSet::new();
+        let mut items = Vec::new();
+        // Inner declarations shadow outer ones with the same name
+        for symbol in document.visible(offset) {
+            if seen.insert(symbol.name.clone()) {
+                items.push(json!({
+                    "label": symbol.name,
+                    "kind": symbol.kind.completion_number(),
+                    "detail": symbol.detail,
+                    "documentation": symbol.doc,
+                }));
+            }
+        }
+        for builtin in &self.builtins {
+            if seen.insert(builtin.clone()) {
+                items.push(json!({ "label": builtin, "kind": 3, "detail": "builtin" }));
+            }
+        }
+        for keyword in KEYWORDS {
+            items.push(json!({ "label": keyword, "kind": 14 }));
+        }
+        Ok(json!(items))
+    }
+
+    fn document_symbols(&self, params: &Json) -> Result<Json, ResponseError> {
+        let (_, document) = self.document(params)?;
+        Ok(json!(outline(document, None)))
+    }
+
+    fn rename(&self, params: &Json) -> Result<Json, ResponseError> {
+        let (uri, document, offset) = self.position(params)?;
+        let new_name = params["newName"]
+            .as_str()
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, "Missing newName"))?;
+        if !is_identifier(new_name) {
+            return Err(ResponseError::new(
+                INVALID_PARAMS,
+                format!("'{}' is not a valid name", new_name),
+            ));
+        }
+        let symbol = document.symbol_at(offset).ok_or_else(|| {
+            ResponseError::new(REQUEST_FAILED, "There is no symbol to rename here")
+        })?;
+        let edits: Vec<Json> = document
+            .references(symbol, true)
+            .into_iter()
+            .map(|token| json!({ "range": document.token_range(token), "newText": new_name }))
+            .collect();
+        Ok(json!({ "changes": { uri: edits } }))
+    }
+
+    fn formatting(&self, params: &Json) -> Result<Json, ResponseError> {
+        let (uri, document) = self.document(params)?;
+        // Settings come from the crabby.toml above the file, as with `crabby fmt`
+        let config = match protocol::uri_to_path(uri) {
+            Some(path) => Config::find(&path),
+            None => Ok(Config::default()),
+        };
+        let formatted = config
+            .and_then(|config| FormatOptions::from_config(&config))
+            .and_then(|options| format_source(&document.text, &options))
+            .map_err(|e| ResponseError::new(REQUEST_FAILED, e.to_string()))?;
+        if formatted == document.text {
+            return Ok(json!([]));
+        }
+        Ok(json!([{
+            "range": document.range(0, document.text.len()),
+            "newText": formatted,
+        }]))
+    }
+}
+
+// `DocumentSymbol`s for the declarations directly inside `parent`, with their own inside them
+fn outline(document: &Document, parent: Option<usize>) -> Vec<Json> {
+    document
+        .children(parent)
+        .into_iter()
+        .map(|i| {
+            let symbol = &document.symbols[i];
+            json!({
+                "name": symbol.name,
+                "detail": symbol.detail,
+                "kind": symbol.kind.symbol_number(),
+                "range": document.symbol_range(symbol),
+                "selectionRange": document.token_range(symbol.token),
+                "children": outline(document, Some(i)),
+            })
+        })
+        .collect()
+}
+
+// A name the lexer reads as a single identifier, so not a keyword
+fn is_identifier(name: &str) -> bool {
+    matches!(
+        TokenStream::tokenize(name.to_string()).as_deref(),
+        Ok([token]) if matches!(token.token, Token::Identifier(_)) && token.slice == name
+    )
+}
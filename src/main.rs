@@ -1,20 +1,33 @@
-use crate::etc::deadcode::DeadCodeAnalyzer;
-use crate::parser::*;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use crabby::core::bindings;
+use crabby::core::memory::{self, MemoryChecker};
+use crabby::core::metaprogram::expand_macros;
+use crabby::core::sandbox::{Capabilities, Grant};
+use crabby::etc::bytecode::Bytecode;
+use crabby::etc::config::Config;
+use crabby::etc::deadcode::{self, DeadCodeAnalyzer};
+use crabby::etc::docgen::{self, Documentation};
+use crabby::etc::formatter::{FormatOptions, format_source, unified_diff};
+use crabby::etc::lint::{self, Level, Linter};
+use crabby::etc::printer::print_program;
+use crabby::etc::testing::{self, TestCase, TestOutcome};
+use crabby::etc::typechecker::TypeChecker;
+use crabby::parser::*;
+use crabby::runtime::Limits;
+use crabby::utils::CrabbyError;
+use crabby::value::Value;
+use crabby::{ast, interpreter, lexer, lsp, repl};
 use std::fs;
-use std::path::PathBuf;
-
-mod ast;
-mod core;
-mod etc;
-mod interpreter;
-mod lexer;
-mod modules;
-mod parser;
-mod repl;
-mod runtime;
-mod utils;
-mod value;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Exit codes shared by every command
+const EXIT_FAILURE: i32 = 1; // the program has errors, or a check or a test failed
+const EXIT_USAGE: i32 = 2; // bad arguments or an unreadable file, as with clap's own errors
+const EXIT_RUNTIME: i32 = 3; // the program stopped with a runtime error
+
+// Runs one test or doctest of `crabby test`, giving why it failed
+type DocRun = Box<dyn Fn() -> Result<(), String>>;
 
 #[derive(Parser)]
 #[command(name = "crabby")]
@@ -22,50 +35,892 @@ mod value;
 #[command(about = "Crabby Programming Language Interpreter", long_about=None)]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(disable_version_flag = true)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
-    #[arg(help = "Input .crab or .cb file")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(help = "Input .crab or .cb file to run; without one, starts a REPL")]
     input: Option<PathBuf>,
 
     #[arg(short, long, help = "Show version information")]
     version: bool,
 
-    #[arg(long, help = "Analyze code for unused declarations")]
+    #[command(flatten)]
+    options: RunOptions,
+}
+
+#[derive(Args)]
+struct RunOptions {
+    #[arg(long, help = "Analyze code for unused declarations before running it")]
     deadcodewarn: bool,
-    // #[arg(help = "REPL playground to test Crabby")]
-    // repl: String,
+
+    #[arg(
+        long,
+        help = "Refuse to run a program with dead-code warnings; implies --deadcodewarn"
+    )]
+    deny_warnings: bool,
+
+    #[arg(long, help = "Make integer overflow an error instead of a big integer")]
+    strict_numeric: bool,
+
+    #[command(flatten)]
+    ownership: OwnershipOptions,
+
+    #[command(flatten)]
+    limits: LimitOptions,
+
+    #[command(flatten)]
+    sandbox: SandboxOptions,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    unsafe { backtrace_on_stack_overflow::enable() };
-    let cli = Cli::parse();
+// For running code that isn't trusted; the program stops when it goes over a limit
+#[derive(Args)]
+struct LimitOptions {
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Stop after N iterations of all loops together"
+    )]
+    max_loop_iterations: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Stop when function calls nest more than N deep"
+    )]
+    max_call_depth: Option<usize>,
+
+    #[arg(
+        long = "max-heap",
+        value_name = "BYTES",
+        help = "Stop when the values made add up to more than BYTES"
+    )]
+    max_heap_bytes: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Stop when a string grows longer than BYTES"
+    )]
+    max_string_length: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Stop when an array or dictionary grows longer than N"
+    )]
+    max_array_length: Option<usize>,
+
+    #[arg(long, value_name = "SECONDS", help = "Stop after running for SECONDS")]
+    timeout: Option<f64>,
+}
+
+impl LimitOptions {
+    fn limits(&self) -> Result<Limits, String> {
+        let timeout = match self.timeout {
+            Some(seconds) => Some(
+                Duration::try_from_secs_f64(seconds)
+                    .map_err(|_| format!("invalid --timeout {}", seconds))?,
+            ),
+            None => None,
+        };
+        Ok(Limits {
+            max_loop_iterations: self.max_loop_iterations,
+            max_call_depth: self.max_call_depth,
+            max_heap_bytes: self.max_heap_bytes,
+            max_string_length: self.max_string_length,
+            max_array_length: self.max_array_length,
+            timeout,
+        })
+    }
+}
+
+// What the program may reach outside of the interpreter; each flag grants everything
+// on its own, or only what is listed, as in `--allow-read=./data,./config`
+#[derive(Args)]
+struct SandboxOptions {
+    #[arg(
+        long,
+        value_name = "PATHS",
+        num_args = 0..,
+        require_equals = true,
+        value_delimiter = ',',
+        help = "Let the program read files, or only those under PATHS"
+    )]
+    allow_read: Option<Vec<PathBuf>>,
+
+    #[arg(
+        long,
+        value_name = "PATHS",
+        num_args = 0..,
+        require_equals = true,
+        value_delimiter = ',',
+        help = "Let the program write files, or only those under PATHS"
+    )]
+    allow_write: Option<Vec<PathBuf>>,
+
+    #[arg(
+        long,
+        value_name = "HOSTS",
+        num_args = 0..,
+        require_equals = true,
+        value_delimiter = ',',
+        help = "Let the program use the network, or only HOSTS (`host` or `host:port`)"
+    )]
+    allow_net: Option<Vec<String>>,
+
+    #[arg(long, help = "Let the program read environment variables")]
+    allow_env: bool,
+
+    #[arg(long, help = "Let the program run other programs")]
+    allow_run: bool,
+
+    #[arg(long, help = "Let the program load shared libraries")]
+    allow_ffi: bool,
+}
+
+impl SandboxOptions {
+    fn capabilities(&self) -> Capabilities {
+        fn grant<T: Clone>(flag: &Option<Vec<T>>) -> Grant<T> {
+            match flag {
+                None => Grant::Nothing,
+                Some(items) if items.is_empty() => Grant::Everything,
+                Some(items) => Grant::Only(items.clone()),
+            }
+        }
+        Capabilities {
+            read: grant(&self.allow_read),
+            write: grant(&self.allow_write),
+            net: grant(&self.allow_net),
+            env: self.allow_env,
+            run: self.allow_run,
+            ffi: self.allow_ffi,
+        }
+    }
+}
+
+#[derive(Args)]
+struct OwnershipOptions {
+    #[arg(
+        long = "ownership",
+        default_value = "relaxed",
+        value_parser = ["relaxed", "strict"],
+        help = "How to check ownership; `strict` also moves a variable assigned to another"
+    )]
+    mode: String,
+}
+
+impl OwnershipOptions {
+    fn strict(&self) -> bool {
+        self.mode == "strict"
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Run a program")]
+    Run {
+        #[arg(help = "Input .crab or .cb file")]
+        input: PathBuf,
+
+        #[command(flatten)]
+        options: RunOptions,
+
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            help = "Arguments for the program, available to it as `args`"
+        )]
+        args: Vec<String>,
+    },
+    #[command(about = "Parse, type-check and look for dead code without running a program")]
+    Check {
+        #[arg(help = "Input .crab or .cb file")]
+        input: PathBuf,
+
+        #[arg(long, help = "Fail when there are dead-code warnings")]
+        deny_warnings: bool,
+
+        #[command(flatten)]
+        ownership: OwnershipOptions,
+    },
+    #[command(about = "Print a program with its macros expanded")]
+    Expand {
+        #[arg(help = "Input .crab or .cb file")]
+        input: PathBuf,
+    },
+    #[command(about = "Rewrite source files in the standard layout")]
+    Fmt {
+        #[arg(default_value = ".", help = "Files or directories to format")]
+        paths: Vec<PathBuf>,
+
+        #[arg(
+            long,
+            help = "List the files that need formatting instead of changing them"
+        )]
+        check: bool,
+
+        #[arg(long, help = "Print the changes as a diff instead of making them")]
+        diff: bool,
+    },
+    #[command(about = "Look for code that is likely a mistake or hard to read")]
+    Lint {
+        #[arg(default_value = ".", help = "Files or directories to lint")]
+        paths: Vec<PathBuf>,
+
+        #[arg(long, help = "Apply the suggested fixes to the files")]
+        fix: bool,
+    },
+    #[command(about = "Generate documentation from docstrings")]
+    Doc {
+        #[arg(help = "A .crab or .cb file, or a directory of them")]
+        input: PathBuf,
+
+        #[arg(long, default_value = "html", value_parser = ["html", "json", "markdown", "doublequotes"])]
+        format: String,
+
+        #[arg(
+            long,
+            default_value = "doc",
+            help = "Where the HTML site is written; other formats print to stdout"
+        )]
+        out: PathBuf,
+
+        #[arg(
+            long,
+            help = "Also document functions, structs, enums, classes and traits that are not `pub`"
+        )]
+        private: bool,
+    },
+    #[command(about = "Run the tests in *_test.crab files and report which ones fail")]
+    Test {
+        #[arg(
+            default_value = "tests",
+            help = "A directory of tests, or a single file"
+        )]
+        path: PathBuf,
+
+        #[arg(long, help = "Only run tests whose name contains this text")]
+        filter: Option<String>,
+
+        #[arg(long, help = "Stop at the first failing test")]
+        fail_fast: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Also write the results as JUnit XML"
+        )]
+        junit: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Run the examples in docstrings of every source file instead"
+        )]
+        doc: bool,
+    },
+    #[command(
+        about = "Compile a program to crabvm bytecode (.cby); only variables, arithmetic and print compile for now"
+    )]
+    Build {
+        #[arg(help = "Input .crab or .cb file")]
+        input: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            help = "Output file; defaults to the input with a .cby extension"
+        )]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Show the bytecode of a .cby file, or of a program compiled on the fly")]
+    Disasm {
+        #[arg(help = "Input .cby, .crab or .cb file")]
+        input: PathBuf,
+    },
+    #[command(about = "Start a language server for editors, speaking LSP over stdin and stdout")]
+    Lsp,
+    #[command(about = "Generate a module of `extern` declarations from a C header")]
+    Bindgen {
+        #[arg(help = "C header file")]
+        header: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            help = "Library the functions are loaded from; defaults to the program itself"
+        )]
+        library: Option<String>,
+
+        #[arg(short, long, help = "Output .crab file; defaults to stdout")]
+        output: Option<PathBuf>,
+    },
+}
+
+// Reads and parses a source file
+fn parse_file(input: &Path) -> Result<ast::Program, CrabbyError> {
+    let source = read_source(input)?;
+    parse(lexer::TokenStream::tokenize(source)?)
+}
+
+// File errors name the file, since a bare "No such file or directory" could be any of them
+fn read_source(path: &Path) -> Result<String, CrabbyError> {
+    fs::read_to_string(path).map_err(|e| file_error(path, e))
+}
+
+fn file_error(path: &Path, error: std::io::Error) -> CrabbyError {
+    CrabbyError::IoError(format!("{}: {}", path.display(), error))
+}
+
+// Reads, parses and macro-expands a source file
+fn load_program(input: &Path) -> Result<ast::Program, CrabbyError> {
+    expand_macros(parse_file(input)?)
+}
+
+// Prints an error that stopped a program from loading
+fn load_failure(error: CrabbyError) -> i32 {
+    eprintln!("{}", error);
+    match error {
+        CrabbyError::IoError(_) => EXIT_USAGE,
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn run(input: &Path, options: &RunOptions, args: Vec<String>) -> i32 {
+    let ast = match load_program(input) {
+        Ok(ast) => ast,
+        Err(e) => return load_failure(e),
+    };
+    if let Err(code) = check_ownership(input, &ast, options.ownership.strict()) {
+        return code;
+    }
+
+    // When used, it analyzes any dead & unused code before running it
+    if (options.deadcodewarn || options.deny_warnings)
+        && let Err(code) = report_dead_code(input, &ast, options.deny_warnings)
+    {
+        return code;
+    }
+
+    let absolute_path = match input.canonicalize() {
+        Ok(path) => path,
+        Err(e) => return load_failure(file_error(input, e)),
+    };
+    let limits = match options.limits.limits() {
+        Ok(limits) => limits,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let mut interpreter = new_interpreter(absolute_path, args);
+    interpreter.strict_numeric = options.strict_numeric;
+    if limits != Limits::default() {
+        interpreter.set_limits(limits);
+    }
+    interpreter.set_capabilities(options.sandbox.capabilities());
+    match interpreter.interpret(&ast) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_RUNTIME
+        }
+    }
+}
+
+// The program's command-line arguments are the list `args`
+fn new_interpreter(path: PathBuf, args: Vec<String>) -> interpreter::Interpreter {
+    let mut interpreter = interpreter::Interpreter::new(Some(path));
+    interpreter.env.insert(
+        "args".to_string(),
+        Value::Array(args.into_iter().map(Value::String).collect()),
+    );
+    interpreter
+}
+
+// Each source file under `input` is a module, named by its path from `input`
+fn doc(input: &Path, format: &str, out: &Path, private: bool) -> i32 {
+    let mut files = Vec::new();
+    if let Err(e) = collect_sources(input, &mut files) {
+        return load_failure(e.into());
+    }
+    files.sort();
 
-    if let Some(input) = cli.input {
-        let absolute_path = input.canonicalize().expect("Failed to get absolute path");
-        let source = fs::read_to_string(&absolute_path).expect("Failed to read file");
-        let tokens = lexer::TokenStream::tokenize(source);
-        let ast = parse(tokens.expect("Failed to parse token")).expect("Failed to parse AST");
-        let interpreter = interpreter::Interpreter::new(Some(absolute_path));
-        interpreter.interpret(&ast);
-
-        // Shows the version of Crabby
-        if cli.version {
-            println!("Crabby Version: {}", env!("CARGO_PKG_VERSION"));
-            return Ok(());
+    let mut documentation = Documentation::new(private);
+    for file in &files {
+        let program = match load_program(file) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                return EXIT_FAILURE;
+            }
+        };
+        let relative = match file.strip_prefix(input) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => Path::new(file.file_name().unwrap_or_default()),
+        };
+        documentation.add_module(&docgen::module_name(relative), &program);
+    }
+
+    match format {
+        "json" => println!("{:#}", documentation.to_json()),
+        "markdown" => print!("{}", documentation.to_markdown()),
+        "doublequotes" => print!("{}", documentation.to_double_quotes()),
+        _ => {
+            if let Err(e) = documentation.write_html(out) {
+                eprintln!("{}: {}", out.display(), e);
+                return EXIT_USAGE;
+            }
+            println!("Documented {} module(s) in {}", files.len(), out.display());
+        }
+    }
+    0
+}
+
+fn check(input: &Path, deny_warnings: bool, ownership: &OwnershipOptions) -> i32 {
+    let ast = match load_program(input) {
+        Ok(ast) => ast,
+        Err(e) => return load_failure(e),
+    };
+    let errors = TypeChecker::new().check(&ast);
+    for error in &errors {
+        eprintln!("{}", error);
+    }
+    let owned = check_ownership(input, &ast, ownership.strict());
+    if let Err(code) = report_dead_code(input, &ast, deny_warnings) {
+        return code;
+    }
+    if errors.is_empty() && owned.is_ok() {
+        0
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+// Prints the ownership errors of a program, which keep it from running
+fn check_ownership(input: &Path, ast: &ast::Program, strict: bool) -> Result<(), i32> {
+    let errors = MemoryChecker::new(strict).check_program(ast);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let source = read_source(input).map_err(load_failure)?;
+    eprint!(
+        "{}",
+        memory::render(&errors, &input.display().to_string(), &source)
+    );
+    Err(EXIT_FAILURE)
+}
+
+// Prints the dead-code warnings for a program; with `deny`, they are errors that fail it
+fn report_dead_code(input: &Path, ast: &ast::Program, deny: bool) -> Result<(), i32> {
+    let warnings = DeadCodeAnalyzer::new().analyze(ast).map_err(load_failure)?;
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    let source = read_source(input).map_err(load_failure)?;
+    eprint!(
+        "{}",
+        deadcode::render(&warnings, &input.display().to_string(), &source, deny)
+    );
+    if deny {
+        eprintln!(
+            "error: {} dead-code warning(s) denied by --deny-warnings",
+            warnings.len()
+        );
+        return Err(EXIT_FAILURE);
+    }
+    Ok(())
+}
+
+// Runs each test in the *_test.crab files under `path` (or in the file `path`)
+// with a fresh interpreter, after the rest of its file. A file without tests
+// counts as one test that passes when the file runs without error. With
+// `doc`, the tests are instead the examples in the docstrings of every file.
+fn test(
+    path: &Path,
+    filter: Option<&str>,
+    fail_fast: bool,
+    junit: Option<&Path>,
+    doc: bool,
+) -> i32 {
+    // Running `crabby test` outside a project is the usual way to get here
+    if path == Path::new("tests") && !path.exists() {
+        eprintln!(
+            "No tests directory: ./tests does not exist. Put *_test.crab files there, or pass the directory or file to test"
+        );
+        return EXIT_USAGE;
+    }
+    let mut files = Vec::new();
+    if let Err(e) = collect_sources(path, &mut files) {
+        return load_failure(file_error(path, e));
+    }
+    if path.is_dir() && !doc {
+        files.retain(|file| testing::is_test_file(file));
+    }
+    files.sort();
+
+    let started = Instant::now();
+    let mut outcomes = Vec::new();
+    let mut filtered = 0;
+    'files: for file in &files {
+        let file_name = file.display().to_string();
+        let loaded = load_program(file).and_then(|program| {
+            let source = read_source(file)?;
+            let absolute = file.canonicalize().map_err(|e| file_error(file, e))?;
+            Ok((program, source, absolute))
+        });
+        let (program, source, absolute) = match loaded {
+            Ok(loaded) => loaded,
+            // An empty file has no examples to run
+            Err(CrabbyError::LexerError(location))
+                if doc && location.message == "Empty source file" =>
+            {
+                continue;
+            }
+            // The whole file fails when it does not parse
+            Err(e) => {
+                println!("test {} ... FAILED\n    {}", file_name, e);
+                outcomes.push(TestOutcome {
+                    file: file_name.clone(),
+                    name: file_name,
+                    duration: Default::default(),
+                    failure: Some(e.to_string()),
+                });
+                if fail_fast {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        // Each test, and how to run it
+        let mut runs: Vec<(String, DocRun)> = Vec::new();
+        if doc {
+            let setup = testing::declarations(&program);
+            for doctest in testing::collect_doctests(&program, &source) {
+                let setup = setup.clone();
+                let absolute = absolute.clone();
+                runs.push((
+                    doctest.name.clone(),
+                    Box::new(move || {
+                        let interpreter = new_interpreter(absolute.clone(), Vec::new());
+                        testing::run_doctest(&doctest, &setup, interpreter)
+                    }),
+                ));
+            }
+        } else {
+            let (setup, mut tests) = testing::collect_tests(&program);
+            if tests.is_empty() {
+                tests.push(TestCase {
+                    name: file_name.clone(),
+                    body: ast::Statement::Block(Vec::new()),
+                });
+            }
+            for test in tests {
+                let mut program = setup.clone();
+                program.statements.push(test.body);
+                let absolute = absolute.clone();
+                runs.push((
+                    test.name,
+                    Box::new(move || {
+                        new_interpreter(absolute.clone(), Vec::new())
+                            .interpret(&program)
+                            .map_err(|e| e.to_string())
+                    }),
+                ));
+            }
         }
 
-        // When used, it analyzes any dead & unused code
-        if cli.deadcodewarn {
-            let mut analyzer = DeadCodeAnalyzer::new();
-            let warnings = analyzer.analyze(&ast)?;
-            if !warnings.is_empty() {
-                println!("\nDead code warnings:");
-                for warning in warnings {
-                    println!("Warning: {:?}", warning);
+        for (name, run) in runs {
+            if filter.is_some_and(|filter| !name.contains(filter)) {
+                filtered += 1;
+                continue;
+            }
+            let test_started = Instant::now();
+            let result = run();
+            let outcome = TestOutcome {
+                file: file_name.clone(),
+                name,
+                duration: test_started.elapsed(),
+                failure: result.err(),
+            };
+            match &outcome.failure {
+                None => println!("test {}::{} ... ok", outcome.file, outcome.name),
+                Some(failure) => {
+                    println!("test {}::{} ... FAILED", outcome.file, outcome.name);
+                    for line in failure.lines() {
+                        println!("    {}", line);
+                    }
                 }
             }
+            let failed = outcome.failure.is_some();
+            outcomes.push(outcome);
+            if failed && fail_fast {
+                break 'files;
+            }
         }
     }
 
+    if let Some(junit) = junit
+        && let Err(e) = fs::write(junit, testing::junit_report(&outcomes))
+    {
+        eprintln!("{}: {}", junit.display(), e);
+        return EXIT_USAGE;
+    }
+
+    let failed = outcomes.iter().filter(|o| o.failure.is_some()).count();
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out; finished in {:.2}s",
+        if failed == 0 { "ok" } else { "FAILED" },
+        outcomes.len() - failed,
+        failed,
+        filtered,
+        started.elapsed().as_secs_f64()
+    );
+    if failed == 0 { 0 } else { EXIT_FAILURE }
+}
+
+fn collect_sources(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, files)?;
+        } else if matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("crab" | "cb")
+        ) {
+            files.push(path);
+        }
+    }
     Ok(())
 }
+
+// Formats files in place; `--check` and `--diff` only report, and fail when anything would change
+fn fmt(paths: &[PathBuf], check: bool, diff: bool) -> i32 {
+    let mut files = Vec::new();
+    for path in paths {
+        if let Err(e) = collect_sources(path, &mut files) {
+            eprintln!("{}: {}", path.display(), e);
+            return EXIT_USAGE;
+        }
+    }
+    files.sort();
+
+    let mut unformatted = 0;
+    let mut failed = false;
+    for file in &files {
+        let result = fs::read_to_string(file)
+            .map_err(CrabbyError::from)
+            .and_then(|source| {
+                let options = FormatOptions::from_config(&Config::find(file)?)?;
+                Ok((format_source(&source, &options)?, source))
+            });
+        let (formatted, source) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        unformatted += 1;
+        if diff {
+            print!(
+                "{}",
+                unified_diff(&file.display().to_string(), &source, &formatted)
+            );
+        } else if check {
+            println!("{}", file.display());
+        } else if let Err(e) = fs::write(file, formatted) {
+            eprintln!("{}: {}", file.display(), e);
+            failed = true;
+        }
+    }
+
+    if failed || ((check || diff) && unformatted > 0) {
+        EXIT_FAILURE
+    } else {
+        0
+    }
+}
+
+// Reports lints for each file and fails when one is denied; `--fix` rewrites the
+// files first and reports what is left
+fn lint(paths: &[PathBuf], fix: bool) -> i32 {
+    let mut files = Vec::new();
+    for path in paths {
+        if let Err(e) = collect_sources(path, &mut files) {
+            eprintln!("{}: {}", path.display(), e);
+            return EXIT_USAGE;
+        }
+    }
+    files.sort();
+
+    let mut denied = 0;
+    let mut fixable = 0;
+    let mut failed = false;
+    for file in &files {
+        let result = fs::read_to_string(file)
+            .map_err(CrabbyError::from)
+            .and_then(|mut source| {
+                let mut linter = Linter::default();
+                linter.configure(&Config::find(file)?)?;
+                let test_file = testing::is_test_file(file);
+                let mut lints = linter.lint(&source, test_file)?;
+                if fix && lints.iter().any(|lint| lint.fix.is_some()) {
+                    source = lint::apply_fixes(&source, &lints);
+                    fs::write(file, &source)?;
+                    lints = linter.lint(&source, test_file)?;
+                }
+                Ok((lints, source))
+            });
+        let (lints, source) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        denied += lints
+            .iter()
+            .filter(|lint| lint.level == Level::Deny)
+            .count();
+        fixable += lints.iter().filter(|lint| lint.fix.is_some()).count();
+        eprint!(
+            "{}",
+            lint::render(&lints, &file.display().to_string(), &source)
+        );
+    }
+    if fixable > 0 && !fix {
+        eprintln!("{} of these can be fixed with `crabby lint --fix`", fixable);
+    }
+
+    if failed || denied > 0 {
+        EXIT_FAILURE
+    } else {
+        0
+    }
+}
+
+fn build(input: &Path, output: Option<PathBuf>) -> i32 {
+    let bytecode = match load_program(input).and_then(|ast| Bytecode::compile(&ast)) {
+        Ok(bytecode) => bytecode,
+        Err(e) => return load_failure(e),
+    };
+    let output = output.unwrap_or_else(|| input.with_extension("cby"));
+    if let Err(e) = fs::write(&output, bytecode.to_bytes()) {
+        return load_failure(file_error(&output, e));
+    }
+    println!("Compiled {} to {}", input.display(), output.display());
+    0
+}
+
+fn disasm(input: &Path) -> i32 {
+    let bytecode = if input.extension().is_some_and(|e| e == "cby") {
+        fs::read(input)
+            .map_err(|e| file_error(input, e))
+            .and_then(|bytes| Bytecode::from_bytes(&bytes))
+    } else {
+        load_program(input).and_then(|ast| Bytecode::compile(&ast))
+    };
+    match bytecode {
+        Ok(bytecode) => {
+            print!("{}", bytecode.disassemble());
+            0
+        }
+        Err(e) => load_failure(e),
+    }
+}
+
+fn bindgen(header: &Path, library: Option<&str>, output: Option<&Path>) -> i32 {
+    let module = match bindings::generate(header, library) {
+        Ok(module) => module,
+        Err(e) => return load_failure(e),
+    };
+    match output {
+        Some(output) => {
+            if let Err(e) = fs::write(output, module) {
+                return load_failure(e.into());
+            }
+            println!("Generated {} from {}", output.display(), header.display());
+        }
+        None => print!("{}", module),
+    }
+    0
+}
+
+#[tokio::main]
+async fn main() {
+    unsafe { backtrace_on_stack_overflow::enable() };
+    let cli = Cli::parse();
+
+    // Shows the version of Crabby
+    if cli.version {
+        println!("Crabby Version: {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
+    let code = match cli.command {
+        Some(Command::Run {
+            input,
+            options,
+            args,
+        }) => run(&input, &options, args),
+        Some(Command::Check {
+            input,
+            deny_warnings,
+            ownership,
+        }) => check(&input, deny_warnings, &ownership),
+        Some(Command::Expand { input }) => match load_program(&input) {
+            Ok(expanded) => {
+                print!("{}", print_program(&expanded));
+                0
+            }
+            Err(e) => load_failure(e),
+        },
+        Some(Command::Fmt { paths, check, diff }) => fmt(&paths, check, diff),
+        Some(Command::Lint { paths, fix }) => lint(&paths, fix),
+        Some(Command::Doc {
+            input,
+            format,
+            out,
+            private,
+        }) => doc(&input, &format, &out, private),
+        Some(Command::Test {
+            path,
+            filter,
+            fail_fast,
+            junit,
+            doc,
+        }) => test(&path, filter.as_deref(), fail_fast, junit.as_deref(), doc),
+        Some(Command::Build { input, output }) => build(&input, output),
+        Some(Command::Disasm { input }) => disasm(&input),
+        Some(Command::Lsp) => match lsp::run() {
+            Ok(code) => code,
+            Err(e) => load_failure(e),
+        },
+        Some(Command::Bindgen {
+            header,
+            library,
+            output,
+        }) => bindgen(&header, library.as_deref(), output.as_deref()),
+        // `crabby file.crab` is short for `crabby run file.crab`
+        None => match cli.input {
+            Some(input) => run(&input, &cli.options, Vec::new()),
+            None => match repl::run(cli.options.strict_numeric) {
+                Ok(()) => 0,
+                Err(e) => load_failure(e),
+            },
+        },
+    };
+    std::process::exit(code);
+}
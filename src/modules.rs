@@ -1,10 +1,10 @@
 // Module handler for Crabby's import && export system
 
-use crate::fs;
 use crate::lexer::TokenStream;
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::rc::Rc;
 
 use crate::interpreter::Interpreter;
 use crate::parser::*;
@@ -12,7 +12,7 @@ use crate::utils::CrabbyError;
 use crate::value::Value;
 
 pub struct ModuleCache {
-    loaded: HashMap<PathBuf, Arc<Module>>,
+    loaded: HashMap<PathBuf, Rc<Module>>,
     loading: HashSet<PathBuf>,
 }
 
@@ -24,6 +24,12 @@ pub struct Module {
     pub exports: HashMap<String, Value>,
 }
 
+impl Default for Module {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Module {
     pub fn new() -> Self {
         Self {
@@ -53,9 +59,9 @@ impl Module {
 
     pub fn resolve_path(current_file: &Path, import_path: &str) -> PathBuf {
         if let Some(current_dir) = current_file.parent() {
-            if import_path.starts_with("./") {
+            if let Some(relative) = import_path.strip_prefix("./") {
                 // Handle explicit relative path
-                current_dir.join(&import_path[2..])
+                current_dir.join(relative)
             } else if import_path.starts_with("../") {
                 // Handle parent directory reference
                 current_dir.join(import_path)
@@ -73,7 +79,7 @@ impl Module {
         cache: &mut ModuleCache,
         current_file: &Path,
         source: &str,
-    ) -> Result<Arc<Module>, CrabbyError> {
+    ) -> Result<Rc<Module>, CrabbyError> {
         let resolved_path = Module::resolve_path(current_file, source);
 
         if let Some(module) = cache.loaded.get(&resolved_path) {
@@ -105,7 +111,7 @@ impl Module {
         let private_items = interpreter.module.private_items.clone();
         interpreter.interpret(&ast)?;
 
-        let module = Arc::new(Module {
+        let module = Rc::new(Module {
             exports,
             variable,
             public_items,
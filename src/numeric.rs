@@ -0,0 +1,250 @@
+// Crabby's numbers: an `Integer` is an i64 that grows into a big integer instead
+// of overflowing (or fails, in strict numeric mode), and a `Float` is an f64
+
+use std::cmp::Ordering;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use crate::ast::BinaryOp;
+use crate::utils::CrabbyError;
+use crate::value::Value;
+
+pub fn is_number(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Integer(_) | Value::BigInt(_) | Value::Float(_)
+    )
+}
+
+// Big integers that fit in 64 bits go back to being plain integers
+pub fn normalize(n: BigInt) -> Value {
+    match n.to_i64() {
+        Some(n) => Value::Integer(n),
+        None => Value::BigInt(n),
+    }
+}
+
+// Arithmetic, bitwise and shift operators on two numbers
+pub fn binary(
+    operator: &BinaryOp,
+    left: &Value,
+    right: &Value,
+    strict: bool,
+) -> Result<Value, CrabbyError> {
+    if let (Value::Integer(l), Value::Integer(r)) = (left, right)
+        && let Some(result) = small_integer(operator, *l, *r)?
+    {
+        return Ok(Value::Integer(result));
+    }
+
+    let result = match (to_big(left), to_big(right)) {
+        (Some(l), Some(r)) => big_integer(operator, l, r)?,
+        _ => return float(operator, to_f64(left), to_f64(right)),
+    };
+
+    if strict && matches!(result, Value::BigInt(_)) {
+        return Err(CrabbyError::RuntimeError(format!(
+            "Integer overflow: {} {} {} does not fit in 64 bits",
+            left, operator, right
+        )));
+    }
+    Ok(result)
+}
+
+// About how many bits an integer `**` or `<<` will need, so a limit can refuse it
+// before the work starts; `None` for everything else
+pub fn result_bits(operator: &BinaryOp, left: &Value, right: &Value) -> Option<u64> {
+    if !matches!(operator, BinaryOp::Pow | BinaryOp::ShiftLeft) {
+        return None;
+    }
+    let (l, r) = (to_big(left)?, to_big(right)?.to_u64()?);
+    match operator {
+        BinaryOp::Pow => {
+            let magnitude = l.abs();
+            let log2 = match magnitude.to_f64() {
+                Some(m) if m.is_finite() => m.log2().max(0.0),
+                _ => magnitude.bits() as f64,
+            };
+            Some((r as f64 * log2).min(u64::MAX as f64) as u64)
+        }
+        BinaryOp::ShiftLeft if !l.is_zero() => Some(l.bits().saturating_add(r)),
+        _ => None,
+    }
+}
+
+pub fn negate(value: &Value, strict: bool) -> Result<Value, CrabbyError> {
+    match value {
+        Value::Integer(n) => match n.checked_neg() {
+            Some(n) => Ok(Value::Integer(n)),
+            None if strict => Err(CrabbyError::RuntimeError(format!(
+                "Integer overflow: -({}) does not fit in 64 bits",
+                n
+            ))),
+            None => Ok(Value::BigInt(-BigInt::from(*n))),
+        },
+        Value::BigInt(n) => Ok(normalize(-n)),
+        Value::Float(f) => Ok(Value::Float(-f)),
+        other => Err(CrabbyError::TypeError(
+            None,
+            format!("Cannot negate {}", other.type_name()),
+        )),
+    }
+}
+
+pub fn bit_not(value: &Value) -> Result<Value, CrabbyError> {
+    match value {
+        Value::Integer(n) => Ok(Value::Integer(!n)),
+        Value::BigInt(n) => Ok(normalize(!n)),
+        other => Err(CrabbyError::TypeError(
+            None,
+            format!("Cannot apply '~' to {}", other.type_name()),
+        )),
+    }
+}
+
+// `None` only when a float is NaN
+pub fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Integer(l), Value::Integer(r)) => Some(l.cmp(r)),
+        (l, r) => match (to_big(l), to_big(r)) {
+            (Some(l), Some(r)) => Some(l.cmp(&r)),
+            _ => to_f64(l).partial_cmp(&to_f64(r)),
+        },
+    }
+}
+
+// The i64 fast path; `None` means the result needs a big integer or a float
+fn small_integer(operator: &BinaryOp, l: i64, r: i64) -> Result<Option<i64>, CrabbyError> {
+    if matches!(operator, BinaryOp::Div | BinaryOp::Mod) && r == 0 {
+        return Err(division_by_zero());
+    }
+    if matches!(operator, BinaryOp::ShiftLeft | BinaryOp::ShiftRight) && r < 0 {
+        return Err(negative_shift(r));
+    }
+
+    Ok(match operator {
+        BinaryOp::Add => l.checked_add(r),
+        BinaryOp::Sub => l.checked_sub(r),
+        BinaryOp::Mul => l.checked_mul(r),
+        BinaryOp::Div => l.checked_div(r),
+        BinaryOp::Mod if r == -1 => Some(0),
+        BinaryOp::Mod => Some(l.mod_floor(&r)),
+        BinaryOp::Pow => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+        BinaryOp::BitAnd => Some(l & r),
+        BinaryOp::BitOr => Some(l | r),
+        BinaryOp::BitXor => Some(l ^ r),
+        BinaryOp::ShiftLeft if r < 63 => Some(l << r).filter(|shifted| shifted >> r == l),
+        BinaryOp::ShiftLeft => None,
+        BinaryOp::ShiftRight => Some(l >> r.min(63)),
+        _ => return Err(invalid_operator(operator)),
+    })
+}
+
+fn big_integer(operator: &BinaryOp, l: BigInt, r: BigInt) -> Result<Value, CrabbyError> {
+    if matches!(operator, BinaryOp::Div | BinaryOp::Mod) && r.is_zero() {
+        return Err(division_by_zero());
+    }
+
+    let result = match operator {
+        BinaryOp::Add => l + r,
+        BinaryOp::Sub => l - r,
+        BinaryOp::Mul => l * r,
+        BinaryOp::Div => l / r,
+        BinaryOp::Mod => l.mod_floor(&r),
+        // `2 ** -1` is 0.5
+        BinaryOp::Pow if r.is_negative() => {
+            return float(operator, to_f64(&normalize(l)), to_f64(&normalize(r)));
+        }
+        BinaryOp::Pow => match r.to_u32() {
+            Some(r) => l.pow(r),
+            None => {
+                return Err(CrabbyError::RuntimeError(format!(
+                    "Exponent {} is too large",
+                    r
+                )));
+            }
+        },
+        BinaryOp::BitAnd => l & r,
+        BinaryOp::BitOr => l | r,
+        BinaryOp::BitXor => l ^ r,
+        BinaryOp::ShiftLeft | BinaryOp::ShiftRight if r.is_negative() => {
+            return Err(CrabbyError::RuntimeError(format!(
+                "Negative shift count: {}",
+                r
+            )));
+        }
+        BinaryOp::ShiftLeft => match r.to_u32() {
+            Some(r) => l << r,
+            None => {
+                return Err(CrabbyError::RuntimeError(format!(
+                    "Shift count {} is too large",
+                    r
+                )));
+            }
+        },
+        // Shifting right by more than the number's width leaves 0 or -1
+        BinaryOp::ShiftRight => match r.to_u64() {
+            Some(r) => l >> r,
+            None if l.is_negative() => BigInt::from(-1),
+            None => BigInt::zero(),
+        },
+        _ => return Err(invalid_operator(operator)),
+    };
+
+    Ok(normalize(result))
+}
+
+fn float(operator: &BinaryOp, l: f64, r: f64) -> Result<Value, CrabbyError> {
+    if matches!(operator, BinaryOp::Div | BinaryOp::Mod) && r == 0.0 {
+        return Err(division_by_zero());
+    }
+
+    let result = match operator {
+        BinaryOp::Add => l + r,
+        BinaryOp::Sub => l - r,
+        BinaryOp::Mul => l * r,
+        BinaryOp::Div => l / r,
+        // The result takes the sign of the divisor, like integer `%`
+        BinaryOp::Mod => l - r * (l / r).floor(),
+        BinaryOp::Pow => l.powf(r),
+        _ => {
+            return Err(CrabbyError::TypeError(
+                None,
+                format!("Cannot apply '{}' to a float", operator),
+            ));
+        }
+    };
+
+    Ok(Value::Float(result))
+}
+
+fn to_big(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Integer(n) => Some(BigInt::from(*n)),
+        Value::BigInt(n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+fn to_f64(value: &Value) -> f64 {
+    match value {
+        Value::Integer(n) => *n as f64,
+        Value::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+        Value::Float(f) => *f,
+        _ => f64::NAN,
+    }
+}
+
+fn division_by_zero() -> CrabbyError {
+    CrabbyError::InterpreterError("Division by zero".to_string())
+}
+
+fn negative_shift(count: i64) -> CrabbyError {
+    CrabbyError::RuntimeError(format!("Negative shift count: {}", count))
+}
+
+fn invalid_operator(operator: &BinaryOp) -> CrabbyError {
+    CrabbyError::InterpreterError(format!("'{}' is not a numeric operator", operator))
+}
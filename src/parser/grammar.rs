@@ -0,0 +1,2019 @@
+use crate::ast::*;
+use crate::format::FormatSpec;
+use crate::lexer::{TemplatePiece, Token, TokenStream};
+use crate::utils::{CrabbyError, ErrorLocation, Span};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::collections::HashMap;
+
+pub struct Parser {
+    tokens: Vec<TokenStream>,
+    current: usize,
+    // `=>` separates a match pattern from its arm, so it cannot start a lambda there
+    in_pattern: bool,
+    // `///` comments, by the index of the token they come before
+    doc_comments: HashMap<usize, String>,
+    // Returned by `peek` past the last token, so nothing mistakes it for real input
+    eof: TokenStream,
+    // How many expressions and statements we are inside of, see `nested`
+    depth: usize,
+}
+
+// Deeper input is refused, since parsing it (and everything that walks the tree
+// afterwards) would overflow the stack instead of failing with an error. Each level
+// takes tens of kilobytes in a debug build, so this fits an 8 MiB main thread
+const MAX_NESTING: usize = 128;
+
+impl Parser {
+    pub fn new(tokens: Vec<TokenStream>) -> Self {
+        let mut doc_comments = HashMap::new();
+        let mut lines: Vec<String> = Vec::new();
+        let mut kept = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            if let Token::Comment(_) = token.token {
+                if let Some(line) = token.token.doc_comment() {
+                    lines.push(line.to_string());
+                }
+                continue;
+            }
+            if !lines.is_empty() {
+                doc_comments.insert(kept.len(), lines.join("\n"));
+                lines.clear();
+            }
+            kept.push(token);
+        }
+
+        // Errors at the end of input point at the last token
+        let eof = TokenStream {
+            token: Token::Eof,
+            span: kept.last().map(|last| last.span).unwrap_or_default(),
+            len: String::new(),
+            slice: String::new(),
+            source: String::new(),
+        };
+        Self {
+            tokens: kept,
+            current: 0,
+            in_pattern: false,
+            doc_comments,
+            eof,
+            depth: 0,
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Program, CrabbyError> {
+        let mut program = Program::new();
+        while !self.is_at_end() {
+            program.statements.push(self.parse_statement()?);
+        }
+        if let Some(Statement::Expression(Expression::String(text))) = program.statements.first() {
+            program.docstring = dedent(text);
+        }
+        Ok(program)
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<Parameter>, CrabbyError> {
+        self.consume(&Token::LParen, "Expected '(' after function name")?;
+        let mut params: Vec<Parameter> = Vec::new();
+
+        while !matches!(self.peek().token, Token::RParen) {
+            let kind = match self.peek().token {
+                Token::Star => {
+                    self.advance(); // consume '*'
+                    if matches!(self.peek().token, Token::Star) {
+                        self.advance(); // consume second '*'
+                        ParameterKind::KeywordVariadic
+                    } else {
+                        ParameterKind::Variadic
+                    }
+                }
+                Token::DoubleStar => {
+                    self.advance(); // consume '**'
+                    ParameterKind::KeywordVariadic
+                }
+                _ => ParameterKind::Positional,
+            };
+
+            let span = self.peek().span;
+            let name = if let Token::Identifier(name) = &self.peek().token {
+                name.clone()
+            } else {
+                return Err(self.error("Expected parameter name"));
+            };
+
+            if params.iter().any(|param| param.name == name) {
+                return Err(self.error(&format!("Duplicate parameter '{}'", name)));
+            }
+            if params
+                .iter()
+                .any(|param| param.kind == ParameterKind::KeywordVariadic)
+            {
+                return Err(self.error("No parameters may follow a '**' parameter"));
+            }
+            if kind == ParameterKind::Variadic
+                && params
+                    .iter()
+                    .any(|param| param.kind == ParameterKind::Variadic)
+            {
+                return Err(self.error("Only one '*' parameter is allowed"));
+            }
+            self.advance();
+
+            let type_annotation = if matches!(self.peek().token, Token::Colon) {
+                self.advance(); // consume ':'
+                Some(self.parse_type_annotation()?)
+            } else {
+                None
+            };
+
+            let default = if matches!(self.peek().token, Token::Equals) {
+                if kind != ParameterKind::Positional {
+                    return Err(self.error("Variadic parameters cannot have a default value"));
+                }
+                self.advance(); // consume '='
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+
+            // Parameters after `*args` are keyword-only, so they may skip defaults
+            let keyword_only = params
+                .iter()
+                .any(|param| param.kind == ParameterKind::Variadic);
+            if kind == ParameterKind::Positional
+                && default.is_none()
+                && !keyword_only
+                && params.iter().any(|param| param.default.is_some())
+            {
+                return Err(self.error(&format!(
+                    "Parameter '{}' without a default follows a parameter with a default",
+                    name
+                )));
+            }
+
+            params.push(Parameter {
+                name,
+                default,
+                kind,
+                type_annotation,
+                span,
+            });
+
+            if matches!(self.peek().token, Token::Comma) {
+                self.advance();
+            } else if !matches!(self.peek().token, Token::RParen) {
+                return Err(self.error("Expected ',' between parameters"));
+            }
+        }
+
+        self.consume(&Token::RParen, "Expected ')' after parameters")?;
+        Ok(params)
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, CrabbyError> {
+        self.nested(Self::parse_documented_statement)
+    }
+
+    fn parse_documented_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let doc_comment = self.doc_comments.remove(&self.current);
+        let mut statement = self.parse_undocumented_statement()?;
+        attach_doc_comment(&mut statement, doc_comment);
+        Ok(statement)
+    }
+
+    fn parse_undocumented_statement(&mut self) -> Result<Statement, CrabbyError> {
+        match &self.peek().token {
+            Token::Loop => self.parse_loop_statement(),
+            Token::For => self.parse_for_statement(),
+            Token::Import => self.parse_import_statement(),
+            Token::Def => self.parse_definition(),
+            Token::Function => self.parse_function(),
+            Token::Extern | Token::Foreign => self.parse_extern_statement(),
+            Token::Decorator => self.parse_decorated_statement(),
+            Token::Public | Token::Protect | Token::Private => self.parse_visible_statement(),
+            Token::Class => self.parse_class_statement(),
+            Token::Macro => self.parse_macro_statement(),
+            Token::TypeDef => self.parse_typedef_statement(),
+            Token::Let => self.parse_let_statement(),
+            Token::Variable => self.parse_var_statement(),
+            Token::Return => {
+                let span = self.peek().span;
+                self.advance(); // consume 'return'
+                let expr = self.parse_expression()?;
+                Ok(Statement::Return(Box::new(expr), span))
+            }
+            Token::Trait => self.parse_trait_statement(),
+            // Token::Implement => self.parse_impl_statement(),
+            // Token::Mutable => parse_mutable_statement(),
+            Token::Match => self.parse_match_statement(),
+            Token::And => self.parse_and_statement(),
+            Token::Enum => self.parse_enum_statement(),
+            Token::Struct => self.parse_struct_statement(),
+            Token::Where => self.parse_where_statement(),
+            Token::If => self.parse_if_statement(),
+            Token::While => self.parse_while_statement(),
+            Token::Async => self.parse_async_statement(),
+            Token::Assert => self.parse_assert_statement(),
+            // Token::Await => self.parse_await_statement(),
+            // `test` is only a keyword before a string
+            Token::Identifier(name)
+                if name == "test"
+                    && matches!(self.peek_next().map(|t| &t.token), Some(Token::String(_))) =>
+            {
+                self.parse_test_statement()
+            }
+            Token::Identifier(_) => {
+                let expr = self.parse_expression()?;
+
+                if matches!(self.peek().token, Token::LBracket) {
+                    self.advance(); // consume '['
+                    let index = self.parse_expression()?;
+                    self.consume(&Token::RBracket, "Expected ']' after array index")?;
+
+                    if matches!(self.peek().token, Token::Equals) {
+                        self.advance(); // consume '='
+                        let value = self.parse_expression()?;
+
+                        Ok(Statement::ArrayAssign {
+                            array: expr,
+                            index: Box::new(index),
+                            value: Box::new(value),
+                        })
+                    } else {
+                        Ok(Statement::Expression(expr))
+                    }
+                } else {
+                    Ok(Statement::Expression(expr))
+                }
+            }
+            _ => {
+                let expr = self.parse_expression()?;
+                Ok(Statement::Expression(expr))
+            }
+        }
+    }
+
+    fn parse_visibility(&mut self) -> Result<Visibility, CrabbyError> {
+        match self.peek().token {
+            Token::Public => {
+                self.advance();
+                Ok(Visibility::Public)
+            }
+            Token::Protect => {
+                self.advance();
+                Ok(Visibility::Protect)
+            }
+            Token::Private => {
+                self.advance();
+                Ok(Visibility::Private)
+            }
+            _ => Ok(Visibility::Private),
+        }
+    }
+
+    fn parse_definition(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'def'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected function name"));
+        };
+        self.advance();
+
+        let type_params = self.parse_type_params()?;
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+
+        let body = self.parse_block()?;
+
+        Ok(Statement::FunctionDef {
+            name,
+            type_params,
+            params,
+            docstring: docstring(&body),
+            body: Box::new(body),
+            return_type,
+            visibility: Visibility::default(),
+            decorators: Vec::new(),
+            span,
+        })
+    }
+
+    // `extern "library" def name(params) -> type`, with no body; `foreign` works too
+    fn parse_extern_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'extern'
+
+        let library = match &self.peek().token {
+            Token::String(library) => {
+                let library = library.clone();
+                self.advance();
+                Some(library)
+            }
+            _ => None,
+        };
+        if library.is_none() && matches!(self.peek().token, Token::Struct) {
+            return self.parse_extern_struct(span);
+        }
+        self.consume(&Token::Def, "Expected 'def' after 'extern'")?;
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected function name"));
+        };
+        self.advance();
+
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+
+        Ok(Statement::Extern {
+            library,
+            name,
+            params,
+            return_type,
+            span,
+        })
+    }
+
+    // `extern struct Point { x: f64, y: f64 }`; `extern` has been consumed
+    fn parse_extern_struct(&mut self, span: Span) -> Result<Statement, CrabbyError> {
+        self.advance(); // consume 'struct'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected struct name"));
+        };
+        self.advance();
+        self.consume(&Token::LBrace, "Expected '{' after struct name")?;
+
+        let mut fields = Vec::new();
+        while !matches!(self.peek().token, Token::RBrace) {
+            let field_name = if let Token::Identifier(name) = &self.peek().token {
+                name.clone()
+            } else {
+                return Err(self.error("Expected field name"));
+            };
+            self.advance();
+
+            self.consume(&Token::Colon, "Expected ':' after field name")?;
+            fields.push(ExternField {
+                name: field_name,
+                type_annotation: self.parse_type_annotation()?,
+            });
+
+            if matches!(self.peek().token, Token::Comma) {
+                self.advance();
+            }
+        }
+
+        self.consume(&Token::RBrace, "Expected '}' after struct fields")?;
+
+        Ok(Statement::ExternStruct { name, fields, span })
+    }
+
+    fn parse_function(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'fun'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected function name"));
+        };
+        self.advance();
+
+        let type_params = self.parse_type_params()?;
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+
+        let body = self.parse_block()?;
+
+        Ok(Statement::FunctionFun {
+            name,
+            type_params,
+            params,
+            docstring: docstring(&body),
+            body: Box::new(body),
+            return_type,
+            visibility: Visibility::default(),
+            decorators: Vec::new(),
+            span,
+        })
+    }
+
+    fn parse_match_statement(&mut self) -> Result<Statement, CrabbyError> {
+        self.advance(); // consume 'match'
+        let value = self.parse_expression()?;
+        self.consume(&Token::LBrace, "Expected '{' after match expression")?;
+
+        let mut arms = Vec::new();
+        while !matches!(self.peek().token, Token::RBrace) {
+            if !matches!(self.peek().token, Token::Case) {
+                return Err(CrabbyError::MissingCaseKeyword(ErrorLocation {
+                    // line: self.tokens.span.line,
+                    line: self.peek().span.line,
+                    // column: self.tokens.span.column,
+                    column: self.peek().span.column,
+                    message: "Expected 'case' keyword!".to_string(),
+                }));
+            }
+            self.advance(); // consume 'case'
+
+            self.in_pattern = true;
+            let pattern = self.parse_expression();
+            self.in_pattern = false;
+            let pattern = pattern?;
+            self.consume(&Token::Arrow, "Expected '=>' after match pattern")?;
+            let body = self.parse_expression()?;
+            arms.push(MatchArm { pattern, body });
+
+            if matches!(self.peek().token, Token::Comma) {
+                self.advance();
+            }
+        }
+
+        self.consume(&Token::RBrace, "Expected '}' after match arms")?;
+
+        Ok(Statement::Match {
+            value: Box::new(value),
+            arms,
+        })
+    }
+
+    fn parse_macro_statement(&mut self) -> Result<Statement, CrabbyError> {
+        self.advance(); // consume 'macro'
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected macro name"));
+        };
+        self.advance();
+        self.consume(&Token::ExclamationMark, "Expected '!' after macro name")?;
+
+        let mut params = Vec::new();
+        for param in self.parse_params()? {
+            if param.kind != ParameterKind::Positional || param.default.is_some() {
+                return Err(self.error(&format!(
+                    "Macro parameter '{}' must be a plain name",
+                    param.name
+                )));
+            }
+            params.push(param.name);
+        }
+        let body = self.parse_block()?;
+
+        Ok(Statement::Macro {
+            name,
+            params,
+            body: Box::new(body),
+        })
+    }
+
+    pub fn parse_async_statement(&mut self) -> Result<Statement, CrabbyError> {
+        self.advance(); // consume 'async'
+
+        if matches!(self.peek().token, Token::Def) {
+            self.advance(); // consume 'def'
+
+            let name = if let Token::Identifier(name) = &self.peek().token {
+                name.clone()
+            } else {
+                return Err(self.error("Expected function name after 'async def'"));
+            };
+            self.advance();
+
+            let params = self.parse_params()?;
+
+            let return_type = self.parse_return_type()?;
+
+            let body = self.parse_block()?;
+
+            Ok(Statement::AsyncFunction {
+                name,
+                params,
+                body: Box::new(body),
+                return_type,
+            })
+        } else {
+            let expr = self.parse_expression()?;
+            Ok(Statement::Expression(expr))
+        }
+    }
+
+    // pub fn parse_await_statement(&mut self) -> Result<Statement, CrabbyError> {
+    //    self.advance(); // consume 'await'
+
+    //    let expr = self.parse_expression()?;
+    //    Ok(Expression::Await { expr: Box::new(expr)});
+    // }
+
+    fn parse_decorated_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let mut decorators = Vec::new();
+        while matches!(self.peek().token, Token::Decorator) {
+            self.advance(); // consume '@'
+
+            let name = if let Token::Identifier(name) = &self.peek().token {
+                name.clone()
+            } else {
+                return Err(self.error("Expected decorator name after '@'"));
+            };
+            self.advance();
+
+            let (arguments, keyword_arguments, called) =
+                if matches!(self.peek().token, Token::LParen) {
+                    self.advance(); // consume '('
+                    let (arguments, keyword_arguments) = self.parse_arguments()?;
+                    (arguments, keyword_arguments, true)
+                } else {
+                    (Vec::new(), Vec::new(), false)
+                };
+
+            decorators.push(Decorator {
+                name,
+                arguments,
+                keyword_arguments,
+                called,
+            });
+        }
+
+        // Doc comments may also sit between the decorators and the declaration
+        let doc_comment = self.doc_comments.remove(&self.current);
+        let mut statement = match self.peek().token {
+            Token::Def => self.parse_definition()?,
+            Token::Function => self.parse_function()?,
+            Token::Class => self.parse_class_statement()?,
+            _ => return Err(self.error("Expected 'def', 'fun' or 'class' after decorator")),
+        };
+
+        match &mut statement {
+            Statement::FunctionDef {
+                decorators: target, ..
+            }
+            | Statement::FunctionFun {
+                decorators: target, ..
+            }
+            | Statement::Class {
+                decorators: target, ..
+            } => *target = decorators,
+            _ => unreachable!(),
+        }
+        attach_doc_comment(&mut statement, doc_comment);
+
+        Ok(statement)
+    }
+
+    // `pub def ...`, `protect fun ...`, `pub struct ...` and the other items
+    fn parse_visible_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let visibility = self.parse_visibility()?;
+
+        let mut statement = match self.peek().token {
+            Token::Def => self.parse_definition()?,
+            Token::Function => self.parse_function()?,
+            Token::Struct => self.parse_struct_statement()?,
+            Token::Enum => self.parse_enum_statement()?,
+            Token::Class => self.parse_class_statement()?,
+            Token::Trait => self.parse_trait_statement()?,
+            _ => {
+                return Err(self.error(
+                    "Expected 'def', 'fun', 'struct', 'enum', 'class' or 'trait' after visibility modifier",
+                ));
+            }
+        };
+
+        match &mut statement {
+            Statement::FunctionDef {
+                visibility: target, ..
+            }
+            | Statement::FunctionFun {
+                visibility: target, ..
+            }
+            | Statement::Struct {
+                visibility: target, ..
+            }
+            | Statement::Enum {
+                visibility: target, ..
+            }
+            | Statement::Class {
+                visibility: target, ..
+            }
+            | Statement::Trait {
+                visibility: target, ..
+            } => *target = visibility,
+            _ => unreachable!(),
+        }
+
+        Ok(statement)
+    }
+
+    fn parse_class_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'class'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected class name"));
+        };
+        self.advance();
+        let type_params = self.parse_type_params()?;
+
+        let parent = if matches!(self.peek().token, Token::Extend) {
+            self.advance(); // consume 'extend'
+            if let Token::Identifier(parent) = &self.peek().token {
+                let parent = parent.clone();
+                self.advance();
+                Some(parent)
+            } else {
+                return Err(self.error("Expected parent class name after 'extend'"));
+            }
+        } else {
+            None
+        };
+
+        self.consume(&Token::LBrace, "Expected '{' after class name")?;
+        let docstring = self.parse_docstring();
+
+        let mut methods = Vec::new();
+        let mut fields = Vec::new();
+        while !matches!(self.peek().token, Token::RBrace) && !self.is_at_end() {
+            match self.peek().token {
+                Token::Def | Token::Function | Token::Decorator => {
+                    methods.push(self.parse_statement()?)
+                }
+                _ => {
+                    self.parse_visibility()?;
+                    if matches!(self.peek().token, Token::Let | Token::Variable) {
+                        self.advance();
+                    }
+
+                    let field = if let Token::Identifier(field) = &self.peek().token {
+                        field.clone()
+                    } else {
+                        return Err(self.error("Expected method or field in class body"));
+                    };
+                    self.advance();
+
+                    let default = if matches!(self.peek().token, Token::Equals) {
+                        self.advance(); // consume '='
+                        Some(self.parse_expression()?)
+                    } else {
+                        None
+                    };
+                    fields.push(ClassField {
+                        name: field,
+                        default,
+                    });
+                }
+            }
+        }
+
+        self.consume(&Token::RBrace, "Expected '}' after class body")?;
+
+        Ok(Statement::Class {
+            name,
+            type_params,
+            parent,
+            methods,
+            fields,
+            decorators: Vec::new(),
+            docstring,
+            visibility: Visibility::default(),
+            span,
+        })
+    }
+
+    // `trait Shape { def area(self) def name(self) { return "shape" } }`, where
+    // methods without a body are the ones implementors must provide
+    fn parse_trait_statement(&mut self) -> Result<Statement, CrabbyError> {
+        self.advance(); // consume 'trait'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected trait name"));
+        };
+        self.advance();
+
+        self.consume(&Token::LBrace, "Expected '{' after trait name")?;
+        let trait_docstring = self.parse_docstring();
+
+        let mut methods = Vec::new();
+        while !matches!(self.peek().token, Token::RBrace) && !self.is_at_end() {
+            let doc_comment = self.doc_comments.remove(&self.current);
+            if !matches!(self.peek().token, Token::Def | Token::Function) {
+                return Err(self.error("Expected 'def' or 'fun' in trait body"));
+            }
+            self.advance(); // consume 'def' or 'fun'
+
+            let method = if let Token::Identifier(method) = &self.peek().token {
+                method.clone()
+            } else {
+                return Err(self.error("Expected method name"));
+            };
+            self.advance();
+            let params = self.parse_params()?;
+            self.parse_return_type()?;
+
+            let body = if matches!(self.peek().token, Token::LBrace) {
+                self.parse_block()?
+            } else {
+                Statement::Block(Vec::new())
+            };
+            methods.push(MethodDefinition {
+                name: method,
+                params,
+                docstring: doc_comment.unwrap_or_else(|| docstring(&body)),
+                body: Box::new(body),
+            });
+        }
+
+        self.consume(&Token::RBrace, "Expected '}' after trait body")?;
+
+        Ok(Statement::Trait {
+            name,
+            methods,
+            docstring: trait_docstring,
+            visibility: Visibility::default(),
+        })
+    }
+
+    // fn parse_impl_statement(&mut self) -> Result<Statement, CrabbyError> {
+    //     self.advance(); // consume 'impl'
+    // }
+
+    // fn parse_mutable_statement(&mut self) -> Result<Statement, CrabbyError> {
+    //     self.advance(); // consume 'mut'
+    // }
+
+    fn parse_and_statement(&mut self) -> Result<Statement, CrabbyError> {
+        self.advance(); // consume 'and'
+        let left = self.parse_expression()?;
+        self.consume(&Token::And, "Expected 'and' operator")?;
+        let right = self.parse_expression()?;
+
+        Ok(Statement::And {
+            left: left.to_string(),
+            right: right.to_string(),
+        })
+    }
+
+    fn parse_if_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'if'
+        let condition = self.parse_expression()?;
+
+        let then_branch = self.parse_block()?;
+
+        let else_branch = if matches!(self.peek().token, Token::Else) {
+            self.advance(); // consume 'else'
+            Some(Box::new(self.parse_block()?))
+        } else {
+            None
+        };
+
+        Ok(Statement::If {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch,
+            span,
+        })
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'while'
+        let condition = self.parse_expression()?;
+        self.consume(&Token::Colon, "Expected ':' after while condition")?;
+        let body = self.parse_block()?;
+
+        Ok(Statement::While {
+            condition: Box::new(condition),
+            body: Box::new(body),
+            span,
+        })
+    }
+
+    fn parse_assert_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'assert'
+        let condition = self.parse_expression()?;
+        let message = if matches!(self.peek().token, Token::Comma) {
+            self.advance(); // consume ','
+            Some(Box::new(self.parse_expression()?))
+        } else {
+            None
+        };
+
+        Ok(Statement::Assert {
+            condition: Box::new(condition),
+            message,
+            span,
+        })
+    }
+
+    fn parse_test_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'test'
+        let Token::String(name) = self.peek().token.clone() else {
+            return Err(self.error("Expected a test name after 'test'"));
+        };
+        self.advance(); // consume the name
+        let body = self.parse_block()?;
+
+        Ok(Statement::Test {
+            name,
+            body: Box::new(body),
+            span,
+        })
+    }
+
+    // A string opening the body of a type documents it
+    fn parse_docstring(&mut self) -> String {
+        match &self.peek().token {
+            Token::String(text) => {
+                let text = dedent(text);
+                self.advance();
+                text
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, CrabbyError> {
+        self.nested(Self::parse_null_coalesce)
+    }
+
+    fn parse_null_coalesce(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_type_test()?;
+
+        while matches!(self.peek().token, Token::NullCoalesce) {
+            let span = self.peek().span;
+            self.advance(); // consume '??'
+            let right = self.parse_type_test()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::NullCoalesce,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // `value is Type`
+    fn parse_type_test(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_comparison()?;
+
+        while matches!(self.peek().token, Token::Is) {
+            self.advance(); // consume 'is'
+            let type_expr = self.parse_type_annotation()?;
+            expr = Expression::Is {
+                value: Box::new(expr),
+                type_expr,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_bit_or()?;
+
+        loop {
+            let operator = match self.peek().token {
+                Token::DoubleEquals => BinaryOp::Eq,
+                Token::NotEquals => BinaryOp::NotEq,
+                Token::LessThan => BinaryOp::Less,
+                Token::LessThanOrEqual => BinaryOp::LessEq,
+                Token::GreaterThan => BinaryOp::Greater,
+                Token::GreaterThanOrEqual => BinaryOp::GreaterEq,
+                _ => break,
+            };
+            let span = self.peek().span;
+            self.advance();
+
+            let right = self.parse_bit_or()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_bit_or(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_bit_xor()?;
+
+        while matches!(self.peek().token, Token::Bar) {
+            let span = self.peek().span;
+            self.advance(); // consume '|'
+            let right = self.parse_bit_xor()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::BitOr,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_bit_xor(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_bit_and()?;
+
+        while matches!(self.peek().token, Token::Caret) {
+            let span = self.peek().span;
+            self.advance(); // consume '^'
+            let right = self.parse_bit_and()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::BitXor,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_bit_and(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_shift()?;
+
+        while matches!(self.peek().token, Token::Ampersand) {
+            let span = self.peek().span;
+            self.advance(); // consume '&'
+            let right = self.parse_shift()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::BitAnd,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_addition()?;
+
+        while matches!(self.peek().token, Token::ShiftLeft | Token::ShiftRight) {
+            let operator = match self.peek().token {
+                Token::ShiftLeft => BinaryOp::ShiftLeft,
+                Token::ShiftRight => BinaryOp::ShiftRight,
+                _ => unreachable!(),
+            };
+            let span = self.peek().span;
+            self.advance();
+
+            let right = self.parse_addition()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_addition(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_multiplication()?;
+
+        while matches!(self.peek().token, Token::Plus | Token::Minus) {
+            let operator = match self.peek().token {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Sub,
+                _ => unreachable!(),
+            };
+            let span = self.peek().span;
+            self.advance();
+
+            let right = self.parse_multiplication()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_multiplication(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_unary()?;
+
+        while matches!(
+            self.peek().token,
+            Token::Star | Token::Slash | Token::Percentage
+        ) || (matches!(self.peek().token, Token::Arrow) && !self.in_pattern)
+        {
+            let operator = match self.peek().token {
+                Token::Star => BinaryOp::Mul,
+                Token::Slash => BinaryOp::Div,
+                Token::Percentage => BinaryOp::Mod,
+                Token::Arrow => BinaryOp::MatchOp,
+                _ => unreachable!(),
+            };
+            let span = self.peek().span;
+            self.advance();
+
+            let right = self.parse_unary()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // `-x`, `~x`, `!x` and `not x`, and the borrows `&x`, `&mut x`, `ref x`, `ref mut x`
+    // and `move x`
+    fn parse_unary(&mut self) -> Result<Expression, CrabbyError> {
+        let operator = match self.peek().token {
+            Token::Minus => UnaryOp::Neg,
+            Token::Tilde => UnaryOp::BitNot,
+            Token::ExclamationMark | Token::Not => UnaryOp::Not,
+            Token::Ampersand | Token::Reference => {
+                let span = self.peek().span;
+                self.advance(); // consume '&' or 'ref'
+                let mutable = matches!(self.peek().token, Token::Mutable);
+                if mutable {
+                    self.advance(); // consume 'mut'
+                }
+                let expr = self.nested(Self::parse_unary)?;
+                return Ok(Expression::Borrow {
+                    expr: Box::new(expr),
+                    mutable,
+                    span,
+                });
+            }
+            Token::Move => {
+                let span = self.peek().span;
+                self.advance(); // consume 'move'
+                let expr = self.nested(Self::parse_unary)?;
+                return Ok(Expression::Move {
+                    expr: Box::new(expr),
+                    span,
+                });
+            }
+            _ => return self.parse_power(),
+        };
+        self.advance();
+
+        let operand = self.nested(Self::parse_unary)?;
+        // Negative literals stay literals, so `-9223372036854775808` is an Integer
+        Ok(match (operator, operand) {
+            (UnaryOp::Neg, Expression::Integer(n)) => Expression::Integer(-n),
+            (UnaryOp::Neg, Expression::Float(f)) => Expression::Float(-f),
+            (UnaryOp::Neg, Expression::BigInteger(n)) => integer_literal(-n),
+            (operator, operand) => Expression::Unary {
+                operator,
+                operand: Box::new(operand),
+            },
+        })
+    }
+
+    // `**` binds tighter than a unary minus on its left and is right-associative
+    fn parse_power(&mut self) -> Result<Expression, CrabbyError> {
+        let base = self.parse_postfix()?;
+
+        if matches!(self.peek().token, Token::DoubleStar) {
+            let span = self.peek().span;
+            self.advance(); // consume '**'
+            let exponent = self.parse_unary()?;
+            return Ok(Expression::Binary {
+                left: Box::new(base),
+                operator: BinaryOp::Pow,
+                right: Box::new(exponent),
+                span,
+            });
+        }
+
+        Ok(base)
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, CrabbyError> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            match self.peek().token {
+                Token::Dot | Token::SafeNavigation => {
+                    let optional = matches!(self.peek().token, Token::SafeNavigation);
+                    self.advance(); // consume '.' or '?.'
+
+                    let name = if let Token::Identifier(name) = &self.peek().token {
+                        name.clone()
+                    } else {
+                        return Err(self.error("Expected field or method name after dot"));
+                    };
+                    self.advance();
+
+                    expr = if matches!(self.peek().token, Token::LParen) {
+                        self.advance(); // consume '('
+                        let (arguments, keyword_arguments) = self.parse_arguments()?;
+                        Expression::MethodCall {
+                            object: Box::new(expr),
+                            method: name,
+                            arguments,
+                            keyword_arguments,
+                            optional,
+                        }
+                    } else {
+                        Expression::Member {
+                            object: Box::new(expr),
+                            field: name,
+                            optional,
+                        }
+                    };
+                }
+                Token::QuestionMark => {
+                    self.advance(); // consume '?'
+                    expr = Expression::Try(Box::new(expr));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, CrabbyError> {
+        match &self.peek().token {
+            Token::Integer(n) => {
+                let n = integer_literal(n.clone());
+                self.advance();
+                Ok(n)
+            }
+            Token::Float(f) => {
+                let f = *f;
+                self.advance();
+                Ok(Expression::Float(f))
+            }
+            Token::String(s) => {
+                let s = s.clone();
+                self.advance();
+                Ok(Expression::String(s))
+            }
+            Token::Identifier(name) => {
+                let name = name.clone();
+                let span = self.peek().span;
+                self.advance();
+                if matches!(self.peek().token, Token::LParen) {
+                    self.parse_function_call(name, span)
+                } else if matches!(self.peek().token, Token::ExclamationMark)
+                    && matches!(self.peek_next().map(|t| &t.token), Some(Token::LParen))
+                {
+                    // `name!(args)`
+                    self.advance(); // consume '!'
+                    self.advance(); // consume '('
+                    let (arguments, keyword_arguments) = self.parse_arguments()?;
+                    if !keyword_arguments.is_empty() {
+                        return Err(self.error("Macros do not take keyword arguments"));
+                    }
+                    Ok(Expression::MacroCall {
+                        name,
+                        arguments,
+                        span,
+                    })
+                } else if matches!(self.peek().token, Token::Arrow) && !self.in_pattern {
+                    // `x => expr`
+                    self.advance(); // consume '=>'
+                    self.parse_arrow_lambda(vec![Parameter::positional(name, span)])
+                } else {
+                    Ok(Expression::Variable { name, span })
+                }
+            }
+            // `expect(value).to_equal(expected)`
+            Token::Expect if matches!(self.peek_next().map(|t| &t.token), Some(Token::LParen)) => {
+                let span = self.peek().span;
+                self.advance(); // consume 'expect'
+                self.parse_function_call(String::from("expect"), span)
+            }
+            Token::Await => {
+                self.advance(); // consume 'await'
+                let expr = self.parse_expression()?;
+                Ok(Expression::Await {
+                    expr: Box::new(expr),
+                })
+            }
+            Token::TypeOf => {
+                self.advance(); // consume 'typeof'
+                let expr = self.parse_postfix()?;
+                Ok(Expression::TypeOf(Box::new(expr)))
+            }
+            Token::True => {
+                self.advance();
+                Ok(Expression::Boolean(true))
+            }
+            Token::Null | Token::Nil => {
+                self.advance();
+                Ok(Expression::Null)
+            }
+            Token::False => {
+                self.advance();
+                Ok(Expression::Boolean(false))
+            }
+            Token::Maybe => {
+                let span = self.peek().span;
+                self.advance();
+                Ok(Expression::Maybe(span))
+            }
+            Token::Probably => {
+                let span = self.peek().span;
+                self.advance();
+                Ok(Expression::Probably(span))
+            }
+            Token::Range => {
+                self.advance(); // consume 'range'
+                self.consume(&Token::LParen, "Expected '(' after 'range'")?;
+                let count = self.parse_expression()?;
+                self.consume(&Token::RParen, "Expected ')' after range count")?;
+                Ok(Expression::Range(Box::new(count)))
+            }
+            Token::Lambda => {
+                self.advance(); // consume 'lambda'
+                let params = self.parse_params()?;
+
+                // self.consume(&Token::Colon, "Expected ':' after parameters")?;
+                let body = self.parse_block()?;
+
+                Ok(Expression::Lambda {
+                    params,
+                    body: Box::new(body),
+                })
+            }
+            Token::FString(pieces) => {
+                let pieces = pieces.clone();
+                let span = self.peek().span;
+                self.advance();
+
+                let mut parts = Vec::new();
+                for piece in pieces {
+                    parts.push(match piece {
+                        TemplatePiece::Text(text) => FStringPart::Text(text),
+                        TemplatePiece::Hole { code, spec } => FStringPart::Hole {
+                            expression: parse_hole(&code, span, self.depth)?,
+                            spec: spec
+                                .map(|spec| FormatSpec::parse(&spec))
+                                .transpose()
+                                .map_err(|message| hole_error(span, message))?,
+                        },
+                    });
+                }
+                Ok(Expression::FString(parts))
+            }
+            Token::LParen if self.is_arrow_lambda() => {
+                // `(a, b) => { ... }`
+                let params = self.parse_params()?;
+                self.consume(&Token::Arrow, "Expected '=>' after lambda parameters")?;
+                self.parse_arrow_lambda(params)
+            }
+            Token::LParen => {
+                self.advance();
+                let expr = self.parse_expression()?;
+                self.consume(&Token::RParen, "Expected ')' after expression")?;
+                Ok(expr)
+            }
+            Token::LBracket => {
+                self.advance(); // consume '['
+                let mut elements = Vec::new();
+
+                if !matches!(self.peek().token, Token::RBracket) {
+                    loop {
+                        elements.push(self.parse_expression()?);
+
+                        if !matches!(self.peek().token, Token::Comma) {
+                            break;
+                        }
+                        self.advance(); // consume ','
+                    }
+                }
+
+                self.consume(&Token::RBracket, "Expected ']' after array elements")?;
+                Ok(Expression::Array(elements))
+            }
+            Token::Eof => Err(self.error("Unexpected end of input; expected an expression")),
+            x => {
+                // let expr = self.parse_expression()?; //SO point 2/2
+
+                // if matches!(self.peek().token, Token::LBracket) {
+                //     self.advance(); // consume '['
+                //     let index = self.parse_expression()?;
+                //     self.consume(&Token::RBracket, "Expected ']' after array index")?;
+
+                //     Ok(Expression::Index {
+                //         array: Box::new(expr),
+                //         index: Box::new(index),
+                //     })
+                // } else {
+                //     Ok(expr)
+                // }
+                // Ok(Expression::String("Bruh".to_string()))
+                Err(self.error(&format!("Unexpected {x:?} at this time.")))
+            }
+        }
+    }
+
+    fn parse_let_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'let'
+
+        let mutable = matches!(self.peek().token, Token::Mutable);
+        if mutable {
+            self.advance(); // consume 'mut'
+        }
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected variable name"));
+        };
+        self.advance();
+
+        let type_annotation = if matches!(self.peek().token, Token::Colon) {
+            self.advance(); // consume ':'
+            Some(self.parse_type_annotation()?)
+        } else {
+            None
+        };
+
+        self.consume(&Token::Equals, "Expected '=' after variable name")?;
+        let value = self.parse_expression()?;
+
+        Ok(Statement::Let {
+            name,
+            value: Box::new(value),
+            type_annotation,
+            mutable,
+            span,
+        })
+    }
+
+    fn parse_var_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'var'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected variable name"));
+        };
+        self.advance();
+
+        let type_annotation = if matches!(self.peek().token, Token::Colon) {
+            self.advance(); // consume ':'
+            Some(self.parse_type_annotation()?)
+        } else {
+            None
+        };
+
+        self.consume(&Token::Equals, "Expected '=' after variable name")?;
+        let value = self.parse_expression()?;
+
+        Ok(Statement::Var {
+            name,
+            value: Box::new(value),
+            type_annotation,
+            span,
+        })
+    }
+
+    fn parse_loop_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'loop'
+
+        let count = self.parse_expression()?;
+
+        // self.consume(&Token::Colon, "Expected ':' after loop count")?;
+        let body = self.parse_block()?;
+
+        Ok(Statement::Loop {
+            count: Box::new(count),
+            body: Box::new(body),
+            span,
+        })
+    }
+
+    fn parse_for_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'for'
+
+        let variable = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected variable name after 'for'"));
+        };
+        self.advance();
+
+        self.consume(&Token::In, "Expected 'in' after variable name")?;
+
+        let iterator = self.parse_expression()?;
+
+        // self.consume(&Token::Colon, "Expected ':' after iterator expression")?;
+        let body = self.parse_block()?;
+
+        Ok(Statement::ForIn {
+            variable,
+            iterator: Box::new(iterator),
+            body: Box::new(body),
+            span,
+        })
+    }
+
+    fn parse_enum_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'enum'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected enum name"));
+        };
+        self.advance();
+        let type_params = self.parse_type_params()?;
+
+        let mut where_clause = None;
+        if matches!(self.peek().token, Token::Where) {
+            self.advance(); // consume 'where'
+            where_clause = Some(Box::new(self.parse_expression()?));
+        }
+
+        self.consume(&Token::LBrace, "Expected '{' after enum name")?;
+        let docstring = self.parse_docstring();
+
+        let mut variants = Vec::new();
+        while !matches!(self.peek().token, Token::RBrace) {
+            let variant_name = if let Token::Identifier(name) = &self.peek().token {
+                name.clone()
+            } else {
+                return Err(self.error("Expected variant name"));
+            };
+            self.advance();
+
+            let fields = if matches!(self.peek().token, Token::LParen) {
+                self.advance(); // consume '('
+                let mut fields = Vec::new();
+
+                while !matches!(self.peek().token, Token::RParen) {
+                    fields.push(self.parse_expression()?);
+                    if matches!(self.peek().token, Token::Comma) {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+
+                self.consume(&Token::RParen, "Expected ')' after variant fields")?;
+                Some(fields)
+            } else {
+                None
+            };
+
+            variants.push(EnumVariant {
+                name: variant_name,
+                fields,
+            });
+
+            if matches!(self.peek().token, Token::Comma) {
+                self.advance();
+            }
+        }
+
+        self.consume(&Token::RBrace, "Expected '}' after enum variants")?;
+
+        Ok(Statement::Enum {
+            name,
+            type_params,
+            variants,
+            where_clause,
+            docstring,
+            visibility: Visibility::default(),
+            span,
+        })
+    }
+
+    fn parse_struct_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'struct'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected struct name"));
+        };
+        self.advance();
+        let type_params = self.parse_type_params()?;
+
+        let mut where_clause = None;
+        if matches!(self.peek().token, Token::Where) {
+            self.advance(); // consume 'where'
+            where_clause = Some(Box::new(self.parse_expression()?));
+        }
+
+        self.consume(&Token::LBrace, "Expected '{' after struct name")?;
+        let docstring = self.parse_docstring();
+
+        let mut fields = Vec::new();
+        while !matches!(self.peek().token, Token::RBrace) {
+            let field_name = if let Token::Identifier(name) = &self.peek().token {
+                name.clone()
+            } else {
+                return Err(self.error("Expected field name"));
+            };
+            self.advance();
+
+            self.consume(&Token::Colon, "Expected ':' after field name")?;
+            let type_expr = self.parse_expression()?;
+
+            fields.push(StructField {
+                name: field_name,
+                type_expr,
+            });
+
+            if matches!(self.peek().token, Token::Comma) {
+                self.advance();
+            }
+        }
+
+        self.consume(&Token::RBrace, "Expected '}' after struct fields")?;
+
+        Ok(Statement::Struct {
+            name,
+            type_params,
+            fields,
+            where_clause,
+            docstring,
+            visibility: Visibility::default(),
+            span,
+        })
+    }
+
+    fn parse_typedef_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'typedef'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected type name after 'typedef'"));
+        };
+        self.advance();
+
+        self.consume(&Token::Equals, "Expected '=' after type name")?;
+        let target = self.parse_type_annotation()?;
+
+        Ok(Statement::TypeDef { name, target, span })
+    }
+
+    fn parse_where_statement(&mut self) -> Result<Statement, CrabbyError> {
+        self.advance(); // consume 'where'
+        let condition = self.parse_expression()?;
+        let expr = self.parse_expression()?;
+        let body = self.parse_block()?;
+
+        Ok(Statement::Expression(Expression::Where {
+            expr: Box::new(expr),
+            condition: Box::new(condition),
+            body: Box::new(body),
+        }))
+    }
+
+    fn parse_import_statement(&mut self) -> Result<Statement, CrabbyError> {
+        let span = self.peek().span;
+        self.advance(); // consume 'import'
+
+        let name = if let Token::Identifier(name) = &self.peek().token {
+            name.clone()
+        } else {
+            return Err(self.error("Expected module name after 'import'"));
+        };
+        self.advance();
+
+        let source = if matches!(self.peek().token, Token::From) {
+            self.advance(); // consume 'from'
+            if let Token::String(path) = &self.peek().token {
+                Some(path.clone())
+            } else {
+                return Err(self.error("Expected string literal after 'from'"));
+            }
+        } else {
+            None
+        };
+        self.advance();
+
+        Ok(Statement::Import { name, source, span })
+    }
+
+    fn parse_function_call(&mut self, name: String, span: Span) -> Result<Expression, CrabbyError> {
+        self.advance(); // consume '('
+        let (arguments, keyword_arguments) = self.parse_arguments()?;
+
+        Ok(Expression::Call {
+            function: name,
+            arguments,
+            keyword_arguments,
+            span,
+        })
+    }
+
+    // Parses a call's argument list up to and including the closing ')'
+    fn parse_arguments(&mut self) -> Result<(Vec<Expression>, Vec<KeywordArgument>), CrabbyError> {
+        let mut arguments = Vec::new();
+        let mut keyword_arguments: Vec<KeywordArgument> = Vec::new();
+        if !matches!(self.peek().token, Token::RParen) {
+            loop {
+                let keyword = match (&self.peek().token, self.peek_next().map(|t| &t.token)) {
+                    (Token::Identifier(keyword), Some(Token::Colon)) => Some(keyword.clone()),
+                    _ => None,
+                };
+
+                if let Some(keyword) = keyword {
+                    if keyword_arguments.iter().any(|arg| arg.name == keyword) {
+                        return Err(
+                            self.error(&format!("Duplicate keyword argument '{}'", keyword))
+                        );
+                    }
+                    self.advance(); // consume keyword
+                    self.advance(); // consume ':'
+                    keyword_arguments.push(KeywordArgument {
+                        name: keyword,
+                        value: self.parse_expression()?,
+                    });
+                } else if !keyword_arguments.is_empty() {
+                    return Err(self.error("Positional argument follows keyword argument"));
+                } else {
+                    arguments.push(self.parse_expression()?);
+                }
+
+                if !matches!(self.peek().token, Token::Comma) {
+                    break;
+                }
+                self.advance(); // consume ','
+            }
+        }
+
+        self.consume(&Token::RParen, "Expected ')' after arguments")?;
+        Ok((arguments, keyword_arguments))
+    }
+
+    // Type annotations such as `Int`, `[Int]`, `Option<Int>`, `String?` or `(Int) -> Int`
+    fn parse_type_annotation(&mut self) -> Result<TypeExpr, CrabbyError> {
+        self.nested(Self::parse_type_expr)
+    }
+
+    fn parse_type_expr(&mut self) -> Result<TypeExpr, CrabbyError> {
+        let mut annotation = match &self.peek().token {
+            Token::Identifier(name) => {
+                let name = name.clone();
+                self.advance();
+
+                if matches!(self.peek().token, Token::LessThan) {
+                    self.advance(); // consume '<'
+                    let mut arguments = vec![self.parse_type_annotation()?];
+                    while matches!(self.peek().token, Token::Comma) {
+                        self.advance();
+                        arguments.push(self.parse_type_annotation()?);
+                    }
+                    if matches!(self.peek().token, Token::ShiftRight) {
+                        // `List<List<Int>>`: take one '>' and leave the other
+                        self.tokens[self.current].token = Token::GreaterThan;
+                    } else {
+                        self.consume(&Token::GreaterThan, "Expected '>' after type arguments")?;
+                    }
+                    TypeExpr::Generic { name, arguments }
+                } else {
+                    TypeExpr::Named(name)
+                }
+            }
+            Token::Null => {
+                self.advance();
+                TypeExpr::Named("null".to_string())
+            }
+            // `*T`, a C pointer, for `extern` functions
+            Token::Star => {
+                self.advance(); // consume '*'
+                let pointee = self.parse_type_annotation()?;
+                TypeExpr::Generic {
+                    name: "ptr".to_string(),
+                    arguments: vec![pointee],
+                }
+            }
+            Token::LBracket => {
+                self.advance(); // consume '['
+                let element = self.parse_type_annotation()?;
+                self.consume(&Token::RBracket, "Expected ']' after list element type")?;
+                TypeExpr::List(Box::new(element))
+            }
+            Token::LParen => {
+                self.advance(); // consume '('
+                let mut params = Vec::new();
+                while !matches!(self.peek().token, Token::RParen) {
+                    params.push(self.parse_type_annotation()?);
+                    if !matches!(self.peek().token, Token::Comma) {
+                        break;
+                    }
+                    self.advance(); // consume ','
+                }
+                self.consume(&Token::RParen, "Expected ')' after parameter types")?;
+                self.consume(&Token::CoolerArrow, "Expected '->' after parameter types")?;
+                let returns = self.parse_type_annotation()?;
+                TypeExpr::Function {
+                    params,
+                    returns: Box::new(returns),
+                }
+            }
+            _ => return Err(self.error("Expected type name")),
+        };
+
+        // `u8[16]`, a C array
+        while matches!(self.peek().token, Token::LBracket) {
+            let length = match self.peek_next().map(|next| &next.token) {
+                Some(Token::Integer(length)) => length
+                    .to_usize()
+                    .ok_or_else(|| self.error("Array length is too large"))?,
+                _ => break,
+            };
+            self.advance(); // consume '['
+            self.advance(); // consume the length
+            self.consume(&Token::RBracket, "Expected ']' after array length")?;
+            annotation = TypeExpr::Array {
+                element: Box::new(annotation),
+                length,
+            };
+        }
+
+        if matches!(self.peek().token, Token::QuestionMark) {
+            self.advance(); // consume '?'
+            annotation = TypeExpr::Optional(Box::new(annotation));
+        }
+
+        Ok(annotation)
+    }
+
+    // An optional `<T, U: Num + Display>` after a definition's name
+    fn parse_type_params(&mut self) -> Result<Vec<TypeParameter>, CrabbyError> {
+        let mut type_params = Vec::new();
+        if !matches!(self.peek().token, Token::LessThan) {
+            return Ok(type_params);
+        }
+        self.advance(); // consume '<'
+
+        while !matches!(self.peek().token, Token::GreaterThan) {
+            let name = if let Token::Identifier(name) = &self.peek().token {
+                name.clone()
+            } else {
+                return Err(self.error("Expected type parameter name"));
+            };
+            self.advance();
+
+            let mut bounds = Vec::new();
+            if matches!(self.peek().token, Token::Colon) {
+                self.advance(); // consume ':'
+                loop {
+                    if let Token::Identifier(bound) = &self.peek().token {
+                        bounds.push(bound.clone());
+                        self.advance();
+                    } else {
+                        return Err(self.error("Expected trait name in type parameter bound"));
+                    }
+                    if !matches!(self.peek().token, Token::Plus) {
+                        break;
+                    }
+                    self.advance(); // consume '+'
+                }
+            }
+
+            type_params.push(TypeParameter { name, bounds });
+            if !matches!(self.peek().token, Token::Comma) {
+                break;
+            }
+            self.advance(); // consume ','
+        }
+
+        self.consume(&Token::GreaterThan, "Expected '>' after type parameters")?;
+        Ok(type_params)
+    }
+
+    // An optional `-> Type` after a parameter list
+    fn parse_return_type(&mut self) -> Result<Option<TypeExpr>, CrabbyError> {
+        if matches!(self.peek().token, Token::CoolerArrow) {
+            self.advance(); // consume '->'
+            Ok(Some(self.parse_type_annotation()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_arrow_lambda(&mut self, params: Vec<Parameter>) -> Result<Expression, CrabbyError> {
+        let body = if matches!(self.peek().token, Token::LBrace) {
+            self.parse_block()?
+        } else {
+            let span = self.peek().span;
+            Statement::Return(Box::new(self.parse_expression()?), span)
+        };
+
+        Ok(Expression::Lambda {
+            params,
+            body: Box::new(body),
+        })
+    }
+
+    // Looks past a parenthesised group to see whether it is a lambda's parameter list
+    fn is_arrow_lambda(&self) -> bool {
+        let mut depth = 0;
+        for (offset, token) in self.tokens[self.current..].iter().enumerate() {
+            match token.token {
+                Token::LParen => depth += 1,
+                Token::RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return matches!(
+                            self.tokens.get(self.current + offset + 1).map(|t| &t.token),
+                            Some(Token::Arrow)
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn parse_block(&mut self) -> Result<Statement, CrabbyError> {
+        self.consume(&Token::LBrace, "Expected '{' at start of block")?;
+
+        let mut statements = Vec::new();
+        while !matches!(self.peek().token, Token::RBrace) && !self.is_at_end() {
+            statements.push(self.parse_statement()?);
+        }
+
+        self.consume(&Token::RBrace, "Expected '}' at end of block")?;
+        Ok(Statement::Block(statements))
+    }
+
+    fn peek(&self) -> &TokenStream {
+        self.tokens.get(self.current).unwrap_or(&self.eof)
+    }
+
+    fn peek_next(&self) -> Option<&TokenStream> {
+        self.tokens.get(self.current + 1)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.tokens.len()
+    }
+
+    fn advance(&mut self) {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+    }
+
+    fn consume(&mut self, expected: &Token, message: &str) -> Result<(), CrabbyError> {
+        if self.peek().token == *expected {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    // Every level of nesting passes through here, so no input can recurse past the limit
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, CrabbyError>,
+    ) -> Result<T, CrabbyError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.error(&format!(
+                "Too deeply nested; at most {} levels are allowed",
+                MAX_NESTING
+            )));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn error(&self, message: &str) -> CrabbyError {
+        let span = &self.peek().span;
+
+        CrabbyError::ParserError(ErrorLocation {
+            line: span.line,
+            column: span.column,
+            message: message.to_string(),
+        })
+    }
+}
+
+pub fn parse(tokens: Vec<TokenStream>) -> Result<Program, CrabbyError> {
+    let mut parser = Parser::new(tokens);
+    parser.parse()
+}
+
+// Literals that fit in 64 bits are plain integers
+fn integer_literal(n: BigInt) -> Expression {
+    match n.to_i64() {
+        Some(n) => Expression::Integer(n),
+        None => Expression::BigInteger(n),
+    }
+}
+
+// Each `{hole}` of an f-string is lexed and parsed as an expression of its own,
+// as deep inside the program as the f-string is
+fn parse_hole(code: &str, span: Span, depth: usize) -> Result<Expression, CrabbyError> {
+    if code.trim().is_empty() {
+        return Err(hole_error(span, "Empty '{}'".to_string()));
+    }
+    let tokens = TokenStream::tokenize(code.to_string()).map_err(|error| match error {
+        CrabbyError::LexerError(location) => hole_error(span, location.message),
+        error => error,
+    })?;
+
+    let mut parser = Parser::new(tokens);
+    parser.depth = depth;
+    let expression = parser.parse_expression().map_err(|error| match error {
+        CrabbyError::ParserError(location) => hole_error(span, location.message),
+        error => error,
+    })?;
+    if !parser.is_at_end() {
+        return Err(hole_error(
+            span,
+            format!("Unexpected '{}' in f-string hole", parser.peek().slice),
+        ));
+    }
+    Ok(expression)
+}
+
+fn hole_error(span: Span, message: String) -> CrabbyError {
+    CrabbyError::ParserError(ErrorLocation {
+        line: span.line,
+        column: span.column,
+        message: format!("In f-string: {}", message),
+    })
+}
+
+// `///` comments replace any docstring the declaration has
+fn attach_doc_comment(statement: &mut Statement, doc_comment: Option<String>) {
+    let Some(doc_comment) = doc_comment else {
+        return;
+    };
+    match statement {
+        Statement::FunctionDef { docstring, .. }
+        | Statement::FunctionFun { docstring, .. }
+        | Statement::Struct { docstring, .. }
+        | Statement::Enum { docstring, .. }
+        | Statement::Class { docstring, .. }
+        | Statement::Trait { docstring, .. } => *docstring = doc_comment,
+        _ => {}
+    }
+}
+
+// A string on the first line of a function body documents it, as in Python
+fn docstring(body: &Statement) -> String {
+    let Statement::Block(statements) = body else {
+        return String::new();
+    };
+    match statements.first() {
+        Some(Statement::Expression(Expression::String(text))) => dedent(text),
+        _ => String::new(),
+    }
+}
+
+// Drops the indentation the lines of a docstring after the first share
+fn dedent(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let indent = lines
+        .iter()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => line.trim(),
+            _ => line.get(indent..).unwrap_or("").trim_end(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
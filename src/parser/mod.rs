@@ -1,3 +1,3 @@
-mod parser;
+mod grammar;
 
-pub use parser::parse;
+pub use grammar::parse;
@@ -1 +0,0 @@
-// Crabby's REPL command feature.
@@ -0,0 +1,324 @@
+// A small line editor for the REPL: cursor movement, history and tab completion
+// Falls back to plain line reading when stdin is not a terminal
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+
+const HISTORY_LIMIT: usize = 1000;
+
+pub enum Input {
+    Line(String),
+    Interrupted, // Ctrl-C
+    Eof,         // Ctrl-D on an empty line, or the end of piped input
+}
+
+pub struct Editor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+impl Editor {
+    pub fn new(history_path: Option<PathBuf>) -> Self {
+        let history = history_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(str::to_string).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let mut editor = Self {
+            history,
+            history_path,
+        };
+        if editor.history.len() > HISTORY_LIMIT {
+            editor.history.drain(..editor.history.len() - HISTORY_LIMIT);
+            editor.save_history();
+        }
+        editor
+    }
+
+    #[cfg(unix)]
+    pub fn is_interactive() -> bool {
+        unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+    }
+
+    #[cfg(not(unix))]
+    pub fn is_interactive() -> bool {
+        false
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(path) = &self.history_path
+            && let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path)
+        {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    fn save_history(&self) {
+        if let Some(path) = &self.history_path {
+            let _ = fs::write(path, self.history.join("\n") + "\n");
+        }
+    }
+
+    // `complete` returns every name that could finish the word before the cursor
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: &dyn Fn(&str) -> Vec<String>,
+    ) -> io::Result<Input> {
+        if !Self::is_interactive() {
+            let mut line = String::new();
+            return Ok(match io::stdin().lock().read_line(&mut line)? {
+                0 => Input::Eof,
+                _ => Input::Line(line.trim_end_matches(['\n', '\r']).to_string()),
+            });
+        }
+
+        #[cfg(unix)]
+        let _raw = RawMode::enable()?;
+        let mut line = LineState {
+            prompt: prompt.to_string(),
+            buffer: Vec::new(),
+            cursor: 0,
+        };
+        // Where we are in the history; `history.len()` is the line being typed
+        let mut position = self.history.len();
+        let mut draft = Vec::new();
+        line.redraw()?;
+
+        loop {
+            let Some(key) = read_key()? else {
+                return Ok(Input::Eof);
+            };
+            match key {
+                Key::Enter => {
+                    print!("\r\n");
+                    io::stdout().flush()?;
+                    return Ok(Input::Line(line.buffer.iter().collect()));
+                }
+                Key::Control('c') => {
+                    print!("^C\r\n");
+                    io::stdout().flush()?;
+                    return Ok(Input::Interrupted);
+                }
+                Key::Control('d') if line.buffer.is_empty() => {
+                    print!("\r\n");
+                    io::stdout().flush()?;
+                    return Ok(Input::Eof);
+                }
+                Key::Control('d') | Key::Delete => {
+                    if line.cursor < line.buffer.len() {
+                        line.buffer.remove(line.cursor);
+                    }
+                }
+                Key::Backspace => {
+                    if line.cursor > 0 {
+                        line.cursor -= 1;
+                        line.buffer.remove(line.cursor);
+                    }
+                }
+                Key::Left => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right => line.cursor = (line.cursor + 1).min(line.buffer.len()),
+                Key::Home | Key::Control('a') => line.cursor = 0,
+                Key::End | Key::Control('e') => line.cursor = line.buffer.len(),
+                Key::Control('u') => {
+                    line.buffer.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                Key::Control('k') => line.buffer.truncate(line.cursor),
+                Key::Control('l') => print!("\x1b[H\x1b[2J"),
+                Key::Up if position > 0 => {
+                    if position == self.history.len() {
+                        draft = line.buffer.clone();
+                    }
+                    position -= 1;
+                    line.set(self.history[position].chars().collect());
+                }
+                Key::Down if position < self.history.len() => {
+                    position += 1;
+                    match self.history.get(position) {
+                        Some(entry) => line.set(entry.chars().collect()),
+                        None => line.set(std::mem::take(&mut draft)),
+                    }
+                }
+                Key::Tab => line.complete(complete)?,
+                Key::Char(c) => {
+                    line.buffer.insert(line.cursor, c);
+                    line.cursor += 1;
+                }
+                _ => {}
+            }
+            line.redraw()?;
+        }
+    }
+}
+
+struct LineState {
+    prompt: String,
+    buffer: Vec<char>,
+    cursor: usize,
+}
+
+impl LineState {
+    fn set(&mut self, buffer: Vec<char>) {
+        self.cursor = buffer.len();
+        self.buffer = buffer;
+    }
+
+    fn redraw(&self) -> io::Result<()> {
+        let text: String = self.buffer.iter().collect();
+        print!("\r{}{}\x1b[K", self.prompt, text);
+        let behind = self.buffer.len() - self.cursor;
+        if behind > 0 {
+            print!("\x1b[{}D", behind);
+        }
+        io::stdout().flush()
+    }
+
+    // Finishes the word before the cursor, or lists the choices when there are several
+    fn complete(&mut self, complete: &dyn Fn(&str) -> Vec<String>) -> io::Result<()> {
+        let start = self.buffer[..self.cursor]
+            .iter()
+            .rposition(|c| !(c.is_alphanumeric() || *c == '_' || *c == ':'))
+            .map_or(0, |i| i + 1);
+        let word: String = self.buffer[start..self.cursor].iter().collect();
+        if word.is_empty() {
+            return Ok(());
+        }
+
+        let mut candidates = complete(&word);
+        candidates.sort();
+        candidates.dedup();
+        let Some(first) = candidates.first() else {
+            return Ok(());
+        };
+
+        let common = candidates.iter().fold(first.clone(), |common, candidate| {
+            common
+                .chars()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        });
+        if common.len() > word.len() {
+            for c in common.chars().skip(word.chars().count()) {
+                self.buffer.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+        } else if candidates.len() > 1 {
+            print!("\r\n{}\r\n", candidates.join("  "));
+        }
+        Ok(())
+    }
+}
+
+enum Key {
+    Char(char),
+    Control(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Unknown,
+}
+
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    match io::stdin().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn read_key() -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte()? else {
+        return Ok(None);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        127 | 8 => Key::Backspace,
+        // `ESC [ A` and friends
+        27 => match (read_byte()?, read_byte()?) {
+            (Some(b'['), Some(b'A')) => Key::Up,
+            (Some(b'['), Some(b'B')) => Key::Down,
+            (Some(b'['), Some(b'C')) => Key::Right,
+            (Some(b'['), Some(b'D')) => Key::Left,
+            (Some(b'['), Some(b'H')) => Key::Home,
+            (Some(b'['), Some(b'F')) => Key::End,
+            (Some(b'['), Some(b'3')) => {
+                read_byte()?; // '~'
+                Key::Delete
+            }
+            _ => Key::Unknown,
+        },
+        1..=26 => Key::Control((b'a' + byte - 1) as char),
+        byte if byte < 32 => Key::Unknown,
+        byte => {
+            // The rest of a UTF-8 character
+            let length = match byte {
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..length {
+                bytes.extend(read_byte()?);
+            }
+            match String::from_utf8(bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+    };
+    Ok(Some(key))
+}
+
+// Puts the terminal in raw mode until dropped
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_oflag &= !libc::OPOST;
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { original })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
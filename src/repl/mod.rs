@@ -0,0 +1,233 @@
+// Crabby's REPL: `crabby` with no input file starts one.
+// Every line runs in the same interpreter, so definitions carry over between lines.
+
+mod editor;
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::ast::{Program, Statement};
+use crate::core::metaprogram::MacroExpander;
+use crate::core::sandbox::Capabilities;
+use crate::etc::typechecker::TypeChecker;
+use crate::interpreter::Interpreter;
+use crate::lexer::{Token, TokenStream};
+use crate::parser::parse;
+use crate::utils::{CrabbyError, escape};
+use crate::value::Value;
+use editor::{Editor, Input};
+
+const PROMPT: &str = ">>> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+const COMMANDS: [(&str, &str); 6] = [
+    (":help", "Show this list"),
+    (":type <expr>", "Show the static type of an expression"),
+    (":ast <code>", "Show the syntax tree of some code"),
+    (":load <file>", "Run a file in this session"),
+    (":reset", "Forget every definition"),
+    (":quit", "Leave the REPL (or press Ctrl-D)"),
+];
+
+struct Session {
+    interpreter: Interpreter,
+    // Macros and types declared on earlier lines stay known
+    macros: MacroExpander,
+    checker: TypeChecker,
+    strict_numeric: bool,
+}
+
+impl Session {
+    fn new(strict_numeric: bool) -> Self {
+        let mut interpreter = Interpreter::new(None);
+        interpreter.strict_numeric = strict_numeric;
+        // Whoever types at the prompt can do all of this anyway
+        interpreter.set_capabilities(Capabilities::all());
+        Self {
+            interpreter,
+            macros: MacroExpander::new(),
+            checker: TypeChecker::new(),
+            strict_numeric,
+        }
+    }
+
+    fn parse(&mut self, source: &str) -> Result<Program, CrabbyError> {
+        let tokens = TokenStream::tokenize(source.to_string())?;
+        self.macros.expand(parse(tokens)?)
+    }
+
+    // Runs some code, printing the value of each expression statement
+    fn eval(&mut self, source: &str) -> Result<(), CrabbyError> {
+        let program = match self.parse(source) {
+            Ok(program) => program,
+            // A line with nothing but a comment
+            Err(CrabbyError::LexerError(location)) if location.message == "Empty source file" => {
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        self.checker.check(&program);
+        for statement in &program.statements {
+            let value = self.interpreter.interpret_statement(statement)?;
+            if let (Statement::Expression(_), Some(value)) = (statement, value)
+                && !matches!(value, Value::Void)
+            {
+                println!("{}", show(&value));
+            }
+        }
+        Ok(())
+    }
+
+    // Returns `false` when the REPL should exit
+    fn command(&mut self, line: &str) -> bool {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        let result = match command {
+            ":help" | ":h" => {
+                for (command, description) in COMMANDS {
+                    println!("  {:<14} {}", command, description);
+                }
+                Ok(())
+            }
+            ":type" | ":t" => self.show_type(argument),
+            ":ast" => self.parse(argument).map(|program| {
+                for statement in &program.statements {
+                    println!("{:#?}", statement);
+                }
+            }),
+            ":load" | ":l" => fs::read_to_string(argument)
+                .map_err(CrabbyError::from)
+                .and_then(|source| self.eval(&source)),
+            ":reset" => {
+                *self = Session::new(self.strict_numeric);
+                println!("Session reset");
+                Ok(())
+            }
+            ":quit" | ":q" | ":exit" => return false,
+            _ => Err(CrabbyError::InterpreterError(format!(
+                "Unknown command '{}'; try :help",
+                command
+            ))),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+        true
+    }
+
+    fn show_type(&mut self, source: &str) -> Result<(), CrabbyError> {
+        let program = self.parse(source)?;
+        match program.statements.as_slice() {
+            [Statement::Expression(expression)] => {
+                println!("{}", self.checker.type_of(expression));
+                Ok(())
+            }
+            _ => Err(CrabbyError::InterpreterError(
+                ":type takes a single expression".to_string(),
+            )),
+        }
+    }
+
+    fn complete(&self, word: &str) -> Vec<String> {
+        if word.starts_with(':') {
+            return COMMANDS
+                .iter()
+                .filter_map(|(command, _)| command.split(' ').next())
+                .filter(|command| command.starts_with(word))
+                .map(String::from)
+                .collect();
+        }
+        self.interpreter
+            .names()
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .collect()
+    }
+}
+
+pub fn run(strict_numeric: bool) -> Result<(), CrabbyError> {
+    let history_path =
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".crabby_history"));
+    let mut editor = Editor::new(history_path);
+    let mut session = Session::new(strict_numeric);
+    let interactive = Editor::is_interactive();
+
+    if interactive {
+        println!(
+            "Crabby {} REPL. Type :help for commands, Ctrl-D to exit.",
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+
+    // Lines of a statement that isn't finished yet
+    let mut pending = String::new();
+    loop {
+        let prompt = match (interactive, pending.is_empty()) {
+            (false, _) => "",
+            (true, true) => PROMPT,
+            (true, false) => CONTINUATION_PROMPT,
+        };
+        let line = match editor.read_line(prompt, &|word| session.complete(word))? {
+            Input::Line(line) => line,
+            Input::Interrupted => {
+                pending.clear();
+                continue;
+            }
+            Input::Eof => break,
+        };
+        editor.add_history(&line);
+
+        if pending.is_empty() && line.trim_start().starts_with(':') {
+            if !session.command(line.trim()) {
+                break;
+            }
+            continue;
+        }
+
+        pending.push_str(&line);
+        pending.push('\n');
+        if pending.trim().is_empty() {
+            pending.clear();
+            continue;
+        }
+        if is_incomplete(&pending) {
+            continue;
+        }
+
+        let source = std::mem::take(&mut pending);
+        if let Err(e) = session.eval(&source) {
+            eprintln!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+// Open brackets and triple-quoted strings continue on the next line
+fn is_incomplete(source: &str) -> bool {
+    match TokenStream::tokenize(source.to_string()) {
+        Ok(tokens) => {
+            let depth: i64 = tokens
+                .iter()
+                .map(|token| match token.token {
+                    Token::LBrace | Token::LParen | Token::LBracket => 1,
+                    Token::RBrace | Token::RParen | Token::RBracket => -1,
+                    _ => 0,
+                })
+                .sum();
+            depth > 0
+        }
+        Err(CrabbyError::LexerError(location)) => {
+            location.message.starts_with("Unterminated string")
+                && (location.message.ends_with("\"\"\"") || location.message.ends_with("'''"))
+        }
+        Err(_) => false,
+    }
+}
+
+// Strings are quoted so `"1"` and `1` look different
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{}\"", escape(s)),
+        value => value.to_string(),
+    }
+}
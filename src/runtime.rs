@@ -5,6 +5,182 @@
 // interpret.rs - Handles executing a '.crab' file
 // runtime.rs - manages functions, stacks, etc
 
-pub struct RuntimeCheck {}
+// The interpreter reports to a `RuntimeCheck` as it runs, when it has one, and stops
+// with a `LimitExceeded` error once a limit is hit. This is how untrusted code is run:
+//
+//     crabby run --max-loop-iterations 100000 --timeout 2 snippet.crab
 
-impl RuntimeCheck {}
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+use crate::utils::{CrabbyError, Span};
+use crate::value::Value;
+
+// No limit is set by default
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub max_loop_iterations: Option<u64>, // of all loops together
+    pub max_call_depth: Option<usize>,
+    pub max_heap_bytes: Option<usize>, // all the values ever made, added up
+    pub max_string_length: Option<usize>, // in bytes
+    pub max_array_length: Option<usize>, // arrays and dictionaries
+    pub timeout: Option<Duration>,
+}
+
+pub struct RuntimeCheck {
+    limits: Limits,
+    started: Instant,
+    iterations: u64,
+    heap_bytes: usize,
+    calls: Vec<(String, Option<Span>)>, // each function running, and where it was called
+    location: Option<Span>,             // the statement running
+}
+
+impl RuntimeCheck {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            started: Instant::now(),
+            iterations: 0,
+            heap_bytes: 0,
+            calls: Vec::new(),
+            location: None,
+        }
+    }
+
+    // Called before each statement
+    pub fn statement(&mut self, span: Option<Span>) -> Result<(), CrabbyError> {
+        if span.is_some() {
+            self.location = span;
+        }
+        match self.limits.timeout {
+            Some(timeout) if self.started.elapsed() > timeout => {
+                Err(self.exceeded(format!("ran for longer than {:?}", timeout), "--timeout"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // When `--timeout` runs out, if it was given
+    pub fn deadline(&self) -> Option<Instant> {
+        self.limits.timeout.map(|timeout| self.started + timeout)
+    }
+
+    pub fn loop_iteration(&mut self) -> Result<(), CrabbyError> {
+        self.iterations += 1;
+        match self.limits.max_loop_iterations {
+            Some(max) if self.iterations > max => Err(self.exceeded(
+                format!("ran more than {} loop iterations", max),
+                "--max-loop-iterations",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn enter_call(&mut self, name: &str) -> Result<(), CrabbyError> {
+        if let Some(max) = self.limits.max_call_depth
+            && self.calls.len() >= max
+        {
+            return Err(self.exceeded(
+                format!("nested calls more than {} deep by calling `{}`", max, name),
+                "--max-call-depth",
+            ));
+        }
+        self.calls.push((name.to_string(), self.location));
+        Ok(())
+    }
+
+    pub fn exit_call(&mut self) {
+        if let Some((_, location)) = self.calls.pop() {
+            self.location = location;
+        }
+    }
+
+    // Called with each value the interpreter makes
+    pub fn allocated(&mut self, value: &Value) -> Result<(), CrabbyError> {
+        let (length, max, what, flag) = match value {
+            Value::String(s) => (
+                s.len(),
+                self.limits.max_string_length,
+                "bytes long",
+                "--max-string-length",
+            ),
+            Value::Array(items) => (
+                items.len(),
+                self.limits.max_array_length,
+                "elements long",
+                "--max-array-length",
+            ),
+            Value::Dict(entries) => (
+                entries.len(),
+                self.limits.max_array_length,
+                "entries long",
+                "--max-array-length",
+            ),
+            _ => (0, None, "", ""),
+        };
+        if let Some(max) = max.filter(|max| length > *max) {
+            return Err(self.exceeded(
+                format!(
+                    "made a {} {} {}, more than the limit of {}",
+                    value.type_name(),
+                    length,
+                    what,
+                    max
+                ),
+                flag,
+            ));
+        }
+
+        self.heap_bytes = self.heap_bytes.saturating_add(heap_size(value));
+        match self.limits.max_heap_bytes {
+            Some(max) if self.heap_bytes > max => {
+                Err(self.exceeded(format!("allocated more than {} bytes", max), "--max-heap"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Called before working out a number that takes about `bits` bits, since
+    // `3 ** 400000000` would take long enough to build that the limit is never seen
+    pub fn will_allocate_integer(&mut self, bits: u64) -> Result<(), CrabbyError> {
+        let bytes = usize::try_from(bits.div_ceil(8)).unwrap_or(usize::MAX);
+        match self.limits.max_heap_bytes {
+            Some(max) if self.heap_bytes.saturating_add(bytes) > max => Err(self.exceeded(
+                format!(
+                    "would make an integer of about {} bytes, more than the {} bytes allowed",
+                    bytes, max
+                ),
+                "--max-heap",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Says which limit was hit, where, and in which functions
+    fn exceeded(&self, what: String, flag: &str) -> CrabbyError {
+        let mut message = format!("the program {} (see {})", what, flag);
+        for (name, call) in self.calls.iter().rev() {
+            message.push_str(&format!("\n  in `{}`", name));
+            if let Some(call) = call {
+                message.push_str(&format!(", called at line {}", call.line));
+            }
+        }
+        CrabbyError::LimitExceeded(self.location, message)
+    }
+}
+
+// What making a value takes beyond the `Value` itself; the values inside an array
+// or dictionary were counted when they were made
+fn heap_size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::BigInt(n) => n.bits().div_ceil(8) as usize,
+        Value::Array(items) => items.len() * size_of::<Value>(),
+        Value::Dict(entries) => entries
+            .iter()
+            .map(|(key, _)| key.len() + size_of::<(String, Value)>())
+            .sum(),
+        _ => 0,
+    }
+}
@@ -1,11 +1,18 @@
 use std::error::Error;
 use std::fmt;
 
-use crate::ast::{BinaryOp, Expression, Statement};
-use crate::etc::deadcode::DeadCodeWarning;
+use codespan_reporting::diagnostic::Diagnostic;
+use codespan_reporting::files::SimpleFile;
+use codespan_reporting::term::{self, termcolor::Buffer};
+
+use crate::ast::{
+    BinaryOp, Expression, FStringPart, Parameter, ParameterKind, Statement, TypeExpr,
+    TypeParameter, UnaryOp,
+};
+use crate::etc::deadcode::{DeadCodeWarning, SymbolKind};
 use crate::value::Value;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -36,10 +43,16 @@ pub enum CrabbyError {
     LexerError(ErrorLocation),
     ParserError(ErrorLocation),
     InterpreterError(String),
-    TypeError(String),
+    TypeError(Option<Span>, String), // span of the offending code, when known statically
     RuntimeError(String),
     IoError(String),
+    CompileError(String),
     MissingCaseKeyword(ErrorLocation),
+    ExpansionError(Span, String),
+    AssertionError(Option<Span>, String), // a failed `assert` or `expect(...)` check
+    LimitExceeded(Option<Span>, String),  // a limit set with `runtime::Limits`, and where
+    // Carries an `Err`/null value out of a function via `?`; never escapes a function call
+    Propagated(Value),
 }
 
 impl fmt::Display for Span {
@@ -52,9 +65,9 @@ impl fmt::Display for Span {
     }
 }
 
-impl std::fmt::Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
     }
 }
 
@@ -64,7 +77,7 @@ impl Expression {
             (Expression::Integer(a), Expression::Integer(b)) => a == b,
             (Expression::Float(a), Expression::Float(b)) => a == b,
             (Expression::String(a), Expression::String(b)) => a == b,
-            (Expression::Variable(a), Expression::Variable(b)) => a == b,
+            (Expression::Variable { name: a, .. }, Expression::Variable { name: b, .. }) => a == b,
             (Expression::Boolean(a), Expression::Boolean(b)) => a == b,
             (Expression::Array(a), Expression::Array(b)) => {
                 if a.len() != b.len() {
@@ -89,7 +102,29 @@ impl Expression {
 
 impl fmt::Display for DeadCodeWarning {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(
+            f,
+            "line {}, column {}: {} [{}]",
+            self.span.line,
+            self.span.column,
+            self.message(),
+            self.code()
+        )
+    }
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Variable => "variable",
+            SymbolKind::Parameter => "parameter",
+            SymbolKind::Import => "import",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Class => "class",
+        };
+        write!(f, "{}", kind)
     }
 }
 
@@ -97,10 +132,14 @@ impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expression::Integer(n) => write!(f, "{}", n),
+            Expression::BigInteger(n) => write!(f, "{}", n),
             Expression::Float(f_val) => write!(f, "{}", f_val),
             Expression::String(s) => write!(f, "{}", s),
-            Expression::Variable(name) => write!(f, "{}", name),
+            Expression::Variable { name, .. } => write!(f, "{}", name),
             Expression::Boolean(b) => write!(f, "{}", b),
+            Expression::Maybe(_) => write!(f, "maybe"),
+            Expression::Probably(_) => write!(f, "probably"),
+            Expression::Null => write!(f, "null"),
             Expression::Range(count) => write!(f, "range({})", count),
             Expression::Pattern(pattern) => write!(f, "{:?}", pattern),
             Expression::Where {
@@ -114,14 +153,36 @@ impl fmt::Display for Expression {
                 left,
                 operator,
                 right,
+                span: _,
             } => {
                 write!(f, "({} {} {})", left, operator, right)
             }
-            Expression::FString {
-                template,
-                expressions: _,
-            } => {
-                write!(f, "f\"{}\"", template)
+            Expression::Unary { operator, operand } => write!(f, "{}{}", operator, operand),
+            Expression::Borrow {
+                expr,
+                mutable: true,
+                ..
+            } => write!(f, "&mut {}", expr),
+            Expression::Borrow { expr, .. } => write!(f, "&{}", expr),
+            Expression::Move { expr, .. } => write!(f, "move {}", expr),
+            Expression::FString(parts) => {
+                write!(f, "f\"")?;
+                for part in parts {
+                    match part {
+                        FStringPart::Text(text) => {
+                            write!(f, "{}", escape(text).replace('{', "{{").replace('}', "}}"))?
+                        }
+                        FStringPart::Hole {
+                            expression,
+                            spec: None,
+                        } => write!(f, "{{{}}}", expression)?,
+                        FStringPart::Hole {
+                            expression,
+                            spec: Some(spec),
+                        } => write!(f, "{{{}:{}}}", expression, spec)?,
+                    }
+                }
+                write!(f, "\"")
             }
             Expression::Await { expr } => {
                 write!(f, "await {}", expr)
@@ -129,6 +190,8 @@ impl fmt::Display for Expression {
             Expression::Call {
                 function,
                 arguments,
+                keyword_arguments,
+                ..
             } => {
                 write!(
                     f,
@@ -137,12 +200,26 @@ impl fmt::Display for Expression {
                     arguments
                         .iter()
                         .map(|arg| arg.to_string())
+                        .chain(
+                            keyword_arguments
+                                .iter()
+                                .map(|arg| format!("{}: {}", arg.name, arg.value))
+                        )
                         .collect::<Vec<_>>()
                         .join(", ")
                 )
             }
             Expression::Lambda { params, body } => {
-                write!(f, "lambda({}) {}", params.join(", "), body)
+                write!(
+                    f,
+                    "lambda({}) {}",
+                    params
+                        .iter()
+                        .map(|param| param.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    body
+                )
             }
             Expression::Array(elements) => {
                 write!(
@@ -158,6 +235,119 @@ impl fmt::Display for Expression {
             Expression::Index { array, index } => {
                 write!(f, "{}[{}]", array, index)
             }
+            Expression::Member {
+                object,
+                field,
+                optional,
+            } => {
+                write!(
+                    f,
+                    "{}{}{}",
+                    object,
+                    if *optional { "?." } else { "." },
+                    field
+                )
+            }
+            Expression::MethodCall {
+                object,
+                method,
+                arguments,
+                keyword_arguments,
+                optional,
+            } => {
+                write!(
+                    f,
+                    "{}{}{}({})",
+                    object,
+                    if *optional { "?." } else { "." },
+                    method,
+                    arguments
+                        .iter()
+                        .map(|arg| arg.to_string())
+                        .chain(
+                            keyword_arguments
+                                .iter()
+                                .map(|arg| format!("{}: {}", arg.name, arg.value))
+                        )
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+            Expression::Try(expr) => write!(f, "{}?", expr),
+            Expression::TypeOf(expr) => write!(f, "typeof({})", expr),
+            Expression::Is { value, type_expr } => write!(f, "{} is {}", value, type_expr),
+            Expression::MacroCall {
+                name, arguments, ..
+            } => {
+                write!(
+                    f,
+                    "{}!({})",
+                    name,
+                    arguments
+                        .iter()
+                        .map(|arg| arg.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParameterKind::Positional => write!(f, "{}", self.name)?,
+            ParameterKind::Variadic => write!(f, "*{}", self.name)?,
+            ParameterKind::KeywordVariadic => write!(f, "**{}", self.name)?,
+        }
+        if let Some(annotation) = &self.type_annotation {
+            write!(f, ": {}", annotation)?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " = {}", default)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TypeParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bounds.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}: {}", self.name, self.bounds.join(" + "))
+        }
+    }
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::Named(name) => write!(f, "{}", name),
+            TypeExpr::List(element) => write!(f, "[{}]", element),
+            TypeExpr::Generic { name, arguments } => write!(
+                f,
+                "{}<{}>",
+                name,
+                arguments
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            TypeExpr::Optional(inner) => write!(f, "{}?", inner),
+            TypeExpr::Function { params, returns } => write!(
+                f,
+                "({}) -> {}",
+                params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                returns
+            ),
+            TypeExpr::Array { element, length } => write!(f, "{}[{}]", element, length),
         }
     }
 }
@@ -169,9 +359,32 @@ impl fmt::Display for BinaryOp {
             BinaryOp::Sub => write!(f, "-"),
             BinaryOp::Mul => write!(f, "*"),
             BinaryOp::Div => write!(f, "/"),
-            BinaryOp::Eq => write!(f, "="),
+            BinaryOp::Mod => write!(f, "%"),
+            BinaryOp::Pow => write!(f, "**"),
+            BinaryOp::BitAnd => write!(f, "&"),
+            BinaryOp::BitOr => write!(f, "|"),
+            BinaryOp::BitXor => write!(f, "^"),
+            BinaryOp::ShiftLeft => write!(f, "<<"),
+            BinaryOp::ShiftRight => write!(f, ">>"),
+            BinaryOp::Eq => write!(f, "=="),
+            BinaryOp::NotEq => write!(f, "!="),
+            BinaryOp::Less => write!(f, "<"),
+            BinaryOp::LessEq => write!(f, "<="),
+            BinaryOp::Greater => write!(f, ">"),
+            BinaryOp::GreaterEq => write!(f, ">="),
             BinaryOp::Dot => write!(f, "."),
             BinaryOp::MatchOp => write!(f, "=>"),
+            BinaryOp::NullCoalesce => write!(f, "??"),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-"),
+            UnaryOp::BitNot => write!(f, "~"),
+            UnaryOp::Not => write!(f, "!"),
         }
     }
 }
@@ -214,10 +427,36 @@ impl fmt::Display for CrabbyError {
                 "Missing case keyword at line {}, column {}: {}",
                 loc.line, loc.column, loc.message
             ),
+            CrabbyError::ExpansionError(span, msg) => write!(
+                f,
+                "Macro expansion error at line {}, column {}: {}",
+                span.line, span.column, msg
+            ),
+            CrabbyError::AssertionError(Some(span), msg) => write!(
+                f,
+                "Assertion failed at line {}, column {}: {}",
+                span.line, span.column, msg
+            ),
+            CrabbyError::AssertionError(None, msg) => write!(f, "Assertion failed: {}", msg),
+            CrabbyError::LimitExceeded(Some(span), msg) => write!(
+                f,
+                "Limit exceeded at line {}, column {}: {}",
+                span.line, span.column, msg
+            ),
+            CrabbyError::LimitExceeded(None, msg) => write!(f, "Limit exceeded: {}", msg),
             CrabbyError::InterpreterError(msg) => write!(f, "Interpreter error: {}", msg),
-            CrabbyError::TypeError(msg) => write!(f, "Type error: {}", msg),
+            CrabbyError::TypeError(Some(span), msg) => write!(
+                f,
+                "Type error at line {}, column {}: {}",
+                span.line, span.column, msg
+            ),
+            CrabbyError::TypeError(None, msg) => write!(f, "Type error: {}", msg),
             CrabbyError::RuntimeError(msg) => write!(f, "Runtime error: {}", msg),
             CrabbyError::IoError(msg) => write!(f, "IO error: {}", msg),
+            CrabbyError::CompileError(msg) => write!(f, "Compile error: {}", msg),
+            CrabbyError::Propagated(value) => {
+                write!(f, "Unhandled {} propagated with '?'", value)
+            }
         }
     }
 }
@@ -229,3 +468,38 @@ impl From<std::io::Error> for CrabbyError {
         CrabbyError::IoError(error.to_string())
     }
 }
+
+// Renders diagnostics against the source they were found in, as `check`, `lint` and
+// the ownership checker print them
+pub fn render_diagnostics(
+    diagnostics: impl IntoIterator<Item = Diagnostic<()>>,
+    file_name: &str,
+    source: &str,
+) -> String {
+    let file = SimpleFile::new(file_name, source);
+    let config = term::Config::default();
+    let mut buffer = Buffer::no_color();
+    for diagnostic in diagnostics {
+        // Writing to a buffer can't fail, except on a span outside the source
+        let _ = term::emit(&mut buffer, &config, &file, &diagnostic);
+    }
+    String::from_utf8_lossy(buffer.as_slice()).into_owned()
+}
+
+// Writes `text` back as the inside of a double-quoted string literal
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,22 +1,80 @@
 // Value file that defines what value **exist** in Crabby.
 
-use crate::ast::Statement;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{ClassField, Parameter, Statement, TypeExpr};
 use crate::utils::CrabbyError;
+use num_bigint::BigInt;
+use num_traits::Zero;
+
+// Calls a `@memoize`d function has already answered
+pub type Memo = Rc<RefCell<Vec<(Vec<Value>, Value)>>>;
 
 #[derive(Clone, PartialEq)]
 pub struct Function {
-    pub params: Vec<String>,
+    pub params: Vec<Parameter>,
     pub body: Box<Statement>,
+    // Variables visible where the function was defined, so closures keep them
+    pub captured: Vec<(String, Value)>,
+    // Set by the built-in decorators `@memoize`, `@deprecated` and `@test`
+    pub memo: Option<Memo>,
+    pub deprecated: Option<String>,
+    pub is_test: bool,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Class {
+    pub name: String,
+    pub parent: Option<String>,
+    pub fields: Vec<ClassField>,
+    pub methods: Vec<(String, Function)>,
+}
+
+// A struct value, enum variant or class instance
+#[derive(Clone, PartialEq)]
+pub struct Instance {
+    pub type_name: String,
+    pub variant: Option<String>, // set for enum variants
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Function {
+    pub fn new(params: Vec<Parameter>, body: Box<Statement>) -> Self {
+        Self {
+            params,
+            body,
+            captured: Vec::new(),
+            memo: None,
+            deprecated: None,
+            is_test: false,
+        }
+    }
+
+    // Renders the parameter list, e.g. `(a, b = 10, *rest, **options)`
+    pub fn signature(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(|p| p.to_string()).collect();
+        format!("({})", params.join(", "))
+    }
 }
 
 #[derive(Clone)]
 pub enum Value {
     Integer(i64),
+    BigInt(BigInt), // only for integers that do not fit in an `Integer`
     Float(f64),
     String(String),
     Lambda(Function),
+    Class(Class),
     Boolean(bool),
     Array(Vec<Value>),
+    Dict(Vec<(String, Value)>),
+    Ok(Box<Value>),
+    Err(Box<Value>),
+    Instance(Instance),
+    Type(TypeExpr), // what `typeof` returns; printable and comparable
+    Null,
     Void,
 }
 
@@ -24,49 +82,181 @@ impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
             (Value::Float(a), Value::Float(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Lambda(_), Value::Lambda(_)) => false,
+            (Value::Class(a), Value::Class(b)) => a.name == b.name,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Dict(a), Value::Dict(b)) => a == b,
+            (Value::Ok(a), Value::Ok(b)) => a == b,
+            (Value::Err(a), Value::Err(b)) => a == b,
+            (Value::Instance(a), Value::Instance(b)) => a == b,
+            (Value::Type(a), Value::Type(b)) => a == b,
+            // `typeof(person) == Person`, where `Person` is a class
+            (Value::Type(TypeExpr::Named(name)), Value::Class(class))
+            | (Value::Class(class), Value::Type(TypeExpr::Named(name))) => *name == class.name,
+            (Value::Null, Value::Null) => true,
             (Value::Void, Value::Void) => true,
             _ => false,
         }
     }
 }
 
-impl Value {
-    pub fn to_string(&self) -> String {
+// How `print` and string interpolation show a value
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Value::Integer(n) => n.to_string(),
-            Value::Float(f) => f.to_string(),
-            Value::String(s) => s.clone(),
-            Value::Lambda(_) => "<lambda>".to_string(),
-            Value::Boolean(b) => b.to_string(),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::BigInt(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::String(s) => f.write_str(s),
+            Value::Lambda(function) => write!(f, "<lambda{}>", function.signature()),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Boolean(b) => write!(f, "{}", b),
             Value::Array(elements) => {
                 let elements_str: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
-                format!("[{}]", elements_str.join(", "))
+                write!(f, "[{}]", elements_str.join(", "))
             }
-            Value::Void => "void".to_string(),
+            Value::Dict(entries) => {
+                let entries_str: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", entries_str.join(", "))
+            }
+            Value::Ok(value) => write!(f, "Ok({})", value),
+            Value::Err(error) => write!(f, "Err({})", error),
+            Value::Instance(instance) => {
+                let fields: Vec<String> = instance
+                    .fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                match &instance.variant {
+                    Some(variant) if fields.is_empty() => {
+                        write!(f, "{}.{}", instance.type_name, variant)
+                    }
+                    Some(variant) => {
+                        let values: Vec<String> =
+                            instance.fields.iter().map(|(_, v)| v.to_string()).collect();
+                        write!(
+                            f,
+                            "{}.{}({})",
+                            instance.type_name,
+                            variant,
+                            values.join(", ")
+                        )
+                    }
+                    None => write!(f, "{} {{{}}}", instance.type_name, fields.join(", ")),
+                }
+            }
+            Value::Type(ty) => write!(f, "{}", ty),
+            Value::Null => f.write_str("null"),
+            Value::Void => f.write_str("void"),
         }
     }
+}
 
+impl Value {
     pub fn matches(&self, other: &Value) -> bool {
         match (self, other) {
             (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
             (Value::Float(a), Value::Float(b)) => (a - b).abs() < f64::EPSILON,
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Array(a), Value::Array(b)) => {
                 a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.matches(y))
             }
+            (Value::Dict(a), Value::Dict(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((ka, va), (kb, vb))| ka == kb && va.matches(vb))
+            }
+            (Value::Ok(a), Value::Ok(b)) => a.matches(b),
+            (Value::Err(a), Value::Err(b)) => a.matches(b),
+            (Value::Instance(_), Value::Instance(_))
+            | (Value::Type(_), Value::Type(_) | Value::Class(_)) => self == other,
+            (Value::Null, Value::Null) => true,
             (Value::Void, Value::Void) => true,
             // Lambda comparison is always false since functions aren't comparable
             (Value::Lambda(_), Value::Lambda(_)) => false,
+            (Value::Class(a), Value::Class(b)) => a.name == b.name,
             _ => false,
         }
     }
 
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) | Value::BigInt(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Lambda(_) => "function",
+            Value::Class(_) => "class",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Dict(_) => "dict",
+            Value::Ok(_) | Value::Err(_) => "result",
+            Value::Instance(_) => "instance",
+            Value::Type(_) => "type",
+            Value::Null => "null",
+            Value::Void => "void",
+        }
+    }
+
+    // `if` and `while` take booleans or optionals: null is false and a present value is
+    // true. Numbers are not conditions, so `if 1` stays an error
+    pub fn condition(&self) -> Result<bool, CrabbyError> {
+        match self {
+            Value::Boolean(b) => Ok(*b),
+            Value::Null => Ok(false),
+            Value::Integer(_) | Value::BigInt(_) | Value::Float(_) | Value::Void => Err(
+                CrabbyError::InterpreterError("Condition must be boolean".into()),
+            ),
+            _ => Ok(true),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Boolean(b) => *b,
+            Value::Integer(n) => *n != 0,
+            Value::BigInt(n) => !n.is_zero(),
+            Value::Null | Value::Void => false,
+            _ => true,
+        }
+    }
+
+    pub fn get_field(&self, field: &str) -> Result<Value, CrabbyError> {
+        match self {
+            Value::Instance(instance) => instance
+                .fields
+                .iter()
+                .find(|(key, _)| key == field)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| {
+                    CrabbyError::InterpreterError(format!(
+                        "{} has no field '{}'",
+                        instance.type_name, field
+                    ))
+                }),
+            Value::Dict(entries) => entries
+                .iter()
+                .find(|(key, _)| key == field)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| {
+                    CrabbyError::InterpreterError(format!("Dict has no field '{}'", field))
+                }),
+            other => Err(CrabbyError::TypeError(
+                None,
+                format!("Cannot read field '{}' of {}", field, other.type_name()),
+            )),
+        }
+    }
+
     // pub fn equals(&self, other: &Value) -> bool {
     //    match (self, other) {
     //        (Value::Integer(a), Value::Integer(b)) => a == b,
@@ -69,6 +69,10 @@ impl BytecodeFile {
         }
 
         // Writes the instruction section
+        writer.write_all(&(self.instructions.len() as u32).to_le_bytes())?;
+        for instruction in &self.instructions {
+            self.write_instruction(&mut writer, instruction)?;
+        }
         writer.flush()?;
         println!("Bytecode file format:");
         println!("  Magic: {:?}", std::str::from_utf8(Self::MAGIC).unwrap());
@@ -84,7 +88,7 @@ impl BytecodeFile {
         let mut reader = BufReader::new(file);
 
         // Reads and verifies the MAGIC number
-        let mut magic = [0u8; 4];
+        let mut magic = [0u8; Self::MAGIC.len()];
         reader.read_exact(&mut magic)?;
         if magic != Self::MAGIC {
             return Err("Invalid bytecode file: Wrong magic number!".into());
@@ -210,9 +214,9 @@ impl BytecodeFile {
 
         match opcode[0] {
             0x01 => {
-                let mut index_bytes = [0u8; 8];
+                let mut index_bytes = [0u8; 4];
                 reader.read_exact(&mut index_bytes)?;
-                let index = usize::from_le_bytes(index_bytes);
+                let index = u32::from_le_bytes(index_bytes) as usize;
                 Ok(Instructions::LoadConstant(index))
             }
             0x02 => {
@@ -0,0 +1,37 @@
+// `crabby build` and `crabby disasm`: the bytecode subset and how they fail
+
+mod common;
+use common::{crabby, scratch, scratch_dir};
+
+#[test]
+fn build_compiles_the_supported_subset() {
+    let file = scratch("subset", "let x = 1 + 2 * 3\nprint(x)\n");
+    let (output, stderr) = crabby(&["build", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(file.with_extension("cby").exists());
+
+    let (output, stderr) = crabby(&["disasm", file.with_extension("cby").to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr);
+}
+
+#[test]
+fn build_refuses_functions_with_the_line_and_an_alternative() {
+    let file = scratch("functions", "let x = 1\ndef f() {\n    return 1\n}\n");
+    let (output, stderr) = crabby(&["build", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(
+        stderr.contains("Line 2: Bytecode does not support FunctionDef statements yet"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("crabby run"), "{}", stderr);
+    assert!(!file.with_extension("cby").exists());
+}
+
+#[test]
+fn disasm_names_a_missing_file() {
+    let missing = scratch_dir("missing").join("nope.cby");
+    let (output, stderr) = crabby(&["disasm", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("nope.cby"), "{}", stderr);
+}
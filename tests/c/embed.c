@@ -0,0 +1,112 @@
+// Runs Crabby through libcrabby's C interface; built and run by tests/c_api.rs.
+// Prints what failed and exits with 1, or exits with 0.
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "crabby.h"
+
+static int failures = 0;
+
+#define CHECK(condition)                                                 \
+    do {                                                                 \
+        if (!(condition)) {                                              \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #condition); \
+            failures++;                                                  \
+        }                                                                \
+    } while (0)
+
+// `scale(n)` multiplies `n` by the int `user_data` points to
+static int scale(void *user_data, const CrabbyValue *args, size_t count, CrabbyValue *result) {
+    if (count != 1 || args[0].type != CRABBY_TYPE_INTEGER) {
+        result->type = CRABBY_TYPE_STRING;
+        result->string = "scale takes one integer";
+        return 1;
+    }
+    result->type = CRABBY_TYPE_INTEGER;
+    result->integer = args[0].integer * *(int *)user_data;
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    CrabbyEngine *engine = crabby_new();
+    CrabbyValue value;
+
+    CHECK(crabby_eval(engine, "def add(a, b) { return a + b }\nadd(40, 2)", &value) == CRABBY_OK);
+    CHECK(value.type == CRABBY_TYPE_INTEGER && value.integer == 42);
+
+    CrabbyValue args[2] = {
+        {.type = CRABBY_TYPE_STRING, .string = "crab"},
+        {.type = CRABBY_TYPE_STRING, .string = "by"},
+    };
+    CHECK(crabby_call(engine, "add", args, 2, &value) == CRABBY_OK);
+    CHECK(value.type == CRABBY_TYPE_STRING && strcmp(value.string, "crabby") == 0);
+    crabby_value_free(&value);
+    CHECK(value.type == CRABBY_TYPE_NULL && value.string == NULL);
+
+    CrabbyValue ratio = {.type = CRABBY_TYPE_FLOAT, .number = 0.5};
+    CHECK(crabby_set_global(engine, "ratio", ratio) == CRABBY_OK);
+    CHECK(crabby_eval(engine, "let doubled = [ratio * 2]\nprint(doubled)", NULL) == CRABBY_OK);
+    CHECK(crabby_get_global(engine, "doubled", &value) == CRABBY_OK);
+    CHECK(value.type == CRABBY_TYPE_OTHER && strcmp(value.string, "[1]") == 0);
+    crabby_value_free(&value);
+    char *printed = crabby_take_stdout(engine);
+    CHECK(strcmp(printed, "[1]\n") == 0);
+    crabby_string_free(printed);
+
+    int factor = 3;
+    CHECK(crabby_register(engine, "scale", scale, &factor) == CRABBY_OK);
+    CHECK(crabby_eval(engine, "scale(14)", &value) == CRABBY_OK);
+    CHECK(value.type == CRABBY_TYPE_INTEGER && value.integer == 42);
+    CHECK(crabby_last_error(engine) == NULL);
+
+    CHECK(crabby_eval(engine, "scale(\"x\")", NULL) == CRABBY_ERROR);
+    const CrabbyError *error = crabby_last_error(engine);
+    CHECK(error != NULL && error->kind == CRABBY_ERROR_KIND_RUNTIME);
+    CHECK(error != NULL && strstr(error->message, "scale takes one integer") != NULL);
+
+    CHECK(crabby_eval(engine, "let x = 1\nlet = 2", NULL) == CRABBY_ERROR);
+    error = crabby_last_error(engine);
+    CHECK(error != NULL && error->kind == CRABBY_ERROR_KIND_SYNTAX && error->line == 2);
+
+    CHECK(crabby_get_global(engine, "missing", &value) == CRABBY_ERROR);
+
+    if (argc > 1) {
+        CHECK(crabby_eval_file(engine, argv[1], &value) == CRABBY_OK);
+        CHECK(value.type == CRABBY_TYPE_BOOLEAN && value.boolean);
+    }
+    CHECK(crabby_eval_file(engine, "/nonexistent/script.crab", NULL) == CRABBY_ERROR);
+    error = crabby_last_error(engine);
+    CHECK(error != NULL && error->kind == CRABBY_ERROR_KIND_IO);
+
+    CHECK(crabby_eval(engine, "@deprecated\ndef old() { return 1 }\nold()", NULL) == CRABBY_OK);
+    char *warnings = crabby_take_stderr(engine);
+    CHECK(strstr(warnings, "old") != NULL);
+    crabby_string_free(warnings);
+    warnings = crabby_take_stderr(engine);
+    CHECK(strcmp(warnings, "") == 0);
+    crabby_string_free(warnings);
+
+    // Nesting too deep to parse is a syntax error, not a stack overflow
+    char nested[2100] = "print(";
+    memset(nested + 6, '(', 1000);
+    memset(nested + 1007, ')', 1000);
+    nested[1006] = '1';
+    strcpy(nested + 2007, ")");
+    CHECK(crabby_eval(engine, nested, NULL) == CRABBY_ERROR);
+    error = crabby_last_error(engine);
+    CHECK(error != NULL && error->kind == CRABBY_ERROR_KIND_SYNTAX);
+    CHECK(error != NULL && strstr(error->message, "Too deeply nested") != NULL);
+
+    // An unsupported statement is an error, and the engine keeps working
+    CHECK(crabby_eval(engine, "import math from \"math\"", NULL) == CRABBY_ERROR);
+    error = crabby_last_error(engine);
+    CHECK(error != NULL && error->kind == CRABBY_ERROR_KIND_RUNTIME);
+    CHECK(error != NULL && strstr(error->message, "import is not supported yet") != NULL);
+    CHECK(crabby_eval(engine, "1 + 1", &value) == CRABBY_OK);
+    CHECK(value.type == CRABBY_TYPE_INTEGER && value.integer == 2);
+
+    crabby_free(engine);
+    return failures == 0 ? 0 : 1;
+}
@@ -0,0 +1,62 @@
+// Builds tests/c/embed.c against libcrabby and include/crabby.h, and runs it
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn the_committed_header_is_up_to_date() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let generated = PathBuf::from(env!("OUT_DIR")).join("crabby.h");
+    assert!(
+        fs::read_to_string(root.join("include").join("crabby.h")).ok()
+            == fs::read_to_string(&generated).ok(),
+        "include/crabby.h is out of date; copy it from {}",
+        generated.display()
+    );
+}
+
+#[test]
+fn c_programs_can_embed_crabby() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // libcrabby is built next to the `crabby` executable
+    let libraries = PathBuf::from(env!("CARGO_BIN_EXE_crabby"))
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let dir = std::env::temp_dir().join("crabby-c-api");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.crab");
+    fs::write(&script, "let ready = true\nready\n").unwrap();
+
+    let program = dir.join("embed");
+    let compiled = Command::new("cc")
+        .arg(root.join("tests").join("c").join("embed.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&libraries)
+        .arg("-lcrabby")
+        .arg("-o")
+        .arg(&program)
+        .output()
+        .expect("a C compiler is installed as `cc`");
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+
+    let output = Command::new(&program)
+        .arg(&script)
+        .env("LD_LIBRARY_PATH", &libraries)
+        .env("DYLD_LIBRARY_PATH", &libraries)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
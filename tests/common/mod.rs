@@ -0,0 +1,65 @@
+// Helpers shared by the integration tests; each test file uses only some of them
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crabby::etc::typechecker::TypeChecker;
+use crabby::lexer::TokenStream;
+use crabby::parser::parse;
+
+// An empty directory for one test, under a name no other test file uses
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crabby-{}-{}", env!("CARGO_CRATE_NAME"), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A scratch directory holding `source` as main.crab; returns the file
+pub fn scratch(name: &str, source: &str) -> PathBuf {
+    let file = scratch_dir(name).join("main.crab");
+    fs::write(&file, source).unwrap();
+    file
+}
+
+// Runs the binary, giving its output and its stderr as text
+pub fn crabby(args: &[&str]) -> (Output, String) {
+    finish(Command::new(env!("CARGO_BIN_EXE_crabby")).args(args))
+}
+
+// Same, from inside `dir`, for commands that read relative paths
+pub fn crabby_in(dir: &Path, args: &[&str]) -> (Output, String) {
+    finish(
+        Command::new(env!("CARGO_BIN_EXE_crabby"))
+            .current_dir(dir)
+            .args(args),
+    )
+}
+
+fn finish(command: &mut Command) -> (Output, String) {
+    let output = command.output().expect("crabby runs");
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    (output, stderr)
+}
+
+// What `crabby check` reports for `source`, without running it
+pub fn check(source: &str) -> Vec<String> {
+    let program = parse(TokenStream::tokenize(source.to_string()).unwrap()).unwrap();
+    TypeChecker::new()
+        .check(&program)
+        .iter()
+        .map(|error| error.to_string())
+        .collect()
+}
+
+pub fn assert_rejects(source: &str, message: &str) {
+    let errors = check(source);
+    assert!(
+        errors.iter().any(|error| error.contains(message)),
+        "expected {:?} in {:?}",
+        message,
+        errors
+    );
+}
@@ -0,0 +1,111 @@
+// Dead-code warnings from `crabby check` and `crabby run --deny-warnings`
+
+mod common;
+use common::{crabby, scratch};
+
+const SOURCE: &str = r#"import math from "math"
+
+def area(width, height) {
+    return width * width
+    print("done")
+}
+
+def total(items) {
+    var sum = 0
+    var sum = 1
+    for item in items {
+        var sum = sum + item
+    }
+    let items = []
+    return sum
+}
+
+def _helper() {
+    return 1
+}
+
+print(area(2, 3))
+print(total([1, 2]))
+"#;
+
+#[test]
+fn check_reports_each_warning_at_its_location() {
+    let file = scratch("check", SOURCE);
+    let (output, stderr) = crabby(&["check", file.to_str().unwrap()]);
+
+    // The import can't run yet, which is an error; the warnings still come with it
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    for expected in [
+        "Type error at line 1, column 1: import is not supported yet",
+        "warning[unused_import]: unused import `math`",
+        "main.crab:1:8",
+        "warning[unused_parameter]: unused parameter `height`",
+        "main.crab:3:17",
+        "warning[unreachable_code]: unreachable statement",
+        "main.crab:5:5",
+        "warning[unused_assignment]: value assigned to `sum` is never read",
+        "main.crab:9:9",
+        "warning[shadowed_variable]: `items` shadows a variable of the same name",
+        "main.crab:14:9",
+        "previously declared here",
+    ] {
+        assert!(
+            stderr.contains(expected),
+            "missing {:?} in\n{}",
+            expected,
+            stderr
+        );
+    }
+    // Reassigning in a nested block may keep the earlier value alive
+    assert!(!stderr.contains("main.crab:10:9"), "{}", stderr);
+    // Names starting with `_` are unused on purpose
+    assert!(!stderr.contains("_helper"), "{}", stderr);
+}
+
+#[test]
+fn deny_warnings_fails_before_running() {
+    let file = scratch(
+        "deny",
+        "def greet(name) {\n    return \"hi\"\n}\nprint(greet(1))\n",
+    );
+    let (output, stderr) = crabby(&["run", "--deny-warnings", file.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(
+        stderr.contains("error[unused_parameter]: unused parameter `name`"),
+        "{}",
+        stderr
+    );
+    assert!(output.stdout.is_empty());
+
+    let clean = scratch(
+        "clean",
+        "def greet(name) {\n    return name\n}\nprint(greet(1))\n",
+    );
+    let (output, stderr) = crabby(&["check", "--deny-warnings", clean.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stderr.is_empty(), "{}", stderr);
+}
+
+#[test]
+fn a_rebound_variable_gets_one_warning_per_binding() {
+    let file = scratch(
+        "rebound",
+        "def f() {\n    let x = 1\n    let x = 2\n    return 0\n}\nprint(f())\n",
+    );
+    let (output, stderr) = crabby(&["check", file.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(stderr.matches("main.crab:2:").count(), 1, "{}", stderr);
+    assert!(
+        stderr.contains("warning[unused_assignment]: value assigned to `x` is never read"),
+        "{}",
+        stderr
+    );
+    assert_eq!(stderr.matches("main.crab:3:").count(), 1, "{}", stderr);
+    assert!(
+        stderr.contains("warning[unused_variable]: unused variable `x`"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,85 @@
+// Decorators on functions and methods, and class field defaults
+
+use crabby::Engine;
+
+fn run(source: &str) -> (String, String) {
+    let mut engine = Engine::new();
+    engine.eval(source).unwrap();
+    (engine.take_stdout(), engine.take_stderr())
+}
+
+#[test]
+fn class_fields_start_from_their_defaults() {
+    let (output, _) = run(r#"
+class Counter {
+    var count = 5
+    var step = count_step()
+    var label
+}
+def count_step() {
+    return 2
+}
+let counter = Counter()
+print(counter.count)
+print(counter.step)
+print(counter.label)
+print(Counter(count: 1).count)
+print(Counter(7).count)
+"#);
+    assert_eq!(output, "5\n2\nnull\n1\n7\n");
+}
+
+#[test]
+fn method_decorators_apply_like_function_decorators() {
+    let (output, warnings) = run(r#"
+def shout(method) {
+    return (self) => {
+        print("HEY")
+        return method(self)
+    }
+}
+class Calculator {
+    var base = 10
+
+    @memoize
+    def square(self, n) {
+        print("computing")
+        return n * n
+    }
+
+    @deprecated("use square")
+    def old(self) {
+        return self.base
+    }
+
+    @shout
+    def name(self) {
+        return "calc"
+    }
+}
+let calc = Calculator()
+print(calc.square(3))
+print(calc.square(3))
+print(calc.old())
+print(calc.name())
+"#);
+    assert_eq!(output, "computing\n9\n9\n10\nHEY\ncalc\n");
+    assert_eq!(warnings, "Warning: 'old' is deprecated: use square\n");
+}
+
+#[test]
+fn methods_are_found_on_parent_classes() {
+    let (output, _) = run(r#"
+class Animal {
+    var sound = "..."
+    def speak(self) {
+        return self.sound
+    }
+}
+class Dog extend Animal {
+    var sound = "woof"
+}
+print(Dog().speak())
+"#);
+    assert_eq!(output, "woof\n");
+}
@@ -0,0 +1,130 @@
+// `crabby doc` over a scratch project of two modules
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value as Json;
+
+mod common;
+use common::{crabby, scratch_dir};
+
+const SHAPES: &str = r#""""Shapes and how to measure them."""
+
+/// A point in the plane; see `distance`.
+pub struct Point {
+    x: Int,
+    y: Int
+}
+
+struct Cache {
+    points: [Point]
+}
+
+pub trait Area {
+    """Something with an area"""
+    def area(self)
+}
+
+pub def distance(a: Point, b: Point) -> Float {
+    """How far apart two `Point`s are"""
+    return 0.0
+}
+
+def helper() {
+    return 1
+}
+"#;
+
+fn project(name: &str) -> PathBuf {
+    let dir = scratch_dir(name);
+    fs::create_dir_all(dir.join("src/geo")).unwrap();
+    fs::write(dir.join("src/geo/shapes.crab"), SHAPES).unwrap();
+    fs::write(
+        dir.join("src/main.crab"),
+        "import distance from \"geo/shapes\"\n",
+    )
+    .unwrap();
+    dir
+}
+
+fn crabby_doc(dir: &Path, args: &[&str]) -> String {
+    let src = dir.join("src");
+    let (output, stderr) = crabby(&[&["doc", src.to_str().unwrap()], args].concat());
+    assert!(output.status.success(), "{}", stderr);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn json_hides_private_items() {
+    let dir = project("json");
+    let json: Json = serde_json::from_str(&crabby_doc(&dir, &["--format", "json"])).unwrap();
+    let shapes = &json["modules"][0];
+    assert_eq!(shapes["name"], "geo.shapes");
+    assert_eq!(shapes["doc"], "Shapes and how to measure them.");
+
+    let names: Vec<&str> = shapes["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Point", "Area", "distance"]);
+    assert_eq!(
+        shapes["items"][0]["doc"],
+        "A point in the plane; see `distance`."
+    );
+    assert_eq!(shapes["items"][1]["doc"], "Something with an area");
+    assert_eq!(shapes["items"][0]["signature"], "pub struct Point");
+    assert_eq!(
+        shapes["items"][2]["signature"],
+        "pub def distance(a: Point, b: Point) -> Float"
+    );
+
+    let json: Json =
+        serde_json::from_str(&crabby_doc(&dir, &["--format", "json", "--private"])).unwrap();
+    let names: Vec<&str> = json["modules"][0]["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Point", "Cache", "Area", "distance", "helper"]);
+}
+
+#[test]
+fn html_site_links_items_and_modules() {
+    let dir = project("html");
+    let out = dir.join("site");
+    crabby_doc(&dir, &["--out", out.to_str().unwrap()]);
+
+    let shapes = fs::read_to_string(out.join("geo.shapes.html")).unwrap();
+    assert!(
+        shapes.contains(r#"<section id="struct.Point">"#),
+        "{}",
+        shapes
+    );
+    // The parameter types link to the struct, and `distance` in the docs to the function
+    assert!(
+        shapes.contains(r#"a: <a href="geo.shapes.html#struct.Point">Point</a>"#),
+        "{}",
+        shapes
+    );
+    assert!(
+        shapes.contains(r#"<a href="geo.shapes.html#function.distance"><code>distance</code></a>"#),
+        "{}",
+        shapes
+    );
+    assert!(!shapes.contains("helper"), "{}", shapes);
+    assert!(!shapes.contains("Cache"), "{}", shapes);
+
+    let main = fs::read_to_string(out.join("main.html")).unwrap();
+    assert!(
+        main.contains(r#"<a href="geo.shapes.html">geo.shapes</a>"#),
+        "{}",
+        main
+    );
+
+    let index = fs::read_to_string(out.join("search-index.js")).unwrap();
+    assert!(index.contains(r#""name":"distance""#), "{}", index);
+    assert!(out.join("index.html").exists());
+}
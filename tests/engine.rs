@@ -0,0 +1,126 @@
+// Hosting Crabby from Rust with `crabby::Engine`
+
+use std::collections::HashMap;
+
+use crabby::{Capabilities, CrabbyError, Engine, FromValue, IntoValue, Value};
+
+#[test]
+fn scripts_call_rust_and_rust_calls_scripts() {
+    let mut engine = Engine::new();
+    engine.register_function("add", |a: i64, b: i64| a + b);
+    engine.register_function("shout", |s: String| format!("{}!", s.to_uppercase()));
+    engine.add_builtin("count", |args| Ok(Value::Integer(args.len() as i64)));
+    engine.set_global("limit", 10);
+    engine.set_global("names", vec!["ferris", "corro"]);
+
+    engine
+        .eval(
+            r#"
+def greet(name) {
+    print(shout(name))
+    return add(limit, count(1, 2, 3))
+}
+let answer = add(40, 2)
+"#,
+        )
+        .unwrap();
+
+    let result = engine
+        .call_function("greet", vec!["ferris".into_value()])
+        .unwrap();
+    assert_eq!(i64::from_value(result).unwrap(), 13);
+    assert_eq!(engine.take_stdout(), "FERRIS!\n");
+    assert_eq!(engine.take_stdout(), "");
+    assert_eq!(engine.get_global::<i64>("answer").unwrap(), 42);
+    assert_eq!(
+        engine.get_global::<Vec<String>>("names").unwrap(),
+        ["ferris", "corro"]
+    );
+    assert_eq!(
+        i64::from_value(engine.eval("add(1, 1) * 2").unwrap()).unwrap(),
+        4
+    );
+
+    // Functions defined in the script come first
+    engine.eval("def add(a, b) { return 0 }").unwrap();
+    assert_eq!(engine.eval("add(1, 1)").unwrap(), Value::Integer(0));
+}
+
+#[test]
+fn values_convert_both_ways() {
+    let mut engine = Engine::new();
+    let mut scores = HashMap::new();
+    scores.insert("ferris".to_string(), 1.5);
+    engine.set_global("scores", scores.clone());
+    engine.set_global("missing", None::<i64>);
+    engine.set_global("outcome", Err::<i64, _>("nope"));
+
+    assert_eq!(
+        engine.get_global::<HashMap<String, f64>>("scores").unwrap(),
+        scores
+    );
+    assert_eq!(engine.get_global::<Option<i64>>("missing").unwrap(), None);
+    assert_eq!(
+        engine.get_global::<Result<i64, String>>("outcome").unwrap(),
+        Err("nope".to_string())
+    );
+
+    let error = engine.get_global::<String>("scores").unwrap_err();
+    assert_eq!(error.to_string(), "Type error: expected a string, got dict");
+    assert!(matches!(
+        engine.get_global::<i64>("nothing"),
+        Err(CrabbyError::InterpreterError(_))
+    ));
+
+    engine.register_function("half", |n: f64| n / 2.0);
+    let error = engine.eval(r#"half("two")"#).unwrap_err();
+    assert!(error.to_string().contains("expected a float, got string"));
+    let error = engine.eval("half(1, 2)").unwrap_err();
+    assert!(error.to_string().contains("half takes 1 argument, got 2"));
+}
+
+#[test]
+fn plugins_are_sandboxed_and_captured() {
+    let mut engine = Engine::new();
+    engine
+        .eval(
+            r#"
+@deprecated("use new_api")
+def old_api() {
+    return env("HOME")
+}
+"#,
+        )
+        .unwrap();
+
+    let denied = engine.call_function("old_api", Vec::new()).unwrap();
+    assert_eq!(
+        Result::<String, String>::from_value(denied).unwrap(),
+        Err(r#"permission denied: access to the environment variable "HOME" (run with --allow-env)"#.to_string())
+    );
+    assert_eq!(
+        engine.take_stderr(),
+        "Warning: 'old_api' is deprecated: use new_api\n"
+    );
+
+    engine.set_capabilities(Capabilities {
+        env: true,
+        ..Capabilities::default()
+    });
+    let allowed = engine.call_function("old_api", Vec::new()).unwrap();
+    assert!(matches!(allowed, Value::Ok(_)));
+}
+
+#[test]
+fn unsupported_statements_are_errors_not_panics() {
+    let mut engine = Engine::new();
+    let error = engine
+        .eval("let x = 1\nimport math from \"math\"")
+        .unwrap_err();
+    assert!(
+        matches!(&error, CrabbyError::RuntimeError(message) if message == "import is not supported yet at line 2"),
+        "{}",
+        error
+    );
+    assert!(matches!(engine.eval("x + 1"), Ok(Value::Integer(2))));
+}
@@ -0,0 +1,367 @@
+// `extern def` calls C functions, from a library built here and from libc and libm
+// `crabby bindgen` is only checked from the Rust bindgen writes, as libclang may be
+// missing
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crabby::core::bindings;
+
+mod common;
+use common::{crabby_in, scratch_dir};
+
+const LIBRARY: &str = r#"
+#include <stdbool.h>
+#include <stdint.h>
+#include <string.h>
+
+double scale(int times, double x, int plus, double y) { return times * x + plus * y; }
+int length(const char *s) { return s ? (int)strlen(s) : -1; }
+const char *greeting(bool loud) { return loud ? "HELLO" : "hello"; }
+const char *nothing(void) { return 0; }
+static int counter;
+int *counter_address(void) { return &counter; }
+int read_counter(int *address) { return *address; }
+void bump(int *address, int by) { *address += by; }
+bool is_even(int n) { return n % 2 == 0; }
+
+typedef struct { double x, y; } Point;
+typedef struct { char tag; int count; double weight; } Mixed;
+typedef struct { int values[3]; Point corner; } Shape;
+double dot(Point a, Point b) { return a.x * b.x + a.y * b.y; }
+Point midpoint(Point a, Point b) { Point m = { (a.x + b.x) / 2, (a.y + b.y) / 2 }; return m; }
+Mixed describe(char tag, int count) { Mixed m = { tag, count, count * 1.5 }; return m; }
+int shape_sum(const Shape *s) { return s->values[0] + s->values[1] + s->values[2]; }
+void shape_grow(Shape *s, int by) { for (int i = 0; i < 3; i++) s->values[i] += by; s->corner.x += by; }
+void split(double x, int *whole, double *rest) { *whole = (int)x; *rest = x - (int)x; }
+int sum(const int *values, int count) { int total = 0; for (int i = 0; i < count; i++) total += values[i]; return total; }
+uint8_t wrap(uint8_t n) { return n + 1; }
+uint64_t huge(void) { return UINT64_MAX; }
+int8_t negative(void) { return -5; }
+float half(float x) { return x / 2; }
+
+int apply(int (*f)(int, int), int a, int b) { return f(a, b); }
+void each(void (*f)(int), int count) { for (int i = 0; i < count; i++) f(i); }
+double integrate(double (*f)(double), double low, double high, int steps) {
+    double total = 0, width = (high - low) / steps;
+    for (int i = 0; i < steps; i++) total += f(low + (i + 0.5) * width) * width;
+    return total;
+}
+"#;
+
+fn scratch(name: &str, source: &str) -> PathBuf {
+    let dir = scratch_dir(name);
+    fs::write(dir.join("sample.c"), LIBRARY).unwrap();
+    let compiled = Command::new("cc")
+        .current_dir(&dir)
+        .args(["-shared", "-fPIC", "-o", "libsample.so", "sample.c"])
+        .output()
+        .expect("a C compiler is installed as `cc`");
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+    fs::write(dir.join("main.crab"), source).unwrap();
+    dir
+}
+
+const CALLS: &str = r#"extern "./libsample.so" def scale(times: int, x: float, plus: int, y: float) -> float
+extern "./libsample.so" def length(s: string) -> int
+extern "./libsample.so" def greeting(loud: bool) -> string
+extern "./libsample.so" def nothing() -> string
+extern "./libsample.so" def counter_address() -> *int
+extern "./libsample.so" def read_counter(address: *int) -> int
+extern "./libsample.so" def bump(address: *int, by: int)
+extern "./libsample.so" def is_even(n: int) -> bool
+extern "libm.so.6" def cos(x: float) -> float
+extern def strlen(s: string) -> int
+
+print(scale(3, 1.5, 2, 0.25))
+print(length("crabby"))
+print(length(null))
+print(greeting(true))
+print(greeting(false))
+print(nothing())
+let counter = counter_address()
+bump(counter, 5)
+bump(counter, 2)
+print(read_counter(counter))
+print(is_even(4))
+print(is_even(7))
+print(cos(0.0))
+print(strlen("claws"))
+"#;
+
+#[test]
+fn extern_functions_take_and_return_c_values() {
+    let dir = scratch("calls", CALLS);
+    let (output, stderr) = crabby_in(&dir, &["run", "--allow-ffi", "main.crab"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "5\n6\n-1\nHELLO\nhello\nnull\n7\ntrue\nfalse\n1\n5\n"
+    );
+}
+
+#[test]
+fn extern_needs_allow_ffi() {
+    let dir = scratch("deny", CALLS);
+    let (output, stderr) = crabby_in(&dir, &["run", "main.crab"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        stderr.contains(r#"permission denied: loading "./libsample.so" (run with --allow-ffi)"#),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn extern_arguments_are_checked() {
+    let dir = scratch(
+        "types",
+        "extern \"./libsample.so\" def length(s: string) -> int\nprint(length(42))\n",
+    );
+    let (output, stderr) = crabby_in(&dir, &["run", "--allow-ffi", "main.crab"]);
+    assert_ne!(output.status.code(), Some(0));
+    assert!(
+        stderr.contains("argument `s` of `length` must be a string, got int"),
+        "{}",
+        stderr
+    );
+}
+
+const STRUCTS: &str = r#"extern struct Point { x: f64, y: f64 }
+extern struct Mixed { tag: i8, count: i32, weight: f64 }
+extern struct Shape { values: i32[3], corner: Point }
+extern "./libsample.so" def dot(a: Point, b: Point) -> f64
+extern "./libsample.so" def midpoint(a: Point, b: Point) -> Point
+extern "./libsample.so" def describe(tag: i8, count: i32) -> Mixed
+extern "./libsample.so" def shape_sum(s: *Shape) -> i32
+extern "./libsample.so" def shape_grow(s: *Shape, by: i32)
+extern "./libsample.so" def split(x: f64, whole: *i32, rest: *f64)
+extern "./libsample.so" def sum(values: *i32, count: i32) -> i32
+extern "./libsample.so" def wrap(n: u8) -> u8
+extern "./libsample.so" def huge() -> u64
+extern "./libsample.so" def negative() -> i8
+extern "./libsample.so" def half(x: f32) -> f32
+
+print(dot(Point(1.0, 2.0), Point(3.0, 4.0)))
+print(midpoint(Point(1.0, 2.0), Point(3.0, 6.0)))
+print(describe(7, 4))
+let shape = Shape([1, 2, 3], Point(0.5, 0.5))
+print(shape_sum(shape))
+let mut grown = shape
+shape_grow(&mut grown, 10)
+print(grown)
+print(shape)
+let mut whole = 0
+let mut rest = 0.0
+split(2.75, &mut whole, &mut rest)
+print(whole)
+print(rest)
+print(sum([1, 2, 3, 4], 4))
+print(wrap(254))
+print(huge())
+print(negative())
+print(half(3.0))
+"#;
+
+#[test]
+fn extern_structs_arrays_and_widths_are_laid_out_as_in_c() {
+    let dir = scratch("structs", STRUCTS);
+    let (output, stderr) = crabby_in(&dir, &["run", "--allow-ffi", "main.crab"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "11\n\
+         Point {x: 2, y: 4}\n\
+         Mixed {tag: 7, count: 4, weight: 6}\n\
+         6\n\
+         Shape {values: [11, 12, 13], corner: Point {x: 10.5, y: 0.5}}\n\
+         Shape {values: [1, 2, 3], corner: Point {x: 0.5, y: 0.5}}\n\
+         2\n0.75\n10\n255\n18446744073709551615\n-5\n1.5\n"
+    );
+}
+
+#[test]
+fn extern_values_are_checked_against_c_types() {
+    for (declarations, call, message) in [
+        (
+            "extern \"./libsample.so\" def wrap(n: u8) -> u8",
+            "wrap(256)",
+            "argument `n` of `wrap` must be an integer that fits in u8, got integer",
+        ),
+        (
+            "extern struct Point { x: f64, y: f64 }\nextern \"./libsample.so\" def dot(a: Point, b: Point) -> f64",
+            "dot(Point(1.0, \"2\"), Point(0.0, 0.0))",
+            "argument `a` of `dot` has a field `y` that must be a float, got string",
+        ),
+        (
+            "extern struct Point { x: f64, y: f64 }\nextern struct Shape { values: i32[3], corner: Point }\nextern \"./libsample.so\" def shape_sum(s: Shape) -> i32",
+            "shape_sum(null)",
+            "`Shape` is too big to pass by value; make `s` of `shape_sum` a pointer",
+        ),
+        (
+            "extern \"./libsample.so\" def apply(f: (i32, i32) -> i32, a: i32, b: i32) -> i32",
+            "apply(lambda(a, b) { return \"no\" }, 2, 3)",
+            "a callback's result must be an integer that fits in i32, got string",
+        ),
+    ] {
+        let dir = scratch("checks", &format!("{}\nprint({})\n", declarations, call));
+        let (output, stderr) = crabby_in(&dir, &["run", "--allow-ffi", "main.crab"]);
+        assert_eq!(output.status.code(), Some(3), "{}", call);
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
+
+const CALLBACKS: &str = r#"extern "./libsample.so" def apply(f: (i32, i32) -> i32, a: i32, b: i32) -> i32
+extern "./libsample.so" def each(f: (i32) -> void, count: i32)
+extern "./libsample.so" def integrate(f: (f64) -> f64, low: f64, high: f64, steps: i32) -> f64
+extern def qsort(base: *i32, count: usize, size: usize, compare: (*i32, *i32) -> i32)
+
+def add(a, b) {
+    return a + b
+}
+
+def show(n) {
+    print(n)
+}
+
+print(apply(add, 2, 3))
+print(apply(lambda(a, b) { return a * b }, 2, 3))
+each(show, 3)
+print(integrate(lambda(x) { return 2.0 * x }, 0.0, 3.0, 3))
+let mut numbers = [5, 3, 9, 1]
+qsort(&mut numbers, 4, 4, lambda(a, b) { return a - b })
+print(numbers)
+"#;
+
+#[test]
+fn crabby_functions_are_passed_as_c_callbacks() {
+    let dir = scratch("callbacks", CALLBACKS);
+    let (output, stderr) = crabby_in(&dir, &["run", "--allow-ffi", "main.crab"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "5\n6\n0\n1\n2\n9\n[1, 3, 5, 9]\n"
+    );
+}
+
+// What bindgen writes for a header declaring some of the library
+const BINDINGS: &str = r#"/* automatically generated by rust-bindgen 0.70.1 */
+
+pub const SIDES: u32 = 3;
+pub const SCALE: f64 = 1.5;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Shape {
+    pub values: [::std::os::raw::c_int; 3usize],
+    pub corner: Point,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Counter {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union Number {
+    pub i: ::std::os::raw::c_int,
+    pub d: f64,
+}
+pub type binary = ::std::option::Option<
+    unsafe extern "C" fn(
+        arg1: ::std::os::raw::c_int,
+        arg2: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int,
+>;
+extern "C" {
+    pub fn dot(a: Point, b: Point) -> f64;
+}
+extern "C" {
+    pub fn shape_grow(s: *mut Shape, by: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn apply(f: binary, a: ::std::os::raw::c_int, b: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn length(s: *const ::std::os::raw::c_char) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn read_counter(address: *mut Counter) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn counter_address() -> *mut Counter;
+}
+extern "C" {
+    pub fn report(format: *const ::std::os::raw::c_char, ...) -> ::std::os::raw::c_int;
+}
+"#;
+
+#[test]
+fn bindgen_output_becomes_extern_declarations() {
+    let module = bindings::translate(BINDINGS, "sample.h", Some("./libsample.so")).unwrap();
+    assert_eq!(
+        module,
+        r#"// Generated from sample.h by `crabby bindgen`
+
+let SIDES = 3
+
+let SCALE = 1.5
+
+extern struct Point {
+    x: f64,
+    y: f64,
+}
+
+extern struct Shape {
+    values: i32[3],
+    corner: Point,
+}
+
+// union `Number` left out: unions aren't supported
+
+extern "./libsample.so" def dot(a: Point, b: Point) -> f64
+
+extern "./libsample.so" def shape_grow(s: ptr<Shape>, by: i32)
+
+extern "./libsample.so" def apply(f: (i32, i32) -> i32, a: i32, b: i32) -> i32
+
+extern "./libsample.so" def length(s: string) -> i32
+
+extern "./libsample.so" def read_counter(address: ptr) -> i32
+
+extern "./libsample.so" def counter_address() -> ptr
+
+// `report` left out: variadic functions aren't supported
+"#
+    );
+
+    let program = format!(
+        "{}\n{}",
+        module,
+        r#"let mut shape = Shape([1, 2, 3], Point(SCALE, 0.0))
+shape_grow(&mut shape, SIDES)
+print(shape)
+print(dot(shape.corner, Point(2.0, 1.0)))
+print(apply(lambda(a, b) { return a - b }, 7, 2))
+print(length("claws"))
+print(read_counter(counter_address()))
+"#
+    );
+    let dir = scratch("bindgen", &program);
+    let (output, stderr) = crabby_in(&dir, &["run", "--allow-ffi", "main.crab"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Shape {values: [4, 5, 6], corner: Point {x: 4.5, y: 0}}\n9\n5\n5\n0\n"
+    );
+}
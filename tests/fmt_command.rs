@@ -0,0 +1,76 @@
+// `crabby fmt` against the golden files in tests/fmt: each `<name>.crab` must format to
+// `<name>.expected.crab`, and formatting that again must change nothing
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+mod common;
+use common::{crabby, scratch_dir};
+
+const GOLDEN: [&str; 2] = ["comments", "long_lines"];
+
+// The input file and the expected output file of a golden test
+fn golden(name: &str) -> (PathBuf, PathBuf) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fmt");
+    (
+        dir.join(format!("{}.crab", name)),
+        dir.join(format!("{}.expected.crab", name)),
+    )
+}
+
+fn scratch(name: &str, source: &Path) -> PathBuf {
+    let file = scratch_dir(name).join("main.crab");
+    fs::copy(source, &file).unwrap();
+    file
+}
+
+fn crabby_fmt(args: &[&str]) -> Output {
+    crabby(&[&["fmt"], args].concat()).0
+}
+
+#[test]
+fn formats_to_the_golden_files() {
+    for name in GOLDEN {
+        let (input, expected) = golden(name);
+        let expected = fs::read_to_string(expected).unwrap();
+        let file = scratch(name, &input);
+        let output = crabby_fmt(&[file.to_str().unwrap()]);
+        assert!(output.status.success(), "{}", name);
+        assert_eq!(fs::read_to_string(&file).unwrap(), expected, "{}", name);
+    }
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for name in GOLDEN {
+        let (_, expected) = golden(name);
+        let file = scratch(&format!("{}-again", name), &expected);
+        let expected = fs::read_to_string(expected).unwrap();
+        let output = crabby_fmt(&["--check", file.to_str().unwrap()]);
+        assert!(
+            output.status.success(),
+            "{}: {}",
+            name,
+            String::from_utf8_lossy(&output.stdout)
+        );
+        crabby_fmt(&[file.to_str().unwrap()]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), expected, "{}", name);
+    }
+}
+
+#[test]
+fn keeps_every_comment() {
+    let (input, expected) = golden("comments");
+    let expected = fs::read_to_string(expected).unwrap();
+    let comments = |source: &str| -> Vec<String> {
+        source
+            .lines()
+            .filter_map(|line| line.find("//").map(|at| line[at..].to_string()))
+            .collect()
+    };
+    assert_eq!(
+        comments(&expected),
+        comments(&fs::read_to_string(input).unwrap())
+    );
+}
@@ -0,0 +1,98 @@
+// Lambdas, default parameters, keyword arguments and rest parameters
+
+use crabby::Engine;
+
+fn run(source: &str) -> String {
+    let mut engine = Engine::new();
+    engine.eval(source).unwrap();
+    engine.take_stdout()
+}
+
+fn error(source: &str) -> String {
+    Engine::new().eval(source).unwrap_err().to_string()
+}
+
+#[test]
+fn arrow_lambdas_take_expressions_and_blocks() {
+    let output = run(r#"
+let double = x => x * 2
+let add = (a, b) => {
+    return a + b
+}
+let inc = lambda(x) { return x + 1 }
+print(double(4))
+print(add(2, 3))
+print(inc(1))
+"#);
+    assert_eq!(output, "8\n5\n2\n");
+}
+
+#[test]
+fn parameters_have_defaults_and_take_keywords() {
+    let output = run(r#"
+def greet(name, greeting = "Hello") {
+    return f"{greeting}, {name}!"
+}
+print(greet("Ferris"))
+print(greet("Ferris", "Hi"))
+print(greet(greeting: "Hey", name: "Corro"))
+"#);
+    assert_eq!(output, "Hello, Ferris!\nHi, Ferris!\nHey, Corro!\n");
+}
+
+#[test]
+fn rest_parameters_collect_what_is_left() {
+    let output = run(r#"
+def total(first, *rest, **options) {
+    return [first, rest, options]
+}
+print(total(1, 2, 3, scale: 2))
+print(total(1))
+"#);
+    assert_eq!(output, "[1, [2, 3], {scale: 2}]\n[1, [], {}]\n");
+}
+
+#[test]
+fn named_functions_are_values() {
+    let output = run(r#"
+def inc(x, by = 1) {
+    return x + by
+}
+def apply(f, x) {
+    return f(x)
+}
+let h = inc
+print(h(1))
+print(h(1, by: 10))
+print(apply(inc, 6))
+print(inc)
+"#);
+    assert_eq!(output, "2\n11\n7\n<lambda(x, by = 1)>\n");
+}
+
+#[test]
+fn bad_arguments_are_named() {
+    let greet = "def greet(name, greeting = \"Hello\") {\n    return name\n}\n";
+    for (call, message) in [
+        (
+            "greet(nam: \"x\")",
+            "Function greet got an unexpected keyword argument 'nam'",
+        ),
+        (
+            "greet(\"a\", name: \"b\")",
+            "Function greet got multiple values for argument 'name'",
+        ),
+        (
+            "greet(name: \"a\", name: \"b\")",
+            "Duplicate keyword argument 'name'",
+        ),
+        ("greet()", "Function greet missing required argument 'name'"),
+        (
+            "greet(\"a\", \"b\", \"c\")",
+            "Function greet expects 2 arguments, got 3",
+        ),
+    ] {
+        let found = error(&format!("{}{}", greet, call));
+        assert!(found.contains(message), "{}: {}", call, found);
+    }
+}
@@ -0,0 +1,29 @@
+// Generic functions and structs, with trait bounds on their type parameters
+
+mod common;
+use common::{assert_rejects, check};
+
+#[test]
+fn generics_are_bound_per_call_and_bounds_are_enforced() {
+    let add = "def add<T: Num>(a: T, b: T) -> T {\n    return a + b\n}\n";
+    let pair = "struct Pair<A: Num, B> {\n    left: A,\n    right: B\n}\n";
+    assert_eq!(
+        check(&format!(
+            "{}{}let sum: Int = add(3, 5)\nlet half: Float = add(1.5, 2.5)\nlet pair: Pair<Int, String>? = null\n",
+            add, pair
+        )),
+        Vec::<String>::new()
+    );
+    assert_rejects(
+        &format!("{}let wrong = add(true, false)", add),
+        "Type Bool does not implement trait Num required by T in call to add (argument 'a')",
+    );
+    assert_rejects(
+        &format!("{}let mixed = add(1, \"x\")", add),
+        "Argument 'b' of 'add' expects Int, got String",
+    );
+    assert_rejects(
+        &format!("{}let pair: Pair<Bool, Int>? = null", pair),
+        "Type Bool does not implement trait Num required by A in Pair",
+    );
+}
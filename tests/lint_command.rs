@@ -0,0 +1,143 @@
+// `crabby lint`: rules, crabby.toml levels, suppression comments and `--fix`
+
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+use common::{crabby, scratch_dir};
+
+const SOURCE: &str = r#"def addNumbers(firstValue, b) {
+    if firstValue == 0.5 {
+    }
+    if b > 1 {
+        return 1
+    } else {
+        return 2
+    }
+}
+
+let coin = maybe // crabby: allow(maybe_probably)
+let other = probably
+print(addNumbers(1, 2))
+"#;
+
+fn scratch(name: &str, config: Option<&str>) -> PathBuf {
+    project(name, SOURCE, config)
+}
+
+fn project(name: &str, source: &str, config: Option<&str>) -> PathBuf {
+    let dir = scratch_dir(name);
+    fs::write(dir.join("main.crab"), source).unwrap();
+    if let Some(config) = config {
+        fs::write(dir.join("crabby.toml"), config).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn lint_reports_rules_and_honors_config() {
+    let dir = scratch("report", None);
+    let (output, stderr) = crabby(&["lint", dir.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    for expected in [
+        "warning[naming]: `addNumbers` should be written in snake_case",
+        "main.crab:1:5",
+        "fix: rename it to `add_numbers`",
+        "warning[empty_block]: empty `if` block",
+        "warning[float_equality]",
+        "main.crab:2:19",
+        "warning[redundant_else]",
+        "main.crab:6:7",
+        "warning[maybe_probably]: `probably` outside of a test",
+        "main.crab:12:13",
+        "3 of these can be fixed with `crabby lint --fix`",
+    ] {
+        assert!(
+            stderr.contains(expected),
+            "missing {:?} in\n{}",
+            expected,
+            stderr
+        );
+    }
+    // The comment only silences its own line
+    assert!(!stderr.contains("main.crab:11:"), "{}", stderr);
+
+    let dir = scratch(
+        "config",
+        Some("[lint]\nfloat_equality = \"deny\"\nnaming = \"allow\"\n"),
+    );
+    let (output, stderr) = crabby(&["lint", dir.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("error[float_equality]"), "{}", stderr);
+    assert!(!stderr.contains("[naming]"), "{}", stderr);
+
+    let dir = scratch("unknown", Some("[lint]\nfloat_eq = \"deny\"\n"));
+    let (output, stderr) = crabby(&["lint", dir.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(
+        stderr.contains("`lint.float_eq` must be the name of a lint rule"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn fix_rewrites_the_file() {
+    let dir = scratch("fix", None);
+    let (output, stderr) = crabby(&["lint", "--fix", dir.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+
+    let fixed = fs::read_to_string(dir.join("main.crab")).unwrap();
+    assert_eq!(
+        fixed,
+        r#"def add_numbers(first_value, b) {
+    if first_value == 0.5 {
+    }
+    if b > 1 {
+        return 1
+    }
+    return 2
+}
+
+let coin = maybe // crabby: allow(maybe_probably)
+let other = probably
+print(add_numbers(1, 2))
+"#
+    );
+    // What can't be fixed is still reported
+    assert!(stderr.contains("warning[float_equality]"), "{}", stderr);
+    assert!(!stderr.contains("[naming]"), "{}", stderr);
+
+    let (output, _) = crabby(&["run", dir.join("main.crab").to_str().unwrap()]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+}
+
+#[test]
+fn a_comment_on_its_own_line_silences_the_next_one() {
+    let dir = project(
+        "own-line",
+        "print(1)\n// crabby: allow(empty_block)\nif true { }\n\n// a plain comment\nif true { }\nprint(2)\n",
+        None,
+    );
+    let (output, stderr) = crabby(&["lint", dir.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(!stderr.contains("main.crab:3:"), "{}", stderr);
+    // Lines after comments are counted once, so the next warning is where it should be
+    assert!(stderr.contains("main.crab:6:1"), "{}", stderr);
+}
+
+#[test]
+fn fixed_else_bodies_keep_the_indentation_of_their_block() {
+    let dir = project(
+        "indent",
+        "def sign(x) {\n  if x < 0 {\n    return -1\n  } else {\n    if x == 0 {\n      return 0\n    }\n    return 1\n  }\n}\nprint(sign(5))\n",
+        None,
+    );
+    let (output, stderr) = crabby(&["lint", "--fix", dir.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(dir.join("main.crab")).unwrap(),
+        "def sign(x) {\n  if x < 0 {\n    return -1\n  }\n  if x == 0 {\n    return 0\n  }\n  return 1\n}\nprint(sign(5))\n"
+    );
+}
@@ -0,0 +1,337 @@
+// Scripted JSON-RPC sessions against `crabby lsp`
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{Value as Json, json};
+
+const URI: &str = "file:///tmp/crabby-lsp-test/main.crab";
+
+const SOURCE: &str = r#"def greet(name) {
+    """
+    Says hello to `name`.
+    """
+    return "Hello, " + name
+}
+
+let who = "Crabby"
+print(greet(who))
+def unused() {
+    return 1
+}
+"#;
+
+struct Session {
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    next_id: i64,
+    // Notifications that arrived while waiting for a response
+    notifications: Vec<Json>,
+}
+
+impl Session {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_crabby"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("crabby lsp starts");
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let mut session = Self {
+            child,
+            input,
+            output,
+            next_id: 0,
+            notifications: Vec::new(),
+        };
+        let result = session.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(result["capabilities"]["hoverProvider"], true);
+        session.notify("initialized", json!({}));
+        session
+    }
+
+    fn send(&mut self, message: Json) {
+        let body = message.to_string();
+        write!(self.input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Json {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.output.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.output.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn request(&mut self, method: &str, params: Json) -> Json {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                assert!(
+                    message.get("error").is_none(),
+                    "{} failed: {}",
+                    method,
+                    message
+                );
+                return message["result"].clone();
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    fn error(&mut self, method: &str, params: Json) -> Json {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                return message["error"].clone();
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    fn diagnostics(&mut self) -> Vec<Json> {
+        let message = match self.notifications.pop() {
+            Some(message) => message,
+            None => self.receive(),
+        };
+        assert_eq!(message["method"], "textDocument/publishDiagnostics");
+        assert_eq!(message["params"]["uri"], URI);
+        message["params"]["diagnostics"].as_array().unwrap().clone()
+    }
+
+    fn open(&mut self, text: &str) -> Vec<Json> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "crabby", "version": 1, "text": text } }),
+        );
+        self.diagnostics()
+    }
+
+    fn at(&mut self, method: &str, line: u32, character: u32, extra: Json) -> Json {
+        let mut params = json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        });
+        for (key, value) in extra.as_object().unwrap() {
+            params[key] = value.clone();
+        }
+        self.request(method, params)
+    }
+
+    fn finish(mut self) {
+        assert_eq!(self.request("shutdown", Json::Null), Json::Null);
+        self.notify("exit", Json::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn range(line: u32, start: u32, end: u32) -> Json {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+#[test]
+fn diagnostics_follow_changes() {
+    let mut session = Session::start();
+
+    let diagnostics = session.open(SOURCE);
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0]["severity"], 2);
+    assert_eq!(diagnostics[0]["range"], range(9, 4, 10));
+
+    session.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "let x = 1\nlet = 2\n" }],
+        }),
+    );
+    let diagnostics = session.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+
+    session.notify(
+        "textDocument/didClose",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    assert!(session.diagnostics().is_empty());
+    session.finish();
+}
+
+// What the buffer looks like halfway through typing a call
+#[test]
+fn unterminated_documents_are_diagnosed_not_fatal() {
+    let mut session = Session::start();
+
+    let diagnostics = session.open("let x = 1\nprint(");
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0]["severity"], 1);
+
+    for (version, text) in [
+        (2, "let x = 1\nprint(-"),
+        (3, "let x = [1,"),
+        (4, "print(x +"),
+    ] {
+        session.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": version },
+                "contentChanges": [{ "text": text }],
+            }),
+        );
+        let diagnostics = session.diagnostics();
+        assert_eq!(diagnostics.len(), 1, "{}: {:?}", text, diagnostics);
+    }
+
+    // Still answering requests once the call is finished
+    session.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 5 },
+            "contentChanges": [{ "text": SOURCE }],
+        }),
+    );
+    assert_eq!(session.diagnostics().len(), 1);
+    let definition = session.at("textDocument/definition", 8, 7, json!({}));
+    assert_eq!(definition, json!({ "uri": URI, "range": range(0, 4, 9) }));
+    session.finish();
+}
+
+#[test]
+fn navigation() {
+    let mut session = Session::start();
+    session.open(SOURCE);
+
+    // `greet` in `print(greet(who))` goes to its definition
+    let definition = session.at("textDocument/definition", 8, 7, json!({}));
+    assert_eq!(definition, json!({ "uri": URI, "range": range(0, 4, 9) }));
+
+    // `name` is used once besides the parameter
+    let references = session.at(
+        "textDocument/references",
+        0,
+        11,
+        json!({ "context": { "includeDeclaration": false } }),
+    );
+    assert_eq!(
+        references,
+        json!([{ "uri": URI, "range": range(4, 23, 27) }])
+    );
+
+    let hover = session.at("textDocument/hover", 8, 8, json!({}));
+    let text = hover["contents"]["value"].as_str().unwrap();
+    assert!(text.contains("def greet(name)"), "{}", text);
+    assert!(text.contains("Says hello to `name`."), "{}", text);
+
+    let symbols = session.request(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    let names: Vec<&str> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["greet", "who", "unused"]);
+    assert_eq!(symbols[0]["range"]["end"]["line"], 5);
+
+    session.finish();
+}
+
+#[test]
+fn completion_rename_and_formatting() {
+    let mut session = Session::start();
+    session.open(SOURCE);
+
+    // Inside `greet`, its parameter is offered along with globals and builtins
+    let items = session.at("textDocument/completion", 4, 8, json!({}));
+    let labels: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    for expected in ["name", "greet", "print", "return"] {
+        assert!(
+            labels.contains(&expected),
+            "{} missing from {:?}",
+            expected,
+            labels
+        );
+    }
+    // `who` is declared after the function, at the top level
+    assert!(labels.contains(&"who"));
+
+    let rename = session.at("textDocument/rename", 7, 5, json!({ "newName": "someone" }));
+    let edits = rename["changes"][URI].as_array().unwrap();
+    assert_eq!(edits.len(), 2);
+    assert_eq!(edits[0]["range"], range(7, 4, 7));
+    assert_eq!(edits[1]["range"], range(8, 12, 15));
+
+    let error = session.error(
+        "textDocument/rename",
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": 7, "character": 5 },
+            "newName": "while",
+        }),
+    );
+    assert_eq!(error["code"], -32602);
+
+    session.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "def f(a,b) {\nreturn a+b\n}\n" }],
+        }),
+    );
+    session.diagnostics();
+    let edits = session.request(
+        "textDocument/formatting",
+        json!({ "textDocument": { "uri": URI }, "options": { "tabSize": 4, "insertSpaces": true } }),
+    );
+    assert_eq!(edits[0]["newText"], "def f(a, b) {\n    return a + b\n}\n");
+
+    session.finish();
+}
+
+#[test]
+fn protocol_errors() {
+    let mut session = Session::start();
+    let error = session.error("workspace/unknownThing", json!({}));
+    assert_eq!(error["code"], -32601);
+    let error = session.error(
+        "textDocument/hover",
+        json!({ "textDocument": { "uri": "file:///not/open.crab" }, "position": { "line": 0, "character": 0 } }),
+    );
+    assert_eq!(error["code"], -32602);
+    session.finish();
+}
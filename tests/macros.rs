@@ -0,0 +1,97 @@
+// Macro expansion, hygiene and `crabby expand`
+
+use crabby::Engine;
+
+mod common;
+use common::{crabby, scratch};
+
+const MACROS: &str = r#"macro repeat!(value, count) {
+    loop count {
+        print(value)
+    }
+}
+
+macro square!(x) {
+    x * x
+}
+
+macro show_swapped!(a, b) {
+    let tmp = a
+    print(b)
+    print(tmp)
+}
+
+repeat!("hi", 2)
+let tmp = 4
+print(square!(tmp + 1))
+show_swapped!("first", tmp)
+print(tmp)
+"#;
+
+#[test]
+fn macros_expand_hygienically_before_running() {
+    let mut engine = Engine::new();
+    engine.eval(MACROS).unwrap();
+    assert_eq!(engine.take_stdout(), "hi\nhi\n25\n4\nfirst\n4\n");
+}
+
+#[test]
+fn renamed_bindings_skip_names_the_program_already_uses() {
+    let source = r#"macro show_swapped!(a, b) {
+    let tmp = a
+    print(b)
+    print(tmp)
+}
+
+let __tmp_1 = "mine"
+show_swapped!("first", __tmp_1)
+print(__tmp_1)
+"#;
+    let mut engine = Engine::new();
+    engine.eval(source).unwrap();
+    assert_eq!(engine.take_stdout(), "mine\nfirst\nmine\n");
+
+    let file = scratch("collision", source);
+    let (output, stderr) = crabby(&["expand", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("let __tmp_2 = \"first\""), "{}", stdout);
+}
+
+#[test]
+fn expand_prints_the_program_without_macros() {
+    let file = scratch("expand", MACROS);
+    let (output, stderr) = crabby(&["expand", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "loop 2 {\n    print(\"hi\")\n}\n\
+         let tmp = 4\n\
+         print((tmp + 1) * (tmp + 1))\n\
+         let __tmp_1 = \"first\"\n\
+         print(tmp)\n\
+         print(__tmp_1)\n\
+         print(tmp)\n"
+    );
+}
+
+#[test]
+fn bad_macro_calls_are_reported_at_the_call_site() {
+    for (source, message) in [
+        (
+            "macro twice!(x) {\n    x + x\n}\nprint(twice!(1, 2))\n",
+            "line 4, column 7: Macro twice! expects 1 arguments, got 2",
+        ),
+        ("print(missing!(1))\n", "Undefined macro: missing!"),
+        (
+            "macro forever!() {\n    forever!()\n}\nforever!()\n",
+            "line 4, column 1: in forever!: Macro forever! exceeded the expansion limit",
+        ),
+    ] {
+        let file = scratch("errors", source);
+        let (output, stderr) = crabby(&["expand", file.to_str().unwrap()]);
+        assert!(!output.status.success());
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
@@ -0,0 +1,74 @@
+// Big integers, --strict-numeric, numeric literals and division
+
+use crabby::Engine;
+
+fn run(source: &str) -> String {
+    let mut engine = Engine::new();
+    engine.eval(source).unwrap();
+    engine.take_stdout()
+}
+
+fn error(source: &str, strict: bool) -> String {
+    let mut engine = Engine::new();
+    engine.set_strict_numeric(strict);
+    engine.eval(source).unwrap_err().to_string()
+}
+
+#[test]
+fn integers_promote_to_big_integers_instead_of_wrapping() {
+    let output = run(r#"
+print(9223372036854775807 + 1)
+print(-9223372036854775807 - 2)
+print(2 ** 100)
+print(1 << 70)
+print(2 ** 64 / 2 ** 63)
+print(99999999999999999999 - 99999999999999999998)
+"#);
+    assert_eq!(
+        output,
+        "9223372036854775808\n-9223372036854775809\n1267650600228229401496703205376\n\
+         1180591620717411303424\n2\n1\n"
+    );
+}
+
+#[test]
+fn strict_numeric_turns_promotion_into_an_error() {
+    let found = error("print(9223372036854775807 + 1)", true);
+    assert!(
+        found.contains("Integer overflow: 9223372036854775807 + 1 does not fit in 64 bits"),
+        "{}",
+        found
+    );
+    let found = error("print(2 ** 64)", true);
+    assert!(found.contains("Integer overflow"), "{}", found);
+    assert!(error("print(1 / 0)", false).contains("Division by zero"));
+}
+
+#[test]
+fn division_and_modulo_round_as_documented() {
+    let output = run(r#"
+print(7 / 2)
+print(7.0 / 2)
+print(-7 % 2)
+print(7.5 % 2)
+print(1_000 + 0xFF + 0o10 + 0b11)
+"#);
+    assert_eq!(output, "3\n3.5\n1\n1.5\n1266\n");
+}
+
+#[test]
+fn slash_slash_is_always_a_comment() {
+    assert_eq!(run("let x = 5 // done :)\nprint(x)"), "5\n");
+    assert_eq!(run("print(7) // see foo)\nprint(8) // 8"), "7\n8\n");
+}
+
+#[test]
+fn malformed_literals_are_lexer_errors() {
+    for source in ["print(1_)", "print(1_.5)", "print(0xFF_)"] {
+        let found = error(source, false);
+        assert!(found.contains("`_` goes between digits"), "{}", found);
+    }
+    assert!(error("print(0x)", false).contains("expected hex digits"));
+    assert!(error("print(0o)", false).contains("expected octal digits"));
+    assert!(error("print(0b_)", false).contains("expected binary digits"));
+}
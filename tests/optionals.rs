@@ -0,0 +1,138 @@
+// Null, optional annotations, `??`, `?.` and `?` propagation
+
+use crabby::Engine;
+
+fn run(source: &str) -> String {
+    let mut engine = Engine::new();
+    engine.eval(source).unwrap();
+    engine.take_stdout()
+}
+
+fn error(source: &str) -> String {
+    Engine::new().eval(source).unwrap_err().to_string()
+}
+
+#[test]
+fn null_coalescing_uses_the_default_only_for_null() {
+    let output = run(r#"
+let name: String? = null
+let title: String? = "Dr"
+print(name ?? "anonymous")
+print(title ?? "none")
+print(null ?? null ?? 3)
+"#);
+    assert_eq!(output, "anonymous\nDr\n3\n");
+}
+
+#[test]
+fn safe_navigation_stops_at_null() {
+    let output = run(r#"
+def profile(**fields) {
+    return fields
+}
+let user = profile(name: "crab", address: null)
+let address = profile(city: "Reef")
+let home = profile(address: address)
+print(user?.address?.city)
+print(home?.address?.city)
+"#);
+    assert_eq!(output, "null\nReef\n");
+}
+
+#[test]
+fn question_mark_returns_early_on_err_and_null() {
+    let output = run(r#"
+def parse_age(input) {
+    if input == null {
+        return Err("missing age")
+    }
+    return Ok(input)
+}
+def next_age(input) {
+    let age = parse_age(input)?
+    return age + 1
+}
+def first(items) {
+    let item = items?
+    return item
+}
+print(next_age(41))
+print(next_age(null))
+print(first(null))
+"#);
+    assert_eq!(output, "42\nErr(missing age)\nnull\n");
+}
+
+#[test]
+fn dereferencing_null_names_the_expression() {
+    let found = error("let user = null\nprint(user.name)");
+    assert!(
+        found.contains("Cannot read field 'name': `user` is null"),
+        "{}",
+        found
+    );
+}
+
+#[test]
+fn optional_annotations_reject_null_only_when_not_optional() {
+    assert_eq!(run("let name: String? = null\nprint(name)"), "null\n");
+    let found = error("let name: String = null");
+    assert!(found.contains("null"), "{}", found);
+}
+
+#[test]
+fn conditions_take_booleans_and_optionals_but_not_numbers() {
+    let output = run(r#"
+let name: String? = null
+if name {
+    print("set")
+} else {
+    print("unset")
+}
+if "crab" {
+    print("present")
+}
+if true {
+    print("yes")
+}
+while 0: {
+    print("never")
+}
+while null: {
+    print("never")
+}
+"#);
+    assert_eq!(output, "unset\npresent\nyes\n");
+    let found = error("if 1 {\n    print(1)\n}");
+    assert!(found.contains("Condition must be boolean"), "{}", found);
+}
+
+#[test]
+fn deep_nesting_is_a_parser_error_not_a_stack_overflow() {
+    let nested = |open: &str, close: &str, depth: usize| {
+        format!("print({}1{})", open.repeat(depth), close.repeat(depth))
+    };
+    // The limit is sized for a main thread; test threads only get 2 MiB
+    let (output, errors) = std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(move || {
+            let output = run(&nested("[", "]", 100));
+            let errors: Vec<String> = [("(", ")"), ("[", "]"), ("- ", ""), ("a?.b ?? (", ")")]
+                .iter()
+                .map(|(open, close)| error(&nested(open, close, 1000)))
+                .collect();
+            (output, errors)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert_eq!(output, format!("{}1{}\n", "[".repeat(100), "]".repeat(100)));
+    for found in errors {
+        assert!(
+            found.contains("Too deeply nested; at most 128 levels are allowed"),
+            "{}",
+            found
+        );
+    }
+}
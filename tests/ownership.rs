@@ -0,0 +1,77 @@
+// Ownership and borrow errors, reported before a program runs
+
+mod common;
+use common::{crabby, scratch};
+
+#[test]
+fn borrows_and_moves_are_checked_before_running() {
+    let file = scratch(
+        "errors",
+        r#"let mut s = "text"
+let r = &mut s
+print(s)
+print(r)
+
+let items = [1, 2]
+let taken = move items
+print(items)
+
+let frozen = [1]
+let alias = ref mut frozen
+
+def leak() {
+    let local = "mine"
+    return &local
+}
+"#,
+    );
+    let (output, stderr) = crabby(&["run", file.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(output.stdout.is_empty());
+    for expected in [
+        "error[borrow-conflict]: cannot use `s` while it is mutably borrowed",
+        "main.crab:3:7",
+        "mutable borrow occurs here",
+        "borrow later used here",
+        "error[moved-value]: use of moved value `items`",
+        "main.crab:8:7",
+        "value moved here",
+        "error[immutable-variable]: cannot borrow `frozen` as mutable",
+        "error[escaping-borrow]: cannot return a reference to the local variable `local`",
+        "main.crab:15:12",
+    ] {
+        assert!(
+            stderr.contains(expected),
+            "missing {:?} in\n{}",
+            expected,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn a_borrow_ends_at_its_last_use() {
+    let file = scratch(
+        "valid",
+        r#"let a = "hello"
+let b = a
+let shared = &a
+let mut m = "world"
+let w = &mut m
+print(w)
+print([m, shared, b])
+"#,
+    );
+    let (output, stderr) = crabby(&["run", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+
+    // Only strict mode moves `a` into `b`
+    let (output, stderr) = crabby(&["check", "--ownership=strict", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(
+        stderr.contains("error[moved-value]: use of moved value `a`"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,90 @@
+// The REPL read from a pipe: meta-commands, multi-line input and the history file
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn home(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crabby-repl-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn repl(home: &PathBuf, input: &str) -> (Output, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_crabby"))
+        .env("HOME", home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("crabby runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    (output, stdout, stderr)
+}
+
+#[test]
+fn definitions_carry_over_and_meta_commands_answer() {
+    let dir = home("commands");
+    let script = dir.join("script.crab");
+    fs::write(&script, "let loaded = 42\n").unwrap();
+    let input = format!(
+        "def add(a, b) {{\n    return a + b\n}}\nadd(1, 2)\n\"text\"\n:type 1 + 2.5\n\
+         :load {}\nloaded\n:reset\nloaded\n:bogus\n:help\n:quit\nprint(\"never\")\n",
+        script.display()
+    );
+    let (output, stdout, stderr) = repl(&dir, &input);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stdout.starts_with("3\n\"text\"\nFloat\n42\nSession reset\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("  :load <file>   Run a file in this session\n"));
+    assert!(!stdout.contains("never"));
+    assert_eq!(
+        stderr,
+        "Interpreter error: Undefined variable: loaded\n\
+         Interpreter error: Unknown command ':bogus'; try :help\n"
+    );
+}
+
+#[test]
+fn every_line_is_appended_to_the_history_file() {
+    let dir = home("history");
+    let (output, _, _) = repl(&dir, "let x = 1\n\nx\nx\n:quit\n");
+    assert!(output.status.success());
+    // Blank lines and immediate repeats are left out
+    assert_eq!(
+        fs::read_to_string(dir.join(".crabby_history")).unwrap(),
+        "let x = 1\nx\n:quit\n"
+    );
+
+    repl(&dir, "x + 1\n");
+    assert_eq!(
+        fs::read_to_string(dir.join(".crabby_history")).unwrap(),
+        "let x = 1\nx\n:quit\nx + 1\n"
+    );
+}
+
+#[test]
+fn history_is_trimmed_to_the_last_thousand_lines() {
+    let dir = home("limit");
+    let old: String = (0..1005).map(|n| format!("print({})\n", n)).collect();
+    fs::write(dir.join(".crabby_history"), old).unwrap();
+    repl(&dir, "");
+    let history = fs::read_to_string(dir.join(".crabby_history")).unwrap();
+    let lines: Vec<&str> = history.lines().collect();
+    assert_eq!(lines.len(), 1000);
+    assert_eq!(lines[0], "print(5)");
+    assert_eq!(lines[999], "print(1004)");
+}
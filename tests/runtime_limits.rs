@@ -0,0 +1,162 @@
+// `crabby run` limits: stopping runaway programs with where and why they stopped
+
+mod common;
+use common::{crabby, scratch};
+
+const SPIN: &str = r#"def spin(n) {
+    var i = 0
+    while true: {
+        let x = i
+    }
+}
+def outer() {
+    return spin(1)
+}
+print("start")
+outer()
+"#;
+
+const GROW: &str = r#"var s = "ab"
+loop 16 {
+    let s = s + s
+}
+print("done")
+"#;
+
+#[test]
+fn runaway_loops_stop_with_a_call_chain() {
+    let file = scratch("spin", SPIN);
+    let file = file.to_str().unwrap();
+
+    let (output, stderr) = crabby(&["run", "--max-loop-iterations", "1000", file]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "start\n");
+    for expected in [
+        "Limit exceeded at line 4, column 9",
+        "ran more than 1000 loop iterations (see --max-loop-iterations)",
+        "in `spin`, called at line 8",
+        "in `outer`, called at line 11",
+    ] {
+        assert!(
+            stderr.contains(expected),
+            "missing {:?} in\n{}",
+            expected,
+            stderr
+        );
+    }
+
+    let (output, stderr) = crabby(&["run", "--timeout", "0.2", file]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("(see --timeout)"), "{}", stderr);
+
+    let (output, stderr) = crabby(&["run", "--max-call-depth", "1", file]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(
+        stderr.contains("nested calls more than 1 deep by calling `spin`"),
+        "{}",
+        stderr
+    );
+
+    let (output, _) = crabby(&["run", "--timeout", "-1", file]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn strings_and_heap_are_bounded() {
+    let file = scratch("grow", GROW);
+    let file = file.to_str().unwrap();
+
+    let (output, stderr) = crabby(&["run", "--max-string-length", "4", file]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(
+        stderr.contains("made a string 8 bytes long, more than the limit of 4"),
+        "{}",
+        stderr
+    );
+
+    let (output, stderr) = crabby(&["run", "--max-heap", "1000", file]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(
+        stderr.contains("allocated more than 1000 bytes (see --max-heap)"),
+        "{}",
+        stderr
+    );
+
+    // Nothing is limited unless asked
+    let (output, stderr) = crabby(&["run", file]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "done\n");
+}
+
+#[test]
+fn huge_integers_are_refused_before_they_are_built() {
+    for (name, source, bytes) in [
+        (
+            "pow",
+            "print(\"start\")\nprint(3 ** 400000000)\n",
+            "79248125",
+        ),
+        (
+            "shift",
+            "print(\"start\")\nprint(1 << 4000000000)\n",
+            "500000001",
+        ),
+    ] {
+        let file = scratch(name, source);
+        let started = std::time::Instant::now();
+        let (output, stderr) = crabby(&[
+            "run",
+            "--timeout",
+            "1",
+            "--max-heap",
+            "1000",
+            file.to_str().unwrap(),
+        ]);
+        assert!(started.elapsed().as_secs() < 5, "{}", name);
+        assert_eq!(output.status.code(), Some(3), "{}", stderr);
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "start\n");
+        assert!(
+            stderr.contains(&format!(
+                "would make an integer of about {} bytes, more than the 1000 bytes allowed (see --max-heap)",
+                bytes
+            )),
+            "{}",
+            stderr
+        );
+    }
+
+    // Ones that fit are still worked out
+    let file = scratch("fits", "print(2 ** 100)\nprint(1 << 70)\n");
+    let (output, stderr) = crabby(&["run", "--max-heap", "1000", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1267650600228229401496703205376\n1180591620717411303424\n"
+    );
+}
+
+#[test]
+fn deeply_nested_input_is_an_error_before_it_runs() {
+    let parens = format!("print({}1{})\n", "(".repeat(1000), ")".repeat(1000));
+    let arrays = format!("print({}1{})\n", "[".repeat(300), "]".repeat(300));
+    for (name, source) in [("parens", parens), ("arrays", arrays)] {
+        let file = scratch(name, &source);
+        let file = file.to_str().unwrap();
+        for args in [
+            &["run", "--max-heap", "1000", file][..],
+            &["check", file],
+            &["fmt", file],
+            &["lint", file],
+            &["expand", file],
+        ] {
+            let (output, stderr) = crabby(args);
+            assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr);
+            assert!(
+                stderr.contains("Too deeply nested; at most 128 levels are allowed"),
+                "{:?}: {}",
+                args,
+                stderr
+            );
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Native functions only reach files, the network and so on when `crabby run` allows it
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+
+mod common;
+use common::{crabby_in, scratch_dir};
+
+fn scratch(name: &str, source: &str) -> PathBuf {
+    let dir = scratch_dir(name);
+    fs::create_dir(dir.join("data")).unwrap();
+    fs::write(dir.join("data").join("greeting.txt"), "hello").unwrap();
+    fs::write(dir.join("secret.txt"), "hunter2").unwrap();
+    fs::write(dir.join("main.crab"), source).unwrap();
+    dir
+}
+
+const FILES: &str = r#"print(read_file("data/greeting.txt"))
+print(read_file("data/../secret.txt"))
+print(write_file("data/out.txt", "written"))
+print(env("PATH"))
+print(exec("echo", ["hi"]))
+print(loadlib("libcrabby_missing.so"))
+def guarded() {
+    let text = read_file("secret.txt")?
+    return Ok(text)
+}
+print(guarded())
+"#;
+
+#[test]
+fn nothing_is_allowed_by_default() {
+    let dir = scratch("deny", FILES);
+    let (output, stderr) = crabby_in(&dir, &["run", "main.crab"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"Err(permission denied: read access to "data/greeting.txt" (run with --allow-read))
+Err(permission denied: read access to "data/../secret.txt" (run with --allow-read))
+Err(permission denied: write access to "data/out.txt" (run with --allow-write))
+Err(permission denied: access to the environment variable "PATH" (run with --allow-env))
+Err(permission denied: running "echo" (run with --allow-run))
+Err(permission denied: loading "libcrabby_missing.so" (run with --allow-ffi))
+Err(permission denied: read access to "secret.txt" (run with --allow-read))
+"#
+    );
+    assert!(!dir.join("data").join("out.txt").exists());
+}
+
+#[test]
+fn grants_are_limited_to_what_is_listed() {
+    let dir = scratch("grant", FILES);
+    let (output, stderr) = crabby_in(
+        &dir,
+        &[
+            "run",
+            "--allow-read=data",
+            "--allow-write=data",
+            "--allow-run",
+            "main.crab",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "Ok(hello)");
+    // `..` can't be used to leave the allowed directory
+    assert!(
+        lines[1].contains("permission denied: read access"),
+        "{}",
+        stdout
+    );
+    assert_eq!(lines[2], "Ok(void)");
+    assert!(lines[3].contains("--allow-env"), "{}", stdout);
+    assert_eq!(lines[4..6], ["Ok(hi", ")"]);
+    assert!(lines[6].contains("--allow-ffi"), "{}", stdout);
+    assert!(lines[7].contains("permission denied"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(dir.join("data").join("out.txt")).unwrap(),
+        "written"
+    );
+}
+
+#[test]
+fn fetch_needs_the_host_allowed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..read]);
+        }
+        assert!(request.starts_with(b"GET /ping HTTP/1.0\r\n"));
+        stream
+            .write_all(b"HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\npong")
+            .unwrap();
+    });
+
+    let source = format!("print(fetch(\"http://127.0.0.1:{}/ping\"))\n", port);
+    let dir = scratch("net", &source);
+    let (output, _) = crabby_in(&dir, &["run", "--allow-net=localhost", "main.crab"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "Err(permission denied: network access to 127.0.0.1:{} (run with --allow-net))\n",
+            port
+        )
+    );
+
+    let allowed = format!("--allow-net=127.0.0.1:{}", port);
+    let (output, stderr) = crabby_in(&dir, &["run", &allowed, "main.crab"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Ok(pong)\n");
+    server.join().unwrap();
+}
+
+#[test]
+fn fetch_gives_up_when_the_program_runs_out_of_time() {
+    // Accepts the connection, then never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || listener.accept().map(|(stream, _)| stream));
+
+    let source = format!(
+        "print(fetch(\"http://127.0.0.1:{}/slow\"))\nprint(\"after\")\n",
+        port
+    );
+    let dir = scratch("slow", &source);
+    let allowed = format!("--allow-net=127.0.0.1:{}", port);
+    let started = std::time::Instant::now();
+    let (output, stderr) = crabby_in(&dir, &["run", &allowed, "--timeout", "1", "main.crab"]);
+    assert!(started.elapsed().as_secs() < 10, "{}", stderr);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "Err(cannot fetch \"http://127.0.0.1:{}/slow\": timed out)\n",
+            port
+        )
+    );
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("(see --timeout)"), "{}", stderr);
+    drop(server.join().unwrap());
+}
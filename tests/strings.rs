@@ -0,0 +1,135 @@
+// String literals: escapes, quotes, raw and triple-quoted strings, docstrings and f-strings
+
+use crabby::Engine;
+use crabby::ast::Statement;
+use crabby::lexer::TokenStream;
+
+fn run(source: &str) -> String {
+    let mut engine = Engine::new();
+    engine.eval(source).unwrap();
+    engine.take_stdout()
+}
+
+fn error(source: &str) -> String {
+    Engine::new().eval(source).unwrap_err().to_string()
+}
+
+#[test]
+fn escapes_are_applied_in_either_quote() {
+    let output = run(r#"
+print("tab\there\nnew \"quoted\" \u{1F980} \\ end")
+print('single "double" inside \'')
+print("joined \
+line")
+"#);
+    assert_eq!(
+        output,
+        "tab\there\nnew \"quoted\" \u{1F980} \\ end\nsingle \"double\" inside '\njoined line\n"
+    );
+}
+
+#[test]
+fn raw_strings_keep_backslashes_and_braces() {
+    let output = run(r#"
+print(r"raw \n \d {x}")
+print(r'raw \t')
+"#);
+    assert_eq!(output, "raw \\n \\d {x}\nraw \\t\n");
+}
+
+#[test]
+fn triple_quoted_strings_span_lines() {
+    let output = run(r#"
+print("""
+first
+  second "quotes" \t tab""")
+print('''a
+b''')
+print(r"""raw \n
+too""")
+"#);
+    assert_eq!(
+        output,
+        "first\n  second \"quotes\" \t tab\na\nb\nraw \\n\ntoo\n"
+    );
+}
+
+#[test]
+fn docstrings_document_files_and_functions_without_printing() {
+    let source = r#""""
+Greetings.
+
+Say hello with `greet`.
+"""
+def greet(name) {
+    """Says hello."""
+    return "hi " + name
+}
+print(greet("you"))
+"#;
+    assert_eq!(run(source), "hi you\n");
+
+    let tokens = TokenStream::tokenize(source.to_string()).unwrap();
+    let program = crabby::parser::parse(tokens).unwrap();
+    assert_eq!(program.docstring, "Greetings.\n\nSay hello with `greet`.");
+    let Some(Statement::FunctionDef { docstring, .. }) = program.statements.get(1) else {
+        panic!("expected a function, got {:?}", program.statements.get(1));
+    };
+    assert_eq!(docstring, "Says hello.");
+}
+
+#[test]
+fn f_string_holes_take_expressions_and_format_specs() {
+    let output = run(r#"
+def greet(name) {
+    return "hi " + name
+}
+let x = 3.14159
+let name = "crab"
+let n = 1234567
+print(f"[{x:>8.2}] [{x:.2f}] [{name:>10}] [{name:<6}|] [{name:^8}]")
+print(f"[{n:,}] [{n:+d}] [{255:x}] [{7:08.3f}] {{braces}}")
+print(f"{greet(name)} {1 + 1} {'quoted'}")
+print(f'''{name}
+{n}''')
+"#);
+    assert_eq!(
+        output,
+        "[    3.14] [3.14] [      crab] [crab  |] [  crab  ]\n\
+         [1,234,567] [+1234567] [ff] [0007.000] {braces}\n\
+         hi crab 2 quoted\n\
+         crab\n1234567\n"
+    );
+}
+
+#[test]
+fn malformed_strings_are_lexer_errors() {
+    for (source, message) in [
+        (r#"print("a\q")"#, r"Unknown escape sequence '\q'"),
+        (
+            r#"print("\u{110000}")"#,
+            r"'\u{110000}' is not a valid unicode character",
+        ),
+        (
+            r#"print("\u12")"#,
+            r"Expected '\u{XXXX}' with 1 to 6 hex digits",
+        ),
+        (
+            r#"print("abc"#,
+            "Unterminated string; expected a closing \"",
+        ),
+        (
+            "print('abc\nprint(1)",
+            "Unterminated string; expected a closing '",
+        ),
+        (r#"print(f"{x")"#, "Unclosed '{' in f-string"),
+        (
+            r#"print(f"a } b")"#,
+            "Single '}' in f-string; write '}}' for a literal brace",
+        ),
+        (r#"print(f"{1:?}")"#, "In f-string: Invalid format spec '?'"),
+    ] {
+        let found = error(source);
+        assert!(found.contains(message), "{:?}: {}", source, found);
+    }
+}
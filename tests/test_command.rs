@@ -0,0 +1,185 @@
+// `crabby test` over a scratch directory of test files
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+mod common;
+use common::{crabby, crabby_in, scratch_dir};
+
+const MATH_TEST: &str = r#"def add(a, b) {
+    return a + b
+}
+
+test "adds" {
+    assert add(1, 2) == 3, "1 + 2 should be 3"
+    expect(add(2, 2)).to_equal(4)
+}
+
+test "compares lists" {
+    expect([1, [2, 3]]).to_equal([1, [2, 4]])
+}
+
+@test
+def checks_helpers() {
+    expect([1, 2, 3]).to_contain(2)
+    expect("crabby").to_have_length(6)
+    expect(() => add(1)).to_throw("missing")
+}
+"#;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = scratch_dir(name);
+    fs::write(dir.join("math_test.crab"), MATH_TEST).unwrap();
+    // Not a test file, so never run
+    fs::write(dir.join("helper.crab"), "let = broken").unwrap();
+    dir
+}
+
+fn crabby_test(args: &[&str]) -> (Output, String) {
+    let (output, _) = crabby(&[&["test"], args].concat());
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    (output, stdout)
+}
+
+#[test]
+fn reports_each_test_with_diffs() {
+    let dir = scratch("report");
+    let junit = dir.join("results.xml");
+    let (output, stdout) =
+        crabby_test(&[dir.to_str().unwrap(), "--junit", junit.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("math_test.crab::adds ... ok"), "{}", stdout);
+    assert!(
+        stdout.contains("math_test.crab::checks_helpers ... ok"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("math_test.crab::compares lists ... FAILED"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("at [1][1]: expected 4, got 3"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("2 passed; 1 failed; 0 filtered out"),
+        "{}",
+        stdout
+    );
+
+    let xml = fs::read_to_string(junit).unwrap();
+    assert!(
+        xml.contains(r#"<testsuites name="crabby" tests="3" failures="1""#),
+        "{}",
+        xml
+    );
+    assert!(xml.contains(r#"<testcase name="adds""#), "{}", xml);
+    assert!(
+        xml.contains("<failure message=\"Assertion failed: values differ\">"),
+        "{}",
+        xml
+    );
+}
+
+#[test]
+fn filters_and_fails_fast() {
+    let dir = scratch("filter");
+    let (output, stdout) = crabby_test(&[dir.to_str().unwrap(), "--filter", "add"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert!(
+        stdout.contains("1 passed; 0 failed; 2 filtered out"),
+        "{}",
+        stdout
+    );
+
+    let (output, stdout) = crabby_test(&[dir.to_str().unwrap(), "--fail-fast"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(!stdout.contains("checks_helpers"), "{}", stdout);
+}
+
+const SHAPES: &str = r#"/// Twice `x`
+///
+/// ```
+/// print(double(3)) // => 6
+/// ```
+def double(x) {
+    return x * 2
+}
+
+def area(w, h) {
+    """
+    ```crabby
+    print(area(2, 3)) // => 5
+    ```
+
+    ```text
+    not crabby, so never run
+    ```
+    """
+    return w * h
+}
+
+print("only the declarations run before an example")
+"#;
+
+#[test]
+fn runs_docstring_examples() {
+    let dir = scratch_dir("doc");
+    fs::write(dir.join("shapes.crab"), SHAPES).unwrap();
+    fs::write(dir.join("empty.crab"), "").unwrap();
+
+    let (output, stdout) = crabby_test(&[dir.to_str().unwrap(), "--doc"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(
+        stdout.contains("shapes.crab::double (line 3) ... ok"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("shapes.crab::area (line 12) ... FAILED"),
+        "{}",
+        stdout
+    );
+    // The line of the file the wrong expectation is on
+    assert!(
+        stdout.contains("at line 13: expected \"5\", printed \"6\""),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("declarations run"), "{}", stdout);
+    assert!(
+        stdout.contains("1 passed; 1 failed; 0 filtered out"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn missing_paths_are_named() {
+    let dir = scratch_dir("missing");
+    let crabby = |args: &[&str]| {
+        let (output, stderr) = crabby_in(&dir, args);
+        (output.status.code(), stderr)
+    };
+
+    let (code, stderr) = crabby(&["test"]);
+    assert_eq!(code, Some(2));
+    assert!(
+        stderr.starts_with("No tests directory: ./tests does not exist."),
+        "{}",
+        stderr
+    );
+
+    let (code, stderr) = crabby(&["test", "elsewhere"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.starts_with("IO error: elsewhere: "), "{}", stderr);
+
+    let (code, stderr) = crabby(&["run", "missing.crab"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.starts_with("IO error: missing.crab: "), "{}", stderr);
+}
@@ -0,0 +1,32 @@
+// The gradual type checker behind `crabby check`: annotations and optionals
+
+mod common;
+use common::{assert_rejects, check};
+
+#[test]
+fn annotated_code_is_checked_and_unannotated_code_is_left_alone() {
+    let area = "def area(width: Float, height: Float) -> Float {\n    return width * height\n}\n";
+    assert_eq!(
+        check(&format!(
+            "{}let square: Float = area(2, 2)\nlet anything = area\nlet shown: String = null ?? \"x\"\n",
+            area
+        )),
+        Vec::<String>::new()
+    );
+    assert_rejects(
+        "let wrong: Int = \"text\"",
+        "line 1, column 1: Cannot assign String to 'wrong' of type Int",
+    );
+    assert_rejects(
+        &format!("{}area(\"wide\", 2)", area),
+        "Argument 'width' of 'area' expects Float, got String",
+    );
+    assert_rejects(
+        "def bad() -> Int {\n    return \"no\"\n}",
+        "line 2, column 5: Function 'bad' is declared to return Int, but returns String",
+    );
+    assert_rejects(
+        "let nickname: String? = null\nlet length = nickname.length",
+        "`nickname` is String?, which may be null; use '?.' to access it",
+    );
+}
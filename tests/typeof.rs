@@ -0,0 +1,46 @@
+// `typeof` and `is`, which give type values and booleans, and `typedef` aliases
+
+use crabby::Engine;
+
+mod common;
+use common::{assert_rejects, check};
+
+#[test]
+fn typeof_and_is_give_type_values_and_booleans() {
+    assert_eq!(
+        check(
+            "let x = \"s\"\nlet t: Type = typeof x\nlet b: Bool = x is Int\ntypedef Id = Int\nlet id: Id = 1\n"
+        ),
+        Vec::<String>::new()
+    );
+    assert_rejects(
+        "let x = \"s\"\nlet n: Int = typeof x",
+        "Cannot assign Type to 'n' of type Int",
+    );
+    assert_rejects(
+        "let x = \"s\"\nlet s: String = x is String",
+        "Cannot assign Bool to 's' of type String",
+    );
+    assert_rejects(
+        "typedef Id = Int\nlet id: Id = \"abc\"",
+        "Cannot assign String to 'id' of type Int",
+    );
+
+    let mut engine = Engine::new();
+    engine
+        .eval(
+            r#"
+struct Person {
+    name: String
+}
+let person = Person(name: "John")
+print(typeof "crab")
+print(typeof(person))
+print(person is Person)
+print(67 is String)
+print(null is Int?)
+"#,
+        )
+        .unwrap();
+    assert_eq!(engine.take_stdout(), "String\nPerson\ntrue\nfalse\ntrue\n");
+}